@@ -0,0 +1,177 @@
+use futures_util::future::{AbortHandle, AbortRegistration};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+pub const AI_CANCELLED_EVENT: &str = "ai-analysis-cancelled";
+pub const CANCELLED_ERROR: &str = "AI request cancelled";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AiTaskKind {
+    Analysis,
+    Gate,
+    // Rolling transcript summary (see `memory`)
+    Summary,
+    // Reference document retrieval (see `retrieval`)
+    Embedding,
+}
+
+impl AiTaskKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Analysis => "analysis",
+            Self::Gate => "gate",
+            Self::Summary => "summary",
+            Self::Embedding => "embedding",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "analysis" => Some(Self::Analysis),
+            "gate" => Some(Self::Gate),
+            "summary" => Some(Self::Summary),
+            "embedding" => Some(Self::Embedding),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CancelReason {
+    // cancel_ai_request was called for this id
+    Requested,
+    // A newer request for the same assistant replaced it
+    Superseded,
+}
+
+// Payload of the `ai-analysis-cancelled` event.
+#[derive(Debug, Clone, Serialize)]
+pub struct AiCancelled {
+    pub request_id: String,
+    pub kind: AiTaskKind,
+    pub assistant_id: String,
+    pub reason: CancelReason,
+}
+
+struct AiTask {
+    kind: AiTaskKind,
+    assistant_id: String,
+    handle: AbortHandle,
+    reason: Option<CancelReason>,
+}
+
+// In-flight analysis and gate requests, keyed by request id.
+#[derive(Default)]
+pub struct AiTaskRegistry {
+    tasks: HashMap<String, AiTask>,
+}
+
+impl AiTaskRegistry {
+    // Register a request and return the registration its future must be
+    // wrapped with. With `supersede`, earlier requests of the same kind for
+    // the same assistant are aborted first.
+    pub fn start(
+        &mut self,
+        request_id: &str,
+        kind: AiTaskKind,
+        assistant_id: &str,
+        supersede: bool,
+    ) -> AbortRegistration {
+        if supersede {
+            for (id, task) in self.tasks.iter_mut() {
+                if id != request_id && task.kind == kind && task.assistant_id == assistant_id {
+                    task.reason.get_or_insert(CancelReason::Superseded);
+                    task.handle.abort();
+                }
+            }
+        }
+        let (handle, registration) = AbortHandle::new_pair();
+        if let Some(previous) = self.tasks.insert(
+            request_id.to_string(),
+            AiTask {
+                kind,
+                assistant_id: assistant_id.to_string(),
+                handle,
+                reason: None,
+            },
+        ) {
+            // A reused id must not leave the older request running untracked
+            previous.handle.abort();
+        }
+        registration
+    }
+
+    // Returns false when no request with this id is running.
+    pub fn cancel(&mut self, request_id: &str) -> bool {
+        match self.tasks.get_mut(request_id) {
+            Some(task) => {
+                task.reason.get_or_insert(CancelReason::Requested);
+                task.handle.abort();
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Drop a completed request; reports why it was cancelled, if it was.
+    pub fn finish(&mut self, request_id: &str) -> Option<AiCancelled> {
+        let task = self.tasks.remove(request_id)?;
+        task.reason.map(|reason| AiCancelled {
+            request_id: request_id.to_string(),
+            kind: task.kind,
+            assistant_id: task.assistant_id,
+            reason,
+        })
+    }
+}
+
+// Id for requests the frontend did not name (e.g. older gate calls).
+pub fn generated_request_id(prefix: &str) -> String {
+    static NEXT: AtomicU64 = AtomicU64::new(1);
+    format!("{}-{}", prefix, NEXT.fetch_add(1, Ordering::Relaxed))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::future::Abortable;
+    use futures_util::FutureExt;
+
+    #[test]
+    fn test_cancel_aborts_registered_future() {
+        let mut registry = AiTaskRegistry::default();
+        let registration = registry.start("a", AiTaskKind::Analysis, "general", false);
+        assert!(registry.cancel("a"));
+        assert!(!registry.cancel("missing"));
+
+        let outcome = Abortable::new(std::future::pending::<()>(), registration).now_or_never();
+        assert!(matches!(outcome, Some(Err(_))));
+        let cancelled = registry.finish("a").unwrap();
+        assert_eq!(cancelled.reason, CancelReason::Requested);
+        assert!(registry.finish("a").is_none());
+    }
+
+    #[test]
+    fn test_supersede_only_matches_same_assistant_and_kind() {
+        let mut registry = AiTaskRegistry::default();
+        let old = registry.start("old", AiTaskKind::Analysis, "general", true);
+        let other_assistant = registry.start("other", AiTaskKind::Analysis, "coach", true);
+        let gate = registry.start("gate", AiTaskKind::Gate, "general", true);
+        let _new = registry.start("new", AiTaskKind::Analysis, "general", true);
+
+        let run = |registration| Abortable::new(async {}, registration).now_or_never();
+        assert!(matches!(run(old), Some(Err(_))));
+        assert!(matches!(run(other_assistant), Some(Ok(()))));
+        assert!(matches!(run(gate), Some(Ok(()))));
+
+        assert_eq!(
+            registry.finish("old").map(|c| c.reason),
+            Some(CancelReason::Superseded)
+        );
+        assert!(registry.finish("other").is_none());
+        assert!(registry.finish("new").is_none());
+    }
+}
@@ -0,0 +1,321 @@
+use crate::{
+    llm::{
+        self, AiError, ChatRequest, ChatResponse, ChatRole, DeltaCallback, LlmProvider,
+        StreamEvent, TokenUsage,
+    },
+    retry::RetryPolicy,
+    utils::log_to_file,
+};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+const API_URL: &str = "https://api.anthropic.com/v1";
+const API_VERSION: &str = "2023-06-01";
+
+#[derive(Debug, Serialize)]
+struct WireMessage {
+    role: &'static str,
+    content: String,
+}
+
+#[derive(Debug, Serialize)]
+struct MessagesRequest {
+    model: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    system: String,
+    messages: Vec<WireMessage>,
+    max_tokens: u32,
+    temperature: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    stop_sequences: Vec<String>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    stream: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct ContentBlock {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    text: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct WireUsage {
+    // Excludes tokens read from or written to the prompt cache
+    #[serde(default)]
+    input_tokens: u32,
+    #[serde(default)]
+    output_tokens: u32,
+    #[serde(default)]
+    cache_creation_input_tokens: u32,
+    #[serde(default)]
+    cache_read_input_tokens: u32,
+}
+
+impl From<WireUsage> for TokenUsage {
+    fn from(value: WireUsage) -> Self {
+        Self {
+            input_tokens: value.input_tokens
+                + value.cache_creation_input_tokens
+                + value.cache_read_input_tokens,
+            output_tokens: value.output_tokens,
+            cached_input_tokens: value.cache_read_input_tokens,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct MessagesResponse {
+    content: Vec<ContentBlock>,
+    #[serde(default)]
+    usage: Option<WireUsage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ModelData {
+    id: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ModelsResponse {
+    data: Vec<ModelData>,
+}
+
+fn messages_request(request: &ChatRequest, stream: bool) -> MessagesRequest {
+    let (system, turns) = request.split_system();
+    MessagesRequest {
+        model: request.model.clone(),
+        system,
+        messages: turns
+            .into_iter()
+            .map(|m| WireMessage {
+                role: if m.role == ChatRole::Assistant {
+                    "assistant"
+                } else {
+                    "user"
+                },
+                content: m.content,
+            })
+            .collect(),
+        max_tokens: request.max_tokens,
+        temperature: request.temperature,
+        top_p: request.top_p,
+        stop_sequences: request.stop.clone(),
+        stream,
+    }
+}
+
+// Native Anthropic Messages API.
+pub struct AnthropicProvider {
+    api_key: String,
+    client: reqwest::Client,
+    timeout: Duration,
+    retry: RetryPolicy,
+}
+
+impl AnthropicProvider {
+    pub fn new(api_key: &str, timeout: Duration) -> Self {
+        Self {
+            api_key: api_key.to_string(),
+            client: llm::http_client(),
+            timeout,
+            retry: RetryPolicy::default(),
+        }
+    }
+
+    fn request(&self, builder: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        builder
+            .header("x-api-key", &self.api_key)
+            .header("anthropic-version", API_VERSION)
+    }
+
+    async fn post_messages(&self, body: &MessagesRequest) -> Result<reqwest::Response, AiError> {
+        llm::send_with_retry("Anthropic", &self.retry, self.timeout, || {
+            let request = self
+                .request(self.client.post(format!("{}/messages", API_URL)))
+                .header("Content-Type", "application/json")
+                .json(body);
+            if body.stream {
+                request
+            } else {
+                request.timeout(self.timeout)
+            }
+        })
+        .await
+    }
+}
+
+#[async_trait]
+impl LlmProvider for AnthropicProvider {
+    fn label(&self) -> &'static str {
+        "Anthropic"
+    }
+
+    async fn chat(&self, request: &ChatRequest) -> Result<ChatResponse, AiError> {
+        let response = self
+            .post_messages(&messages_request(request, false))
+            .await?;
+        let message: MessagesResponse = llm::read_json("Anthropic", response).await?;
+        let text: String = message
+            .content
+            .into_iter()
+            .filter(|block| block.kind == "text")
+            .map(|block| block.text)
+            .collect();
+        Ok(ChatResponse {
+            text,
+            usage: message.usage.map(TokenUsage::from),
+            generation_id: None,
+        })
+    }
+
+    async fn chat_stream(
+        &self,
+        request: &ChatRequest,
+        on_delta: &mut DeltaCallback<'_>,
+    ) -> Result<ChatResponse, AiError> {
+        let response = self.post_messages(&messages_request(request, true)).await?;
+        llm::collect_stream(
+            "Anthropic",
+            response,
+            parse_stream_event,
+            self.timeout,
+            on_delta,
+        )
+        .await
+    }
+
+    async fn list_models(&self) -> Result<Vec<String>, AiError> {
+        log_to_file("Anthropic: Fetching available models");
+        let response = llm::send_with_retry("Anthropic", &self.retry, self.timeout, || {
+            self.request(self.client.get(format!("{}/models?limit=1000", API_URL)))
+                .timeout(self.timeout)
+        })
+        .await?;
+        let models: ModelsResponse = llm::read_json("Anthropic", response).await?;
+        // The API already lists newest models first
+        Ok(models.data.into_iter().map(|model| model.id).collect())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct StreamDelta {
+    #[serde(rename = "type", default)]
+    kind: String,
+    #[serde(default)]
+    text: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct StreamMessage {
+    #[serde(default)]
+    usage: WireUsage,
+}
+
+#[derive(Debug, Deserialize)]
+struct StreamError {
+    message: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct StreamPayload {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    message: Option<StreamMessage>,
+    #[serde(default)]
+    delta: Option<StreamDelta>,
+    #[serde(default)]
+    usage: Option<WireUsage>,
+    #[serde(default)]
+    error: Option<StreamError>,
+}
+
+// Interpret one SSE payload from the Messages stream.
+pub fn parse_stream_event(data: &str) -> Result<Vec<StreamEvent>, String> {
+    let payload: StreamPayload = serde_json::from_str(data.trim())
+        .map_err(|e| format!("Failed to parse Anthropic stream event: {}", e))?;
+    let mut events = Vec::new();
+    match payload.kind.as_str() {
+        "message_start" => {
+            if let Some(message) = payload.message {
+                events.push(StreamEvent::Usage(message.usage.into()));
+            }
+        }
+        "content_block_delta" => {
+            if let Some(delta) = payload.delta {
+                if delta.kind == "text_delta" && !delta.text.is_empty() {
+                    events.push(StreamEvent::Delta(delta.text));
+                }
+            }
+        }
+        "message_delta" => {
+            if let Some(usage) = payload.usage {
+                events.push(StreamEvent::Usage(usage.into()));
+            }
+            events.push(StreamEvent::Finished);
+        }
+        "message_stop" => events.push(StreamEvent::Done),
+        "error" => {
+            let message = payload
+                .error
+                .map(|e| e.message)
+                .unwrap_or_else(|| "Unknown error".to_string());
+            return Err(format!("Anthropic stream error: {}", message));
+        }
+        // ping, content_block_start, content_block_stop
+        _ => {}
+    }
+    Ok(events)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_stream_event() {
+        let start = r#"{"type":"message_start","message":{"id":"msg_1","usage":{"input_tokens":25,"output_tokens":1,"cache_read_input_tokens":100}}}"#;
+        assert_eq!(
+            parse_stream_event(start).unwrap(),
+            vec![StreamEvent::Usage(TokenUsage {
+                input_tokens: 125,
+                output_tokens: 1,
+                cached_input_tokens: 100
+            })]
+        );
+        let delta =
+            r#"{"type":"content_block_delta","index":0,"delta":{"type":"text_delta","text":"Hi"}}"#;
+        assert_eq!(
+            parse_stream_event(delta).unwrap(),
+            vec![StreamEvent::Delta("Hi".into())]
+        );
+        let end = r#"{"type":"message_delta","delta":{"stop_reason":"end_turn"},"usage":{"output_tokens":15}}"#;
+        assert_eq!(
+            parse_stream_event(end).unwrap(),
+            vec![
+                StreamEvent::Usage(TokenUsage {
+                    input_tokens: 0,
+                    output_tokens: 15,
+                    cached_input_tokens: 0
+                }),
+                StreamEvent::Finished
+            ]
+        );
+        assert_eq!(
+            parse_stream_event(r#"{"type":"message_stop"}"#).unwrap(),
+            vec![StreamEvent::Done]
+        );
+        assert!(parse_stream_event(r#"{"type":"ping"}"#).unwrap().is_empty());
+
+        let error =
+            r#"{"type":"error","error":{"type":"overloaded_error","message":"Overloaded"}}"#;
+        assert!(parse_stream_event(error)
+            .unwrap_err()
+            .contains("Overloaded"));
+    }
+}
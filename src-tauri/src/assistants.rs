@@ -1,8 +1,22 @@
+use crate::config::AiProvider;
+use crate::gate::GateMode;
+use crate::retrieval::RetrievalConfig;
+use crate::structured;
+use crate::templates::{PromptVars, Template};
 use crate::utils::log_to_file;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+pub const ASSISTANTS_CONFIG_PATH: &str = "../config/assistants.json";
+pub const ASSISTANTS_CHANGED_EVENT: &str = "assistants-changed";
+// How often the watcher checks assistants.json for external edits
+const WATCH_INTERVAL: Duration = Duration::from_secs(1);
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Assistant {
@@ -12,31 +26,192 @@ pub struct Assistant {
     pub system_prompt: String,
     pub gate_instructions: String,
     #[serde(default)]
+    pub gate_mode: GateMode,
+    // Whether the gate router considers this assistant
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    #[serde(default)]
     pub output_policy: String,
     #[serde(default = "default_user_prompt_template")]
     pub user_prompt: String,
+    // Background for `{{context}}`, e.g. the candidate's CV
+    #[serde(default)]
+    pub context: String,
+    // Markdown, text or PDF files searched for `{{context}}`, relative to
+    // this file's directory (see retrieval.rs)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub documents: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retrieval: Option<RetrievalConfig>,
+    // Overrides of the provider, models and sampling in config.json; see
+    // `provider_for_assistant` for precedence
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provider: Option<AiProvider>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gate_model: Option<String>,
+    // Analysis only; the gate keeps its own small budget
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stop: Option<Vec<String>>,
+    // JSON Schema the answer must follow; the parsed answer is sent to the UI
+    // and stored as `data` (see structured.rs)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output_schema: Option<serde_json::Value>,
+}
+
+// Completion budget of an analysis when the assistant sets none
+pub const DEFAULT_MAX_TOKENS: u32 = 500;
+
+impl Assistant {
+    // The rules every assistant must pass, on load and on edits
+    pub fn validate(&self) -> Result<(), String> {
+        if self.id.is_empty() {
+            return Err("Assistant with empty ID found in config".to_string());
+        }
+        if self.name.is_empty() {
+            return Err(format!("Assistant '{}' has empty name", self.id));
+        }
+        if self.system_prompt.is_empty() {
+            return Err(format!("Assistant '{}' has empty system_prompt", self.id));
+        }
+        if self.user_prompt.trim().is_empty() {
+            return Err(format!("Assistant '{}' has empty user_prompt", self.id));
+        }
+        self.check_generation()?;
+        if let Some(schema) = &self.output_schema {
+            structured::check_schema(schema)
+                .map_err(|e| format!("Assistant '{}': {}", self.id, e))?;
+        }
+        if self.documents.iter().any(|d| d.trim().is_empty()) {
+            return Err(format!(
+                "Assistant '{}' has an empty document path",
+                self.id
+            ));
+        }
+        if let Some(retrieval) = &self.retrieval {
+            retrieval
+                .validate()
+                .map_err(|e| format!("Assistant '{}': {}", self.id, e))?;
+        }
+        if let Err(e) = Template::parse(&self.user_prompt) {
+            return Err(format!(
+                "Assistant '{}' has an invalid user_prompt: {}",
+                self.id, e
+            ));
+        }
+        Ok(())
+    }
+
+    fn check_generation(&self) -> Result<(), String> {
+        for (field, value) in [("model", &self.model), ("gate_model", &self.gate_model)] {
+            if value.as_ref().is_some_and(|m| m.trim().is_empty()) {
+                return Err(format!("Assistant '{}' has empty {}", self.id, field));
+            }
+        }
+        if self.max_tokens == Some(0) {
+            return Err(format!("Assistant '{}' has max_tokens 0", self.id));
+        }
+        if let Some(temperature) = self.temperature.filter(|t| !(0.0..=2.0).contains(t)) {
+            return Err(format!(
+                "Assistant '{}' has temperature {} outside 0-2",
+                self.id, temperature
+            ));
+        }
+        if let Some(top_p) = self.top_p.filter(|p| !(*p > 0.0 && *p <= 1.0)) {
+            return Err(format!(
+                "Assistant '{}' has top_p {} outside (0, 1]",
+                self.id, top_p
+            ));
+        }
+        if let Some(stop) = &self.stop {
+            // OpenAI accepts at most four
+            if stop.len() > 4 || stop.iter().any(|s| s.is_empty()) {
+                return Err(format!(
+                    "Assistant '{}' allows at most 4 non-empty stop sequences",
+                    self.id
+                ));
+            }
+            // The OpenRouter SDK cannot send them
+            if self.provider == Some(AiProvider::Openrouter) && !stop.is_empty() {
+                return Err(format!(
+                    "Assistant '{}' sets stop sequences, which OpenRouter does not support",
+                    self.id
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn default_enabled() -> bool {
+    true
 }
 
 pub fn default_user_prompt_template() -> String {
     "Analyze the latest user intent in the transcript.\n- If a previous assistant answer is shown above, DO NOT repeat it.\n- Only add new information, corrections, or next steps relevant to the newest utterances.\n- Be concise and avoid duplication.\n\n{{transcript}}".into()
 }
 
+// One step of a pipeline. `assistant` supplies the system prompt, output
+// policy and generation settings, `prompt` replaces its user prompt. A step
+// without an assistant reuses the previous step's, the first the default.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PipelineStep {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub assistant: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompt: Option<String>,
+}
+
+// Steps run in order, each getting the previous output as `{{input}}`; only
+// the last one streams to the UI.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Pipeline {
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    pub steps: Vec<PipelineStep>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 struct AssistantsConfig {
     assistants: Vec<Assistant>,
     default_assistant: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pipelines: Vec<Pipeline>,
+}
+
+// Payload of `assistants-changed`
+#[derive(Debug, Clone, Serialize)]
+pub struct AssistantsChanged {
+    pub assistants: Vec<Assistant>,
+    pub default_assistant: String,
+    pub pipelines: Vec<Pipeline>,
 }
 
+#[derive(Clone)]
 pub struct AssistantManager {
     assistants: HashMap<String, Assistant>,
+    // File order, kept when saving
+    order: Vec<String>,
     default_id: String,
+    pipelines: Vec<Pipeline>,
 }
 
 impl AssistantManager {
     pub fn empty() -> Self {
         Self {
             assistants: HashMap::new(),
+            order: Vec::new(),
             default_id: "".to_string(),
+            pipelines: Vec::new(),
         }
     }
 
@@ -58,46 +233,29 @@ impl AssistantManager {
                             return Err("No assistants defined in config file".to_string());
                         }
 
-                        let mut assistants = HashMap::new();
+                        let mut manager = Self::empty();
                         for assistant in config.assistants {
-                            if assistant.id.is_empty() {
-                                return Err("Assistant with empty ID found in config".to_string());
-                            }
-                            if assistant.name.is_empty() {
-                                return Err(format!("Assistant '{}' has empty name", assistant.id));
-                            }
-                            if assistant.system_prompt.is_empty() {
-                                return Err(format!(
-                                    "Assistant '{}' has empty system_prompt",
-                                    assistant.id
-                                ));
-                            }
-                            if assistant.user_prompt.trim().is_empty() {
-                                return Err(format!(
-                                    "Assistant '{}' has empty user_prompt",
-                                    assistant.id
-                                ));
-                            }
-                            assistants.insert(assistant.id.clone(), assistant);
+                            manager.create(assistant)?;
                         }
 
                         // Validate default_assistant exists
-                        if !assistants.contains_key(&config.default_assistant) {
+                        if !manager.assistants.contains_key(&config.default_assistant) {
                             return Err(format!(
                                 "Default assistant '{}' not found in assistants list",
                                 config.default_assistant
                             ));
                         }
+                        manager.default_id = config.default_assistant;
+                        for pipeline in config.pipelines {
+                            manager.add_pipeline(pipeline)?;
+                        }
 
                         log_to_file(&format!(
                             "Successfully loaded {} assistants from config",
-                            assistants.len()
+                            manager.assistants.len()
                         ));
 
-                        Ok(Self {
-                            assistants,
-                            default_id: config.default_assistant,
-                        })
+                        Ok(manager)
                     }
                     Err(e) => Err(format!("Failed to parse assistants config JSON: {}", e)),
                 }
@@ -106,6 +264,154 @@ impl AssistantManager {
         }
     }
 
+    // Write through a temporary file so readers never see a partial config.
+    pub fn save_to_file<P: AsRef<Path>>(&self, path: P) -> Result<(), String> {
+        let path = path.as_ref();
+        let config = AssistantsConfig {
+            assistants: self
+                .order
+                .iter()
+                .filter_map(|id| self.assistants.get(id).cloned())
+                .collect(),
+            default_assistant: self.default_id.clone(),
+            pipelines: self.pipelines.clone(),
+        };
+        let json = serde_json::to_string_pretty(&config)
+            .map_err(|e| format!("Failed to serialize assistants: {}", e))?;
+        let tmp_path = path.with_extension("json.tmp");
+        fs::write(&tmp_path, json)
+            .map_err(|e| format!("Failed to write assistants config {:?}: {}", tmp_path, e))?;
+        fs::rename(&tmp_path, path)
+            .map_err(|e| format!("Failed to replace assistants config {:?}: {}", path, e))
+    }
+
+    pub fn create(&mut self, assistant: Assistant) -> Result<(), String> {
+        assistant.validate()?;
+        if self.assistants.contains_key(&assistant.id) || self.get_pipeline(&assistant.id).is_some()
+        {
+            return Err(format!("Assistant '{}' already exists", assistant.id));
+        }
+        self.order.push(assistant.id.clone());
+        self.assistants.insert(assistant.id.clone(), assistant);
+        Ok(())
+    }
+
+    pub fn update(&mut self, assistant: Assistant) -> Result<(), String> {
+        assistant.validate()?;
+        match self.assistants.get_mut(&assistant.id) {
+            Some(existing) => {
+                *existing = assistant;
+                Ok(())
+            }
+            None => Err(format!("Assistant '{}' not found", assistant.id)),
+        }
+    }
+
+    pub fn delete(&mut self, id: &str) -> Result<(), String> {
+        if !self.assistants.contains_key(id) {
+            return Err(format!("Assistant '{}' not found", id));
+        }
+        if id == self.default_id {
+            return Err(format!(
+                "Assistant '{}' is the default; choose another default first",
+                id
+            ));
+        }
+        if let Some(pipeline) = self
+            .pipelines
+            .iter()
+            .find(|p| p.steps.iter().any(|s| s.assistant.as_deref() == Some(id)))
+        {
+            return Err(format!(
+                "Assistant '{}' is used by pipeline '{}'",
+                id, pipeline.id
+            ));
+        }
+        self.assistants.remove(id);
+        self.order.retain(|existing| existing != id);
+        Ok(())
+    }
+
+    pub fn set_default(&mut self, id: &str) -> Result<(), String> {
+        if !self.assistants.contains_key(id) {
+            return Err(format!("Assistant '{}' not found", id));
+        }
+        self.default_id = id.to_string();
+        Ok(())
+    }
+
+    pub fn changed_payload(&self) -> AssistantsChanged {
+        AssistantsChanged {
+            assistants: self.list_assistants().into_iter().cloned().collect(),
+            default_assistant: self.default_id.clone(),
+            pipelines: self.list_pipelines().to_vec(),
+        }
+    }
+
+    fn add_pipeline(&mut self, pipeline: Pipeline) -> Result<(), String> {
+        if pipeline.id.is_empty() {
+            return Err("Pipeline with empty ID found in config".to_string());
+        }
+        if self.assistants.contains_key(&pipeline.id) || self.get_pipeline(&pipeline.id).is_some() {
+            return Err(format!("Pipeline id '{}' is already in use", pipeline.id));
+        }
+        if pipeline.steps.is_empty() {
+            return Err(format!("Pipeline '{}' has no steps", pipeline.id));
+        }
+        for (index, step) in pipeline.steps.iter().enumerate() {
+            let step_name = format!("Pipeline '{}' step {}", pipeline.id, index + 1);
+            match (&step.assistant, &step.prompt) {
+                (None, None) => {
+                    return Err(format!("{} needs an assistant or a prompt", step_name));
+                }
+                (Some(id), _) if !self.assistants.contains_key(id) => {
+                    return Err(format!("{} uses unknown assistant '{}'", step_name, id));
+                }
+                _ => {}
+            }
+            if let Some(prompt) = &step.prompt {
+                if prompt.trim().is_empty() {
+                    return Err(format!("{} has an empty prompt", step_name));
+                }
+                Template::parse(prompt)
+                    .map_err(|e| format!("{} has an invalid prompt: {}", step_name, e))?;
+            }
+        }
+        self.pipelines.push(pipeline);
+        Ok(())
+    }
+
+    pub fn list_pipelines(&self) -> &[Pipeline] {
+        &self.pipelines
+    }
+
+    pub fn get_pipeline(&self, id: &str) -> Option<&Pipeline> {
+        self.pipelines.iter().find(|p| p.id == id)
+    }
+
+    // The assistant each step runs as, with the step's prompt applied.
+    pub fn pipeline_steps(&self, pipeline: &Pipeline) -> Vec<Assistant> {
+        let mut current = self.get_default_assistant().clone();
+        pipeline
+            .steps
+            .iter()
+            .map(|step| {
+                if let Some(assistant) = step
+                    .assistant
+                    .as_ref()
+                    .and_then(|id| self.get_assistant(id))
+                {
+                    current = assistant.clone();
+                }
+                let mut assistant = current.clone();
+                if let Some(prompt) = &step.prompt {
+                    assistant.user_prompt = prompt.clone();
+                }
+                assistant
+            })
+            .collect()
+    }
+
     pub fn get_assistant(&self, id: &str) -> Option<&Assistant> {
         self.assistants.get(id)
     }
@@ -122,29 +428,238 @@ impl AssistantManager {
         assistants
     }
 
+    // Candidates for the gate router, sorted like `list_assistants`.
+    pub fn enabled_assistants(&self) -> Vec<&Assistant> {
+        self.list_assistants()
+            .into_iter()
+            .filter(|a| a.enabled)
+            .collect()
+    }
+
     pub fn get_default_id(&self) -> &str {
         &self.default_id
     }
 }
 
-pub fn render_user_prompt(template: &str, transcript: &str) -> String {
-    if template.contains("{{transcript}}") || template.contains("{{TRANSCRIPT}}") {
-        let mut replaced = template.replace("{{transcript}}", transcript);
-        if replaced.contains("{{TRANSCRIPT}}") {
-            replaced = replaced.replace("{{TRANSCRIPT}}", transcript);
-        }
-        replaced
-    } else {
-        let mut base = template.to_string();
-        if base.trim().is_empty() {
-            transcript.to_string()
-        } else {
-            let needs_gap = !base.ends_with('\n');
-            if needs_gap {
-                base.push_str("\n\n");
-            }
-            base.push_str(transcript);
-            base
+// Modification time and size, to tell external edits from what we last saw.
+type FileStamp = (SystemTime, u64);
+
+fn file_stamp(path: &Path) -> Option<FileStamp> {
+    let meta = fs::metadata(path).ok()?;
+    Some((meta.modified().ok()?, meta.len()))
+}
+
+// Polls assistants.json and reloads it after external edits. Our own saves
+// are marked first so they do not come back as external changes.
+#[derive(Clone, Default)]
+pub struct AssistantsWatcher {
+    seen: Arc<Mutex<Option<FileStamp>>>,
+    started: Arc<AtomicBool>,
+}
+
+impl AssistantsWatcher {
+    // Record the file as it is now, e.g. after loading or saving it.
+    pub fn mark(&self, path: &Path) {
+        *self.seen.lock().unwrap() = file_stamp(path);
+    }
+
+    // Save under the watcher's lock so the poll cannot pick up our own write.
+    pub fn save(&self, manager: &AssistantManager, path: &Path) -> Result<(), String> {
+        let mut seen = self.seen.lock().unwrap();
+        manager.save_to_file(path)?;
+        *seen = file_stamp(path);
+        Ok(())
+    }
+
+    // Starts the polling thread once; later calls are no-ops. A broken edit
+    // is logged and the previous assistants stay loaded.
+    pub fn start<F>(&self, path: PathBuf, manager: Arc<Mutex<AssistantManager>>, on_reload: F)
+    where
+        F: Fn(&AssistantManager) + Send + 'static,
+    {
+        if self.started.swap(true, Ordering::SeqCst) {
+            return;
         }
+        let seen = self.seen.clone();
+        thread::spawn(move || loop {
+            thread::sleep(WATCH_INTERVAL);
+            let stamp = file_stamp(&path);
+            if stamp.is_none() || stamp == *seen.lock().unwrap() {
+                continue;
+            }
+            // Same lock order as edits: manager, then the stamp
+            let mut manager = manager.lock().unwrap();
+            let mut seen = seen.lock().unwrap();
+            let stamp = file_stamp(&path);
+            if stamp.is_none() || stamp == *seen {
+                continue;
+            }
+            *seen = stamp;
+            match AssistantManager::load_from_file(&path) {
+                Ok(loaded) => {
+                    log_to_file("Assistants config changed on disk; reloaded");
+                    *manager = loaded;
+                    on_reload(&manager);
+                }
+                Err(e) => log_to_file(&format!("Ignoring edited assistants config: {}", e)),
+            }
+        });
+    }
+}
+
+// Templates are validated when assistants load; one that still fails to
+// parse is sent as plain text with the transcript appended.
+pub fn render_user_prompt(template: &str, vars: &PromptVars) -> String {
+    let template = Template::parse(template).unwrap_or_else(|e| {
+        log_to_file(&format!("Invalid user prompt template: {}", e));
+        Template::literal(template)
+    });
+    template.render(vars)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_generation() {
+        let mut assistant: Assistant = serde_json::from_str(
+            r#"{"id": "a", "name": "A", "description": "", "system_prompt": "S",
+                "gate_instructions": "", "provider": "anthropic", "model": "claude-x",
+                "max_tokens": 900, "temperature": 0.7, "top_p": 1.0, "stop": ["END"]}"#,
+        )
+        .unwrap();
+        assert_eq!(assistant.provider, Some(AiProvider::Anthropic));
+        assert!(assistant.check_generation().is_ok());
+
+        assistant.top_p = Some(0.0);
+        assert!(assistant.check_generation().is_err());
+        assistant.top_p = None;
+        assistant.temperature = Some(2.5);
+        assert!(assistant.check_generation().is_err());
+        assistant.temperature = None;
+        assistant.stop = Some(vec![
+            "a".into(),
+            "b".into(),
+            "c".into(),
+            "d".into(),
+            "e".into(),
+        ]);
+        assert!(assistant.check_generation().is_err());
+        assistant.stop = Some(vec!["END".into()]);
+        assistant.provider = Some(AiProvider::Openrouter);
+        assert!(assistant.check_generation().is_err());
+        assistant.stop = None;
+        assert!(assistant.check_generation().is_ok());
+        assistant.model = Some(" ".into());
+        assert!(assistant.check_generation().is_err());
+    }
+
+    #[test]
+    fn test_edit_and_save() {
+        let assistant = |id: &str| Assistant {
+            id: id.into(),
+            name: id.to_uppercase(),
+            description: String::new(),
+            system_prompt: "S".into(),
+            gate_instructions: String::new(),
+            gate_mode: GateMode::default(),
+            enabled: true,
+            output_policy: String::new(),
+            user_prompt: default_user_prompt_template(),
+            context: String::new(),
+            documents: Vec::new(),
+            retrieval: None,
+            provider: None,
+            model: None,
+            gate_model: None,
+            max_tokens: None,
+            temperature: None,
+            top_p: None,
+            stop: None,
+            output_schema: None,
+        };
+        let mut manager = AssistantManager::empty();
+        manager.create(assistant("b")).unwrap();
+        manager.create(assistant("a")).unwrap();
+        manager.set_default("b").unwrap();
+        assert!(manager.create(assistant("a")).is_err());
+        assert!(manager.delete("b").is_err());
+        assert!(manager.set_default("missing").is_err());
+
+        let mut broken = assistant("a");
+        broken.user_prompt = "{{unknown}}".into();
+        assert!(manager.update(broken).is_err());
+        let mut renamed = assistant("a");
+        renamed.name = "Renamed".into();
+        manager.update(renamed).unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("assistants.json");
+        manager.save_to_file(&path).unwrap();
+        assert!(!path.with_extension("json.tmp").exists());
+        let json = fs::read_to_string(&path).unwrap();
+        assert!(json.find("\"b\"").unwrap() < json.find("\"a\"").unwrap());
+        assert!(!json.contains("top_p"));
+
+        let mut loaded = AssistantManager::load_from_file(&path).unwrap();
+        assert_eq!(loaded.get_default_id(), "b");
+        assert_eq!(loaded.get_assistant("a").unwrap().name, "Renamed");
+        loaded.set_default("a").unwrap();
+        loaded.delete("b").unwrap();
+        assert_eq!(loaded.changed_payload().assistants.len(), 1);
+    }
+
+    #[test]
+    fn test_pipelines() {
+        let assistant = |id: &str| {
+            format!(
+                r#"{{"id": "{0}", "name": "{0}", "description": "", "system_prompt": "{0} system",
+                    "gate_instructions": ""}}"#,
+                id
+            )
+        };
+        let config = |pipelines: &str| {
+            format!(
+                r#"{{"assistants": [{}, {}], "default_assistant": "general", "pipelines": [{}]}}"#,
+                assistant("general"),
+                assistant("interview"),
+                pipelines
+            )
+        };
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("assistants.json");
+        let load = |pipelines: &str| {
+            fs::write(&path, config(pipelines)).unwrap();
+            AssistantManager::load_from_file(&path)
+        };
+
+        let mut manager = load(
+            r#"{"id": "answer", "name": "Answer", "steps": [
+                {"prompt": "Extract the last question: {{latest_turn}}"},
+                {"assistant": "interview", "prompt": "Draft an answer to: {{input}}"},
+                {"prompt": "Shorten to 2 sentences: {{input}}"}]}"#,
+        )
+        .unwrap();
+        let pipeline = manager.get_pipeline("answer").unwrap().clone();
+        let steps = manager.pipeline_steps(&pipeline);
+        let ids: Vec<&str> = steps.iter().map(|a| a.id.as_str()).collect();
+        assert_eq!(ids, vec!["general", "interview", "interview"]);
+        assert_eq!(steps[2].user_prompt, "Shorten to 2 sentences: {{input}}");
+        assert!(manager.delete("interview").is_err());
+        manager.save_to_file(&path).unwrap();
+        assert!(AssistantManager::load_from_file(&path)
+            .unwrap()
+            .get_pipeline("answer")
+            .is_some());
+
+        assert!(load(r#"{"id": "p", "name": "P", "steps": []}"#).is_err());
+        assert!(load(r#"{"id": "p", "name": "P", "steps": [{}]}"#).is_err());
+        assert!(load(r#"{"id": "p", "name": "P", "steps": [{"assistant": "missing"}]}"#).is_err());
+        assert!(load(r#"{"id": "p", "name": "P", "steps": [{"prompt": "{{inptu}}"}]}"#).is_err());
+        assert!(
+            load(r#"{"id": "interview", "name": "P", "steps": [{"assistant": "general"}]}"#)
+                .is_err()
+        );
     }
 }
@@ -0,0 +1,180 @@
+use crate::ai_tasks::AiTaskKind;
+use serde::{Deserialize, Serialize};
+
+pub const BUDGET_EXCEEDED_EVENT: &str = "budget-exceeded";
+pub const BUDGET_WARNING_EVENT: &str = "budget-warning";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BudgetAction {
+    // Refuse further calls until the day or session rolls over
+    #[default]
+    Block,
+    // Keep going with the provider's gate model for the main analysis
+    Downgrade,
+}
+
+// Spending caps in USD, measured from recorded usage (see `usage`).
+// OpenRouter calls count once their generation cost is fetched, right after
+// each call; other calls the price table cannot cost are logged and do not
+// count.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BudgetConfig {
+    #[serde(default)]
+    pub daily_limit_usd: Option<f64>,
+    #[serde(default)]
+    pub session_limit_usd: Option<f64>,
+    // Fraction of a limit at which `budget-warning` is emitted
+    #[serde(default = "default_warn_at")]
+    pub warn_at: f64,
+    #[serde(default)]
+    pub on_exceeded: BudgetAction,
+}
+
+fn default_warn_at() -> f64 {
+    0.8
+}
+
+impl Default for BudgetConfig {
+    fn default() -> Self {
+        Self {
+            daily_limit_usd: None,
+            session_limit_usd: None,
+            warn_at: default_warn_at(),
+            on_exceeded: BudgetAction::default(),
+        }
+    }
+}
+
+impl BudgetConfig {
+    pub fn is_enabled(&self) -> bool {
+        self.daily_limit_usd.is_some() || self.session_limit_usd.is_some()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BudgetScope {
+    Daily,
+    Session,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BudgetStatus {
+    pub scope: BudgetScope,
+    pub spent_usd: f64,
+    pub limit_usd: f64,
+}
+
+// Payload of `budget-warning` and `budget-exceeded`. `action` is what was
+// done to the request; warnings carry none.
+#[derive(Debug, Clone, Serialize)]
+pub struct BudgetEvent {
+    pub request_id: String,
+    pub kind: AiTaskKind,
+    #[serde(flatten)]
+    pub status: BudgetStatus,
+    pub action: Option<BudgetAction>,
+    pub model: String,
+}
+
+impl BudgetStatus {
+    pub fn describe(&self) -> String {
+        let scope = match self.scope {
+            BudgetScope::Daily => "Daily",
+            BudgetScope::Session => "Session",
+        };
+        format!(
+            "{} AI budget exceeded: ${:.4} of ${:.2} spent",
+            scope, self.spent_usd, self.limit_usd
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum BudgetVerdict {
+    Allow,
+    Warn(BudgetStatus),
+    Exceeded(BudgetStatus),
+}
+
+// An exceeded limit wins over a warning; the daily limit is checked first.
+// `session_spent` is None when no session is being recorded.
+pub fn evaluate(
+    config: &BudgetConfig,
+    daily_spent: f64,
+    session_spent: Option<f64>,
+) -> BudgetVerdict {
+    let checks = [
+        (
+            BudgetScope::Daily,
+            config.daily_limit_usd,
+            Some(daily_spent),
+        ),
+        (
+            BudgetScope::Session,
+            config.session_limit_usd,
+            session_spent,
+        ),
+    ];
+    let mut warning = None;
+    for (scope, limit, spent) in checks {
+        let (Some(limit_usd), Some(spent_usd)) = (limit, spent) else {
+            continue;
+        };
+        let status = BudgetStatus {
+            scope,
+            spent_usd,
+            limit_usd,
+        };
+        if spent_usd >= limit_usd {
+            return BudgetVerdict::Exceeded(status);
+        }
+        if warning.is_none() && spent_usd >= limit_usd * config.warn_at.clamp(0.0, 1.0) {
+            warning = Some(status);
+        }
+    }
+    warning.map_or(BudgetVerdict::Allow, BudgetVerdict::Warn)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_evaluate_limits() {
+        let config = BudgetConfig {
+            daily_limit_usd: Some(1.0),
+            session_limit_usd: Some(0.25),
+            ..Default::default()
+        };
+        assert_eq!(evaluate(&config, 0.1, Some(0.1)), BudgetVerdict::Allow);
+        assert_eq!(evaluate(&config, 0.1, None), BudgetVerdict::Allow);
+        assert!(matches!(
+            evaluate(&config, 0.85, Some(0.1)),
+            BudgetVerdict::Warn(BudgetStatus {
+                scope: BudgetScope::Daily,
+                ..
+            })
+        ));
+        // A session over its cap wins over a daily warning
+        assert!(matches!(
+            evaluate(&config, 0.9, Some(0.3)),
+            BudgetVerdict::Exceeded(BudgetStatus {
+                scope: BudgetScope::Session,
+                ..
+            })
+        ));
+        assert!(matches!(
+            evaluate(&config, 1.0, Some(0.0)),
+            BudgetVerdict::Exceeded(BudgetStatus {
+                scope: BudgetScope::Daily,
+                ..
+            })
+        ));
+        assert_eq!(
+            evaluate(&BudgetConfig::default(), 100.0, Some(100.0)),
+            BudgetVerdict::Allow
+        );
+    }
+}
@@ -1,4 +1,7 @@
-use crate::utils::log_to_file;
+use crate::{
+    budget::BudgetConfig, llm::ProviderSettings, memory::MemoryConfig, usage::PriceTable,
+    utils::log_to_file,
+};
 use serde::{Deserialize, Serialize};
 use std::fs;
 use std::path::Path;
@@ -10,6 +13,32 @@ pub struct SonioxConfig {
     pub audio_format: String,
     #[serde(default = "default_translation")]
     pub translation: String,
+    #[serde(default = "default_soniox_model")]
+    pub model: String,
+    #[serde(default = "default_language_hints")]
+    pub language_hints: Vec<String>,
+    #[serde(default)]
+    pub context: String,
+    #[serde(default)]
+    pub context_terms: Vec<String>,
+    #[serde(default = "default_true")]
+    pub enable_endpoint_detection: bool,
+    #[serde(default = "default_true")]
+    pub enable_speaker_diarization: bool,
+    #[serde(default = "default_translation_target")]
+    pub translation_target: String,
+    #[serde(default = "default_translation_language_a")]
+    pub translation_language_a: String,
+    #[serde(default = "default_translation_language_b")]
+    pub translation_language_b: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WhisperConfig {
+    #[serde(default)]
+    pub model_path: String,
+    #[serde(default = "default_whisper_language")]
+    pub language: String,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -19,15 +48,47 @@ pub struct OpenAIConfig {
     pub model: String,
     #[serde(default = "default_gate_model")]
     pub gate_model: String,
+    // Any OpenAI-compatible server (Ollama, llama.cpp, LM Studio, ...)
+    #[serde(default = "default_openai_base_url")]
+    pub base_url: String,
+    // Local servers usually accept requests without a key
+    #[serde(default = "default_true")]
+    pub require_api_key: bool,
+    // Per-request timeout; for streams, the longest allowed gap between chunks
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 #[derive(Default)]
 pub enum AiProvider {
     #[default]
     Openai,
     Openrouter,
+    Anthropic,
+    Gemini,
+}
+
+impl AiProvider {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "openai" => Some(Self::Openai),
+            "openrouter" => Some(Self::Openrouter),
+            "anthropic" => Some(Self::Anthropic),
+            "gemini" => Some(Self::Gemini),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Openai => "openai",
+            Self::Openrouter => "openrouter",
+            Self::Anthropic => "anthropic",
+            Self::Gemini => "gemini",
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -37,6 +98,30 @@ pub struct OpenRouterConfig {
     pub model: String,
     #[serde(default = "default_openrouter_gate_model")]
     pub gate_model: String,
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AnthropicConfig {
+    pub api_key: String,
+    #[serde(default = "default_anthropic_model")]
+    pub model: String,
+    #[serde(default = "default_anthropic_gate_model")]
+    pub gate_model: String,
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GeminiConfig {
+    pub api_key: String,
+    #[serde(default = "default_gemini_model")]
+    pub model: String,
+    #[serde(default = "default_gemini_gate_model")]
+    pub gate_model: String,
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -59,16 +144,84 @@ pub struct UIConfig {
     pub default_assistant: String,
     #[serde(default = "AiProvider::default")]
     pub ai_provider: AiProvider,
+    #[serde(default = "default_transcription_provider")]
+    pub transcription_provider: String, // "soniox" | "whisper"
+    // Abort an assistant's in-flight request when a newer one starts
+    #[serde(default)]
+    pub auto_cancel_previous: bool,
+    // One gate call picks which enabled assistants run, instead of gating
+    // only the selected one
+    #[serde(default)]
+    pub gate_router: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct AppConfig {
     pub soniox: SonioxConfig,
+    #[serde(default)]
+    pub whisper: WhisperConfig,
     pub openai: OpenAIConfig,
     #[serde(default)]
     pub openrouter: OpenRouterConfig,
+    #[serde(default)]
+    pub anthropic: AnthropicConfig,
+    #[serde(default)]
+    pub gemini: GeminiConfig,
     pub recording: RecordingConfig,
     pub ui: UIConfig,
+    // Per-model token prices used for cost accounting
+    #[serde(default)]
+    pub pricing: PriceTable,
+    #[serde(default)]
+    pub budget: BudgetConfig,
+    // Rolling summary for long transcripts
+    #[serde(default)]
+    pub memory: MemoryConfig,
+}
+
+impl AppConfig {
+    // Key, endpoint and default models for the given provider.
+    pub fn provider_settings(&self, kind: &AiProvider) -> ProviderSettings {
+        let (api_key, model, gate_model, timeout_secs) = match kind {
+            AiProvider::Openai => (
+                &self.openai.api_key,
+                &self.openai.model,
+                &self.openai.gate_model,
+                self.openai.timeout_secs,
+            ),
+            AiProvider::Openrouter => (
+                &self.openrouter.api_key,
+                &self.openrouter.model,
+                &self.openrouter.gate_model,
+                self.openrouter.timeout_secs,
+            ),
+            AiProvider::Anthropic => (
+                &self.anthropic.api_key,
+                &self.anthropic.model,
+                &self.anthropic.gate_model,
+                self.anthropic.timeout_secs,
+            ),
+            AiProvider::Gemini => (
+                &self.gemini.api_key,
+                &self.gemini.model,
+                &self.gemini.gate_model,
+                self.gemini.timeout_secs,
+            ),
+        };
+        let is_openai = matches!(kind, AiProvider::Openai);
+        ProviderSettings {
+            api_key: api_key.clone(),
+            base_url: if is_openai {
+                self.openai.base_url.clone()
+            } else {
+                String::new()
+            },
+            require_api_key: !is_openai || self.openai.require_api_key,
+            model: model.clone(),
+            gate_model: gate_model.clone(),
+            timeout_secs,
+        }
+    }
 }
 
 // Default functions
@@ -78,18 +231,60 @@ fn default_audio_format() -> String {
 fn default_translation() -> String {
     "none".to_string()
 }
+fn default_soniox_model() -> String {
+    "stt-rt-preview-v2".to_string()
+}
+fn default_language_hints() -> Vec<String> {
+    vec!["en".to_string(), "pl".to_string()]
+}
+fn default_true() -> bool {
+    true
+}
+fn default_translation_target() -> String {
+    "es".to_string()
+}
+fn default_translation_language_a() -> String {
+    "en".to_string()
+}
+fn default_translation_language_b() -> String {
+    "es".to_string()
+}
+fn default_whisper_language() -> String {
+    "auto".to_string()
+}
+fn default_transcription_provider() -> String {
+    "soniox".to_string()
+}
 fn default_model() -> String {
     "gpt-4.1".to_string()
 }
 fn default_gate_model() -> String {
     "gpt-4.1-nano".to_string()
 }
+fn default_openai_base_url() -> String {
+    crate::openai::DEFAULT_BASE_URL.to_string()
+}
+fn default_timeout_secs() -> u64 {
+    crate::llm::DEFAULT_TIMEOUT_SECS
+}
 fn default_openrouter_model() -> String {
     "deepseek/deepseek-chat-v3-0324:free".to_string()
 }
 fn default_openrouter_gate_model() -> String {
     "deepseek/deepseek-chat-v3-0324:free".to_string()
 }
+fn default_anthropic_model() -> String {
+    "claude-sonnet-4-0".to_string()
+}
+fn default_anthropic_gate_model() -> String {
+    "claude-3-5-haiku-latest".to_string()
+}
+fn default_gemini_model() -> String {
+    "gemini-2.5-flash".to_string()
+}
+fn default_gemini_gate_model() -> String {
+    "gemini-2.5-flash-lite".to_string()
+}
 fn default_format() -> String {
     "mp3".to_string()
 }
@@ -109,6 +304,24 @@ impl Default for SonioxConfig {
             api_key: "".to_string(),
             audio_format: default_audio_format(),
             translation: default_translation(),
+            model: default_soniox_model(),
+            language_hints: default_language_hints(),
+            context: String::new(),
+            context_terms: Vec::new(),
+            enable_endpoint_detection: true,
+            enable_speaker_diarization: true,
+            translation_target: default_translation_target(),
+            translation_language_a: default_translation_language_a(),
+            translation_language_b: default_translation_language_b(),
+        }
+    }
+}
+
+impl Default for WhisperConfig {
+    fn default() -> Self {
+        Self {
+            model_path: "".to_string(),
+            language: default_whisper_language(),
         }
     }
 }
@@ -119,6 +332,9 @@ impl Default for OpenAIConfig {
             api_key: "".to_string(),
             model: default_model(),
             gate_model: default_gate_model(),
+            base_url: default_openai_base_url(),
+            require_api_key: true,
+            timeout_secs: default_timeout_secs(),
         }
     }
 }
@@ -129,6 +345,29 @@ impl Default for OpenRouterConfig {
             api_key: "".to_string(),
             model: default_openrouter_model(),
             gate_model: default_openrouter_gate_model(),
+            timeout_secs: default_timeout_secs(),
+        }
+    }
+}
+
+impl Default for AnthropicConfig {
+    fn default() -> Self {
+        Self {
+            api_key: "".to_string(),
+            model: default_anthropic_model(),
+            gate_model: default_anthropic_gate_model(),
+            timeout_secs: default_timeout_secs(),
+        }
+    }
+}
+
+impl Default for GeminiConfig {
+    fn default() -> Self {
+        Self {
+            api_key: "".to_string(),
+            model: default_gemini_model(),
+            gate_model: default_gemini_gate_model(),
+            timeout_secs: default_timeout_secs(),
         }
     }
 }
@@ -150,6 +389,9 @@ impl Default for UIConfig {
             enable_ai: false,
             default_assistant: default_assistant(),
             ai_provider: AiProvider::default(),
+            transcription_provider: default_transcription_provider(),
+            auto_cancel_previous: false,
+            gate_router: false,
         }
     }
 }
@@ -0,0 +1,382 @@
+use std::path::Path;
+
+use crate::sessions::SessionDetail;
+use crate::soniox::{clean_transcript_text_preserve_spacing, format_transcript_line};
+use crate::transcription::{TranscriptSegment, TranslationStatus};
+
+// Subtitle cues longer than this are split at word boundaries
+const MAX_CUE_CHARS: usize = 84;
+// Reading-speed estimate for segments without token timing
+const ESTIMATED_MS_PER_CHAR: u64 = 60;
+const MIN_CUE_MS: u64 = 1_000;
+const MAX_ESTIMATED_CUE_MS: u64 = 7_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Srt,
+    Vtt,
+    Markdown,
+    Json,
+}
+
+impl ExportFormat {
+    pub fn parse(raw: &str) -> Result<Self, String> {
+        match raw.trim().to_lowercase().as_str() {
+            "srt" => Ok(ExportFormat::Srt),
+            "vtt" | "webvtt" => Ok(ExportFormat::Vtt),
+            "md" | "markdown" => Ok(ExportFormat::Markdown),
+            "json" => Ok(ExportFormat::Json),
+            other => Err(format!(
+                "Unsupported export format '{}': expected srt, vtt, markdown or json",
+                other
+            )),
+        }
+    }
+}
+
+pub fn render(detail: &SessionDetail, format: ExportFormat) -> Result<String, String> {
+    match format {
+        ExportFormat::Srt => Ok(render_srt(detail)),
+        ExportFormat::Vtt => Ok(render_vtt(detail)),
+        ExportFormat::Markdown => Ok(render_markdown(detail)),
+        ExportFormat::Json => serde_json::to_string_pretty(detail)
+            .map_err(|e| format!("Failed to serialize session: {}", e)),
+    }
+}
+
+pub fn export_to_file<P: AsRef<Path>>(
+    detail: &SessionDetail,
+    format: ExportFormat,
+    path: P,
+) -> Result<(), String> {
+    let path = path.as_ref();
+    let content = render(detail, format)?;
+    if let Some(parent) = path.parent() {
+        if !parent.as_os_str().is_empty() {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create export directory: {}", e))?;
+        }
+    }
+    std::fs::write(path, content).map_err(|e| format!("Failed to write export {:?}: {}", path, e))
+}
+
+fn speaker_label(speaker: i64) -> String {
+    if speaker > 0 {
+        format!("Speaker {}", speaker)
+    } else {
+        "Speaker".to_string()
+    }
+}
+
+// Same cleaning and sentence formatting the live transcript uses.
+fn display_text(segment: &TranscriptSegment) -> String {
+    format_transcript_line(&clean_transcript_text_preserve_spacing(&segment.text))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Cue {
+    start_ms: u64,
+    end_ms: u64,
+    speaker: i64,
+    text: String,
+}
+
+// Build subtitle cues from spoken segments. Translations are left out since
+// they repeat the timing of the original speech. Segments without timing
+// continue from the previous cue with a reading-speed estimate.
+fn build_cues(segments: &[TranscriptSegment]) -> Vec<Cue> {
+    let mut cues = Vec::new();
+    let mut cursor_ms = 0u64;
+
+    for segment in segments {
+        if segment.translation_status == TranslationStatus::Translation {
+            continue;
+        }
+        let text = display_text(segment);
+        if text.is_empty() {
+            continue;
+        }
+
+        let start_ms = segment.start_ms.unwrap_or(cursor_ms);
+        let estimated = (text.chars().count() as u64 * ESTIMATED_MS_PER_CHAR)
+            .clamp(MIN_CUE_MS, MAX_ESTIMATED_CUE_MS);
+        let end_ms = segment
+            .end_ms
+            .filter(|end| *end > start_ms)
+            .unwrap_or(start_ms + estimated);
+
+        // Spread the segment's time over its chunks by length
+        let chunks = split_cue_text(&text, MAX_CUE_CHARS);
+        let total_chars: usize = chunks.iter().map(|c| c.chars().count()).sum();
+        let span = end_ms - start_ms;
+        let mut chunk_start = start_ms;
+        let mut chars_done = 0usize;
+        for chunk in chunks {
+            chars_done += chunk.chars().count();
+            let chunk_end = start_ms + span * chars_done as u64 / total_chars.max(1) as u64;
+            cues.push(Cue {
+                start_ms: chunk_start,
+                end_ms: chunk_end.max(chunk_start + 1),
+                speaker: segment.speaker,
+                text: chunk,
+            });
+            chunk_start = chunk_end;
+        }
+        cursor_ms = cursor_ms.max(end_ms);
+    }
+
+    cues
+}
+
+fn split_cue_text(text: &str, max_chars: usize) -> Vec<String> {
+    let mut chunks = Vec::new();
+    let mut current = String::new();
+    for word in text.split_whitespace() {
+        let needed = if current.is_empty() {
+            word.chars().count()
+        } else {
+            current.chars().count() + 1 + word.chars().count()
+        };
+        if needed > max_chars && !current.is_empty() {
+            chunks.push(std::mem::take(&mut current));
+        }
+        if !current.is_empty() {
+            current.push(' ');
+        }
+        current.push_str(word);
+    }
+    if !current.is_empty() {
+        chunks.push(current);
+    }
+    chunks
+}
+
+fn format_timestamp(ms: u64, fraction_separator: char) -> String {
+    let hours = ms / 3_600_000;
+    let minutes = (ms / 60_000) % 60;
+    let seconds = (ms / 1_000) % 60;
+    let millis = ms % 1_000;
+    format!(
+        "{:02}:{:02}:{:02}{}{:03}",
+        hours, minutes, seconds, fraction_separator, millis
+    )
+}
+
+fn render_srt(detail: &SessionDetail) -> String {
+    let mut out = String::new();
+    for (index, cue) in build_cues(&detail.segments).iter().enumerate() {
+        out.push_str(&format!(
+            "{}\n{} --> {}\n{}: {}\n\n",
+            index + 1,
+            format_timestamp(cue.start_ms, ','),
+            format_timestamp(cue.end_ms, ','),
+            speaker_label(cue.speaker),
+            cue.text
+        ));
+    }
+    out
+}
+
+fn render_vtt(detail: &SessionDetail) -> String {
+    let mut out = String::from("WEBVTT\n\n");
+    for cue in build_cues(&detail.segments) {
+        out.push_str(&format!(
+            "{} --> {}\n<v {}>{}\n\n",
+            format_timestamp(cue.start_ms, '.'),
+            format_timestamp(cue.end_ms, '.'),
+            speaker_label(cue.speaker),
+            cue.text
+        ));
+    }
+    out
+}
+
+fn render_markdown(detail: &SessionDetail) -> String {
+    let session = &detail.session;
+    let mut out = format!("# Session {}\n\n", session.id);
+    out.push_str(&format!("- Started: {}\n", session.started_at));
+    if let Some(ended) = &session.ended_at {
+        out.push_str(&format!("- Ended: {}\n", ended));
+    }
+    if let Some(duration) = session.duration_ms {
+        out.push_str(&format!(
+            "- Duration: {}\n",
+            format_timestamp(duration, '.')
+        ));
+    }
+    out.push_str(&format!(
+        "- Audio: `{}` ({})\n\n## Transcript\n",
+        session.audio_path, session.audio_format
+    ));
+
+    // Consecutive segments from one speaker form a single paragraph
+    let mut paragraphs: Vec<String> = Vec::new();
+    let mut last_speaker: Option<i64> = None;
+    for segment in &detail.segments {
+        let text = display_text(segment);
+        if text.is_empty() {
+            continue;
+        }
+        if segment.translation_status == TranslationStatus::Translation {
+            let language = segment.language.as_deref().unwrap_or("?");
+            paragraphs.push(format!("> [{}] {}", language, text));
+            last_speaker = None;
+            continue;
+        }
+        if last_speaker == Some(segment.speaker) {
+            if let Some(paragraph) = paragraphs.last_mut() {
+                paragraph.push(' ');
+                paragraph.push_str(&text);
+                continue;
+            }
+        }
+        let mut paragraph = format!("**{}**", speaker_label(segment.speaker));
+        if let Some(start) = segment.start_ms {
+            paragraph.push_str(&format!(" ({})", format_timestamp(start, '.')));
+        }
+        if let Some(language) = &segment.language {
+            paragraph.push_str(&format!(" [{}]", language));
+        }
+        paragraph.push_str(": ");
+        paragraph.push_str(&text);
+        paragraphs.push(paragraph);
+        last_speaker = Some(segment.speaker);
+    }
+    for paragraph in paragraphs {
+        out.push('\n');
+        out.push_str(&paragraph);
+        out.push('\n');
+    }
+
+    if !detail.answers.is_empty() {
+        out.push_str("\n## Assistant answers\n");
+        for answer in &detail.answers {
+            out.push_str(&format!(
+                "\n### {} · {} · {}\n\n{}\n",
+                answer.assistant_id,
+                answer.model,
+                answer.completed_at,
+                answer.answer.trim()
+            ));
+        }
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sessions::{AnswerRecord, SessionSummary};
+
+    fn segment(
+        text: &str,
+        speaker: i64,
+        start_ms: Option<u64>,
+        end_ms: Option<u64>,
+    ) -> TranscriptSegment {
+        TranscriptSegment {
+            speaker,
+            language: Some("en".into()),
+            text: text.into(),
+            start_ms,
+            end_ms,
+            confidence: None,
+            is_final: true,
+            translation_status: TranslationStatus::None,
+        }
+    }
+
+    fn detail(segments: Vec<TranscriptSegment>) -> SessionDetail {
+        SessionDetail {
+            session: SessionSummary {
+                id: "20250301-101500-000".into(),
+                started_at: "2025-03-01T10:15:00+01:00".into(),
+                ended_at: None,
+                audio_path: "/tmp/a.wav".into(),
+                audio_format: "wav".into(),
+                duration_ms: Some(65_000),
+                segment_count: segments.len() as u32,
+                answer_count: 1,
+                title: None,
+            },
+            segments,
+            gate_decisions: Vec::new(),
+            answers: vec![AnswerRecord {
+                request_id: "r1".into(),
+                assistant_id: "general".into(),
+                provider: "openai".into(),
+                model: "gpt-4.1".into(),
+                answer: "Summary.".into(),
+                data: None,
+                started_at: "2025-03-01T10:16:00+01:00".into(),
+                completed_at: "2025-03-01T10:16:02+01:00".into(),
+            }],
+        }
+    }
+
+    #[test]
+    fn test_srt_uses_token_timing_and_cleaning() {
+        let d = detail(vec![
+            segment("hello <UNK>there", 1, Some(1_500), Some(3_250)),
+            segment("hi", 2, Some(61_000), Some(62_000)),
+        ]);
+        let srt = render(&d, ExportFormat::Srt).unwrap();
+        assert_eq!(
+            srt,
+            "1\n00:00:01,500 --> 00:00:03,250\nSpeaker 1: Hello there.\n\n\
+             2\n00:01:01,000 --> 00:01:02,000\nSpeaker 2: Hi.\n\n"
+        );
+    }
+
+    #[test]
+    fn test_vtt_header_and_voice_tags() {
+        let d = detail(vec![segment("hello", 0, None, None)]);
+        let vtt = render(&d, ExportFormat::Vtt).unwrap();
+        assert!(vtt.starts_with("WEBVTT\n\n00:00:00.000 --> 00:00:01.000\n<v Speaker>Hello.\n"));
+    }
+
+    #[test]
+    fn test_long_segments_split_into_cues() {
+        let text = "word ".repeat(40);
+        let cues = build_cues(&[segment(&text, 1, Some(0), Some(10_000))]);
+        assert!(cues.len() > 1);
+        assert!(cues.iter().all(|c| c.text.chars().count() <= MAX_CUE_CHARS));
+        assert_eq!(cues.first().unwrap().start_ms, 0);
+        assert_eq!(cues.last().unwrap().end_ms, 10_000);
+        assert!(cues.windows(2).all(|w| w[0].end_ms == w[1].start_ms));
+    }
+
+    #[test]
+    fn test_markdown_groups_speakers_and_lists_answers() {
+        let mut translated = segment("hola", 1, None, None);
+        translated.translation_status = TranslationStatus::Translation;
+        translated.language = Some("es".into());
+        let d = detail(vec![
+            segment("hello", 1, Some(0), Some(500)),
+            segment("again", 1, Some(600), Some(900)),
+            translated,
+            segment("bye", 2, Some(1_000), Some(1_500)),
+        ]);
+        let md = render(&d, ExportFormat::Markdown).unwrap();
+        assert!(md.contains("**Speaker 1** (00:00:00.000) [en]: Hello. Again.\n"));
+        assert!(md.contains("> [es] Hola.\n"));
+        assert!(md.contains("**Speaker 2** (00:00:01.000) [en]: Bye.\n"));
+        assert!(md.contains("### general · gpt-4.1 · 2025-03-01T10:16:02+01:00\n\nSummary.\n"));
+    }
+
+    #[test]
+    fn test_json_is_lossless() {
+        let d = detail(vec![segment("hello <UNK>", 1, Some(10), Some(20))]);
+        let json = render(&d, ExportFormat::Json).unwrap();
+        let back: SessionDetail = serde_json::from_str(&json).unwrap();
+        assert_eq!(back, d);
+    }
+
+    #[test]
+    fn test_parse_format() {
+        assert_eq!(ExportFormat::parse("WebVTT").unwrap(), ExportFormat::Vtt);
+        assert_eq!(ExportFormat::parse("md").unwrap(), ExportFormat::Markdown);
+        assert!(ExportFormat::parse("docx").is_err());
+    }
+}
@@ -1,44 +1,17 @@
-use crate::{openai, utils::log_to_file};
+use crate::{
+    llm::{
+        AiError, ChatMessage, ChatRequest, ChatRole, ErrorCategory, JsonSchemaFormat, LlmProvider,
+        TokenUsage,
+    },
+    utils::log_to_file,
+};
 use serde::{Deserialize, Serialize};
-
-// Reuse chat request/response structures from a minimal local definition
-// to keep this module self-contained and focused on gating.
-
-#[derive(Debug, Serialize)]
-struct ChatMessage {
-    role: String,
-    content: String,
-}
-
-#[derive(Debug, Serialize)]
-struct ChatRequest {
-    model: String,
-    messages: Vec<ChatMessage>,
-    max_completion_tokens: u32,
-    temperature: f32,
-}
-
-#[derive(Debug, Deserialize)]
-struct ChatChoice {
-    message: ChatMessageOut,
-}
-
-#[derive(Debug, Deserialize)]
-struct ChatMessageOut {
-    role: String,
-    content: String,
-}
-
-#[derive(Debug, Deserialize)]
-struct ChatResponse {
-    choices: Vec<ChatChoice>,
-}
+use serde_json::{json, Map, Value};
 
 // Lightweight gating API: decide whether to run a full analysis
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub struct GateOptions {
-    pub api_key: String,
     #[serde(default = "default_gate_model")]
     pub model: String,
     #[serde(default = "default_main_system_prompt")]
@@ -62,6 +35,63 @@ fn default_gate_instructions() -> String {
     "Run when there is new, materially different user intent, a new completed question/sentence, or when prior output no longer fits. Skip for partial/unstable ASR text, trivial edits, or small punctuation changes. Also skip if the last assistant output already addresses the current prompt adequately or if the user's thoughts seem unfinished.".into()
 }
 
+// How an assistant decides whether to run: ask the gate model, use the local
+// heuristics only, or ask the model only when the heuristics are unsure.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GateMode {
+    #[default]
+    Llm,
+    Local,
+    Hybrid,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GateSource {
+    Llm,
+    Local,
+}
+
+// Which parsing path produced a gate decision.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GateParse {
+    // The reply was exactly the expected JSON object
+    Strict,
+    // The object was found inside code fences or surrounding prose
+    Extracted,
+    // Values needed coercion, e.g. "true" as a string or a missing `run`
+    Lenient,
+    // No usable JSON; the local heuristics decided instead
+    Fallback,
+    // No model call was made
+    Local,
+}
+
+impl GateParse {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Strict => "strict",
+            Self::Extracted => "extracted",
+            Self::Lenient => "lenient",
+            Self::Fallback => "fallback",
+            Self::Local => "local",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "strict" => Some(Self::Strict),
+            "extracted" => Some(Self::Extracted),
+            "lenient" => Some(Self::Lenient),
+            "fallback" => Some(Self::Fallback),
+            "local" => Some(Self::Local),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct GateJson {
     pub run: bool,
@@ -70,6 +100,16 @@ pub struct GateJson {
     pub confidence: Option<f32>,
 }
 
+// A gate decision plus what it cost; skips made without a model call have no usage.
+#[derive(Debug, Clone)]
+pub struct GateOutcome {
+    pub decision: GateJson,
+    pub usage: Option<TokenUsage>,
+    pub generation_id: Option<String>,
+    pub called_model: bool,
+    pub parse: GateParse,
+}
+
 #[derive(Debug, Clone)]
 pub struct GatePrompt {
     pub system_prompt: String,
@@ -139,72 +179,200 @@ pub fn log_gate_prompt(provider_label: &str, model: &str, prompt: &GatePrompt) {
     ));
 }
 
+// Schema for providers with a structured-output mode. Strict mode requires
+// every property to be listed as required.
+pub fn gate_response_format() -> JsonSchemaFormat {
+    JsonSchemaFormat {
+        name: "gate_decision".into(),
+        schema: json!({
+            "type": "object",
+            "properties": {
+                "run": {"type": "boolean"},
+                "instruction": {"type": "string", "enum": ["NEEDED", "NOT_NEEDED"]},
+                "reason": {"type": "string"},
+                "confidence": {"type": "number"}
+            },
+            "required": ["run", "instruction", "reason", "confidence"],
+            "additionalProperties": false
+        }),
+    }
+}
+
+// The first balanced `{...}` in `text` that parses as a JSON object, skipping
+// braces inside strings. Handles ```json fences and chatty preambles.
+pub(crate) fn extract_json_object(text: &str) -> Option<Map<String, Value>> {
+    for (start, _) in text.match_indices('{') {
+        let mut depth = 0usize;
+        let mut in_string = false;
+        let mut escaped = false;
+        for (offset, c) in text[start..].char_indices() {
+            if in_string {
+                match c {
+                    _ if escaped => escaped = false,
+                    '\\' => escaped = true,
+                    '"' => in_string = false,
+                    _ => {}
+                }
+                continue;
+            }
+            match c {
+                '"' => in_string = true,
+                '{' => depth += 1,
+                '}' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        let candidate = &text[start..=start + offset];
+                        if let Ok(Value::Object(map)) = serde_json::from_str(candidate) {
+                            return Some(map);
+                        }
+                        break;
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+    None
+}
+
+pub(crate) fn lenient_bool(value: &Value) -> Option<bool> {
+    match value {
+        Value::Bool(b) => Some(*b),
+        Value::Number(n) => n.as_f64().map(|n| n != 0.0),
+        Value::String(s) => match s.trim().to_ascii_lowercase().as_str() {
+            "true" | "yes" | "1" | "needed" | "run" => Some(true),
+            "false" | "no" | "0" | "not_needed" | "skip" => Some(false),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+pub(crate) fn lenient_number(value: &Value) -> Option<f32> {
+    match value {
+        Value::Number(n) => n.as_f64().map(|n| n as f32),
+        Value::String(s) => s.trim().trim_end_matches('%').trim().parse().ok(),
+        _ => None,
+    }
+}
+
+fn lenient_gate(map: &Map<String, Value>) -> Option<GateJson> {
+    let instruction = map
+        .get("instruction")
+        .and_then(Value::as_str)
+        .map(|s| s.trim().to_ascii_uppercase());
+    let run = map
+        .get("run")
+        .and_then(lenient_bool)
+        .or(match instruction.as_deref() {
+            Some("NEEDED") => Some(true),
+            Some("NOT_NEEDED") => Some(false),
+            _ => None,
+        })?;
+    let reason = map.get("reason").map(|v| match v {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    });
+    Some(GateJson {
+        run,
+        instruction,
+        reason,
+        confidence: map.get("confidence").and_then(lenient_number),
+    })
+}
+
+// Fill in `instruction` and normalize `confidence`.
+fn normalize(mut gate: GateJson) -> GateJson {
+    gate.instruction = Some(match gate.instruction {
+        Some(instruction) => instruction.trim().to_ascii_uppercase(),
+        None => if gate.run { "NEEDED" } else { "NOT_NEEDED" }.into(),
+    });
+    gate.confidence = normalize_confidence(gate.confidence);
+    gate
+}
+
+// Clamp a confidence into [0, 1]. Models sometimes answer in percent, so
+// whole numbers from 2 to 100 are rescaled; anything else just clamps.
+pub(crate) fn normalize_confidence(confidence: Option<f32>) -> Option<f32> {
+    confidence
+        .filter(|c| c.is_finite())
+        .map(|c| {
+            if c.fract() == 0.0 && (2.0..=100.0).contains(&c) {
+                c / 100.0
+            } else {
+                c
+            }
+        })
+        .map(|c| c.clamp(0.0, 1.0))
+}
+
+pub fn parse_gate_json(content: &str) -> Option<(GateJson, GateParse)> {
+    let content = content.trim();
+    if let Ok(gate) = serde_json::from_str::<GateJson>(content) {
+        return Some((normalize(gate), GateParse::Strict));
+    }
+    let map = extract_json_object(content)?;
+    if let Ok(gate) = serde_json::from_value::<GateJson>(Value::Object(map.clone())) {
+        return Some((normalize(gate), GateParse::Extracted));
+    }
+    lenient_gate(&map).map(|gate| (normalize(gate), GateParse::Lenient))
+}
+
+// `fallback` is used when the model does not return usable JSON; callers
+// pass the local heuristic decision.
 pub fn interpret_gate_response(
     provider_label: &str,
     content: &str,
-    current_transcript: &str,
-    previous_transcript: &str,
-) -> GateJson {
-    match serde_json::from_str::<GateJson>(content) {
-        Ok(mut gate) => {
-            if gate.instruction.is_none() {
-                gate.instruction = Some(if gate.run {
-                    "NEEDED".into()
-                } else {
-                    "NOT_NEEDED".into()
-                });
-            }
-
+    fallback: GateJson,
+) -> (GateJson, GateParse) {
+    match parse_gate_json(content) {
+        Some((gate, parse)) => {
             log_to_file(&format!(
-                "{}(Gate): Decision run={} instruction={:?} confidence={:?} reason={}",
+                "{}(Gate): Decision run={} instruction={:?} confidence={:?} parse={} reason={}",
                 provider_label,
                 gate.run,
                 gate.instruction,
                 gate.confidence,
+                parse.as_str(),
                 gate.reason.clone().unwrap_or_default()
             ));
-
-            gate
+            (gate, parse)
         }
-        Err(err) => {
+        None => {
             log_to_file(&format!(
-                "{}(Gate): JSON parse error: {} | content: {}",
-                provider_label, err, content
+                "{}(Gate): No usable JSON in content: {}",
+                provider_label, content
             ));
 
-            let ends_sentence = current_transcript.trim_end().ends_with(['.', '!', '?']);
-            let growth = current_transcript
-                .len()
-                .saturating_sub(previous_transcript.len());
-            let run = ends_sentence && growth >= 50;
-
             log_to_file(&format!(
-                "{}(Gate): Fallback decision run={} ends_sentence={} growth_chars={}",
-                provider_label, run, ends_sentence, growth
+                "{}(Gate): Fallback decision run={} reason={}",
+                provider_label,
+                fallback.run,
+                fallback.reason.clone().unwrap_or_default()
             ));
 
-            GateJson {
-                run,
-                instruction: Some(if run {
-                    "NEEDED".into()
-                } else {
-                    "NOT_NEEDED".into()
-                }),
-                reason: Some("Fallback heuristic".into()),
-                confidence: Some(0.3),
-            }
+            let gate = GateJson {
+                // Heuristics are only a stand-in for the model here
+                confidence: Some(fallback.confidence.unwrap_or(0.3).min(0.3)),
+                ..fallback
+            };
+            (gate, GateParse::Fallback)
         }
     }
 }
 
+// Ask the provider's gate model whether the main assistant should run.
 pub async fn should_run_gate(
+    provider: &dyn LlmProvider,
     opts: GateOptions,
     current_transcript: String,
     previous_transcript: String,
     last_output: Option<String>,
-) -> Result<GateJson, String> {
+    fallback: GateJson,
+) -> Result<GateOutcome, AiError> {
+    let label = provider.label();
     let prompt = match prepare_gate_prompt(
-        "OpenAI",
+        label,
         &opts.main_system_prompt,
         &opts.gate_instructions,
         &current_transcript,
@@ -212,10 +380,18 @@ pub async fn should_run_gate(
         last_output.as_deref(),
     ) {
         Ok(prompt) => prompt,
-        Err(skip) => return Ok(skip),
+        Err(skip) => {
+            return Ok(GateOutcome {
+                decision: skip,
+                usage: None,
+                generation_id: None,
+                called_model: false,
+                parse: GateParse::Local,
+            })
+        }
     };
 
-    log_gate_prompt("OpenAI", &opts.model, &prompt);
+    log_gate_prompt(label, &opts.model, &prompt);
 
     let GatePrompt {
         system_prompt,
@@ -223,62 +399,88 @@ pub async fn should_run_gate(
         ..
     } = prompt;
 
-    let model_id = opts.model.clone();
-    let temp = openai::temperature_for_model(&model_id, 0.0);
-    let request = ChatRequest {
-        model: model_id,
+    let mut request = ChatRequest {
+        model: opts.model,
         messages: vec![
-            ChatMessage {
-                role: "system".into(),
-                content: system_prompt,
-            },
-            ChatMessage {
-                role: "user".into(),
-                content: user_prompt,
-            },
+            ChatMessage::new(ChatRole::System, system_prompt),
+            ChatMessage::new(ChatRole::User, user_prompt),
         ],
-        max_completion_tokens: 120,
-        temperature: temp,
+        max_tokens: 120,
+        temperature: 0.0,
+        response_format: Some(gate_response_format()),
+        top_p: None,
+        stop: Vec::new(),
     };
 
-    let client = reqwest::Client::new();
-    let resp = client
-        .post("https://api.openai.com/v1/chat/completions")
-        .header("Authorization", format!("Bearer {}", opts.api_key))
-        .header("Content-Type", "application/json")
-        .json(&request)
-        .send()
-        .await
-        .map_err(|e| format!("Failed to connect to OpenAI: {}", e))?;
-
-    let status = resp.status();
-    if !status.is_success() {
-        let error_text = resp
-            .text()
-            .await
-            .unwrap_or_else(|_| "Unknown error".to_string());
-        log_to_file(&format!(
-            "OpenAI(Gate): API error {}: {}",
-            status, error_text
-        ));
-        return Err(format!("OpenAI API error: {}", status));
+    let response = match provider.chat(&request).await {
+        // Some models and local servers reject structured output; ask again
+        // without it and rely on the tolerant parser
+        Err(e)
+            if matches!(e.status, Some(400 | 422))
+                && e.category != ErrorCategory::ModelNotFound =>
+        {
+            log_to_file(&format!(
+                "{}(Gate): structured output rejected ({}); retrying without it",
+                label, e
+            ));
+            request.response_format = None;
+            provider.chat(&request).await
+        }
+        other => other,
     }
+    .map_err(|e| {
+        log_to_file(&format!("{}(Gate): API error: {}", label, e));
+        e
+    })?;
+    let content = response.text.trim().to_string();
+    log_to_file(&format!("{}(Gate): Raw response=<<<{}>>>", label, content));
+    let (decision, parse) = interpret_gate_response(label, &content, fallback);
+    Ok(GateOutcome {
+        decision,
+        usage: response.usage,
+        generation_id: response.generation_id,
+        called_model: true,
+        parse,
+    })
+}
 
-    let chat: ChatResponse = resp
-        .json()
-        .await
-        .map_err(|e| format!("Failed to parse OpenAI response: {}", e))?;
-
-    if let Some(choice) = chat.choices.first() {
-        let content = choice.message.content.trim().to_string();
-        log_to_file(&format!("OpenAI(Gate): Raw response=<<<{}>>>", content));
-        Ok(interpret_gate_response(
-            "OpenAI",
-            &content,
-            &current_transcript,
-            &previous_transcript,
-        ))
-    } else {
-        Err("No response from OpenAI".into())
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_gate_json_paths() {
+        let (gate, parse) = parse_gate_json(
+            r#"{"run": true, "instruction": "NEEDED", "reason": "new question", "confidence": 0.9}"#,
+        )
+        .unwrap();
+        assert!(gate.run);
+        assert_eq!(parse, GateParse::Strict);
+
+        let fenced = "Sure, here it is:\n```json\n{\"run\": false, \"reason\": \"a {partial} thought\", \"confidence\": 0.7}\n```";
+        let (gate, parse) = parse_gate_json(fenced).unwrap();
+        assert!(!gate.run);
+        assert_eq!(gate.instruction.as_deref(), Some("NOT_NEEDED"));
+        assert_eq!(gate.reason.as_deref(), Some("a {partial} thought"));
+        assert_eq!(parse, GateParse::Extracted);
+
+        let (gate, parse) =
+            parse_gate_json(r#"{"run": "true", "confidence": "85%", "reason": "asked"}"#).unwrap();
+        assert!(gate.run);
+        assert_eq!(gate.confidence, Some(0.85));
+        assert_eq!(parse, GateParse::Lenient);
+
+        // `run` missing but implied by the instruction; confidence clamped
+        let (gate, _) =
+            parse_gate_json(r#"Decision: {"instruction": "needed", "confidence": 7}"#).unwrap();
+        assert!(gate.run);
+        assert_eq!(gate.confidence, Some(0.07));
+        let (gate, _) = parse_gate_json(r#"{"run": false, "confidence": -2}"#).unwrap();
+        assert_eq!(gate.confidence, Some(0.0));
+        let (gate, _) = parse_gate_json(r#"{"run": true, "confidence": 1.5}"#).unwrap();
+        assert_eq!(gate.confidence, Some(1.0));
+
+        assert!(parse_gate_json("I think we should run it.").is_none());
+        assert!(parse_gate_json(r#"{"reason": "no verdict"}"#).is_none());
     }
 }
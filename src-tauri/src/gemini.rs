@@ -0,0 +1,353 @@
+use crate::{
+    llm::{
+        self, AiError, ChatRequest, ChatResponse, ChatRole, DeltaCallback, ErrorCategory,
+        LlmProvider, StreamEvent, TokenUsage,
+    },
+    retry::RetryPolicy,
+    utils::log_to_file,
+};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+const API_URL: &str = "https://generativelanguage.googleapis.com/v1beta";
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Part {
+    #[serde(default)]
+    text: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Content {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    role: Option<String>,
+    #[serde(default)]
+    parts: Vec<Part>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct GenerationConfig {
+    max_output_tokens: u32,
+    temperature: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    stop_sequences: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response_mime_type: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct GenerateRequest {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    system_instruction: Option<Content>,
+    contents: Vec<Content>,
+    generation_config: GenerationConfig,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Candidate {
+    #[serde(default)]
+    content: Option<Content>,
+    #[serde(default)]
+    finish_reason: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct UsageMetadata {
+    #[serde(default)]
+    prompt_token_count: u32,
+    #[serde(default)]
+    candidates_token_count: u32,
+    #[serde(default)]
+    cached_content_token_count: u32,
+}
+
+impl From<UsageMetadata> for TokenUsage {
+    fn from(value: UsageMetadata) -> Self {
+        Self {
+            input_tokens: value.prompt_token_count,
+            output_tokens: value.candidates_token_count,
+            cached_input_tokens: value.cached_content_token_count,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiError {
+    message: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GenerateResponse {
+    #[serde(default)]
+    candidates: Vec<Candidate>,
+    #[serde(default)]
+    usage_metadata: Option<UsageMetadata>,
+    #[serde(default)]
+    error: Option<ApiError>,
+}
+
+impl GenerateResponse {
+    fn text(&self) -> String {
+        self.candidates
+            .first()
+            .and_then(|c| c.content.as_ref())
+            .map(|content| content.parts.iter().map(|p| p.text.as_str()).collect())
+            .unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ModelData {
+    name: String,
+    #[serde(default)]
+    supported_generation_methods: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ModelsResponse {
+    #[serde(default)]
+    models: Vec<ModelData>,
+}
+
+fn generate_request(request: &ChatRequest) -> GenerateRequest {
+    let (system, turns) = request.split_system();
+    GenerateRequest {
+        system_instruction: (!system.is_empty()).then(|| Content {
+            role: None,
+            parts: vec![Part { text: system }],
+        }),
+        contents: turns
+            .into_iter()
+            .map(|m| Content {
+                role: Some(
+                    if m.role == ChatRole::Assistant {
+                        "model"
+                    } else {
+                        "user"
+                    }
+                    .to_string(),
+                ),
+                parts: vec![Part { text: m.content }],
+            })
+            .collect(),
+        generation_config: GenerationConfig {
+            max_output_tokens: request.max_tokens,
+            temperature: request.temperature,
+            top_p: request.top_p,
+            stop_sequences: request.stop.clone(),
+            // JSON mode only: the response schema dialect is an OpenAPI subset
+            response_mime_type: request
+                .response_format
+                .as_ref()
+                .map(|_| "application/json".to_string()),
+        },
+    }
+}
+
+// Model ids are listed as "models/<id>"; accept either form.
+fn model_path(model: &str) -> String {
+    format!("models/{}", model.trim_start_matches("models/"))
+}
+
+// Google Gemini generateContent API.
+pub struct GeminiProvider {
+    api_key: String,
+    client: reqwest::Client,
+    timeout: Duration,
+    retry: RetryPolicy,
+}
+
+impl GeminiProvider {
+    pub fn new(api_key: &str, timeout: Duration) -> Self {
+        Self {
+            api_key: api_key.to_string(),
+            client: llm::http_client(),
+            timeout,
+            retry: RetryPolicy::default(),
+        }
+    }
+
+    async fn post_generate(
+        &self,
+        request: &ChatRequest,
+        method: &str,
+        stream: bool,
+    ) -> Result<reqwest::Response, AiError> {
+        let url = format!("{}/{}:{}", API_URL, model_path(&request.model), method);
+        let body = generate_request(request);
+        llm::send_with_retry("Gemini", &self.retry, self.timeout, || {
+            let request = self
+                .client
+                .post(&url)
+                .header("x-goog-api-key", &self.api_key)
+                .header("Content-Type", "application/json")
+                .json(&body);
+            if stream {
+                request
+            } else {
+                request.timeout(self.timeout)
+            }
+        })
+        .await
+    }
+}
+
+#[async_trait]
+impl LlmProvider for GeminiProvider {
+    fn label(&self) -> &'static str {
+        "Gemini"
+    }
+
+    async fn chat(&self, request: &ChatRequest) -> Result<ChatResponse, AiError> {
+        let response = self
+            .post_generate(request, "generateContent", false)
+            .await?;
+        let generated: GenerateResponse = llm::read_json("Gemini", response).await?;
+        if generated.candidates.is_empty() {
+            return Err(AiError::new(
+                ErrorCategory::Other,
+                "No response from Gemini",
+            ));
+        }
+        Ok(ChatResponse {
+            text: generated.text(),
+            usage: generated.usage_metadata.map(TokenUsage::from),
+            generation_id: None,
+        })
+    }
+
+    async fn chat_stream(
+        &self,
+        request: &ChatRequest,
+        on_delta: &mut DeltaCallback<'_>,
+    ) -> Result<ChatResponse, AiError> {
+        let response = self
+            .post_generate(request, "streamGenerateContent?alt=sse", true)
+            .await?;
+        llm::collect_stream(
+            "Gemini",
+            response,
+            parse_stream_event,
+            self.timeout,
+            on_delta,
+        )
+        .await
+    }
+
+    async fn list_models(&self) -> Result<Vec<String>, AiError> {
+        log_to_file("Gemini: Fetching available models");
+        let response = llm::send_with_retry("Gemini", &self.retry, self.timeout, || {
+            self.client
+                .get(format!("{}/models?pageSize=1000", API_URL))
+                .header("x-goog-api-key", &self.api_key)
+                .timeout(self.timeout)
+        })
+        .await?;
+        let listed: ModelsResponse = llm::read_json("Gemini", response).await?;
+        let mut models: Vec<String> = listed
+            .models
+            .into_iter()
+            .filter(|m| {
+                m.supported_generation_methods
+                    .iter()
+                    .any(|method| method == "generateContent")
+            })
+            .map(|m| m.name.trim_start_matches("models/").to_string())
+            .collect();
+        models.sort();
+        Ok(models)
+    }
+}
+
+// Interpret one SSE payload from streamGenerateContent. The stream has no
+// terminator; it simply ends after the chunk carrying `finishReason`.
+pub fn parse_stream_event(data: &str) -> Result<Vec<StreamEvent>, String> {
+    let chunk: GenerateResponse = serde_json::from_str(data.trim())
+        .map_err(|e| format!("Failed to parse Gemini stream chunk: {}", e))?;
+    if let Some(error) = chunk.error {
+        return Err(format!("Gemini stream error: {}", error.message));
+    }
+    let mut events = Vec::new();
+    let text = chunk.text();
+    if !text.is_empty() {
+        events.push(StreamEvent::Delta(text));
+    }
+    if chunk
+        .candidates
+        .first()
+        .is_some_and(|c| c.finish_reason.is_some())
+    {
+        events.push(StreamEvent::Finished);
+    }
+    if let Some(usage) = chunk.usage_metadata {
+        events.push(StreamEvent::Usage(usage.into()));
+    }
+    Ok(events)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::ChatMessage;
+
+    #[test]
+    fn test_generate_request_shape() {
+        let request = ChatRequest {
+            model: "gemini-2.0-flash".into(),
+            messages: vec![
+                ChatMessage::new(ChatRole::System, "Be brief"),
+                ChatMessage::new(ChatRole::User, "Hi"),
+            ],
+            max_tokens: 120,
+            temperature: 0.0,
+            response_format: None,
+            top_p: None,
+            stop: Vec::new(),
+        };
+        let body = serde_json::to_value(generate_request(&request)).unwrap();
+        assert_eq!(body["systemInstruction"]["parts"][0]["text"], "Be brief");
+        assert_eq!(body["contents"][0]["role"], "user");
+        assert_eq!(body["contents"][0]["parts"][0]["text"], "Hi");
+        assert_eq!(body["generationConfig"]["maxOutputTokens"], 120);
+        assert!(body["generationConfig"].get("responseMimeType").is_none());
+        assert_eq!(
+            model_path("models/gemini-2.0-flash"),
+            "models/gemini-2.0-flash"
+        );
+    }
+
+    #[test]
+    fn test_parse_stream_event() {
+        let delta = r#"{"candidates":[{"content":{"role":"model","parts":[{"text":"Hel"},{"text":"lo"}]}}]}"#;
+        assert_eq!(
+            parse_stream_event(delta).unwrap(),
+            vec![StreamEvent::Delta("Hello".into())]
+        );
+        let last = r#"{"candidates":[{"content":{"parts":[{"text":"!"}]},"finishReason":"STOP"}],"usageMetadata":{"promptTokenCount":9,"candidatesTokenCount":4}}"#;
+        assert_eq!(
+            parse_stream_event(last).unwrap(),
+            vec![
+                StreamEvent::Delta("!".into()),
+                StreamEvent::Finished,
+                StreamEvent::Usage(TokenUsage {
+                    input_tokens: 9,
+                    output_tokens: 4,
+                    cached_input_tokens: 0
+                })
+            ]
+        );
+        let error = r#"{"error":{"code":429,"message":"Resource exhausted"}}"#;
+        assert!(parse_stream_event(error)
+            .unwrap_err()
+            .contains("Resource exhausted"));
+    }
+}
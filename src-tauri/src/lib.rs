@@ -1,31 +1,68 @@
 // Learn more about Tauri commands at https://tauri.app/develop/calling-rust/
 use std::{
+    collections::HashMap,
     fs::File,
-    path::PathBuf,
+    path::{Path, PathBuf},
     sync::{mpsc, Arc, Mutex},
     thread,
     time::{Duration, Instant},
 };
 
 use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
-use futures_util::StreamExt;
+use futures_util::future::{Abortable, FutureExt};
 use serde::Serialize;
-use tauri::{Emitter, State};
+use tauri::{Emitter, Listener, Manager, State};
 
+mod ai_tasks;
+mod anthropic;
 mod assistants;
 mod audio;
+mod budget;
 mod config;
+mod export;
 mod gate;
+mod gemini;
+mod llm;
+mod local_gate;
+mod memory;
 mod openai;
 mod openrouter;
+mod retrieval;
+mod retry;
+mod router;
+mod sessions;
 mod soniox;
 #[cfg(test)]
 mod soniox_test;
+mod structured;
+mod templates;
 mod transcription;
+mod usage;
 mod utils;
-use crate::assistants::{Assistant, AssistantManager};
+mod whisper;
+use crate::ai_tasks::{AiTaskKind, AiTaskRegistry, AI_CANCELLED_EVENT, CANCELLED_ERROR};
+use crate::assistants::{
+    Assistant, AssistantManager, AssistantsWatcher, Pipeline, ASSISTANTS_CHANGED_EVENT,
+    ASSISTANTS_CONFIG_PATH, DEFAULT_MAX_TOKENS,
+};
+use crate::budget::{
+    BudgetAction, BudgetEvent, BudgetVerdict, BUDGET_EXCEEDED_EVENT, BUDGET_WARNING_EVENT,
+};
 use crate::config::{AiProvider, AppConfig, ConfigManager};
-use crate::soniox::SonioxControl;
+use crate::gate::{GateJson, GateMode, GateParse, GateSource};
+use crate::llm::{
+    AccountUsage, AiError, ChatResponse, ErrorCategory, LlmProvider, ProviderSettings, TokenUsage,
+};
+use crate::local_gate::TurnTracker;
+use crate::memory::{CompactionJob, TranscriptMemory, TranscriptWindow, MEMORY_ID};
+use crate::retrieval::{DocumentIndex, DocumentIndexes};
+use crate::router::{RouteCandidate, RouteChoice, ROUTER_ID};
+use crate::sessions::{ActiveSession, AnswerRecord, GateRecord, SessionStore};
+use crate::templates::PromptVars;
+use crate::transcription::{
+    AudioChunk, TranscriptSegment, TranscriptUpdate, TranscriptionProvider, TRANSCRIPT_UPDATE_EVENT,
+};
+use crate::usage::{CostSource, UsageFilters, UsageRecord, UsageReport};
 use crate::utils::log_to_file;
 pub use audio::AudioWriter;
 
@@ -90,6 +127,9 @@ fn greet(name: &str) -> String {
     format!("Hello, {}! You've been greeted from Rust!", name)
 }
 
+// Session id, first seq and the segments to store from there
+type SegmentCheckpoint = (String, usize, Vec<TranscriptSegment>);
+
 #[derive(Clone)]
 struct AppState {
     // Enhanced state management with atomic transitions
@@ -108,18 +148,31 @@ struct AppState {
     vad_session_path: Arc<Mutex<Option<PathBuf>>>,
     is_writing_enabled: Arc<Mutex<bool>>, // Controls whether samples are written during pause
     // Real-time transcription session (provider-agnostic)
-    soniox_tx: Arc<Mutex<Option<tokio::sync::mpsc::Sender<transcription::AudioChunk>>>>,
-    soniox_ctrl: Arc<Mutex<Option<tokio::sync::mpsc::Sender<SonioxControl>>>>,
-    // Visible provider kind (currently Soniox), to ease future swaps
-    transcriber_kind: Arc<Mutex<Option<transcription::ProviderKind>>>,
+    transcriber: Arc<Mutex<Option<Box<dyn TranscriptionProvider>>>>,
     // Track if we're in voice detection mode vs manual recording mode
     is_voice_detection_mode: Arc<Mutex<bool>>,
     // Track if voice is currently being detected (only used in voice detection mode)
     voice_currently_detected: Arc<Mutex<bool>>,
     // Assistant manager for multiple AI assistants
     assistant_manager: Arc<Mutex<AssistantManager>>,
+    // Reloads assistants.json after external edits
+    assistants_watcher: AssistantsWatcher,
+    // Chunked reference documents per assistant, for `{{context}}`
+    document_indexes: DocumentIndexes,
     // App configuration
     app_config: Arc<Mutex<AppConfig>>,
+    // Persistent session history (opened on first use)
+    session_store: Arc<Mutex<Option<SessionStore>>>,
+    // Session currently being recorded, if any
+    active_session: Arc<Mutex<Option<ActiveSession>>>,
+    // Transcript checkpoints, written in order off the transcription thread
+    segment_writer: Arc<Mutex<Option<mpsc::Sender<SegmentCheckpoint>>>>,
+    // In-flight analysis and gate requests, cancellable by request id
+    ai_tasks: Arc<Mutex<AiTaskRegistry>>,
+    // Speaker changes and endpoints seen since the last gate check
+    turn_tracker: Arc<Mutex<TurnTracker>>,
+    // Latest transcript segments and the rolling summary of older speech
+    transcript_memory: Arc<Mutex<TranscriptMemory>>,
 }
 
 impl Default for AppState {
@@ -133,13 +186,19 @@ impl Default for AppState {
             writer_state: Arc::new(Mutex::new(None)),
             vad_session_path: Arc::new(Mutex::new(None)),
             is_writing_enabled: Arc::new(Mutex::new(false)),
-            soniox_tx: Arc::new(Mutex::new(None)),
-            soniox_ctrl: Arc::new(Mutex::new(None)),
-            transcriber_kind: Arc::new(Mutex::new(None)),
+            transcriber: Arc::new(Mutex::new(None)),
             is_voice_detection_mode: Arc::new(Mutex::new(false)),
             voice_currently_detected: Arc::new(Mutex::new(false)),
             assistant_manager: Arc::new(Mutex::new(AssistantManager::empty())),
+            assistants_watcher: AssistantsWatcher::default(),
+            document_indexes: DocumentIndexes::default(),
             app_config: Arc::new(Mutex::new(AppConfig::default())),
+            session_store: Arc::new(Mutex::new(None)),
+            active_session: Arc::new(Mutex::new(None)),
+            segment_writer: Arc::new(Mutex::new(None)),
+            ai_tasks: Arc::new(Mutex::new(AiTaskRegistry::default())),
+            turn_tracker: Arc::new(Mutex::new(TurnTracker::default())),
+            transcript_memory: Arc::new(Mutex::new(TranscriptMemory::default())),
         }
     }
 }
@@ -220,14 +279,367 @@ impl AppState {
             .lock()
             .unwrap_or_else(|e| e.into_inner())
     }
+
+    // Forward captured audio to the active transcription provider, if any
+    fn push_transcription_audio(&self, chunk: AudioChunk) {
+        if let Ok(lock) = self.transcriber.lock() {
+            if let Some(provider) = lock.as_ref() {
+                provider.push_audio(chunk);
+            }
+        }
+    }
+}
+
+// Session history
+impl AppState {
+    fn with_session_store<T>(
+        &self,
+        f: impl FnOnce(&mut SessionStore) -> Result<T, String>,
+    ) -> Result<T, String> {
+        let mut guard = self
+            .session_store
+            .lock()
+            .map_err(|_| "Failed to lock session store".to_string())?;
+        if guard.is_none() {
+            *guard = Some(SessionStore::open(sessions::default_db_path())?);
+        }
+        match guard.as_mut() {
+            Some(store) => f(store),
+            None => Err("Session store unavailable".to_string()),
+        }
+    }
+
+    // Start recording history for a new audio file. Failures are logged but
+    // never block the recording itself.
+    fn begin_session(&self, audio_path: &std::path::Path, audio_format: &str) {
+        let audio_path = audio_path.to_string_lossy().to_string();
+        match self.with_session_store(|store| store.create_session(&audio_path, audio_format)) {
+            Ok(id) => {
+                log_to_file(&format!("Session {} started for {}", id, audio_path));
+                *self.active_session.lock().unwrap() = Some(ActiveSession::new(id, audio_path));
+            }
+            Err(e) => log_to_file(&format!("Failed to start session history: {}", e)),
+        }
+    }
+
+    // Persist the final transcript and duration of the active session.
+    fn finish_session(&self) {
+        let Some(active) = self.active_session.lock().unwrap().take() else {
+            return;
+        };
+        let duration_ms =
+            sessions::audio_duration_ms(&active.audio_path).unwrap_or(active.elapsed_ms());
+        let segments = active.final_segments();
+        let result = self.with_session_store(|store| {
+            store.replace_segments(&active.id, &segments)?;
+            store.finish_session(&active.id, Some(duration_ms))
+        });
+        match result {
+            Ok(()) => log_to_file(&format!(
+                "Session {} finished: {} segments, {} ms",
+                active.id,
+                segments.len(),
+                duration_ms
+            )),
+            Err(e) => log_to_file(&format!("Failed to finish session {}: {}", active.id, e)),
+        }
+    }
+
+    fn active_session_title(&self) -> Option<String> {
+        self.active_session
+            .lock()
+            .ok()
+            .and_then(|guard| guard.as_ref().and_then(|s| s.title.clone()))
+    }
+
+    fn active_session_id(&self) -> Option<String> {
+        self.active_session
+            .lock()
+            .ok()
+            .and_then(|guard| guard.as_ref().map(|s| s.id.clone()))
+    }
+
+    fn record_transcript_update(&self, update: &TranscriptUpdate) {
+        if let Ok(mut tracker) = self.turn_tracker.lock() {
+            tracker.observe(update);
+        }
+        if let Ok(mut memory) = self.transcript_memory.lock() {
+            memory.observe(update);
+        }
+        let checkpoint = self.active_session.lock().ok().and_then(|mut guard| {
+            let active = guard.as_mut()?;
+            active.observe(update);
+            let (first_seq, segments) = active.checkpoint(update.endpoint)?;
+            Some((active.id.clone(), first_seq, segments))
+        });
+        // Saved as the transcript grows so a crash or force-quit keeps it
+        if let Some(checkpoint) = checkpoint {
+            self.queue_checkpoint(checkpoint);
+        }
+    }
+
+    // Hand a checkpoint to the writer thread, starting it on first use.
+    fn queue_checkpoint(&self, checkpoint: SegmentCheckpoint) {
+        let mut writer = self.segment_writer.lock().unwrap();
+        let tx = writer.get_or_insert_with(|| {
+            let (tx, rx) = mpsc::channel::<SegmentCheckpoint>();
+            let state = self.clone();
+            thread::spawn(move || {
+                for (id, first_seq, segments) in rx {
+                    state.save_checkpoint(&id, first_seq, &segments);
+                }
+            });
+            tx
+        });
+        if tx.send(checkpoint).is_err() {
+            log_to_file("Transcript writer stopped; checkpoint dropped");
+            *writer = None;
+        }
+    }
+
+    fn save_checkpoint(&self, id: &str, first_seq: usize, segments: &[TranscriptSegment]) {
+        let result = self.with_session_store(|store| {
+            // A finished session already holds its full transcript
+            if self.active_session_id().as_deref() != Some(id) {
+                return Ok(());
+            }
+            store.save_segments_from(id, first_seq, segments)
+        });
+        if let Err(e) = result {
+            log_to_file(&format!(
+                "Failed to save transcript of session {}: {}",
+                id, e
+            ));
+        }
+    }
+
+    fn record_gate_decision(&self, record: GateRecord) {
+        let Some(id) = self.active_session_id() else {
+            return;
+        };
+        if let Err(e) = self.with_session_store(|store| store.add_gate_decision(&id, &record)) {
+            log_to_file(&format!("Failed to record gate decision: {}", e));
+        }
+    }
+
+    // Apply the spending caps before a model call. Returns the model to use:
+    // unchanged, or `fallback_model` when a cap is exceeded in downgrade mode.
+    // Without a fallback an exceeded cap always blocks the call.
+    fn enforce_budget(
+        &self,
+        app: &tauri::AppHandle,
+        kind: AiTaskKind,
+        request_id: &str,
+        model: String,
+        fallback_model: Option<&str>,
+    ) -> Result<String, AiError> {
+        let config = self.app_config.lock().unwrap().budget.clone();
+        if !config.is_enabled() {
+            return Ok(model);
+        }
+        let session_id = self.active_session_id();
+        let today = chrono::Local::now().format("%Y-%m-%d").to_string();
+        let spent = self.with_session_store(|store| {
+            let daily = store.total_cost(&UsageFilters {
+                from: Some(today.clone()),
+                to: Some(today.clone()),
+                ..Default::default()
+            })?;
+            let session = match &session_id {
+                Some(id) => Some(store.total_cost(&UsageFilters {
+                    session_id: Some(id.clone()),
+                    ..Default::default()
+                })?),
+                None => None,
+            };
+            Ok((daily, session))
+        });
+        let (daily_spent, session_spent) = match spent {
+            Ok(spent) => spent,
+            Err(e) => {
+                // Spend cannot be measured; do not lock the user out over it
+                log_to_file(&format!("Budget: failed to load spend: {}", e));
+                return Ok(model);
+            }
+        };
+
+        match budget::evaluate(&config, daily_spent, session_spent) {
+            BudgetVerdict::Allow => Ok(model),
+            BudgetVerdict::Warn(status) => {
+                log_to_file(&format!(
+                    "Budget: warning {:?} spent={:.4} limit={:.2}",
+                    status.scope, status.spent_usd, status.limit_usd
+                ));
+                let _ = app.emit(
+                    BUDGET_WARNING_EVENT,
+                    BudgetEvent {
+                        request_id: request_id.to_string(),
+                        kind,
+                        status,
+                        action: None,
+                        model: model.clone(),
+                    },
+                );
+                Ok(model)
+            }
+            BudgetVerdict::Exceeded(status) => {
+                let message = status.describe();
+                let (action, model) = match (config.on_exceeded, fallback_model) {
+                    (BudgetAction::Downgrade, Some(fallback)) => {
+                        (BudgetAction::Downgrade, fallback.to_string())
+                    }
+                    // Nothing cheaper to switch to
+                    _ => (BudgetAction::Block, model),
+                };
+                log_to_file(&format!(
+                    "Budget: {} request_id={} action={:?}",
+                    message, request_id, action
+                ));
+                let _ = app.emit(
+                    BUDGET_EXCEEDED_EVENT,
+                    BudgetEvent {
+                        request_id: request_id.to_string(),
+                        kind,
+                        status,
+                        action: Some(action),
+                        model: model.clone(),
+                    },
+                );
+                match action {
+                    BudgetAction::Block => Err(AiError::new(ErrorCategory::Budget, message)),
+                    BudgetAction::Downgrade => Ok(model),
+                }
+            }
+        }
+    }
+
+    // Price and store one model call, returning the priced record. Usage is
+    // kept even outside a recorded session.
+    // `settings` are the ones the call was made with; their key is used to
+    // look up the billed cost when the provider reports a generation id.
+    fn record_usage(&self, mut record: UsageRecord, settings: &ProviderSettings) -> UsageRecord {
+        record.session_id = self.active_session_id();
+        let budget_enabled = {
+            let config = self.app_config.lock().unwrap();
+            record.apply_prices(&config.pricing);
+            config.budget.is_enabled()
+        };
+        let usage_id = match self.with_session_store(|store| store.add_usage(&record)) {
+            Ok(id) => Some(id),
+            Err(e) => {
+                log_to_file(&format!("Failed to record AI usage: {}", e));
+                None
+            }
+        };
+        if record.cost_source == CostSource::Unpriced {
+            if let Some(generation_id) = record.generation_id.clone() {
+                // Fetch the OpenRouter cost now so the next budget check sees it
+                if let Some(usage_id) = usage_id {
+                    let kind = AiProvider::parse(&record.provider).unwrap_or_default();
+                    let state = self.clone();
+                    let settings = settings.clone();
+                    tauri::async_runtime::spawn(async move {
+                        resolve_generation(&state, &kind, &settings, usage_id, &generation_id)
+                            .await;
+                    });
+                }
+            } else if budget_enabled {
+                log_to_file(&format!(
+                    "Budget: {} has no price in `pricing`; its spend does not count towards the limits",
+                    record.model
+                ));
+            }
+        }
+        record
+    }
+
+    fn record_answer(&self, record: AnswerRecord) {
+        let Some(id) = self.active_session_id() else {
+            return;
+        };
+        if let Err(e) = self.with_session_store(|store| store.add_answer(&id, &record)) {
+            log_to_file(&format!("Failed to record assistant answer: {}", e));
+        }
+    }
+}
+
+const AI_USAGE_EVENT: &str = "ai-usage";
+// OpenRouter publishes generation stats shortly after the response
+const GENERATION_COST_DELAY: Duration = Duration::from_secs(2);
+
+fn usage_record(
+    kind: AiTaskKind,
+    request_id: &str,
+    assistant_id: &str,
+    provider: &AiProvider,
+    model: &str,
+    response: &ChatResponse,
+) -> UsageRecord {
+    UsageRecord {
+        request_id: request_id.to_string(),
+        session_id: None,
+        kind,
+        assistant_id: assistant_id.to_string(),
+        provider: provider.as_str().to_string(),
+        model: model.to_string(),
+        tokens: response.usage.unwrap_or_default(),
+        cost_usd: None,
+        cost_source: CostSource::Unpriced,
+        generation_id: response.generation_id.clone(),
+        created_at: sessions::now_timestamp(),
+    }
+}
+
+// Build the provider backend, using the key sent by the UI over the stored one.
+fn provider_for(
+    provider_kind: &AiProvider,
+    api_key: &str,
+    state: &State<'_, AppState>,
+) -> Result<(Box<dyn LlmProvider>, ProviderSettings), AiError> {
+    let mut settings = state
+        .app_config
+        .lock()
+        .unwrap()
+        .provider_settings(provider_kind);
+    settings.api_key = api_key.trim().to_string();
+    let provider = llm::build_provider(provider_kind, &settings)?;
+    Ok((provider, settings))
+}
+
+// Backend for one assistant's calls. The assistant's `provider`, `model` and
+// `gate_model` win over the request (the provider and model picked in the
+// UI), which wins over the provider's section in config.json. Request models
+// and the UI's key only apply while the assistant stays on that provider.
+fn provider_for_assistant(
+    state: &AppState,
+    assistant: &Assistant,
+    requested: &AiProvider,
+    api_key: &str,
+    model: Option<String>,
+    gate_model: Option<String>,
+) -> Result<(Box<dyn LlmProvider>, AiProvider, ProviderSettings), AiError> {
+    let kind = assistant
+        .provider
+        .clone()
+        .unwrap_or_else(|| requested.clone());
+    let same = kind == *requested;
+    let mut settings = state.app_config.lock().unwrap().provider_settings(&kind);
+    if same {
+        settings.api_key = api_key.trim().to_string();
+    }
+    if let Some(model) = assistant.model.clone().or(model.filter(|_| same)) {
+        settings.model = model;
+    }
+    if let Some(gate_model) = assistant.gate_model.clone().or(gate_model.filter(|_| same)) {
+        settings.gate_model = gate_model;
+    }
+    let provider = llm::build_provider(&kind, &settings)?;
+    Ok((provider, kind, settings))
 }
 
 fn resolve_provider(provider: Option<String>, state: &State<'_, AppState>) -> AiProvider {
     if let Some(force) = provider {
-        match force.to_lowercase().as_str() {
-            "openrouter" => AiProvider::Openrouter,
-            _ => AiProvider::Openai,
-        }
+        AiProvider::parse(&force).unwrap_or_default()
     } else {
         let cfg = state.app_config.lock().unwrap();
         cfg.ui.ai_provider.clone()
@@ -561,6 +973,7 @@ fn start_recording(
             },
         };
         *state_for_thread.recording_session.lock().unwrap() = Some(session);
+        state_for_thread.begin_session(&finalize_path, &_target_format);
 
         // Enable writing initially
         *state_for_thread.is_writing_enabled.lock().unwrap() = true;
@@ -710,6 +1123,7 @@ fn arm_auto_recording(
                     .format(&format!("recording-%Y%m%d-%H%M%S.{}", file_extension))
                     .to_string();
                 base.push(ts);
+                state_for_thread.begin_session(&base, &target_format);
                 *path_guard = Some(base);
             }
         }
@@ -989,17 +1403,13 @@ fn arm_auto_recording(
                 }
             }
 
-            // Always send audio to Soniox for better context
+            // Always send audio to the transcriber for better context
             // Voice detection filtering will happen at the transcript display level
-            if let Ok(lock) = state_for_thread.soniox_tx.lock() {
-                if let Some(tx) = lock.as_ref() {
-                    let _ = tx.try_send(soniox::AudioChunk {
-                        samples: as_i16.to_vec(),
-                        channels: channels as u16,
-                        sample_rate: sample_rate as u32,
-                    });
-                }
-            }
+            state_for_thread.push_transcription_audio(AudioChunk {
+                samples: as_i16.to_vec(),
+                channels: channels as u16,
+                sample_rate: sample_rate as u32,
+            });
         };
 
         let stream = match config.sample_format() {
@@ -1110,6 +1520,7 @@ fn finalize_auto_recording(
 
     // Clear session path after finalization attempt
     *state.inner().vad_session_path.lock().unwrap() = None;
+    state.inner().finish_session();
     res
 }
 
@@ -1128,10 +1539,12 @@ fn stop_recording(state: State<AppState>) -> Result<String, String> {
 
     // Signal stop and wait for completion.
     let _ = tx.send(());
-    match done_rx.recv() {
+    let res = match done_rx.recv() {
         Ok(res) => res,
         Err(e) => Err(format!("Recording thread error: {e}")),
-    }
+    };
+    state.inner().finish_session();
+    res
 }
 
 // New pause/resume commands using the enhanced state management
@@ -1152,105 +1565,103 @@ fn get_recording_state(state: State<AppState>) -> Result<RecordingState, String>
     state.inner().get_current_state()
 }
 
-// Soniox Tauri commands
+// Transcription Tauri commands
 #[tauri::command]
-fn start_soniox_session(
+fn start_transcription_session(
     app: tauri::AppHandle,
     state: State<AppState>,
-    mut opts: soniox::SonioxOptions,
+    opts: transcription::TranscriptionOptions,
 ) -> Result<(), String> {
-    {
-        let mut tx_guard = state.inner().soniox_tx.lock().unwrap();
-        if let Some(existing) = tx_guard.as_ref() {
-            if existing.is_closed() {
-                log_to_file(
-                    "Transcription: detected stale Soniox sender — clearing before restart",
-                );
-                *tx_guard = None;
-            }
-        }
-    }
-
-    {
-        let mut ctrl_guard = state.inner().soniox_ctrl.lock().unwrap();
-        if let Some(existing) = ctrl_guard.as_ref() {
-            if existing.is_closed() {
-                log_to_file("Transcription: detected stale Soniox control channel — clearing");
-                *ctrl_guard = None;
-            }
+    let mut guard = state.inner().transcriber.lock().unwrap();
+    if let Some(existing) = guard.as_ref() {
+        if existing.is_running() {
+            return Err(format!(
+                "{:?} transcription session already running",
+                existing.kind()
+            ));
         }
+        log_to_file("Transcription: detected stale provider session — clearing before restart");
+        *guard = None;
     }
 
-    if state.inner().soniox_tx.lock().unwrap().is_some() {
-        return Err("Soniox session already running".into());
-    }
-    if opts.api_key.trim().is_empty() {
-        // Try environment variable
-        if let Ok(k) = std::env::var("SONIOX_API_KEY") {
-            opts.api_key = k;
-        }
-        // Try config file: config/soniox.local.json
-        if opts.api_key.trim().is_empty() {
-            let mut cfg_path =
-                std::env::current_dir().unwrap_or_else(|_| std::path::PathBuf::from("."));
-            cfg_path.push("config/soniox.local.json");
-            if let Ok(txt) = std::fs::read_to_string(&cfg_path) {
-                if let Ok(json) = serde_json::from_str::<serde_json::Value>(&txt) {
-                    if let Some(k) = json.get("api_key").and_then(|v| v.as_str()) {
-                        opts.api_key = k.to_string();
-                    }
-                }
-            }
-        }
-        if opts.api_key.trim().is_empty() {
-            return Err("Missing Soniox API key. Provide api_key, SONIOX_API_KEY env, or config/soniox.local.json".into());
-        }
-    }
+    let mut provider = transcription::build_provider(opts);
     log_to_file(&format!(
-        "Transcription: starting provider Soniox with key: {}...",
-        &opts.api_key[..8]
+        "Transcription: starting provider {:?}",
+        provider.kind()
     ));
-    let handle = tauri::async_runtime::block_on(
-        transcription::providers::soniox_adapter::start_session(app, opts),
-    )?;
-    *state.inner().soniox_tx.lock().unwrap() = Some(handle.tx);
-    if let Some(ctrl) = handle.ctrl {
-        *state.inner().soniox_ctrl.lock().unwrap() = Some(ctrl);
-    } else {
-        *state.inner().soniox_ctrl.lock().unwrap() = None;
-    }
-    *state.inner().transcriber_kind.lock().unwrap() = Some(transcription::ProviderKind::Soniox);
-    log_to_file("Transcription provider started: Soniox");
+    provider.start(app)?;
+    log_to_file(&format!(
+        "Transcription provider started: {:?}",
+        provider.kind()
+    ));
+    *guard = Some(provider);
     Ok(())
 }
 
 #[tauri::command]
-fn stop_soniox_session(state: State<AppState>) -> Result<(), String> {
-    *state.inner().soniox_tx.lock().unwrap() = None;
-    *state.inner().soniox_ctrl.lock().unwrap() = None;
-    *state.inner().transcriber_kind.lock().unwrap() = None;
+fn stop_transcription_session(state: State<AppState>) -> Result<(), String> {
+    if let Some(mut provider) = state.inner().transcriber.lock().unwrap().take() {
+        provider.stop();
+        log_to_file(&format!(
+            "Transcription provider stopped: {:?}",
+            provider.kind()
+        ));
+    }
     Ok(())
 }
 
 #[tauri::command]
 async fn clear_transcript_state(state: State<'_, AppState>) -> Result<(), String> {
-    let sender = state
+    let guard = state
         .inner()
-        .soniox_ctrl
+        .transcriber
         .lock()
-        .map_err(|_| "Failed to lock Soniox control channel".to_string())?
-        .clone();
+        .map_err(|_| "Failed to lock transcription provider".to_string())?;
 
-    if let Some(ctrl) = sender {
-        ctrl.send(SonioxControl::ClearTranscript)
-            .await
+    if let Some(provider) = guard.as_ref() {
+        provider
+            .clear()
             .map_err(|e| format!("Failed to clear transcript state: {}", e))?;
         log_to_file("Transcription control: cleared transcript state");
     }
+    state.turn_tracker.lock().unwrap().clear();
+    state.transcript_memory.lock().unwrap().clear();
+
+    Ok(())
+}
+
+#[tauri::command]
+async fn flush_transcription(state: State<'_, AppState>) -> Result<(), String> {
+    let guard = state
+        .inner()
+        .transcriber
+        .lock()
+        .map_err(|_| "Failed to lock transcription provider".to_string())?;
+
+    if let Some(provider) = guard.as_ref() {
+        provider
+            .flush()
+            .map_err(|e| format!("Failed to flush transcription: {}", e))?;
+        log_to_file("Transcription control: requested flush");
+    }
 
     Ok(())
 }
 
+// One analysis call; the fan-out in `stream_ai_analysis` runs several.
+#[derive(Clone)]
+struct AnalysisRequest {
+    model: Option<String>,
+    assistant_id: Option<String>,
+    request_id: String,
+    transcript: String,
+    // Summary and recent part, once the transcript was compacted
+    window: Option<TranscriptWindow>,
+    last_output: Option<String>,
+    // Previous pipeline step's output, for `{{input}}`
+    input: Option<String>,
+}
+
 #[tauri::command]
 async fn stream_ai_analysis(
     app: tauri::AppHandle,
@@ -1259,193 +1670,853 @@ async fn stream_ai_analysis(
     api_key: String,
     model: Option<String>,
     assistant_id: Option<String>,
+    assistant_ids: Option<Vec<String>>,
+    pipeline_id: Option<String>,
     request_id: String,
     transcript: String,
     last_output: Option<String>,
+    last_outputs: Option<HashMap<String, String>>,
 ) -> Result<(), String> {
-    let api_key = api_key.trim().to_string();
-    if api_key.is_empty() {
-        return Err("AI provider API key is required".into());
-    }
-
     let provider_kind = resolve_provider(provider, &state);
-
-    let (system_prompt, output_policy, user_prompt_template) = {
-        let manager = state.assistant_manager.lock().unwrap();
-        let assistant = if let Some(id) = assistant_id {
-            manager
-                .get_assistant(&id)
-                .unwrap_or_else(|| manager.get_default_assistant())
-        } else {
-            manager.get_default_assistant()
+    let (llm_provider, settings) = provider_for(&provider_kind, &api_key, &state)?;
+    let request = AnalysisRequest {
+        model,
+        assistant_id,
+        request_id,
+        transcript,
+        window: None,
+        last_output,
+        input: None,
+    };
+    // Compacted once here so a fan-out shares one summary call. It only runs
+    // while one of the requests waiting for it has not been cancelled.
+    let window = transcript_window(
+        &app,
+        &state,
+        llm_provider.as_ref(),
+        &provider_kind,
+        &settings,
+        &request,
+    )
+    .shared();
+    if let Some(pipeline_id) = pipeline_id {
+        let pipeline = state
+            .assistant_manager
+            .lock()
+            .unwrap()
+            .get_pipeline(&pipeline_id)
+            .cloned()
+            .ok_or_else(|| format!("Pipeline '{}' not found", pipeline_id))?;
+        // Registered once, so every step can be cancelled through the request id
+        let work = async {
+            let request = AnalysisRequest {
+                window: window.await,
+                ..request.clone()
+            };
+            run_pipeline(&app, &state, &provider_kind, &api_key, &pipeline, request).await
         };
-        (
-            assistant.system_prompt.clone(),
-            assistant.output_policy.clone(),
-            assistant.user_prompt.clone(),
+        return run_cancellable(&app, &state, &request.request_id, &pipeline.id, work)
+            .await
+            .map(|_| ());
+    }
+    let Some(assistant_ids) = assistant_ids.filter(|ids| !ids.is_empty()) else {
+        return run_analysis(
+            &app,
+            &state,
+            &provider_kind,
+            &api_key,
+            request.clone(),
+            window,
         )
+        .await;
     };
 
-    let selected_model = {
-        let config = state.app_config.lock().unwrap();
-        match provider_kind {
-            AiProvider::Openrouter => model.unwrap_or_else(|| config.openrouter.model.clone()),
-            AiProvider::Openai => model.unwrap_or_else(|| config.openai.model.clone()),
-        }
-    };
-
-    let trimmed_transcript = transcript.trim().to_string();
-
+    // Fan out to several assistants at once, each under "<request_id>:<assistant_id>"
+    let last_outputs = last_outputs.unwrap_or_default();
     log_to_file(&format!(
-        "AI(Main): provider={:?} model={} request_id={} transcript_len={} last_output_len={}",
-        provider_kind,
-        selected_model,
-        request_id,
-        trimmed_transcript.len(),
-        last_output.as_ref().map(|s| s.len()).unwrap_or(0)
+        "AI(Main): request_id={} fan-out to {:?}",
+        request.request_id, assistant_ids
     ));
-
-    if trimmed_transcript.is_empty() {
-        let payload = AiStreamChunk {
-            request_id,
-            segment: None,
-            final_text: Some("No conversation to analyze yet.".to_string()),
-            done: true,
+    let runs = assistant_ids.iter().map(|id| {
+        let request = AnalysisRequest {
+            model: request.model.clone(),
+            assistant_id: Some(id.clone()),
+            request_id: format!("{}:{}", request.request_id, id),
+            transcript: request.transcript.clone(),
+            window: request.window.clone(),
+            last_output: last_outputs
+                .get(id)
+                .cloned()
+                .or_else(|| request.last_output.clone()),
+            input: None,
         };
-        app.emit("ai-analysis-stream", payload)
-            .map_err(|e| format!("Failed to emit AI stream event: {}", e))?;
-        return Ok(());
+        run_analysis(
+            &app,
+            &state,
+            &provider_kind,
+            &api_key,
+            request,
+            window.clone(),
+        )
+    });
+    let results = futures_util::future::join_all(runs).await;
+    // Each failure was already reported through ai-analysis-error
+    let errors: Vec<String> = results.into_iter().filter_map(Result::err).collect();
+    if !errors.is_empty() && errors.len() == assistant_ids.len() {
+        return Err(errors.join("; "));
     }
+    Ok(())
+}
 
-    match provider_kind {
-        AiProvider::Openai => {
-            let opts = openai::OpenAIOptions {
-                api_key,
-                model: selected_model,
-                system_prompt: system_prompt.clone(),
-                output_policy: output_policy.clone(),
-                user_prompt: user_prompt_template.clone(),
-            };
-            match openai::analyze_conversation(opts, trimmed_transcript, last_output.clone()).await
-            {
-                Ok(result) => {
-                    log_to_file(&format!(
-                        "AI(Main): provider=OpenAI request_id={} final=<<<{}>>>",
-                        request_id, result
-                    ));
-                    let payload = AiStreamChunk {
-                        request_id,
-                        segment: None,
-                        final_text: Some(result.clone()),
-                        done: true,
-                    };
-                    app.emit("ai-analysis-stream", payload)
-                        .map_err(|e| format!("Failed to emit AI stream event: {}", e))?;
-                    Ok(())
-                }
-                Err(err) => {
-                    let payload = AiStreamError {
-                        request_id,
-                        message: err.clone(),
-                    };
-                    app.emit("ai-analysis-error", payload)
-                        .map_err(|e| format!("Failed to emit AI error event: {}", e))?;
-                    Err(err)
-                }
-            }
-        }
-        AiProvider::Openrouter => {
-            let messages = openrouter::compose_messages(
-                &system_prompt,
-                &output_policy,
-                &user_prompt_template,
-                &trimmed_transcript,
-                last_output.as_deref(),
-            );
-            let request = openrouter::build_chat_request(&selected_model, messages, 500)?;
-            let client = openrouter::build_client(&api_key)?;
-            let mut stream = client.stream_chat_completion(&request).await.map_err(|e| {
-                log_to_file(&format!("OpenRouter: Stream error: {}", e));
-                format!("OpenRouter stream error: {}", e)
-            })?;
-
-            let mut full_text = String::new();
-            while let Some(event) = stream.next().await {
-                match event {
-                    Ok(chunk) => {
-                        if let Some(content) =
-                            chunk.choices.first().and_then(|choice| choice.content())
-                        {
-                            if content.is_empty() {
-                                continue;
-                            }
-                            full_text.push_str(content);
-                            log_to_file(&format!(
-                                "OpenRouter(Main): request_id={} chunk=<<<{}>>>",
-                                request_id, content
-                            ));
-                            let payload = AiStreamChunk {
-                                request_id: request_id.clone(),
-                                segment: Some(content.to_string()),
-                                final_text: None,
-                                done: false,
-                            };
-                            app.emit("ai-analysis-stream", payload)
-                                .map_err(|e| format!("Failed to emit AI stream event: {}", e))?;
-                        }
+// Summary + recent view of a long transcript, compacting older speech with
+// the summary model first when it has outgrown the threshold. None while
+// memory is off or the transcript is still short.
+async fn transcript_window(
+    app: &tauri::AppHandle,
+    state: &AppState,
+    llm_provider: &dyn LlmProvider,
+    provider_kind: &AiProvider,
+    settings: &ProviderSettings,
+    request: &AnalysisRequest,
+) -> Option<TranscriptWindow> {
+    let model = request.model.as_deref().unwrap_or(&settings.model);
+    let transcript = request.transcript.trim();
+    let request_id = &request.request_id;
+    let config = state.app_config.lock().unwrap().memory.clone();
+    if !config.enabled
+        || memory::estimate_tokens(model, transcript) <= memory::compact_threshold(&config, model)
+    {
+        return None;
+    }
+    let job = state
+        .transcript_memory
+        .lock()
+        .unwrap()
+        .plan_compaction(&config, model);
+    if let Some(job) = job {
+        let slot = CompactionSlot {
+            memory: &state.transcript_memory,
+            job: Some(job),
+        };
+        let job = slot.job();
+        let summary_request_id = format!("{}:{}", request_id, MEMORY_ID);
+        let summary_model = config
+            .summary_model
+            .clone()
+            .unwrap_or_else(|| settings.gate_model.clone());
+        let summary = match state.enforce_budget(
+            app,
+            AiTaskKind::Summary,
+            &summary_request_id,
+            summary_model.clone(),
+            Some(&summary_model),
+        ) {
+            Ok(summary_model) => {
+                match memory::summarize(
+                    llm_provider,
+                    &summary_model,
+                    job,
+                    config.summary_max_tokens,
+                )
+                .await
+                {
+                    Ok(response) => {
+                        let usage = state.record_usage(
+                            usage_record(
+                                AiTaskKind::Summary,
+                                &summary_request_id,
+                                MEMORY_ID,
+                                provider_kind,
+                                &summary_model,
+                                &response,
+                            ),
+                            settings,
+                        );
+                        let _ = app.emit(AI_USAGE_EVENT, usage);
+                        Some(response.text)
                     }
                     Err(e) => {
-                        let message = format!("OpenRouter stream chunk error: {}", e);
-                        log_to_file(&message);
-                        let payload = AiStreamError {
-                            request_id: request_id.clone(),
-                            message: message.clone(),
-                        };
-                        app.emit("ai-analysis-error", payload).map_err(|emit_err| {
-                            format!("Failed to emit AI error event: {}", emit_err)
-                        })?;
-                        return Err(message);
+                        log_to_file(&format!("AI(Memory): compaction failed: {}", e));
+                        None
                     }
                 }
             }
+            Err(e) => {
+                log_to_file(&format!("AI(Memory): compaction skipped: {}", e));
+                None
+            }
+        };
+        slot.apply(summary);
+    }
+    let window = state.transcript_memory.lock().unwrap().window();
+    if let Some(window) = &window {
+        log_to_file(&format!(
+            "AI(Memory): request_id={} transcript_len={} summary_len={} recent_len={}",
+            request_id,
+            transcript.len(),
+            window.summary.len(),
+            window.recent.len()
+        ));
+    }
+    window
+}
 
-            let payload = AiStreamChunk {
-                request_id,
-                segment: None,
-                final_text: Some(full_text),
-                done: true,
-            };
-            log_to_file(&format!(
-                "OpenRouter(Main): request_id={} final=<<<{}>>>",
-                payload.request_id,
-                payload.final_text.as_deref().unwrap_or("")
-            ));
-            app.emit("ai-analysis-stream", payload)
-                .map_err(|e| format!("Failed to emit AI stream event: {}", e))?;
-            Ok(())
+// A planned compaction, handed back to the memory even when the summary call
+// is cancelled so later requests can compact again.
+struct CompactionSlot<'a> {
+    memory: &'a Mutex<TranscriptMemory>,
+    job: Option<CompactionJob>,
+}
+
+impl CompactionSlot<'_> {
+    fn job(&self) -> &CompactionJob {
+        self.job.as_ref().expect("compaction job already applied")
+    }
+
+    fn apply(mut self, summary: Option<String>) {
+        if let Some(job) = self.job.take() {
+            self.memory.lock().unwrap().apply(job, summary);
         }
     }
 }
 
-#[tauri::command]
-async fn analyze_with_openai(
-    transcript: String,
-    api_key: String,
-    model: Option<String>,
-    assistant_id: Option<String>,
-    last_output: Option<String>,
-    state: State<'_, AppState>,
-) -> Result<String, String> {
-    if api_key.is_empty() {
-        return Err("OpenAI API key is required".to_string());
+impl Drop for CompactionSlot<'_> {
+    fn drop(&mut self) {
+        if let Some(job) = self.job.take() {
+            if let Ok(mut memory) = self.memory.lock() {
+                memory.apply(job, None);
+            }
+        }
     }
+}
 
-    let (system_prompt, output_policy, user_prompt) = {
-        let manager = state.assistant_manager.lock().unwrap();
-        let assistant = if let Some(id) = assistant_id {
-            manager
-                .get_assistant(&id)
-                .unwrap_or_else(|| manager.get_default_assistant())
+// Runs one analysis command's work under `request_id`, so cancel_ai_request
+// stops it at any point: retrieval, compaction or the model call. Ok(None)
+// when it was cancelled; that is already reported to the UI.
+async fn run_cancellable<T>(
+    app: &tauri::AppHandle,
+    state: &AppState,
+    request_id: &str,
+    assistant_id: &str,
+    work: impl std::future::Future<Output = Result<T, String>>,
+) -> Result<Option<T>, String> {
+    let registration = {
+        let supersede = state.app_config.lock().unwrap().ui.auto_cancel_previous;
+        state.ai_tasks.lock().unwrap().start(
+            request_id,
+            AiTaskKind::Analysis,
+            assistant_id,
+            supersede,
+        )
+    };
+    let outcome = Abortable::new(work, registration).await;
+    let cancelled = state.ai_tasks.lock().unwrap().finish(request_id);
+    match (outcome, cancelled) {
+        (Ok(result), None) => result.map(Some),
+        (_, Some(cancelled)) => {
+            log_to_file(&format!(
+                "AI(Main): request_id={} cancelled ({:?})",
+                request_id, cancelled.reason
+            ));
+            app.emit(AI_CANCELLED_EVENT, cancelled)
+                .map_err(|e| format!("Failed to emit AI cancelled event: {}", e))?;
+            Ok(None)
+        }
+        (Err(_), None) => Err(CANCELLED_ERROR.to_string()),
+    }
+}
+
+// `requested` and `api_key` are the UI's provider and key; the assistant may
+// use another provider (see `provider_for_assistant`). `window` is the shared
+// transcript compaction, awaited once the request is registered.
+async fn run_analysis(
+    app: &tauri::AppHandle,
+    state: &AppState,
+    requested: &AiProvider,
+    api_key: &str,
+    request: AnalysisRequest,
+    window: impl std::future::Future<Output = Option<TranscriptWindow>>,
+) -> Result<(), String> {
+    let assistant = {
+        let manager = state.assistant_manager.lock().unwrap();
+        let assistant = if let Some(id) = &request.assistant_id {
+            manager
+                .get_assistant(id)
+                .unwrap_or_else(|| manager.get_default_assistant())
+        } else {
+            manager.get_default_assistant()
+        };
+        assistant.clone()
+    };
+    let request_id = request.request_id.clone();
+    let assistant_id = assistant.id.clone();
+    let work = async {
+        let request = AnalysisRequest {
+            window: window.await,
+            ..request
+        };
+        stream_assistant(app, state, requested, api_key, assistant, request).await
+    };
+    run_cancellable(app, state, &request_id, &assistant_id, work)
+        .await
+        .map(|_| ())
+}
+
+// Runs a pipeline's steps in order. Intermediate outputs are logged and
+// stored under "<request_id>:step<N>" without reaching the UI; the last step
+// streams under the request id like a single assistant. The caller registers
+// the request id once for all steps (see `run_cancellable`).
+async fn run_pipeline(
+    app: &tauri::AppHandle,
+    state: &AppState,
+    requested: &AiProvider,
+    api_key: &str,
+    pipeline: &Pipeline,
+    mut request: AnalysisRequest,
+) -> Result<(), String> {
+    let mut steps = state
+        .assistant_manager
+        .lock()
+        .unwrap()
+        .pipeline_steps(pipeline);
+    let Some(last) = steps.pop() else {
+        return Err(format!("Pipeline '{}' has no steps", pipeline.id));
+    };
+    let total = steps.len() + 1;
+    log_to_file(&format!(
+        "AI(Pipeline): request_id={} pipeline={} steps={}",
+        request.request_id, pipeline.id, total
+    ));
+    // An empty transcript is answered by the last step without model calls
+    if request.transcript.trim().is_empty() {
+        steps.clear();
+    }
+
+    for (index, assistant) in steps.iter().enumerate() {
+        let step_id = format!("{}:step{}", request.request_id, index + 1);
+        let output = match run_pipeline_step(
+            app, state, requested, api_key, assistant, &request, &step_id,
+        )
+        .await
+        {
+            Ok(output) => output,
+            Err(err) => {
+                log_to_file(&format!(
+                    "AI(Pipeline): request_id={} step {}/{} failed: {}",
+                    request.request_id,
+                    index + 1,
+                    total,
+                    err
+                ));
+                let payload = AiStreamError {
+                    request_id: request.request_id.clone(),
+                    message: format!(
+                        "Pipeline step {} ({}): {}",
+                        index + 1,
+                        assistant.id,
+                        err.message
+                    ),
+                    category: err.category,
+                    status: err.status,
+                };
+                app.emit("ai-analysis-error", payload)
+                    .map_err(|e| format!("Failed to emit AI error event: {}", e))?;
+                return Err(err.into());
+            }
+        };
+        log_to_file(&format!(
+            "AI(Pipeline): request_id={} step {}/{} ({}) output=<<<{}>>>",
+            step_id,
+            index + 1,
+            total,
+            assistant.id,
+            output
+        ));
+        request.input = Some(output);
+    }
+    stream_assistant(app, state, requested, api_key, last, request).await
+}
+
+// One intermediate pipeline step, without streaming, stored and billed under
+// `step_id`.
+async fn run_pipeline_step(
+    app: &tauri::AppHandle,
+    state: &AppState,
+    requested: &AiProvider,
+    api_key: &str,
+    assistant: &Assistant,
+    request: &AnalysisRequest,
+    step_id: &str,
+) -> Result<String, AiError> {
+    let started_at = sessions::now_timestamp();
+    let (llm_provider, provider_kind, settings, chat_request) =
+        prepare_analysis(app, state, requested, api_key, assistant, request).await?;
+    llm::log_main_prompt(llm_provider.label(), &chat_request);
+
+    let response = llm_provider.chat(&chat_request).await?;
+    state.record_answer(AnswerRecord {
+        request_id: step_id.to_string(),
+        assistant_id: assistant.id.clone(),
+        provider: provider_kind.as_str().to_string(),
+        model: chat_request.model.clone(),
+        answer: response.text.clone(),
+        data: structured_data(assistant, step_id, &response.text),
+        started_at,
+        completed_at: sessions::now_timestamp(),
+    });
+    let usage = state.record_usage(
+        usage_record(
+            AiTaskKind::Analysis,
+            step_id,
+            &assistant.id,
+            &provider_kind,
+            &chat_request.model,
+            &response,
+        ),
+        &settings,
+    );
+    let _ = app.emit(AI_USAGE_EVENT, usage);
+    Ok(response.text)
+}
+
+// Chunks of the assistant's documents to embed per request
+const EMBEDDING_BATCH: usize = 64;
+// Usage label for embeddings served by a base_url other than OpenAI's
+const OPENAI_COMPATIBLE: &str = "openai_compatible";
+
+// `{{context}}` for one analysis: the assistant's own context followed by the
+// document chunks that best match the latest transcript turn.
+async fn retrieve_context(
+    app: &tauri::AppHandle,
+    state: &AppState,
+    requested: &AiProvider,
+    api_key: &str,
+    assistant: &Assistant,
+    request: &AnalysisRequest,
+) -> String {
+    if assistant.documents.is_empty() {
+        return assistant.context.clone();
+    }
+    let config = assistant.retrieval.clone().unwrap_or_default();
+    let base_dir = Path::new(ASSISTANTS_CONFIG_PATH)
+        .parent()
+        .unwrap_or(Path::new("."));
+    let paths = retrieval::resolve_paths(base_dir, &assistant.documents);
+    let index = match state
+        .document_indexes
+        .get(&assistant.id, &paths, &config)
+        .await
+    {
+        Ok(index) => index,
+        Err(e) => {
+            log_to_file(&format!("Retrieval: {}", e));
+            return assistant.context.clone();
+        }
+    };
+
+    let query = {
+        let segments = state.transcript_memory.lock().unwrap().segments().to_vec();
+        let vars = PromptVars {
+            window: request.window.as_ref(),
+            segments: &segments,
+            ..PromptVars::new(request.transcript.trim())
+        };
+        templates::latest_turn(&vars)
+    };
+    if retrieval::tokenize(&query).is_empty() {
+        return assistant.context.clone();
+    }
+    let keyword = index.keyword_search(&query, index.chunks.len());
+    let mut order: Vec<usize> = keyword.iter().map(|(i, _)| *i).collect();
+    if let Some(model) = &config.embedding_model {
+        let record = |usage: TokenUsage, settings: &ProviderSettings| {
+            // Other base_urls are OpenAI-compatible servers, often local and
+            // free; they count only when `pricing` lists the model
+            let (provider, cost_source) = if openai::is_default_base_url(&settings.base_url) {
+                (AiProvider::Openai.as_str(), CostSource::Unpriced)
+            } else {
+                (OPENAI_COMPATIBLE, CostSource::SelfHosted)
+            };
+            let record = state.record_usage(
+                UsageRecord {
+                    request_id: request.request_id.clone(),
+                    session_id: None,
+                    kind: AiTaskKind::Embedding,
+                    assistant_id: assistant.id.clone(),
+                    provider: provider.to_string(),
+                    model: model.clone(),
+                    tokens: usage,
+                    cost_usd: None,
+                    cost_source,
+                    generation_id: None,
+                    created_at: sessions::now_timestamp(),
+                },
+                settings,
+            );
+            let _ = app.emit(AI_USAGE_EVENT, record);
+        };
+        // There is no cheaper embedding model, so an exceeded cap skips them
+        let embedded = match state.enforce_budget(
+            app,
+            AiTaskKind::Embedding,
+            &request.request_id,
+            model.clone(),
+            None,
+        ) {
+            Ok(_) => {
+                embed_for_retrieval(state, requested, api_key, &index, model, &query, record).await
+            }
+            Err(e) => Err(e),
+        };
+        match embedded {
+            Ok((query_vector, chunk_vectors)) => {
+                order =
+                    retrieval::hybrid_rank(&keyword, &query_vector, &chunk_vectors, config.top_k);
+            }
+            // Keep the keyword order
+            Err(e) => log_to_file(&format!(
+                "Retrieval: embeddings with {} failed for {}, using BM25 only: {}",
+                model, assistant.id, e
+            )),
+        }
+    }
+    order.truncate(config.top_k);
+    let chunks: Vec<&retrieval::Chunk> = order.iter().map(|i| &index.chunks[*i]).collect();
+    log_to_file(&format!(
+        "Retrieval: request_id={} assistant={} query_len={} chunks={:?}",
+        request.request_id,
+        assistant.id,
+        query.len(),
+        chunks
+            .iter()
+            .map(|c| format!("{}#{}", c.source, c.index))
+            .collect::<Vec<_>>()
+    ));
+    [
+        assistant.context.trim(),
+        &retrieval::render_context(&chunks),
+    ]
+    .into_iter()
+    .filter(|part| !part.is_empty())
+    .collect::<Vec<_>>()
+    .join("\n\n")
+}
+
+// The query's embedding and the index's chunk embeddings, computed once per
+// index. Always served by the openai section of config.json (or its
+// OpenAI-compatible base_url); the UI key is used when OpenAI is selected.
+// `record` receives the tokens of every call and the settings it used.
+async fn embed_for_retrieval(
+    state: &AppState,
+    requested: &AiProvider,
+    api_key: &str,
+    index: &DocumentIndex,
+    model: &str,
+    query: &str,
+    record: impl Fn(TokenUsage, &ProviderSettings),
+) -> Result<(Vec<f32>, Arc<Vec<Vec<f32>>>), AiError> {
+    let mut settings = state
+        .app_config
+        .lock()
+        .unwrap()
+        .provider_settings(&AiProvider::Openai);
+    if *requested == AiProvider::Openai {
+        settings.api_key = api_key.trim().to_string();
+    }
+    let provider =
+        openai::OpenAiProvider::new(&settings.api_key, &settings.base_url, settings.timeout());
+    let chunk_vectors = match index.cached_embeddings() {
+        Some(vectors) => vectors,
+        None => {
+            let texts: Vec<String> = index.chunks.iter().map(|c| c.text.clone()).collect();
+            let mut vectors = Vec::with_capacity(texts.len());
+            for batch in texts.chunks(EMBEDDING_BATCH) {
+                let (batch_vectors, usage) = provider.embeddings(model, batch).await?;
+                record(usage, &settings);
+                vectors.extend(batch_vectors);
+            }
+            index.store_embeddings(vectors)
+        }
+    };
+    let (mut query_vectors, usage) = provider.embeddings(model, &[query.to_string()]).await?;
+    record(usage, &settings);
+    Ok((query_vectors.pop().unwrap_or_default(), chunk_vectors))
+}
+
+// Provider, resolved kind, its settings and chat request for one assistant's
+// analysis. The budget is checked before `{{context}}` is retrieved, so a
+// blocked analysis spends nothing on embeddings.
+type PreparedAnalysis = (
+    Box<dyn LlmProvider>,
+    AiProvider,
+    ProviderSettings,
+    llm::ChatRequest,
+);
+
+async fn prepare_analysis(
+    app: &tauri::AppHandle,
+    state: &AppState,
+    requested: &AiProvider,
+    api_key: &str,
+    assistant: &Assistant,
+    request: &AnalysisRequest,
+) -> Result<PreparedAnalysis, AiError> {
+    let (llm_provider, provider_kind, settings) = provider_for_assistant(
+        state,
+        assistant,
+        requested,
+        api_key,
+        request.model.clone(),
+        None,
+    )?;
+    let selected_model = state.enforce_budget(
+        app,
+        AiTaskKind::Analysis,
+        &request.request_id,
+        settings.model.clone(),
+        Some(&settings.gate_model),
+    )?;
+    let context = retrieve_context(app, state, requested, api_key, assistant, request).await;
+
+    let transcript = request.transcript.trim();
+    let segments = state.transcript_memory.lock().unwrap().segments().to_vec();
+    let session_title = state.active_session_title();
+    let vars = PromptVars {
+        window: request.window.as_ref(),
+        last_output: request.last_output.as_deref(),
+        segments: &segments,
+        session_title: session_title.as_deref(),
+        context: &context,
+        input: request.input.as_deref(),
+        ..PromptVars::new(transcript)
+    };
+    // The schema also goes into the prompt for providers without a schema mode
+    let output_policy = match &assistant.output_schema {
+        Some(schema) => format!(
+            "{}\n\n{}",
+            assistant.output_policy,
+            structured::schema_instruction(schema)
+        )
+        .trim()
+        .to_string(),
+        None => assistant.output_policy.clone(),
+    };
+    let chat_request = llm::ChatRequest {
+        model: selected_model,
+        messages: llm::compose_messages(
+            &assistant.system_prompt,
+            &output_policy,
+            &assistant.user_prompt,
+            &vars,
+        ),
+        max_tokens: assistant.max_tokens.unwrap_or(DEFAULT_MAX_TOKENS),
+        temperature: assistant.temperature.unwrap_or(0.0),
+        response_format: assistant
+            .output_schema
+            .as_ref()
+            .map(|schema| structured::response_format(&assistant.id, schema)),
+        top_p: assistant.top_p,
+        stop: assistant.stop.clone().unwrap_or_default(),
+    };
+    Ok((llm_provider, provider_kind, settings, chat_request))
+}
+
+// The answer parsed against the assistant's `output_schema`; None without a
+// schema or when the answer does not fit it (the text is still delivered).
+fn structured_data(
+    assistant: &Assistant,
+    request_id: &str,
+    text: &str,
+) -> Option<serde_json::Value> {
+    let schema = assistant.output_schema.as_ref()?;
+    structured::parse_output(text, schema)
+        .map_err(|e| {
+            log_to_file(&format!(
+                "AI(Main): request_id={} answer does not match the output schema of {}: {}",
+                request_id, assistant.id, e
+            ));
+        })
+        .ok()
+}
+
+// Streams one assistant's answer under the request id: deltas and the final
+// text on ai-analysis-stream, failures on ai-analysis-error.
+async fn stream_assistant(
+    app: &tauri::AppHandle,
+    state: &AppState,
+    requested: &AiProvider,
+    api_key: &str,
+    assistant: Assistant,
+    request: AnalysisRequest,
+) -> Result<(), String> {
+    let started_at = sessions::now_timestamp();
+    let resolved_assistant_id = assistant.id.clone();
+    let request_id = request.request_id.clone();
+    let trimmed_transcript = request.transcript.trim().to_string();
+
+    if trimmed_transcript.is_empty() {
+        let payload = AiStreamChunk {
+            request_id,
+            assistant_id: resolved_assistant_id,
+            segment: None,
+            final_text: Some("No conversation to analyze yet.".to_string()),
+            data: None,
+            done: true,
+        };
+        app.emit("ai-analysis-stream", payload)
+            .map_err(|e| format!("Failed to emit AI stream event: {}", e))?;
+        return Ok(());
+    }
+
+    let last_output_len = request.last_output.as_ref().map(|s| s.len()).unwrap_or(0);
+    let (llm_provider, provider_kind, settings, request) =
+        match prepare_analysis(app, state, requested, api_key, &assistant, &request).await {
+            Ok(prepared) => prepared,
+            Err(err) => {
+                let payload = AiStreamError {
+                    request_id: request_id.clone(),
+                    message: err.message.clone(),
+                    category: err.category,
+                    status: err.status,
+                };
+                app.emit("ai-analysis-error", payload)
+                    .map_err(|e| format!("Failed to emit AI error event: {}", e))?;
+                return Err(err.into());
+            }
+        };
+
+    log_to_file(&format!(
+        "AI(Main): provider={:?} model={} request_id={} transcript_len={} last_output_len={}",
+        provider_kind,
+        request.model,
+        request_id,
+        trimmed_transcript.len(),
+        last_output_len
+    ));
+
+    let answer_record = |answer: &str, data: Option<serde_json::Value>| AnswerRecord {
+        request_id: request_id.clone(),
+        assistant_id: resolved_assistant_id.clone(),
+        provider: provider_kind.as_str().to_string(),
+        model: request.model.clone(),
+        answer: answer.to_string(),
+        data,
+        started_at: started_at.clone(),
+        completed_at: sessions::now_timestamp(),
+    };
+
+    let label = llm_provider.label();
+    llm::log_main_prompt(label, &request);
+
+    let app_for_stream = app.clone();
+    let stream_request_id = request_id.clone();
+    let mut on_delta = |content: &str| {
+        log_to_file(&format!(
+            "{}(Main): request_id={} chunk=<<<{}>>>",
+            label, stream_request_id, content
+        ));
+        let payload = AiStreamChunk {
+            request_id: stream_request_id.clone(),
+            assistant_id: resolved_assistant_id.clone(),
+            segment: Some(content.to_string()),
+            final_text: None,
+            data: None,
+            done: false,
+        };
+        app_for_stream
+            .emit("ai-analysis-stream", payload)
+            .map_err(|e| format!("Failed to emit AI stream event: {}", e))
+    };
+    let streamed = match llm_provider.chat_stream(&request, &mut on_delta).await {
+        // Some models and local servers reject structured output; ask
+        // again without it and rely on the prompt
+        Err(e)
+            if request.response_format.is_some()
+                && matches!(e.status, Some(400 | 422))
+                && e.category != ErrorCategory::ModelNotFound =>
+        {
+            log_to_file(&format!(
+                "{}(Main): request_id={} structured output rejected ({}); retrying without it",
+                label, request_id, e
+            ));
+            let plain = llm::ChatRequest {
+                response_format: None,
+                ..request.clone()
+            };
+            llm_provider.chat_stream(&plain, &mut on_delta).await
+        }
+        other => other,
+    };
+    match streamed {
+        Ok(response) => {
+            log_to_file(&format!(
+                "AI(Main): provider={} request_id={} usage={:?} final=<<<{}>>>",
+                label, request_id, response.usage, response.text
+            ));
+            let data = structured_data(&assistant, &request_id, &response.text);
+            state.record_answer(answer_record(&response.text, data.clone()));
+            let usage = state.record_usage(
+                usage_record(
+                    AiTaskKind::Analysis,
+                    &request_id,
+                    &resolved_assistant_id,
+                    &provider_kind,
+                    &request.model,
+                    &response,
+                ),
+                &settings,
+            );
+            let _ = app.emit(AI_USAGE_EVENT, usage);
+            let payload = AiStreamChunk {
+                request_id: request_id.clone(),
+                assistant_id: resolved_assistant_id.clone(),
+                segment: None,
+                final_text: Some(response.text),
+                data,
+                done: true,
+            };
+            app.emit("ai-analysis-stream", payload)
+                .map_err(|e| format!("Failed to emit AI stream event: {}", e))?;
+            Ok(())
+        }
+        Err(err) => {
+            let payload = AiStreamError {
+                request_id: request_id.clone(),
+                message: err.message.clone(),
+                category: err.category,
+                status: err.status,
+            };
+            app.emit("ai-analysis-error", payload)
+                .map_err(|e| format!("Failed to emit AI error event: {}", e))?;
+            Err(err.into())
+        }
+    }
+}
+
+#[tauri::command]
+async fn analyze_with_openai(
+    transcript: String,
+    api_key: String,
+    model: Option<String>,
+    assistant_id: Option<String>,
+    last_output: Option<String>,
+    state: State<'_, AppState>,
+) -> Result<String, String> {
+    let settings = state
+        .app_config
+        .lock()
+        .unwrap()
+        .provider_settings(&AiProvider::Openai);
+    if api_key.trim().is_empty() && settings.require_api_key {
+        return Err("OpenAI API key is required".to_string());
+    }
+
+    let (system_prompt, output_policy, user_prompt, context) = {
+        let manager = state.assistant_manager.lock().unwrap();
+        let assistant = if let Some(id) = assistant_id {
+            manager
+                .get_assistant(&id)
+                .unwrap_or_else(|| manager.get_default_assistant())
         } else {
             manager.get_default_assistant()
         };
@@ -1453,15 +2524,19 @@ async fn analyze_with_openai(
             assistant.system_prompt.clone(),
             assistant.output_policy.clone(),
             assistant.user_prompt.clone(),
+            assistant.context.clone(),
         )
     };
 
     let opts = openai::OpenAIOptions {
         api_key,
-        model: model.unwrap_or_else(|| "gpt-4.1".to_string()),
+        base_url: settings.base_url,
+        model: model.unwrap_or(settings.model),
         system_prompt,
         output_policy,
         user_prompt,
+        context,
+        timeout_secs: settings.timeout_secs,
     };
 
     openai::analyze_conversation(opts, transcript, last_output).await
@@ -1473,13 +2548,49 @@ struct GateDecision {
     instruction: Option<String>,
     reason: Option<String>,
     confidence: Option<f32>,
+    source: GateSource,
+    parse_path: GateParse,
+}
+
+impl AppState {
+    // Store a gate decision with the session and shape it for the frontend.
+    fn finish_gate(
+        &self,
+        assistant_id: String,
+        model: String,
+        gate_json: GateJson,
+        source: GateSource,
+        parse_path: GateParse,
+    ) -> GateDecision {
+        self.record_gate_decision(GateRecord {
+            assistant_id,
+            model,
+            run: gate_json.run,
+            instruction: gate_json.instruction.clone(),
+            reason: gate_json.reason.clone(),
+            confidence: gate_json.confidence,
+            created_at: sessions::now_timestamp(),
+            parse_path: Some(parse_path),
+        });
+        GateDecision {
+            run: gate_json.run,
+            instruction: gate_json.instruction,
+            reason: gate_json.reason,
+            confidence: gate_json.confidence,
+            source,
+            parse_path,
+        }
+    }
 }
 
 #[derive(Serialize, Clone)]
 struct AiStreamChunk {
     request_id: String,
+    assistant_id: String,
     segment: Option<String>,
     final_text: Option<String>,
+    // Final answer parsed against the assistant's output_schema
+    data: Option<serde_json::Value>,
     done: bool,
 }
 
@@ -1487,10 +2598,13 @@ struct AiStreamChunk {
 struct AiStreamError {
     request_id: String,
     message: String,
+    category: ErrorCategory,
+    status: Option<u16>,
 }
 
 #[tauri::command]
 async fn should_run_analysis_gate(
+    app: tauri::AppHandle,
     provider: Option<String>,
     api_key: String,
     model: Option<String>,
@@ -1498,15 +2612,13 @@ async fn should_run_analysis_gate(
     current_transcript: String,
     previous_transcript: String,
     last_output: Option<String>,
+    request_id: Option<String>,
     state: State<'_, AppState>,
-) -> Result<GateDecision, String> {
-    if api_key.trim().is_empty() {
-        return Err("AI provider API key is required".to_string());
-    }
-
-    let provider_kind = resolve_provider(provider, &state);
+) -> Result<GateDecision, AiError> {
+    let request_id = request_id.unwrap_or_else(|| ai_tasks::generated_request_id("gate"));
+    let requested = resolve_provider(provider, &state);
 
-    let (system_prompt, gate_instructions) = {
+    let assistant = {
         let manager = state.assistant_manager.lock().unwrap();
         let assistant = if let Some(id) = assistant_id {
             manager
@@ -1515,89 +2627,290 @@ async fn should_run_analysis_gate(
         } else {
             manager.get_default_assistant()
         };
-        (
-            assistant.system_prompt.clone(),
-            assistant.gate_instructions.clone(),
+        assistant.clone()
+    };
+    let resolved_assistant_id = assistant.id.clone();
+    let gate_mode = assistant.gate_mode;
+    // `model` is the UI's gate model override
+    let (llm_provider, provider_kind, settings) =
+        provider_for_assistant(&state, &assistant, &requested, &api_key, None, model)?;
+
+    let turn = state.turn_tracker.lock().unwrap().take();
+    let local = local_gate::evaluate(&current_transcript, &previous_transcript, turn);
+    log_to_file(&format!(
+        "AI(Gate): request_id={} mode={:?} local run={} clear={} signals={:?}",
+        request_id, gate_mode, local.decision.run, local.clear, local.signals
+    ));
+    let use_local = match gate_mode {
+        GateMode::Llm => false,
+        GateMode::Local => true,
+        GateMode::Hybrid => local.clear,
+    };
+    if use_local {
+        return Ok(state.finish_gate(
+            resolved_assistant_id,
+            "local".into(),
+            local.decision,
+            GateSource::Local,
+            GateParse::Local,
+        ));
+    }
+
+    // The gate already runs on the cheap model, so downgrading keeps it as is
+    let selected_model = settings.gate_model.clone();
+    let selected_model = state.enforce_budget(
+        &app,
+        AiTaskKind::Gate,
+        &request_id,
+        selected_model.clone(),
+        Some(&selected_model),
+    )?;
+
+    let opts = gate::GateOptions {
+        model: selected_model.clone(),
+        main_system_prompt: assistant.system_prompt.clone(),
+        gate_instructions: assistant.gate_instructions.clone(),
+    };
+    let registration = {
+        let supersede = state.app_config.lock().unwrap().ui.auto_cancel_previous;
+        state.ai_tasks.lock().unwrap().start(
+            &request_id,
+            AiTaskKind::Gate,
+            &resolved_assistant_id,
+            supersede,
         )
     };
+    let outcome = Abortable::new(
+        gate::should_run_gate(
+            llm_provider.as_ref(),
+            opts,
+            current_transcript,
+            previous_transcript,
+            last_output,
+            local.decision,
+        ),
+        registration,
+    )
+    .await;
+    if let Some(cancelled) = state.ai_tasks.lock().unwrap().finish(&request_id) {
+        log_to_file(&format!(
+            "AI(Gate): request_id={} cancelled ({:?})",
+            request_id, cancelled.reason
+        ));
+        app.emit(AI_CANCELLED_EVENT, cancelled)
+            .map_err(|e| format!("Failed to emit AI cancelled event: {}", e))?;
+        return Err(AiError::new(ErrorCategory::Cancelled, CANCELLED_ERROR));
+    }
+    let gate_outcome =
+        outcome.map_err(|_| AiError::new(ErrorCategory::Cancelled, CANCELLED_ERROR))??;
+    if gate_outcome.called_model {
+        let response = ChatResponse {
+            usage: gate_outcome.usage,
+            generation_id: gate_outcome.generation_id,
+            ..Default::default()
+        };
+        let usage = state.record_usage(
+            usage_record(
+                AiTaskKind::Gate,
+                &request_id,
+                &resolved_assistant_id,
+                &provider_kind,
+                &selected_model,
+                &response,
+            ),
+            &settings,
+        );
+        let _ = app.emit(AI_USAGE_EVENT, usage);
+    }
+    Ok(state.finish_gate(
+        resolved_assistant_id,
+        selected_model,
+        gate_outcome.decision,
+        GateSource::Llm,
+        gate_outcome.parse,
+    ))
+}
 
-    let default_gate_model = {
-        let config = state.app_config.lock().unwrap();
-        match provider_kind {
-            AiProvider::Openrouter => config.openrouter.gate_model.clone(),
-            AiProvider::Openai => config.openai.gate_model.clone(),
-        }
+#[derive(serde::Serialize)]
+struct RouterDecision {
+    // One entry per candidate assistant
+    assistants: Vec<RouteChoice>,
+    parse_path: GateParse,
+}
+
+// Router mode: one gate call over the enabled assistants (or `assistant_ids`)
+// that returns which of them should run.
+#[tauri::command]
+async fn route_analysis_gate(
+    app: tauri::AppHandle,
+    provider: Option<String>,
+    api_key: String,
+    model: Option<String>,
+    assistant_id: Option<String>,
+    assistant_ids: Option<Vec<String>>,
+    current_transcript: String,
+    previous_transcript: String,
+    last_output: Option<String>,
+    request_id: Option<String>,
+    state: State<'_, AppState>,
+) -> Result<RouterDecision, AiError> {
+    let request_id = request_id.unwrap_or_else(|| ai_tasks::generated_request_id("router"));
+    let provider_kind = resolve_provider(provider, &state);
+    let (llm_provider, settings) = provider_for(&provider_kind, &api_key, &state)?;
+
+    let (primary_id, candidates) = {
+        let manager = state.assistant_manager.lock().unwrap();
+        let primary_id = assistant_id
+            .filter(|id| manager.get_assistant(id).is_some())
+            .unwrap_or_else(|| manager.get_default_id().to_string());
+        let assistants: Vec<&Assistant> = match &assistant_ids {
+            Some(ids) => ids
+                .iter()
+                .filter_map(|id| manager.get_assistant(id))
+                .collect(),
+            None => manager.enabled_assistants(),
+        };
+        let candidates: Vec<RouteCandidate> = assistants
+            .into_iter()
+            .map(|a| RouteCandidate {
+                id: a.id.clone(),
+                name: a.name.clone(),
+                description: a.description.clone(),
+                gate_instructions: a.gate_instructions.clone(),
+            })
+            .collect();
+        (primary_id, candidates)
     };
 
-    let selected_model = model.unwrap_or(default_gate_model);
+    let turn = state.turn_tracker.lock().unwrap().take();
+    let local = local_gate::evaluate(&current_transcript, &previous_transcript, turn);
 
-    let gate_json = match provider_kind {
-        AiProvider::Openai => {
-            let opts = gate::GateOptions {
-                api_key: api_key.clone(),
-                model: selected_model.clone(),
-                main_system_prompt: system_prompt.clone(),
-                gate_instructions: gate_instructions.clone(),
-            };
-            gate::should_run_gate(
-                opts,
-                current_transcript.clone(),
-                previous_transcript.clone(),
-                last_output.clone(),
-            )
-            .await?
-        }
-        AiProvider::Openrouter => {
-            openrouter::run_gate(
-                &api_key,
-                &selected_model,
-                &system_prompt,
-                &gate_instructions,
-                current_transcript,
-                previous_transcript,
-                last_output,
-            )
-            .await?
-        }
+    let selected_model = model.unwrap_or_else(|| settings.gate_model.clone());
+    let selected_model = state.enforce_budget(
+        &app,
+        AiTaskKind::Gate,
+        &request_id,
+        selected_model.clone(),
+        Some(&selected_model),
+    )?;
+
+    let opts = router::RouterOptions {
+        model: selected_model.clone(),
+        candidates,
+        primary_id,
+    };
+    let registration = {
+        let supersede = state.app_config.lock().unwrap().ui.auto_cancel_previous;
+        state
+            .ai_tasks
+            .lock()
+            .unwrap()
+            .start(&request_id, AiTaskKind::Gate, ROUTER_ID, supersede)
     };
+    let outcome = Abortable::new(
+        router::route_assistants(
+            llm_provider.as_ref(),
+            opts,
+            current_transcript,
+            previous_transcript,
+            last_output,
+            local.decision,
+        ),
+        registration,
+    )
+    .await;
+    if let Some(cancelled) = state.ai_tasks.lock().unwrap().finish(&request_id) {
+        log_to_file(&format!(
+            "AI(Router): request_id={} cancelled ({:?})",
+            request_id, cancelled.reason
+        ));
+        app.emit(AI_CANCELLED_EVENT, cancelled)
+            .map_err(|e| format!("Failed to emit AI cancelled event: {}", e))?;
+        return Err(AiError::new(ErrorCategory::Cancelled, CANCELLED_ERROR));
+    }
+    let route_outcome =
+        outcome.map_err(|_| AiError::new(ErrorCategory::Cancelled, CANCELLED_ERROR))??;
+    if route_outcome.called_model {
+        let response = ChatResponse {
+            usage: route_outcome.usage,
+            generation_id: route_outcome.generation_id,
+            ..Default::default()
+        };
+        let usage = state.record_usage(
+            usage_record(
+                AiTaskKind::Gate,
+                &request_id,
+                ROUTER_ID,
+                &provider_kind,
+                &selected_model,
+                &response,
+            ),
+            &settings,
+        );
+        let _ = app.emit(AI_USAGE_EVENT, usage);
+    }
 
-    Ok(GateDecision {
-        run: gate_json.run,
-        instruction: gate_json.instruction,
-        reason: gate_json.reason,
-        confidence: gate_json.confidence,
+    // Session history keeps one gate decision per assistant
+    for choice in &route_outcome.choices {
+        state.record_gate_decision(GateRecord {
+            assistant_id: choice.assistant_id.clone(),
+            model: selected_model.clone(),
+            run: choice.run,
+            instruction: Some(if choice.run { "NEEDED" } else { "NOT_NEEDED" }.into()),
+            reason: choice.reason.clone(),
+            confidence: choice.confidence,
+            created_at: sessions::now_timestamp(),
+            parse_path: Some(route_outcome.parse),
+        });
+    }
+
+    Ok(RouterDecision {
+        assistants: route_outcome.choices,
+        parse_path: route_outcome.parse,
     })
 }
 
+// Abort a running analysis or gate request; returns false if it already finished.
+#[tauri::command]
+fn cancel_ai_request(request_id: String, state: State<AppState>) -> bool {
+    let cancelled = state.ai_tasks.lock().unwrap().cancel(&request_id);
+    log_to_file(&format!(
+        "AI: cancel request_id={} running={}",
+        request_id, cancelled
+    ));
+    cancelled
+}
+
 #[tauri::command]
 async fn get_ai_models(
     provider: Option<String>,
     api_key: String,
     state: State<'_, AppState>,
-) -> Result<Vec<String>, String> {
-    if api_key.trim().is_empty() {
-        return Err("AI provider API key is required".to_string());
-    }
-
+) -> Result<Vec<String>, AiError> {
     let provider_kind = resolve_provider(provider, &state);
-    match provider_kind {
-        AiProvider::Openrouter => openrouter::list_models(&api_key).await,
-        AiProvider::Openai => openai::get_available_models(api_key).await,
-    }
+    let (llm_provider, _) = provider_for(&provider_kind, &api_key, &state)?;
+    llm_provider.list_models().await
 }
 
 #[tauri::command]
-async fn get_openrouter_credits(api_key: String) -> Result<openrouter::CreditSummary, String> {
+async fn get_openrouter_credits(
+    api_key: String,
+    state: State<'_, AppState>,
+) -> Result<AccountUsage, String> {
     if api_key.trim().is_empty() {
         return Err("OpenRouter API key is required".to_string());
     }
 
-    openrouter::get_credits(&api_key).await
+    let (llm_provider, _) = provider_for(&AiProvider::Openrouter, &api_key, &state)?;
+    llm_provider
+        .usage()
+        .await?
+        .ok_or_else(|| "OpenRouter did not report credits".to_string())
 }
 
 #[tauri::command]
-async fn load_assistants(state: State<'_, AppState>) -> Result<(), String> {
-    let config_path = "../config/assistants.json";
+async fn load_assistants(app: tauri::AppHandle, state: State<'_, AppState>) -> Result<(), String> {
+    let config_path = Path::new(ASSISTANTS_CONFIG_PATH);
 
     // Log current working directory for debugging
     match std::env::current_dir() {
@@ -1605,9 +2918,20 @@ async fn load_assistants(state: State<'_, AppState>) -> Result<(), String> {
         Err(e) => log_to_file(&format!("Failed to get current directory: {}", e)),
     }
 
+    // Started even when loading fails, so fixing the file by hand recovers
+    state.assistants_watcher.start(
+        config_path.to_path_buf(),
+        state.assistant_manager.clone(),
+        move |manager| {
+            let _ = app.emit(ASSISTANTS_CHANGED_EVENT, manager.changed_payload());
+        },
+    );
+
     match AssistantManager::load_from_file(config_path) {
         Ok(manager) => {
-            *state.assistant_manager.lock().unwrap() = manager;
+            let mut current = state.assistant_manager.lock().unwrap();
+            state.assistants_watcher.mark(config_path);
+            *current = manager;
             Ok(())
         }
         Err(e) => {
@@ -1618,12 +2942,78 @@ async fn load_assistants(state: State<'_, AppState>) -> Result<(), String> {
     }
 }
 
+// Apply an edit to a copy of the assistants, save it and only then swap it in,
+// so a failed validation or write leaves both memory and disk unchanged.
+fn edit_assistants(
+    app: &tauri::AppHandle,
+    state: &AppState,
+    edit: impl FnOnce(&mut AssistantManager) -> Result<(), String>,
+) -> Result<(), String> {
+    let mut manager = state.assistant_manager.lock().unwrap();
+    if manager.get_default_id().is_empty() {
+        return Err("Assistants are not loaded; fix config/assistants.json first".to_string());
+    }
+    let mut edited = manager.clone();
+    edit(&mut edited)?;
+    state
+        .assistants_watcher
+        .save(&edited, Path::new(ASSISTANTS_CONFIG_PATH))?;
+    *manager = edited;
+    app.emit(ASSISTANTS_CHANGED_EVENT, manager.changed_payload())
+        .map_err(|e| format!("Failed to emit assistants changed event: {}", e))
+}
+
+#[tauri::command]
+async fn create_assistant(
+    app: tauri::AppHandle,
+    assistant: Assistant,
+    state: State<'_, AppState>,
+) -> Result<(), String> {
+    log_to_file(&format!("Creating assistant '{}'", assistant.id));
+    edit_assistants(&app, &state, |manager| manager.create(assistant))
+}
+
+#[tauri::command]
+async fn update_assistant(
+    app: tauri::AppHandle,
+    assistant: Assistant,
+    state: State<'_, AppState>,
+) -> Result<(), String> {
+    log_to_file(&format!("Updating assistant '{}'", assistant.id));
+    edit_assistants(&app, &state, |manager| manager.update(assistant))
+}
+
+#[tauri::command]
+async fn delete_assistant(
+    app: tauri::AppHandle,
+    assistant_id: String,
+    state: State<'_, AppState>,
+) -> Result<(), String> {
+    log_to_file(&format!("Deleting assistant '{}'", assistant_id));
+    edit_assistants(&app, &state, |manager| manager.delete(&assistant_id))
+}
+
+#[tauri::command]
+async fn set_default_assistant(
+    app: tauri::AppHandle,
+    assistant_id: String,
+    state: State<'_, AppState>,
+) -> Result<(), String> {
+    edit_assistants(&app, &state, |manager| manager.set_default(&assistant_id))
+}
+
 #[tauri::command]
 async fn get_assistants(state: State<'_, AppState>) -> Result<Vec<Assistant>, String> {
     let manager = state.assistant_manager.lock().unwrap();
     Ok(manager.list_assistants().into_iter().cloned().collect())
 }
 
+#[tauri::command]
+async fn get_pipelines(state: State<'_, AppState>) -> Result<Vec<Pipeline>, String> {
+    let manager = state.assistant_manager.lock().unwrap();
+    Ok(manager.list_pipelines().to_vec())
+}
+
 #[tauri::command]
 async fn get_default_assistant_id(state: State<'_, AppState>) -> Result<String, String> {
     let manager = state.assistant_manager.lock().unwrap();
@@ -1669,6 +3059,221 @@ async fn create_default_config() -> Result<(), String> {
     ConfigManager::create_default_config()
 }
 
+#[tauri::command]
+async fn list_sessions(
+    state: State<'_, AppState>,
+) -> Result<Vec<sessions::SessionSummary>, String> {
+    state
+        .inner()
+        .with_session_store(|store| store.list_sessions())
+}
+
+#[tauri::command]
+async fn open_session(
+    state: State<'_, AppState>,
+    session_id: String,
+) -> Result<sessions::SessionDetail, String> {
+    state
+        .inner()
+        .with_session_store(|store| store.get_session(&session_id))
+}
+
+// Title a stored session, or the one being recorded when no id is given.
+// Shown in session lists and available to prompts as `{{session_title}}`.
+#[tauri::command]
+async fn set_session_title(
+    state: State<'_, AppState>,
+    session_id: Option<String>,
+    title: String,
+) -> Result<(), String> {
+    let state = state.inner();
+    let id = match session_id.or_else(|| state.active_session_id()) {
+        Some(id) => id,
+        None => return Err("No session is being recorded".to_string()),
+    };
+    state.with_session_store(|store| store.set_title(&id, &title))?;
+    if let Some(active) = state
+        .active_session
+        .lock()
+        .unwrap()
+        .as_mut()
+        .filter(|active| active.id == id)
+    {
+        active.title = Some(title.trim().to_string()).filter(|t| !t.is_empty());
+    }
+    log_to_file(&format!("Session {} titled {:?}", id, title.trim()));
+    Ok(())
+}
+
+#[tauri::command]
+async fn search_sessions(
+    state: State<'_, AppState>,
+    query: String,
+    filters: Option<sessions::SearchFilters>,
+) -> Result<Vec<sessions::SearchHit>, String> {
+    let filters = filters.unwrap_or_default();
+    state
+        .inner()
+        .with_session_store(|store| store.search(&query, &filters))
+}
+
+#[tauri::command]
+async fn query_answer_data(
+    state: State<'_, AppState>,
+    filters: Option<sessions::StructuredFilters>,
+) -> Result<Vec<sessions::StructuredAnswer>, String> {
+    let filters = filters.unwrap_or_default();
+    state
+        .inner()
+        .with_session_store(|store| store.structured_answers(&filters))
+}
+
+// Ask the provider for the billed cost of calls that only have a price-table
+// estimate (or none). Best effort: failures leave the rows for next time.
+// Billed cost of one call, looked up with the key that made it. Retried with
+// a growing delay until the provider has published it or the row runs out of
+// attempts.
+async fn resolve_generation(
+    state: &AppState,
+    kind: &AiProvider,
+    settings: &ProviderSettings,
+    usage_id: i64,
+    generation_id: &str,
+) {
+    let provider = match llm::build_provider(kind, settings) {
+        Ok(provider) => provider,
+        Err(e) => {
+            log_to_file(&format!(
+                "Usage: cannot look up the cost of generation {}: {}",
+                generation_id, e
+            ));
+            return;
+        }
+    };
+    for attempt in 1..=sessions::MAX_COST_ATTEMPTS as u32 {
+        tokio::time::sleep(GENERATION_COST_DELAY * attempt).await;
+        if fetch_generation_cost(state, provider.as_ref(), usage_id, generation_id).await {
+            return;
+        }
+    }
+}
+
+// One cost lookup; true once the cost is stored. Misses count towards the
+// row's attempts.
+async fn fetch_generation_cost(
+    state: &AppState,
+    provider: &dyn LlmProvider,
+    usage_id: i64,
+    generation_id: &str,
+) -> bool {
+    let cost = match provider.generation_cost(generation_id).await {
+        Ok(cost) => cost,
+        Err(e) => {
+            log_to_file(&format!(
+                "Usage: cost for generation {} not available: {}",
+                generation_id, e
+            ));
+            None
+        }
+    };
+    let stored = state.with_session_store(|store| match cost {
+        Some(cost) => store.set_provider_cost(usage_id, cost),
+        None => store.record_cost_attempt(usage_id),
+    });
+    if let Err(e) = stored {
+        log_to_file(&format!("Usage: failed to store provider cost: {}", e));
+    }
+    cost.is_some()
+}
+
+// Costs still missing from earlier sessions, looked up with the OpenRouter
+// key from config.json; the UI's key is not kept between calls.
+async fn resolve_provider_costs(state: &AppState) {
+    let pending = match state.with_session_store(|store| store.unresolved_generations(50)) {
+        Ok(pending) => pending,
+        Err(e) => {
+            log_to_file(&format!("Usage: failed to load pending costs: {}", e));
+            return;
+        }
+    };
+    if pending.is_empty() {
+        return;
+    }
+    let settings = state
+        .app_config
+        .lock()
+        .unwrap()
+        .provider_settings(&AiProvider::Openrouter);
+    let provider = match llm::build_provider(&AiProvider::Openrouter, &settings) {
+        Ok(provider) => provider,
+        Err(e) => {
+            log_to_file(&format!(
+                "Usage: cannot look up {} pending OpenRouter costs: {}",
+                pending.len(),
+                e
+            ));
+            return;
+        }
+    };
+    for (usage_id, generation_id) in pending {
+        fetch_generation_cost(state, provider.as_ref(), usage_id, &generation_id).await;
+    }
+}
+
+// Token and cost totals per session, assistant and model, plus the most
+// recent individual requests.
+#[tauri::command]
+async fn get_usage_report(
+    state: State<'_, AppState>,
+    filters: Option<UsageFilters>,
+) -> Result<UsageReport, String> {
+    let filters = filters.unwrap_or_default();
+    resolve_provider_costs(state.inner()).await;
+    let records = state
+        .inner()
+        .with_session_store(|store| store.usage_records(&filters))?;
+    Ok(UsageReport::build(records, filters.limit))
+}
+
+#[tauri::command]
+async fn export_transcript(
+    state: State<'_, AppState>,
+    session_id: String,
+    format: String,
+    path: String,
+) -> Result<String, String> {
+    let format = export::ExportFormat::parse(&format)?;
+    let detail = state
+        .inner()
+        .with_session_store(|store| store.get_session(&session_id))?;
+    export::export_to_file(&detail, format, &path)?;
+    log_to_file(&format!(
+        "Session {} exported as {:?} to {}",
+        session_id, format, path
+    ));
+    Ok(path)
+}
+
+#[tauri::command]
+async fn delete_session(
+    state: State<'_, AppState>,
+    session_id: String,
+    delete_audio: Option<bool>,
+) -> Result<(), String> {
+    if state.inner().active_session_id().as_deref() == Some(session_id.as_str()) {
+        return Err("Cannot delete the session that is currently recording".to_string());
+    }
+    let audio_path = state
+        .inner()
+        .with_session_store(|store| store.delete_session(&session_id))?;
+    log_to_file(&format!("Session {} deleted", session_id));
+    if delete_audio.unwrap_or(false) && std::path::Path::new(&audio_path).exists() {
+        std::fs::remove_file(&audio_path)
+            .map_err(|e| format!("Session deleted but failed to remove audio file: {}", e))?;
+    }
+    Ok(())
+}
+
 #[derive(Serialize, Clone)]
 struct LevelPayload {
     rms: f32,
@@ -1715,20 +3320,16 @@ fn build_stream_f32(
             }
         }
 
-        // Always send audio to Soniox for better context
+        // Always send audio to the transcriber for better context
         // Voice detection filtering will happen at the transcript display level
-        if let Ok(lock) = app_state.soniox_tx.lock() {
-            if let Some(tx) = lock.as_ref() {
-                let _ = tx.try_send(soniox::AudioChunk {
-                    samples: data
-                        .iter()
-                        .map(|&s| (s.clamp(-1.0, 1.0) * i16::MAX as f32) as i16)
-                        .collect(),
-                    channels,
-                    sample_rate,
-                });
-            }
-        }
+        app_state.push_transcription_audio(AudioChunk {
+            samples: data
+                .iter()
+                .map(|&s| (s.clamp(-1.0, 1.0) * i16::MAX as f32) as i16)
+                .collect(),
+            channels,
+            sample_rate,
+        });
     };
 
     device
@@ -1772,17 +3373,13 @@ fn build_stream_i16(
             }
         }
 
-        // Always send audio to Soniox for better context
+        // Always send audio to the transcriber for better context
         // Voice detection filtering will happen at the transcript display level
-        if let Ok(lock) = app_state.soniox_tx.lock() {
-            if let Some(tx) = lock.as_ref() {
-                let _ = tx.try_send(soniox::AudioChunk {
-                    samples: data.to_vec(),
-                    channels,
-                    sample_rate,
-                });
-            }
-        }
+        app_state.push_transcription_audio(AudioChunk {
+            samples: data.to_vec(),
+            channels,
+            sample_rate,
+        });
     };
 
     device
@@ -1827,17 +3424,13 @@ fn build_stream_u16(
             }
         }
 
-        // Always send audio to Soniox for better context
+        // Always send audio to the transcriber for better context
         // Voice detection filtering will happen at the transcript display level
-        if let Ok(lock) = app_state.soniox_tx.lock() {
-            if let Some(tx) = lock.as_ref() {
-                let _ = tx.try_send(soniox::AudioChunk {
-                    samples: data.iter().map(|&u| (u as i32 - 32768) as i16).collect(),
-                    channels,
-                    sample_rate,
-                });
-            }
-        }
+        app_state.push_transcription_audio(AudioChunk {
+            samples: data.iter().map(|&u| (u as i32 - 32768) as i16).collect(),
+            channels,
+            sample_rate,
+        });
     };
 
     device
@@ -1860,22 +3453,56 @@ pub fn run() {
             pause_recording,
             resume_recording,
             get_recording_state,
-            start_soniox_session,
-            stop_soniox_session,
+            start_transcription_session,
+            stop_transcription_session,
             clear_transcript_state,
+            flush_transcription,
             stream_ai_analysis,
             analyze_with_openai,
             get_ai_models,
             get_openrouter_credits,
             should_run_analysis_gate,
+            route_analysis_gate,
+            cancel_ai_request,
             load_assistants,
             get_assistants,
             get_default_assistant_id,
+            get_pipelines,
+            create_assistant,
+            update_assistant,
+            delete_assistant,
+            set_default_assistant,
             load_app_config,
             save_app_config,
             get_app_config,
-            create_default_config
+            create_default_config,
+            list_sessions,
+            open_session,
+            set_session_title,
+            search_sessions,
+            query_answer_data,
+            get_usage_report,
+            export_transcript,
+            delete_session
         ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .setup(|app| {
+            // Feed transcript updates into turn tracking, memory and session history
+            let handle = app.handle().clone();
+            app.listen(
+                TRANSCRIPT_UPDATE_EVENT,
+                move |event| match serde_json::from_str::<TranscriptUpdate>(event.payload()) {
+                    Ok(update) => handle.state::<AppState>().record_transcript_update(&update),
+                    Err(e) => log_to_file(&format!("Ignoring malformed transcript update: {}", e)),
+                },
+            );
+            Ok(())
+        })
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(|app, event| {
+            // Closing the window mid-meeting still stores the session
+            if let tauri::RunEvent::Exit = event {
+                app.state::<AppState>().finish_session();
+            }
+        });
 }
@@ -0,0 +1,668 @@
+use crate::{
+    anthropic,
+    assistants::render_user_prompt,
+    config::AiProvider,
+    gemini, openai, openrouter,
+    retry::{self, RetryPolicy},
+    templates::PromptVars,
+    utils::log_to_file,
+};
+use async_trait::async_trait;
+use futures_util::StreamExt;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChatRole {
+    System,
+    User,
+    Assistant,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChatMessage {
+    pub role: ChatRole,
+    pub content: String,
+}
+
+impl ChatMessage {
+    pub fn new(role: ChatRole, content: impl Into<String>) -> Self {
+        Self {
+            role,
+            content: content.into(),
+        }
+    }
+}
+
+// Structured output: providers with a JSON-schema mode enforce `schema`,
+// Gemini gets plain JSON mode and Anthropic relies on the prompt.
+#[derive(Debug, Clone, PartialEq)]
+pub struct JsonSchemaFormat {
+    pub name: String,
+    pub schema: serde_json::Value,
+}
+
+// Provider-neutral chat request; each backend maps it onto its own wire format.
+#[derive(Debug, Clone)]
+pub struct ChatRequest {
+    pub model: String,
+    pub messages: Vec<ChatMessage>,
+    pub max_tokens: u32,
+    pub temperature: f32,
+    pub top_p: Option<f32>,
+    // Empty for none
+    pub stop: Vec<String>,
+    pub response_format: Option<JsonSchemaFormat>,
+}
+
+impl ChatRequest {
+    // For APIs that take the system prompt separately and expect the turns to
+    // start with the user and alternate. Leading assistant turns (previous
+    // answers given as context) are folded into the system text and
+    // consecutive turns from the same role are merged.
+    pub fn split_system(&self) -> (String, Vec<ChatMessage>) {
+        let mut system: Vec<&str> = Vec::new();
+        let mut turns: Vec<ChatMessage> = Vec::new();
+        for message in &self.messages {
+            let leading_assistant = turns.is_empty() && message.role == ChatRole::Assistant;
+            if message.role == ChatRole::System || leading_assistant {
+                system.push(&message.content);
+                continue;
+            }
+            match turns.last_mut() {
+                Some(last) if last.role == message.role => {
+                    last.content.push_str("\n\n");
+                    last.content.push_str(&message.content);
+                }
+                _ => turns.push(message.clone()),
+            }
+        }
+        (system.join("\n\n"), turns)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct TokenUsage {
+    // All prompt tokens, cached ones included
+    pub input_tokens: u32,
+    pub output_tokens: u32,
+    // Prompt tokens served from the provider's cache (billed cheaper)
+    #[serde(default)]
+    pub cached_input_tokens: u32,
+}
+
+impl TokenUsage {
+    // Streams report usage piecemeal; keep the latest non-zero counters.
+    pub fn absorb(&mut self, other: TokenUsage) {
+        if other.input_tokens > 0 {
+            self.input_tokens = other.input_tokens;
+        }
+        if other.output_tokens > 0 {
+            self.output_tokens = other.output_tokens;
+        }
+        if other.cached_input_tokens > 0 {
+            self.cached_input_tokens = other.cached_input_tokens;
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ChatResponse {
+    pub text: String,
+    pub usage: Option<TokenUsage>,
+    // Provider-side id for looking up the billed cost later (OpenRouter)
+    pub generation_id: Option<String>,
+}
+
+// Account balance as reported by providers that expose one.
+#[derive(Debug, Clone, Serialize)]
+pub struct AccountUsage {
+    pub total_credits: f64,
+    pub total_usage: f64,
+}
+
+// Coarse failure classes the UI reacts to (re-enter key, wait, pick another
+// model, ...). Serialized in snake_case, e.g. "rate_limit".
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCategory {
+    Auth,
+    Quota,
+    RateLimit,
+    Network,
+    ModelNotFound,
+    Server,
+    // A configured spending cap was reached (see `budget`)
+    Budget,
+    Cancelled,
+    Other,
+}
+
+impl ErrorCategory {
+    pub fn from_status(status: u16, body: &str) -> Self {
+        let body = body.to_lowercase();
+        let mentions_quota = ["quota", "billing", "credit", "insufficient"]
+            .iter()
+            .any(|word| body.contains(word));
+        let mentions_missing_model = body.contains("model_not_found")
+            || (body.contains("model")
+                && (body.contains("not found") || body.contains("does not exist")));
+        match status {
+            401 | 403 => Self::Auth,
+            402 => Self::Quota,
+            429 if mentions_quota => Self::Quota,
+            429 => Self::RateLimit,
+            404 => Self::ModelNotFound,
+            400 | 422 if mentions_missing_model => Self::ModelNotFound,
+            408 | 500..=599 => Self::Server,
+            _ => Self::Other,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AiError {
+    pub category: ErrorCategory,
+    pub message: String,
+    // HTTP status, when the provider answered at all
+    pub status: Option<u16>,
+    #[serde(skip)]
+    pub retry_after: Option<Duration>,
+}
+
+impl AiError {
+    pub fn new(category: ErrorCategory, message: impl Into<String>) -> Self {
+        Self {
+            category,
+            message: message.into(),
+            status: None,
+            retry_after: None,
+        }
+    }
+
+    pub fn from_status(
+        provider_label: &str,
+        status: u16,
+        body: &str,
+        retry_after: Option<Duration>,
+    ) -> Self {
+        let status_text = reqwest::StatusCode::from_u16(status)
+            .map(|code| code.to_string())
+            .unwrap_or_else(|_| status.to_string());
+        Self {
+            category: ErrorCategory::from_status(status, body),
+            message: format!("{} API error: {}", provider_label, status_text),
+            status: Some(status),
+            retry_after,
+        }
+    }
+
+    pub fn network(provider_label: &str, error: impl fmt::Display) -> Self {
+        Self::new(
+            ErrorCategory::Network,
+            format!("Failed to connect to {}: {}", provider_label, error),
+        )
+    }
+
+    // Rate limits, overloaded servers and dropped connections are transient.
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self.category,
+            ErrorCategory::RateLimit | ErrorCategory::Server | ErrorCategory::Network
+        )
+    }
+}
+
+impl fmt::Display for AiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl From<String> for AiError {
+    fn from(message: String) -> Self {
+        Self::new(ErrorCategory::Other, message)
+    }
+}
+
+impl From<AiError> for String {
+    fn from(error: AiError) -> Self {
+        error.message
+    }
+}
+
+pub type DeltaCallback<'a> = dyn FnMut(&str) -> Result<(), String> + Send + 'a;
+
+#[async_trait]
+pub trait LlmProvider: Send + Sync {
+    // Name used in logs and error messages, e.g. "OpenAI"
+    fn label(&self) -> &'static str;
+
+    async fn chat(&self, request: &ChatRequest) -> Result<ChatResponse, AiError>;
+
+    // Calls `on_delta` for every content fragment and returns the full text.
+    // Only the initial request is retried; a stream that fails midway is not.
+    async fn chat_stream(
+        &self,
+        request: &ChatRequest,
+        on_delta: &mut DeltaCallback<'_>,
+    ) -> Result<ChatResponse, AiError>;
+
+    async fn list_models(&self) -> Result<Vec<String>, AiError>;
+
+    async fn usage(&self) -> Result<Option<AccountUsage>, AiError> {
+        Ok(None)
+    }
+
+    // Billed cost in USD of an earlier response, for providers that report it.
+    async fn generation_cost(&self, _generation_id: &str) -> Result<Option<f64>, AiError> {
+        Ok(None)
+    }
+}
+
+pub const DEFAULT_TIMEOUT_SECS: u64 = 60;
+
+// Connection settings for one provider, resolved from AppConfig.
+#[derive(Debug, Clone)]
+pub struct ProviderSettings {
+    pub api_key: String,
+    // Empty means the provider's public endpoint
+    pub base_url: String,
+    pub require_api_key: bool,
+    pub model: String,
+    pub gate_model: String,
+    pub timeout_secs: u64,
+}
+
+impl ProviderSettings {
+    pub fn timeout(&self) -> Duration {
+        Duration::from_secs(self.timeout_secs.max(1))
+    }
+}
+
+pub fn build_provider(
+    kind: &AiProvider,
+    settings: &ProviderSettings,
+) -> Result<Box<dyn LlmProvider>, AiError> {
+    let api_key = settings.api_key.trim();
+    if api_key.is_empty() && settings.require_api_key {
+        return Err(AiError::new(
+            ErrorCategory::Auth,
+            "AI provider API key is required",
+        ));
+    }
+    let timeout = settings.timeout();
+    Ok(match kind {
+        AiProvider::Openai => Box::new(openai::OpenAiProvider::new(
+            api_key,
+            &settings.base_url,
+            timeout,
+        )),
+        AiProvider::Openrouter => Box::new(openrouter::OpenRouterProvider::new(api_key, timeout)?),
+        AiProvider::Anthropic => Box::new(anthropic::AnthropicProvider::new(api_key, timeout)),
+        AiProvider::Gemini => Box::new(gemini::GeminiProvider::new(api_key, timeout)),
+    })
+}
+
+// reqwest has no default timeouts; requests get theirs from the provider.
+pub fn http_client() -> reqwest::Client {
+    reqwest::Client::builder()
+        .connect_timeout(Duration::from_secs(10))
+        .build()
+        .unwrap_or_else(|_| reqwest::Client::new())
+}
+
+// Messages for the main analysis call, shared by every provider.
+pub fn compose_messages(
+    system_prompt: &str,
+    output_policy: &str,
+    user_prompt_template: &str,
+    vars: &PromptVars,
+) -> Vec<ChatMessage> {
+    let effective_system_prompt = if output_policy.trim().is_empty() {
+        system_prompt.to_string()
+    } else {
+        format!("{}\n\n{}", system_prompt, output_policy)
+    };
+
+    let mut messages = vec![ChatMessage::new(ChatRole::System, effective_system_prompt)];
+
+    if let Some(prev) = vars.last_output {
+        if !prev.trim().is_empty() {
+            messages.push(ChatMessage::new(
+                ChatRole::Assistant,
+                format!("Previous assistant answer (for context):\n{}", prev),
+            ));
+        }
+    }
+
+    let user_prompt = render_user_prompt(user_prompt_template, vars);
+    messages.push(ChatMessage::new(ChatRole::User, user_prompt));
+    messages
+}
+
+pub fn log_main_prompt(provider_label: &str, request: &ChatRequest) {
+    let system = request
+        .messages
+        .iter()
+        .find(|m| m.role == ChatRole::System)
+        .map(|m| m.content.as_str())
+        .unwrap_or("");
+    let user = request
+        .messages
+        .iter()
+        .rev()
+        .find(|m| m.role == ChatRole::User)
+        .map(|m| m.content.as_str())
+        .unwrap_or("");
+    log_to_file(&format!(
+        "{}(Main): Prompt model={} system=<<<{}>>> user=<<<{}>>>",
+        provider_label, request.model, system, user
+    ));
+}
+
+// Turn a non-2xx response into the repo's usual "<Provider> API error: <status>".
+pub async fn check_response(
+    provider_label: &str,
+    response: reqwest::Response,
+) -> Result<reqwest::Response, AiError> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+    let retry_after = retry::retry_after(response.headers());
+    let error_text = response
+        .text()
+        .await
+        .unwrap_or_else(|_| "Unknown error".to_string());
+    log_to_file(&format!(
+        "{}: API error {}: {}",
+        provider_label, status, error_text
+    ));
+    Err(AiError::from_status(
+        provider_label,
+        status.as_u16(),
+        &error_text,
+        retry_after,
+    ))
+}
+
+// Send the request produced by `build` until it gets a 2xx response, retrying
+// transient failures. `timeout` bounds the wait for response headers.
+pub async fn send_with_retry(
+    provider_label: &str,
+    policy: &RetryPolicy,
+    timeout: Duration,
+    build: impl Fn() -> reqwest::RequestBuilder,
+) -> Result<reqwest::Response, AiError> {
+    let build = &build;
+    retry::with_retry(provider_label, policy, || async move {
+        let response = match tokio::time::timeout(timeout, build().send()).await {
+            Ok(Ok(response)) => response,
+            Ok(Err(e)) => {
+                log_to_file(&format!("{}: Request error: {}", provider_label, e));
+                return Err(AiError::network(provider_label, e));
+            }
+            Err(_) => {
+                log_to_file(&format!(
+                    "{}: Request timed out after {}s",
+                    provider_label,
+                    timeout.as_secs()
+                ));
+                return Err(AiError::network(
+                    provider_label,
+                    format!("timed out after {}s", timeout.as_secs()),
+                ));
+            }
+        };
+        check_response(provider_label, response).await
+    })
+    .await
+}
+
+pub async fn read_json<T: serde::de::DeserializeOwned>(
+    provider_label: &str,
+    response: reqwest::Response,
+) -> Result<T, AiError> {
+    response.json().await.map_err(|e| {
+        log_to_file(&format!("{}: JSON parse error: {}", provider_label, e));
+        if e.is_timeout() {
+            AiError::network(provider_label, e)
+        } else {
+            AiError::new(
+                ErrorCategory::Other,
+                format!("Failed to parse {} response: {}", provider_label, e),
+            )
+        }
+    })
+}
+
+// Incremental parser for `text/event-stream` bodies. Bytes are buffered until
+// a full line is available so multi-byte characters split across network
+// chunks decode correctly.
+#[derive(Debug, Default)]
+pub struct SseParser {
+    buffer: Vec<u8>,
+    data: Vec<String>,
+}
+
+impl SseParser {
+    // Feed raw bytes; returns the data payloads of every completed event.
+    pub fn push(&mut self, bytes: &[u8]) -> Vec<String> {
+        self.buffer.extend_from_slice(bytes);
+        let mut events = Vec::new();
+        while let Some(pos) = self.buffer.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = self.buffer.drain(..=pos).collect();
+            let line = String::from_utf8_lossy(&line);
+            let line = line.trim_end_matches(['\n', '\r']);
+            if line.is_empty() {
+                if !self.data.is_empty() {
+                    events.push(self.data.join("\n"));
+                    self.data.clear();
+                }
+            } else if let Some(value) = line.strip_prefix("data:") {
+                self.data
+                    .push(value.strip_prefix(' ').unwrap_or(value).to_string());
+            }
+            // Comments (":") and other fields (event, id, retry) are ignored
+        }
+        events
+    }
+
+    // Flush an event left unterminated when the body ends.
+    pub fn finish(&mut self) -> Option<String> {
+        if !self.buffer.is_empty() {
+            let rest = std::mem::take(&mut self.buffer);
+            let line = String::from_utf8_lossy(&rest);
+            if let Some(value) = line.trim_end().strip_prefix("data:") {
+                self.data.push(value.trim_start().to_string());
+            }
+        }
+        if self.data.is_empty() {
+            None
+        } else {
+            let event = self.data.join("\n");
+            self.data.clear();
+            Some(event)
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum StreamEvent {
+    Delta(String),
+    Usage(TokenUsage),
+    Finished,
+    Done,
+}
+
+// Read an SSE chat stream to the end, decoding each payload with `parse`.
+// The stream fails if no bytes arrive for `idle_timeout`.
+pub async fn collect_stream(
+    provider_label: &str,
+    response: reqwest::Response,
+    parse: fn(&str) -> Result<Vec<StreamEvent>, String>,
+    idle_timeout: Duration,
+    on_delta: &mut DeltaCallback<'_>,
+) -> Result<ChatResponse, AiError> {
+    let mut parser = SseParser::default();
+    let mut text = String::new();
+    let mut usage: Option<TokenUsage> = None;
+    let mut finished = false;
+    let mut body = response.bytes_stream();
+
+    'read: loop {
+        let next = tokio::time::timeout(idle_timeout, body.next())
+            .await
+            .map_err(|_| {
+                log_to_file(&format!(
+                    "{}: Stream stalled for {}s",
+                    provider_label,
+                    idle_timeout.as_secs()
+                ));
+                AiError::new(
+                    ErrorCategory::Network,
+                    format!("{} stream timed out", provider_label),
+                )
+            })?;
+        let payloads = match next {
+            Some(Ok(bytes)) => parser.push(&bytes),
+            Some(Err(e)) => {
+                log_to_file(&format!("{}: Stream read error: {}", provider_label, e));
+                return Err(AiError::new(
+                    ErrorCategory::Network,
+                    format!("{} stream interrupted: {}", provider_label, e),
+                ));
+            }
+            None => match parser.finish() {
+                Some(last) => vec![last],
+                None => break 'read,
+            },
+        };
+        for payload in payloads {
+            for event in parse(&payload)? {
+                match event {
+                    StreamEvent::Delta(content) => {
+                        text.push_str(&content);
+                        on_delta(&content)?;
+                    }
+                    StreamEvent::Usage(counts) => {
+                        usage.get_or_insert_with(Default::default).absorb(counts)
+                    }
+                    StreamEvent::Finished => finished = true,
+                    StreamEvent::Done => break 'read,
+                }
+            }
+        }
+    }
+
+    if text.is_empty() && !finished {
+        return Err(AiError::new(
+            ErrorCategory::Network,
+            format!("{} stream ended without a response", provider_label),
+        ));
+    }
+    Ok(ChatResponse {
+        text,
+        usage,
+        generation_id: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sse_parser_handles_split_chunks() {
+        let mut parser = SseParser::default();
+        let body = "data: {\"a\":1}\n\n: keep-alive\n\ndata: caf\u{e9}\n\ndata: [DONE]\n\n";
+        let bytes = body.as_bytes();
+        // Split inside the multi-byte character
+        let split = body.find('\u{e9}').unwrap() + 1;
+        let mut events = parser.push(&bytes[..split]);
+        events.extend(parser.push(&bytes[split..]));
+        assert_eq!(events, vec!["{\"a\":1}", "caf\u{e9}", "[DONE]"]);
+        assert_eq!(parser.finish(), None);
+    }
+
+    #[test]
+    fn test_sse_parser_flushes_unterminated_event() {
+        let mut parser = SseParser::default();
+        assert!(parser.push(b"data: [DONE]").is_empty());
+        assert_eq!(parser.finish().as_deref(), Some("[DONE]"));
+    }
+
+    #[test]
+    fn test_split_system_folds_leading_assistant_turns() {
+        let request = ChatRequest {
+            model: "m".into(),
+            messages: compose_messages(
+                "Be brief",
+                "",
+                "{{transcript}}",
+                &PromptVars {
+                    last_output: Some("earlier"),
+                    ..PromptVars::new("hello")
+                },
+            ),
+            max_tokens: 10,
+            temperature: 0.0,
+            response_format: None,
+            top_p: None,
+            stop: Vec::new(),
+        };
+        let (system, turns) = request.split_system();
+        assert!(system.starts_with("Be brief\n\nPrevious assistant answer"));
+        assert!(system.ends_with("earlier"));
+        assert_eq!(turns.len(), 1);
+        assert_eq!(turns[0].role, ChatRole::User);
+
+        let request = ChatRequest {
+            messages: vec![
+                ChatMessage::new(ChatRole::User, "a"),
+                ChatMessage::new(ChatRole::User, "b"),
+                ChatMessage::new(ChatRole::Assistant, "c"),
+            ],
+            ..request
+        };
+        let (system, turns) = request.split_system();
+        assert!(system.is_empty());
+        assert_eq!(
+            turns,
+            vec![
+                ChatMessage::new(ChatRole::User, "a\n\nb"),
+                ChatMessage::new(ChatRole::Assistant, "c"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_error_category_from_status() {
+        use ErrorCategory::*;
+        assert_eq!(ErrorCategory::from_status(401, ""), Auth);
+        assert_eq!(ErrorCategory::from_status(403, "forbidden"), Auth);
+        assert_eq!(
+            ErrorCategory::from_status(429, "Rate limit reached for requests"),
+            RateLimit
+        );
+        assert_eq!(
+            ErrorCategory::from_status(429, r#"{"error":{"code":"insufficient_quota"}}"#),
+            Quota
+        );
+        assert_eq!(ErrorCategory::from_status(402, ""), Quota);
+        assert_eq!(ErrorCategory::from_status(404, ""), ModelNotFound);
+        assert_eq!(
+            ErrorCategory::from_status(400, "The model `gpt-9` does not exist"),
+            ModelNotFound
+        );
+        assert_eq!(ErrorCategory::from_status(400, "bad temperature"), Other);
+        assert_eq!(ErrorCategory::from_status(529, "overloaded"), Server);
+
+        let error = AiError::from_status("OpenAI", 429, "", None);
+        assert_eq!(error.message, "OpenAI API error: 429 Too Many Requests");
+        assert!(error.is_retryable());
+        assert!(!AiError::from_status("OpenAI", 401, "", None).is_retryable());
+    }
+}
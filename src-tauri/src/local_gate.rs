@@ -0,0 +1,418 @@
+use crate::{gate::GateJson, transcription::TranscriptUpdate};
+use std::collections::HashMap;
+
+// Below this many new words a change is treated as an ASR revision
+const MIN_NEW_WORDS: usize = 3;
+// A finished turn with this many new words is worth an answer on its own
+const SUBSTANTIAL_NEW_WORDS: usize = 12;
+
+// Words that open a question, for languages where a transcript may lack "?".
+// Matched against the first word of the last sentence, lowercased.
+const INTERROGATIVES: &[&str] = &[
+    // English
+    "what",
+    "why",
+    "how",
+    "when",
+    "where",
+    "who",
+    "whom",
+    "whose",
+    "which",
+    "can",
+    "could",
+    "would",
+    "should",
+    "is",
+    "are",
+    "do",
+    "does",
+    "did",
+    "will",
+    "shall",
+    "may",
+    // German
+    "was",
+    "warum",
+    "wieso",
+    "weshalb",
+    "wie",
+    "wann",
+    "wo",
+    "woher",
+    "wohin",
+    "wer",
+    "wen",
+    "wem",
+    "welche",
+    "welcher",
+    "welches",
+    "kannst",
+    "können",
+    "könnten",
+    "ist",
+    "sind",
+    // French
+    "quoi",
+    "pourquoi",
+    "comment",
+    "quand",
+    "où",
+    "qui",
+    "quel",
+    "quelle",
+    "quels",
+    "quelles",
+    "est-ce",
+    // Spanish / Portuguese / Italian
+    "qué",
+    "por qué",
+    "cómo",
+    "cuándo",
+    "dónde",
+    "quién",
+    "cuál",
+    "cuáles",
+    "quando",
+    "onde",
+    "quem",
+    "qual",
+    "perché",
+    "cosa",
+    "chi",
+    "dove",
+    // Russian / Ukrainian
+    "что",
+    "почему",
+    "зачем",
+    "как",
+    "когда",
+    "где",
+    "кто",
+    "какой",
+    "какая",
+    "какие",
+    "сколько",
+    "ли",
+    "що",
+    "чому",
+    "навіщо",
+    "як",
+    "коли",
+    "де",
+    "хто",
+    "який",
+    "яка",
+    "які",
+    "скільки",
+    "чи",
+];
+
+const QUESTION_MARKS: &[char] = &['?', '？', '¿'];
+const SENTENCE_ENDS: &[char] = &['.', '!', '?', '…', '。', '！', '？'];
+
+// Turn-taking signals gathered from transcript updates between two gate checks.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct TurnSignals {
+    // Another speaker took over after the last gate check
+    pub speaker_changed: bool,
+    // The recognizer reported an endpoint (`<end>` from Soniox)
+    pub endpoint: bool,
+}
+
+#[derive(Debug, Default)]
+pub struct TurnTracker {
+    last_speaker: Option<i64>,
+    pending: TurnSignals,
+}
+
+impl TurnTracker {
+    pub fn observe(&mut self, update: &TranscriptUpdate) {
+        if update.endpoint {
+            self.pending.endpoint = true;
+        }
+        let Some(speaker) = update
+            .segments
+            .iter()
+            .rev()
+            .find(|s| s.is_final && !s.text.trim().is_empty())
+            .map(|s| s.speaker)
+        else {
+            return;
+        };
+        if self.last_speaker.is_some_and(|last| last != speaker) {
+            self.pending.speaker_changed = true;
+        }
+        self.last_speaker = Some(speaker);
+    }
+
+    // Signals since the previous call; resets them.
+    pub fn take(&mut self) -> TurnSignals {
+        std::mem::take(&mut self.pending)
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LocalSignals {
+    pub new_words: usize,
+    pub question: bool,
+    pub sentence_complete: bool,
+    pub turn: TurnSignals,
+}
+
+#[derive(Debug, Clone)]
+pub struct LocalDecision {
+    pub decision: GateJson,
+    // False when the signals disagree and a model should have the final say
+    pub clear: bool,
+    pub signals: LocalSignals,
+}
+
+// Spoken text of a rendered transcript, without "Speaker N: [lang]" labels
+// and the separator line.
+fn spoken_text(transcript: &str) -> String {
+    let mut text = String::new();
+    for line in transcript.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.chars().all(|c| c == '=') {
+            continue;
+        }
+        let line = match line.strip_prefix("Speaker") {
+            Some(rest) => rest
+                .split_once(':')
+                .map(|(_, body)| body.trim_start())
+                .unwrap_or(line),
+            None => line,
+        };
+        let line = match line.strip_prefix('[') {
+            Some(rest) => rest
+                .split_once(']')
+                .map(|(_, body)| body.trim_start())
+                .unwrap_or(line),
+            None => line,
+        };
+        if !text.is_empty() {
+            text.push(' ');
+        }
+        text.push_str(line);
+    }
+    text
+}
+
+fn words(text: &str) -> Vec<String> {
+    text.split_whitespace()
+        .map(|w| {
+            w.trim_matches(|c: char| !c.is_alphanumeric())
+                .to_lowercase()
+        })
+        .filter(|w| !w.is_empty())
+        .collect()
+}
+
+// Words in `current` that `previous` does not account for: the common
+// prefix is skipped, then the rest is compared as a multiset so re-ordered
+// or re-recognized words in the tail are not counted twice.
+pub fn new_word_count(previous: &[String], current: &[String]) -> usize {
+    let common = previous
+        .iter()
+        .zip(current)
+        .take_while(|(a, b)| a == b)
+        .count();
+    let mut remaining: HashMap<&str, usize> = HashMap::new();
+    for word in &previous[common..] {
+        *remaining.entry(word.as_str()).or_default() += 1;
+    }
+    current[common..]
+        .iter()
+        .filter(|word| match remaining.get_mut(word.as_str()) {
+            Some(count) if *count > 0 => {
+                *count -= 1;
+                false
+            }
+            _ => true,
+        })
+        .count()
+}
+
+fn last_sentence(text: &str) -> &str {
+    let trimmed = text.trim_end().trim_end_matches(SENTENCE_ENDS);
+    let start = trimmed
+        .rfind(SENTENCE_ENDS)
+        .map(|i| i + trimmed[i..].chars().next().map_or(1, char::len_utf8))
+        .unwrap_or(0);
+    text[start..].trim()
+}
+
+pub fn is_question(sentence: &str) -> bool {
+    let sentence = sentence.trim();
+    if sentence.contains(QUESTION_MARKS) {
+        return true;
+    }
+    let lower = sentence.to_lowercase();
+    let mut tokens = lower
+        .split_whitespace()
+        .map(|w| w.trim_matches(|c: char| !c.is_alphanumeric() && c != '-'));
+    let Some(first) = tokens.next() else {
+        return false;
+    };
+    if INTERROGATIVES.contains(&first) {
+        return true;
+    }
+    // Two-word openers ("por qué") and Russian "... ли" questions
+    let second = tokens.next().unwrap_or_default();
+    INTERROGATIVES.contains(&format!("{} {}", first, second).as_str()) || second == "ли"
+}
+
+pub fn collect_signals(current: &str, previous: &str, turn: TurnSignals) -> LocalSignals {
+    let current = spoken_text(current);
+    let previous = spoken_text(previous);
+    let sentence = last_sentence(&current);
+    LocalSignals {
+        new_words: new_word_count(&words(&previous), &words(&current)),
+        question: is_question(sentence),
+        sentence_complete: current.trim_end().ends_with(SENTENCE_ENDS),
+        turn,
+    }
+}
+
+fn decision(run: bool, reason: &str, confidence: f32) -> GateJson {
+    GateJson {
+        run,
+        instruction: Some(if run { "NEEDED" } else { "NOT_NEEDED" }.into()),
+        reason: Some(format!("Local: {}", reason)),
+        confidence: Some(confidence),
+    }
+}
+
+pub fn evaluate(current: &str, previous: &str, turn: TurnSignals) -> LocalDecision {
+    let signals = collect_signals(current, previous, turn);
+    let finished = signals.sentence_complete || turn.endpoint;
+    let (run, clear, reason, confidence) = if signals.new_words == 0 {
+        (false, true, "no new words", 0.95)
+    } else if signals.new_words < MIN_NEW_WORDS && !signals.question {
+        (false, true, "only minor edits", 0.85)
+    } else if signals.question && finished {
+        (true, true, "completed question", 0.9)
+    } else if !finished && !turn.speaker_changed {
+        (false, true, "utterance not finished", 0.8)
+    } else if signals.new_words >= SUBSTANTIAL_NEW_WORDS && (turn.endpoint || turn.speaker_changed)
+    {
+        (true, true, "finished turn with new content", 0.8)
+    } else {
+        // Finished but short, or a turn change mid-sentence: hard to call
+        let run = finished && signals.new_words >= SUBSTANTIAL_NEW_WORDS;
+        (run, false, "ambiguous", 0.5)
+    };
+    LocalDecision {
+        decision: decision(run, reason, confidence),
+        clear,
+        signals,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transcription::TranscriptSegment;
+
+    fn strings(text: &str) -> Vec<String> {
+        words(text)
+    }
+
+    #[test]
+    fn test_new_word_count_ignores_revisions() {
+        let previous = strings("we should ship the release on friday");
+        assert_eq!(new_word_count(&previous, &previous), 0);
+        let grown = strings("we should ship the release on friday if tests pass");
+        assert_eq!(new_word_count(&previous, &grown), 3);
+        // A re-recognized word in the tail counts once, re-ordering not at all
+        let revised = strings("we should ship the release friday on");
+        assert_eq!(new_word_count(&previous, &revised), 0);
+        let corrected = strings("we should ship the release on monday");
+        assert_eq!(new_word_count(&previous, &corrected), 1);
+    }
+
+    #[test]
+    fn test_is_question_across_languages() {
+        assert!(is_question("Can you send me the report"));
+        assert!(is_question("Wann beginnt das Meeting"));
+        assert!(is_question("Pourquoi pas"));
+        assert!(is_question("¿Por qué no funciona"));
+        assert!(is_question("Почему это не работает"));
+        assert!(is_question("Знаешь ли ты ответ"));
+        assert!(is_question("Чи можна це зробити"));
+        assert!(is_question("That works?"));
+        assert!(!is_question("We ship on Friday."));
+        assert!(!is_question(""));
+    }
+
+    #[test]
+    fn test_evaluate_decisions() {
+        let previous = "Speaker 1: [en] Thanks for joining.\n===============================";
+        let unchanged = evaluate(previous, previous, TurnSignals::default());
+        assert!(!unchanged.decision.run && unchanged.clear);
+
+        let question = "Speaker 1: [en] Thanks for joining. What is the budget for the next quarter?\n===============================";
+        let asked = evaluate(question, previous, TurnSignals::default());
+        assert!(asked.decision.run && asked.clear);
+        assert!(asked.signals.question);
+
+        let partial = "Speaker 1: [en] Thanks for joining. So the plan for the next quarter is to";
+        let unfinished = evaluate(partial, previous, TurnSignals::default());
+        assert!(!unfinished.decision.run && unfinished.clear);
+
+        let statement = "Speaker 1: [en] Thanks for joining. The plan is to hire two engineers and move the launch to the end of March.";
+        let turn = TurnSignals {
+            speaker_changed: true,
+            endpoint: false,
+        };
+        let finished = evaluate(statement, previous, turn);
+        assert!(finished.decision.run && finished.clear);
+        // Without a turn signal the same statement is left to the model
+        let ambiguous = evaluate(statement, previous, TurnSignals::default());
+        assert!(!ambiguous.clear);
+    }
+
+    #[test]
+    fn test_turn_tracker() {
+        let segment = |speaker: i64, is_final: bool| TranscriptSegment {
+            speaker,
+            language: None,
+            text: "hello".into(),
+            start_ms: None,
+            end_ms: None,
+            confidence: None,
+            is_final,
+            translation_status: Default::default(),
+        };
+        let mut tracker = TurnTracker::default();
+        tracker.observe(&TranscriptUpdate {
+            segments: vec![segment(1, true)],
+            ..Default::default()
+        });
+        assert_eq!(tracker.take(), TurnSignals::default());
+        // Tentative tokens from another speaker do not count yet
+        tracker.observe(&TranscriptUpdate {
+            segments: vec![segment(1, true), segment(2, false)],
+            ..Default::default()
+        });
+        assert!(!tracker.take().speaker_changed);
+        tracker.observe(&TranscriptUpdate {
+            segments: vec![segment(1, true), segment(2, true)],
+            endpoint: true,
+            ..Default::default()
+        });
+        assert_eq!(
+            tracker.take(),
+            TurnSignals {
+                speaker_changed: true,
+                endpoint: true
+            }
+        );
+        assert_eq!(tracker.take(), TurnSignals::default());
+    }
+}
@@ -0,0 +1,501 @@
+use crate::{
+    llm::{AiError, ChatMessage, ChatRequest, ChatResponse, ChatRole, LlmProvider},
+    transcription::{TranscriptSegment, TranscriptUpdate, TranslationStatus},
+    utils::log_to_file,
+};
+use serde::{Deserialize, Serialize};
+
+// Assistant id used for the summarizer's usage records
+pub const MEMORY_ID: &str = "memory";
+
+// Rolling summary of long transcripts. Once the transcript outgrows
+// `compact_at_tokens`, everything but the recent tail is folded into a summary
+// and the analysis prompt carries summary + tail instead of the full text.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MemoryConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    // Tail kept verbatim after a compaction
+    #[serde(default = "default_recent_tokens")]
+    pub recent_tokens: usize,
+    // When set, the verbatim tail is the last N seconds of speech instead
+    #[serde(default)]
+    pub recent_seconds: Option<u64>,
+    // Capped at half the analysis model's context window
+    #[serde(default = "default_compact_at_tokens")]
+    pub compact_at_tokens: usize,
+    #[serde(default = "default_summary_max_tokens")]
+    pub summary_max_tokens: u32,
+    // Model that writes the summary; the provider's gate model when unset
+    #[serde(default)]
+    pub summary_model: Option<String>,
+}
+
+fn default_recent_tokens() -> usize {
+    1500
+}
+
+fn default_compact_at_tokens() -> usize {
+    6000
+}
+
+fn default_summary_max_tokens() -> u32 {
+    400
+}
+
+impl Default for MemoryConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            recent_tokens: default_recent_tokens(),
+            recent_seconds: None,
+            compact_at_tokens: default_compact_at_tokens(),
+            summary_max_tokens: default_summary_max_tokens(),
+            summary_model: None,
+        }
+    }
+}
+
+// Model id without a `vendor/` prefix, lowercased.
+fn base_model(model: &str) -> String {
+    model
+        .rsplit('/')
+        .next()
+        .unwrap_or(model)
+        .to_ascii_lowercase()
+}
+
+fn is_openai_o200k(model: &str) -> bool {
+    ["gpt-4o", "gpt-4.1", "gpt-5", "o1", "o3", "o4", "chatgpt-4o"]
+        .iter()
+        .any(|prefix| model.starts_with(prefix))
+}
+
+// Characters of Latin-script text per token for a model family's tokenizer.
+fn chars_per_token(model: &str) -> f32 {
+    let model = base_model(model);
+    if model.starts_with("claude") {
+        3.5
+    } else if is_openai_o200k(&model) || model.starts_with("gemini") {
+        4.0
+    } else if model.starts_with("gpt-") {
+        3.8
+    } else {
+        // Llama, Mistral and other open models have smaller vocabularies
+        3.3
+    }
+}
+
+fn is_cjk(c: char) -> bool {
+    matches!(c as u32,
+        0x3040..=0x30FF // Hiragana, Katakana
+        | 0x3400..=0x4DBF // CJK extension A
+        | 0x4E00..=0x9FFF // CJK unified ideographs
+        | 0xAC00..=0xD7AF // Hangul syllables
+        | 0xF900..=0xFAFF) // CJK compatibility ideographs
+}
+
+// Rough token count without a tokenizer: ASCII is divided by the model's
+// chars-per-token, other scripts cost double, CJK one token per character.
+pub fn estimate_tokens(model: &str, text: &str) -> usize {
+    let (mut latin, mut wide) = (0usize, 0usize);
+    for c in text.chars() {
+        if c.is_ascii() {
+            latin += 1;
+        } else if is_cjk(c) {
+            wide += 1;
+        } else {
+            latin += 2;
+        }
+    }
+    (latin as f32 / chars_per_token(model)).ceil() as usize + wide
+}
+
+// Context window of the analysis model, conservative for unknown ids.
+pub fn context_window(model: &str) -> usize {
+    let model = base_model(model);
+    if model.starts_with("gemini") {
+        1_000_000
+    } else if model.starts_with("claude") {
+        200_000
+    } else if is_openai_o200k(&model) || model.starts_with("gpt-4-turbo") {
+        128_000
+    } else if model.starts_with("gpt-3.5") {
+        16_385
+    } else if model.starts_with("gpt-4") {
+        8_192
+    } else {
+        32_000
+    }
+}
+
+pub fn compact_threshold(config: &MemoryConfig, model: &str) -> usize {
+    config.compact_at_tokens.min(context_window(model) / 2)
+}
+
+// Chronological "Speaker N: [lang] text" lines; consecutive segments of one
+// speaker share a line. Translations are left out, their originals are kept.
+pub fn render_segments(segments: &[TranscriptSegment]) -> String {
+    let mut lines: Vec<(i64, String)> = Vec::new();
+    for segment in segments
+        .iter()
+        .filter(|s| s.translation_status != TranslationStatus::Translation)
+    {
+        match lines.last_mut() {
+            Some((speaker, line)) if *speaker == segment.speaker => {
+                line.push(' ');
+                line.push_str(&segment.text);
+            }
+            _ => {
+                let label = if segment.speaker > 0 {
+                    format!("Speaker {}:", segment.speaker)
+                } else {
+                    "Speaker:".to_string()
+                };
+                let language = segment.language.as_deref().unwrap_or("?");
+                lines.push((
+                    segment.speaker,
+                    format!("{} [{}] {}", label, language, segment.text),
+                ));
+            }
+        }
+    }
+    lines
+        .into_iter()
+        .map(|(_, line)| line)
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+// What an analysis prompt sees of a compacted transcript.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TranscriptWindow {
+    pub summary: String,
+    pub recent: String,
+}
+
+impl TranscriptWindow {
+    // Stand-in for `{{transcript}}` in templates that do not use the slots
+    pub fn combined(&self) -> String {
+        format!(
+            "Summary of the earlier conversation:\n{}\n\nRecent transcript:\n{}",
+            self.summary, self.recent
+        )
+    }
+}
+
+// Older segments to fold into the summary. `until` is exclusive.
+#[derive(Debug, Clone)]
+pub struct CompactionJob {
+    base: usize,
+    until: usize,
+    last_text: String,
+    pub previous_summary: String,
+    pub excerpt: String,
+}
+
+#[derive(Debug, Default)]
+pub struct TranscriptMemory {
+    // Segments of the latest transcript update
+    segments: Vec<TranscriptSegment>,
+    summary: String,
+    // Leading segments already folded into `summary`
+    summarized: usize,
+    // Text of the last folded segment, to notice a replaced transcript
+    summarized_text: String,
+    compacting: bool,
+}
+
+impl TranscriptMemory {
+    pub fn observe(&mut self, update: &TranscriptUpdate) {
+        // Status-only updates ("[no speech detected]") carry no segments
+        if update.segments.is_empty() {
+            return;
+        }
+        self.segments = update.segments.clone();
+        let intact = self.summarized == 0
+            || self
+                .segments
+                .get(self.summarized - 1)
+                .is_some_and(|s| s.text == self.summarized_text);
+        if !intact {
+            log_to_file("Memory: transcript replaced; dropping the rolling summary");
+            self.reset_summary();
+        }
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    pub fn segments(&self) -> &[TranscriptSegment] {
+        &self.segments
+    }
+
+    fn reset_summary(&mut self) {
+        self.summary.clear();
+        self.summarized = 0;
+        self.summarized_text.clear();
+    }
+
+    // Final segments that can no longer change: the last final segment still
+    // absorbs tokens as they are finalized.
+    fn foldable(&self) -> usize {
+        self.segments
+            .iter()
+            .take_while(|s| s.is_final)
+            .count()
+            .saturating_sub(1)
+    }
+
+    // Index of the first segment kept verbatim.
+    fn recent_start(&self, config: &MemoryConfig, model: &str) -> usize {
+        if let Some(seconds) = config.recent_seconds {
+            if let Some(last_end) = self.segments.iter().filter_map(|s| s.end_ms).max() {
+                let cutoff = last_end.saturating_sub(seconds * 1000);
+                return self
+                    .segments
+                    .iter()
+                    .position(|s| s.end_ms.is_none_or(|end| end >= cutoff))
+                    .unwrap_or(self.segments.len());
+            }
+        }
+        let mut tokens = 0;
+        let mut start = self.segments.len();
+        for (i, segment) in self.segments.iter().enumerate().rev() {
+            tokens += estimate_tokens(model, &segment.text);
+            if tokens > config.recent_tokens {
+                break;
+            }
+            start = i;
+        }
+        start
+    }
+
+    // The prompt view once a summary exists.
+    pub fn window(&self) -> Option<TranscriptWindow> {
+        if self.summarized == 0 {
+            return None;
+        }
+        Some(TranscriptWindow {
+            summary: self.summary.clone(),
+            recent: render_segments(&self.segments[self.summarized..]),
+        })
+    }
+
+    // A job when summary plus unsummarized transcript outgrow the threshold.
+    // Only one job runs at a time; finish it with `apply`.
+    pub fn plan_compaction(&mut self, config: &MemoryConfig, model: &str) -> Option<CompactionJob> {
+        if self.compacting {
+            return None;
+        }
+        let pending = render_segments(&self.segments[self.summarized..]);
+        let size = estimate_tokens(model, &self.summary) + estimate_tokens(model, &pending);
+        if size <= compact_threshold(config, model) {
+            return None;
+        }
+        let until = self.recent_start(config, model).min(self.foldable());
+        if until <= self.summarized {
+            return None;
+        }
+        self.compacting = true;
+        Some(CompactionJob {
+            base: self.summarized,
+            until,
+            last_text: self.segments[until - 1].text.clone(),
+            previous_summary: self.summary.clone(),
+            excerpt: render_segments(&self.segments[self.summarized..until]),
+        })
+    }
+
+    // Store the summary written for `job`, unless the transcript moved on
+    // underneath it. `None` when the summary call failed.
+    pub fn apply(&mut self, job: CompactionJob, summary: Option<String>) {
+        self.compacting = false;
+        let Some(summary) = summary.filter(|s| !s.trim().is_empty()) else {
+            return;
+        };
+        let unchanged = self.summarized == job.base
+            && self
+                .segments
+                .get(job.until - 1)
+                .is_some_and(|s| s.text == job.last_text);
+        if !unchanged {
+            log_to_file("Memory: transcript changed during compaction; summary discarded");
+            return;
+        }
+        self.summary = summary.trim().to_string();
+        self.summarized = job.until;
+        self.summarized_text = job.last_text;
+    }
+}
+
+pub fn prepare_summary_prompt(job: &CompactionJob, max_tokens: u32) -> (String, String) {
+    let system_prompt = format!(
+        "You keep a running summary of a live conversation for an assistant that only sees this summary and the latest part of the transcript. Merge the earlier summary with the new transcript excerpt into one summary. Keep decisions, open questions, commitments, names, numbers and who said what. Write in the language of the conversation, as plain text of at most about {} words. No preamble.",
+        max_tokens * 3 / 4
+    );
+    let user_prompt = format!(
+        "Earlier summary:\n{}\n\nTranscript excerpt to fold in:\n{}",
+        if job.previous_summary.is_empty() {
+            "(none)"
+        } else {
+            &job.previous_summary
+        },
+        job.excerpt
+    );
+    (system_prompt, user_prompt)
+}
+
+pub async fn summarize(
+    provider: &dyn LlmProvider,
+    model: &str,
+    job: &CompactionJob,
+    max_tokens: u32,
+) -> Result<ChatResponse, AiError> {
+    let label = provider.label();
+    let (system_prompt, user_prompt) = prepare_summary_prompt(job, max_tokens);
+    log_to_file(&format!(
+        "{}(Memory): Request model={} segments={}..{} excerpt_len={} summary_len={}",
+        label,
+        model,
+        job.base,
+        job.until,
+        job.excerpt.len(),
+        job.previous_summary.len()
+    ));
+    let request = ChatRequest {
+        model: model.to_string(),
+        messages: vec![
+            ChatMessage::new(ChatRole::System, system_prompt),
+            ChatMessage::new(ChatRole::User, user_prompt),
+        ],
+        max_tokens,
+        temperature: 0.0,
+        response_format: None,
+        top_p: None,
+        stop: Vec::new(),
+    };
+    let response = provider.chat(&request).await.map_err(|e| {
+        log_to_file(&format!("{}(Memory): API error: {}", label, e));
+        e
+    })?;
+    log_to_file(&format!(
+        "{}(Memory): Summary=<<<{}>>>",
+        label, response.text
+    ));
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn segment(speaker: i64, text: &str, end_ms: u64, is_final: bool) -> TranscriptSegment {
+        TranscriptSegment {
+            speaker,
+            language: Some("en".into()),
+            text: text.into(),
+            start_ms: Some(end_ms.saturating_sub(1000)),
+            end_ms: Some(end_ms),
+            confidence: None,
+            is_final,
+            translation_status: TranslationStatus::None,
+        }
+    }
+
+    #[test]
+    fn test_estimate_tokens_per_model() {
+        let text = "We should move the launch to March and hire two more engineers.";
+        let claude = estimate_tokens("claude-sonnet-4-0", text);
+        let gpt = estimate_tokens("openai/gpt-4.1", text);
+        assert!(claude > gpt, "{} <= {}", claude, gpt);
+        assert_eq!(gpt, 16);
+        assert_eq!(estimate_tokens("gpt-4.1", "会議を始めます"), 7);
+        assert!(estimate_tokens("gpt-4.1", "Почему") > estimate_tokens("gpt-4.1", "Pochemu"));
+        assert_eq!(estimate_tokens("gpt-4.1", ""), 0);
+
+        let config = MemoryConfig::default();
+        assert_eq!(compact_threshold(&config, "gemini-2.5-flash"), 6000);
+        assert_eq!(compact_threshold(&config, "gpt-4"), 4096);
+    }
+
+    #[test]
+    fn test_render_segments_chronologically() {
+        let mut translated = segment(2, "Hola", 3000, true);
+        translated.translation_status = TranslationStatus::Translation;
+        let segments = vec![
+            segment(1, "Hello.", 1000, true),
+            segment(1, "How are you?", 2000, true),
+            segment(2, "Fine.", 3000, true),
+            translated,
+            segment(1, "Good.", 4000, false),
+        ];
+        assert_eq!(
+            render_segments(&segments),
+            "Speaker 1: [en] Hello. How are you?\nSpeaker 2: [en] Fine.\nSpeaker 1: [en] Good."
+        );
+    }
+
+    #[test]
+    fn test_compaction_keeps_recent_tail() {
+        let config = MemoryConfig {
+            enabled: true,
+            recent_tokens: 20,
+            compact_at_tokens: 60,
+            ..Default::default()
+        };
+        let model = "gpt-4.1";
+        let line = "This sentence is about forty characters.";
+        let mut segments: Vec<TranscriptSegment> = (0..10)
+            .map(|i| segment(i % 2 + 1, line, (i as u64 + 1) * 1000, true))
+            .collect();
+        segments.push(segment(1, "and then", 11_000, false));
+        let mut memory = TranscriptMemory::default();
+        memory.observe(&TranscriptUpdate {
+            segments: segments.clone(),
+            ..Default::default()
+        });
+        assert!(memory.window().is_none());
+
+        let job = memory.plan_compaction(&config, model).unwrap();
+        // Two 10-token segments plus the tentative tail stay verbatim
+        assert_eq!((job.base, job.until), (0, 9));
+        assert!(memory.plan_compaction(&config, model).is_none());
+        memory.apply(job, Some("Earlier: ten remarks.".into()));
+        let window = memory.window().unwrap();
+        assert_eq!(window.summary, "Earlier: ten remarks.");
+        assert_eq!(
+            window.recent,
+            format!("Speaker 2: [en] {}\nSpeaker 1: [en] and then", line)
+        );
+        assert!(memory.plan_compaction(&config, model).is_none());
+
+        // By time: segments ending in the last 2 seconds stay verbatim
+        let timed = MemoryConfig {
+            recent_seconds: Some(2),
+            ..config.clone()
+        };
+        let mut memory = TranscriptMemory::default();
+        memory.observe(&TranscriptUpdate {
+            segments: segments.clone(),
+            ..Default::default()
+        });
+        let job = memory.plan_compaction(&timed, model).unwrap();
+        assert_eq!(job.until, 8);
+
+        // A new transcript drops the summary
+        let mut memory = TranscriptMemory::default();
+        memory.observe(&TranscriptUpdate {
+            segments: segments.clone(),
+            ..Default::default()
+        });
+        let job = memory.plan_compaction(&config, model).unwrap();
+        memory.apply(job, Some("summary".into()));
+        memory.observe(&TranscriptUpdate {
+            segments: vec![segment(1, "Fresh start", 1000, true)],
+            ..Default::default()
+        });
+        assert!(memory.window().is_none());
+    }
+}
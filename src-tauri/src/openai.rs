@@ -1,16 +1,67 @@
-use crate::{assistants::render_user_prompt, utils::log_to_file};
+use crate::{
+    llm::{
+        self, AiError, ChatRequest, ChatResponse, ChatRole, DeltaCallback, ErrorCategory,
+        LlmProvider, StreamEvent, TokenUsage,
+    },
+    retry::RetryPolicy,
+    templates::PromptVars,
+    utils::log_to_file,
+};
+use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+pub const DEFAULT_BASE_URL: &str = "https://api.openai.com/v1";
+
+pub fn default_base_url() -> String {
+    DEFAULT_BASE_URL.to_string()
+}
+
+// Join an OpenAI-compatible base URL (".../v1") with an endpoint path.
+pub fn endpoint_url(base_url: &str, path: &str) -> String {
+    let base = base_url.trim().trim_end_matches('/');
+    let base = if base.is_empty() {
+        DEFAULT_BASE_URL
+    } else {
+        base
+    };
+    format!("{}/{}", base, path.trim_start_matches('/'))
+}
+
+pub fn is_default_base_url(base_url: &str) -> bool {
+    endpoint_url(base_url, "") == endpoint_url(DEFAULT_BASE_URL, "")
+}
+
+// Attach the bearer token, or send no Authorization header in keyless mode.
+pub fn with_auth(request: reqwest::RequestBuilder, api_key: &str) -> reqwest::RequestBuilder {
+    let api_key = api_key.trim();
+    if api_key.is_empty() {
+        request
+    } else {
+        request.header("Authorization", format!("Bearer {}", api_key))
+    }
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub struct OpenAIOptions {
     pub api_key: String,
+    #[serde(default = "default_base_url")]
+    pub base_url: String,
     pub model: String,
     pub system_prompt: String,
     #[serde(default)]
     pub output_policy: String,
     #[serde(default = "crate::assistants::default_user_prompt_template")]
     pub user_prompt: String,
+    #[serde(default)]
+    pub context: String,
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+fn default_timeout_secs() -> u64 {
+    llm::DEFAULT_TIMEOUT_SECS
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -20,11 +71,26 @@ struct ChatMessage {
 }
 
 #[derive(Debug, Serialize)]
-struct ChatRequest {
+struct StreamOptions {
+    include_usage: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct WireRequest {
     model: String,
     messages: Vec<ChatMessage>,
     max_completion_tokens: u32,
     temperature: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    stop: Vec<String>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stream_options: Option<StreamOptions>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response_format: Option<serde_json::Value>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -32,9 +98,40 @@ struct ChatChoice {
     message: ChatMessage,
 }
 
+#[derive(Debug, Default, Deserialize)]
+struct PromptTokensDetails {
+    #[serde(default)]
+    cached_tokens: u32,
+}
+
 #[derive(Debug, Deserialize)]
-struct ChatResponse {
+struct WireUsage {
+    #[serde(default)]
+    prompt_tokens: u32,
+    #[serde(default)]
+    completion_tokens: u32,
+    #[serde(default)]
+    prompt_tokens_details: Option<PromptTokensDetails>,
+}
+
+impl From<WireUsage> for TokenUsage {
+    fn from(value: WireUsage) -> Self {
+        Self {
+            input_tokens: value.prompt_tokens,
+            output_tokens: value.completion_tokens,
+            cached_input_tokens: value
+                .prompt_tokens_details
+                .map(|d| d.cached_tokens)
+                .unwrap_or(0),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct WireResponse {
     choices: Vec<ChatChoice>,
+    #[serde(default)]
+    usage: Option<WireUsage>,
 }
 
 pub fn temperature_for_model(model: &str, default: f32) -> f32 {
@@ -45,6 +142,234 @@ pub fn temperature_for_model(model: &str, default: f32) -> f32 {
     }
 }
 
+// Like the temperature, nucleus sampling is fixed on gpt-5 models.
+pub fn top_p_for_model(model: &str, top_p: Option<f32>) -> Option<f32> {
+    top_p.filter(|_| !model.contains("gpt-5"))
+}
+
+fn role_name(role: ChatRole) -> &'static str {
+    match role {
+        ChatRole::System => "system",
+        ChatRole::User => "user",
+        ChatRole::Assistant => "assistant",
+    }
+}
+
+fn wire_request(request: &ChatRequest, stream: bool) -> WireRequest {
+    WireRequest {
+        model: request.model.clone(),
+        messages: request
+            .messages
+            .iter()
+            .map(|m| ChatMessage {
+                role: role_name(m.role).to_string(),
+                content: m.content.clone(),
+            })
+            .collect(),
+        max_completion_tokens: request.max_tokens,
+        temperature: temperature_for_model(&request.model, request.temperature),
+        top_p: top_p_for_model(&request.model, request.top_p),
+        stop: request.stop.clone(),
+        stream,
+        stream_options: stream.then_some(StreamOptions {
+            include_usage: true,
+        }),
+        response_format: request.response_format.as_ref().map(|format| {
+            serde_json::json!({
+                "type": "json_schema",
+                "json_schema": {
+                    "name": format.name,
+                    "strict": true,
+                    "schema": format.schema,
+                },
+            })
+        }),
+    }
+}
+
+// OpenAI chat completions, or any server that speaks the same API.
+pub struct OpenAiProvider {
+    api_key: String,
+    base_url: String,
+    client: reqwest::Client,
+    timeout: Duration,
+    retry: RetryPolicy,
+}
+
+impl OpenAiProvider {
+    pub fn new(api_key: &str, base_url: &str, timeout: Duration) -> Self {
+        Self {
+            api_key: api_key.to_string(),
+            base_url: base_url.to_string(),
+            client: llm::http_client(),
+            timeout,
+            retry: RetryPolicy::default(),
+        }
+    }
+
+    async fn post_chat(&self, body: &WireRequest) -> Result<reqwest::Response, AiError> {
+        let url = endpoint_url(&self.base_url, "chat/completions");
+        llm::send_with_retry("OpenAI", &self.retry, self.timeout, || {
+            let request = with_auth(self.client.post(&url), &self.api_key)
+                .header("Content-Type", "application/json")
+                .json(body);
+            // A streamed body may legitimately take longer than the timeout
+            if body.stream {
+                request
+            } else {
+                request.timeout(self.timeout)
+            }
+        })
+        .await
+    }
+
+    // Vectors for `inputs` from the /embeddings endpoint, in input order,
+    // and the tokens billed for them.
+    pub async fn embeddings(
+        &self,
+        model: &str,
+        inputs: &[String],
+    ) -> Result<(Vec<Vec<f32>>, TokenUsage), AiError> {
+        let url = endpoint_url(&self.base_url, "embeddings");
+        let body = EmbeddingsRequest {
+            model,
+            input: inputs,
+        };
+        let response = llm::send_with_retry("OpenAI", &self.retry, self.timeout, || {
+            with_auth(self.client.post(&url), &self.api_key)
+                .header("Content-Type", "application/json")
+                .json(&body)
+                .timeout(self.timeout)
+        })
+        .await?;
+        let mut response: EmbeddingsResponse = llm::read_json("OpenAI", response).await?;
+        if response.data.len() != inputs.len() {
+            return Err(AiError::new(
+                ErrorCategory::Other,
+                format!(
+                    "OpenAI returned {} embeddings for {} inputs",
+                    response.data.len(),
+                    inputs.len()
+                ),
+            ));
+        }
+        response.data.sort_by_key(|item| item.index);
+        let usage = TokenUsage {
+            input_tokens: response.usage.map_or(0, |u| u.prompt_tokens),
+            ..TokenUsage::default()
+        };
+        let vectors = response
+            .data
+            .into_iter()
+            .map(|item| item.embedding)
+            .collect();
+        Ok((vectors, usage))
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct EmbeddingsRequest<'a> {
+    model: &'a str,
+    input: &'a [String],
+}
+
+#[derive(Debug, Deserialize)]
+struct EmbeddingItem {
+    index: usize,
+    embedding: Vec<f32>,
+}
+
+#[derive(Debug, Deserialize)]
+struct EmbeddingsUsage {
+    #[serde(default)]
+    prompt_tokens: u32,
+}
+
+#[derive(Debug, Deserialize)]
+struct EmbeddingsResponse {
+    data: Vec<EmbeddingItem>,
+    #[serde(default)]
+    usage: Option<EmbeddingsUsage>,
+}
+
+#[async_trait]
+impl LlmProvider for OpenAiProvider {
+    fn label(&self) -> &'static str {
+        "OpenAI"
+    }
+
+    async fn chat(&self, request: &ChatRequest) -> Result<ChatResponse, AiError> {
+        let response = self.post_chat(&wire_request(request, false)).await?;
+        let chat: WireResponse = llm::read_json("OpenAI", response).await?;
+        let usage = chat.usage.map(TokenUsage::from);
+        match chat.choices.into_iter().next() {
+            Some(choice) => Ok(ChatResponse {
+                text: choice.message.content,
+                usage,
+                generation_id: None,
+            }),
+            None => Err(AiError::new(
+                ErrorCategory::Other,
+                "No response from OpenAI",
+            )),
+        }
+    }
+
+    async fn chat_stream(
+        &self,
+        request: &ChatRequest,
+        on_delta: &mut DeltaCallback<'_>,
+    ) -> Result<ChatResponse, AiError> {
+        let response = self.post_chat(&wire_request(request, true)).await?;
+        llm::collect_stream(
+            "OpenAI",
+            response,
+            parse_stream_event,
+            self.timeout,
+            on_delta,
+        )
+        .await
+    }
+
+    async fn list_models(&self) -> Result<Vec<String>, AiError> {
+        log_to_file(&format!(
+            "OpenAI: Fetching available models from {}",
+            self.base_url
+        ));
+
+        // Local servers name models freely, so only filter the official API
+        let filter_chat_models = is_default_base_url(&self.base_url);
+        let url = endpoint_url(&self.base_url, "models");
+        let response = llm::send_with_retry("OpenAI", &self.retry, self.timeout, || {
+            with_auth(self.client.get(&url), &self.api_key).timeout(self.timeout)
+        })
+        .await?;
+        let models_response: ModelsResponse = llm::read_json("OpenAI", response).await?;
+        let mut models: Vec<String> = models_response
+            .data
+            .into_iter()
+            .filter(|model| {
+                // Filter to only include relevant chat models
+                !filter_chat_models
+                    || model.id.starts_with("gpt-")
+                    || model.id == "o1-preview"
+                    || model.id == "o1-mini"
+            })
+            .map(|model| model.id)
+            .collect();
+
+        // Sort models with newer/better ones first
+        models.sort_by(|a, b| {
+            let order_a = get_model_priority(a);
+            let order_b = get_model_priority(b);
+            order_a.cmp(&order_b)
+        });
+
+        log_to_file(&format!("OpenAI: Found {} relevant models", models.len()));
+        Ok(models)
+    }
+}
+
 pub async fn analyze_conversation(
     opts: OpenAIOptions,
     transcript: String,
@@ -62,101 +387,98 @@ pub async fn analyze_conversation(
         last_out_len
     ));
 
-    // Compose system prompt with any assistant-defined output policy from config
-    let effective_system_prompt = if opts.output_policy.trim().is_empty() {
-        opts.system_prompt.clone()
-    } else {
-        format!("{}\n\n{}", opts.system_prompt, opts.output_policy)
+    let request = ChatRequest {
+        model: opts.model.clone(),
+        messages: llm::compose_messages(
+            &opts.system_prompt,
+            &opts.output_policy,
+            &opts.user_prompt,
+            &PromptVars {
+                last_output: last_output.as_deref(),
+                context: &opts.context,
+                ..PromptVars::new(&transcript)
+            },
+        ),
+        max_tokens: 500,
+        temperature: 0.0,
+        response_format: None,
+        top_p: None,
+        stop: Vec::new(),
     };
+    llm::log_main_prompt("OpenAI", &request);
 
-    let user_prompt = render_user_prompt(&opts.user_prompt, &transcript);
-
-    let mut messages = vec![ChatMessage {
-        role: "system".to_string(),
-        content: effective_system_prompt.clone(),
-    }];
-    if let Some(prev) = last_output {
-        if !prev.trim().is_empty() {
-            messages.push(ChatMessage {
-                role: "assistant".to_string(),
-                content: format!("Previous assistant answer (for context):\n{}", prev),
-            });
-        }
-    }
-    messages.push(ChatMessage {
-        role: "user".to_string(),
-        content: user_prompt,
-    });
+    let provider = OpenAiProvider::new(
+        &opts.api_key,
+        &opts.base_url,
+        Duration::from_secs(opts.timeout_secs.max(1)),
+    );
+    let analysis = provider.chat(&request).await?.text;
+    log_to_file(&format!("OpenAI(Main): Response=<<<{}>>>", analysis));
+    log_to_file(&format!(
+        "OpenAI(Model): Response model={} analysis_len={}",
+        opts.model,
+        analysis.len()
+    ));
+    Ok(analysis)
+}
 
-    if let Some(user_msg) = messages.iter().find(|m| m.role == "user") {
-        log_to_file(&format!(
-            "OpenAI(Main): Prompt system=<<<{}>>> user=<<<{}>>>",
-            effective_system_prompt, user_msg.content
-        ));
-    }
+#[derive(Debug, Deserialize)]
+struct StreamDelta {
+    #[serde(default)]
+    content: Option<String>,
+}
 
-    let temp = temperature_for_model(&opts.model, 0.0);
-    let model_name = opts.model.clone();
-    let request = ChatRequest {
-        model: opts.model,
-        messages,
-        max_completion_tokens: 500,
-        temperature: temp,
-    };
+#[derive(Debug, Deserialize)]
+struct StreamChoice {
+    delta: StreamDelta,
+    #[serde(default)]
+    finish_reason: Option<String>,
+}
 
-    let client = reqwest::Client::new();
+#[derive(Debug, Deserialize)]
+struct StreamError {
+    message: String,
+}
 
-    match client
-        .post("https://api.openai.com/v1/chat/completions")
-        .header("Authorization", format!("Bearer {}", opts.api_key))
-        .header("Content-Type", "application/json")
-        .json(&request)
-        .send()
-        .await
-    {
-        Ok(response) => {
-            let status = response.status();
-            if !status.is_success() {
-                let error_text = response
-                    .text()
-                    .await
-                    .unwrap_or_else(|_| "Unknown error".to_string());
-                log_to_file(&format!("OpenAI: API error {}: {}", status, error_text));
-                return Err(format!("OpenAI API error: {}", status));
-            }
+#[derive(Debug, Deserialize)]
+struct StreamChunk {
+    #[serde(default)]
+    choices: Vec<StreamChoice>,
+    #[serde(default)]
+    usage: Option<WireUsage>,
+    #[serde(default)]
+    error: Option<StreamError>,
+}
 
-            match response.json::<ChatResponse>().await {
-                Ok(chat_response) => {
-                    if let Some(choice) = chat_response.choices.first() {
-                        let analysis = &choice.message.content;
-                        log_to_file(&format!("OpenAI(Main): Response=<<<{}>>>", analysis));
-                        log_to_file(&format!(
-                            "OpenAI(Model): Response model={} analysis_len={}",
-                            model_name,
-                            analysis.len()
-                        ));
-                        Ok(analysis.clone())
-                    } else {
-                        Err("No response from OpenAI".to_string())
-                    }
-                }
-                Err(e) => {
-                    log_to_file(&format!("OpenAI: JSON parse error: {}", e));
-                    Err(format!("Failed to parse OpenAI response: {}", e))
-                }
-            }
+// Interpret one SSE payload from the chat completions stream.
+pub fn parse_stream_event(data: &str) -> Result<Vec<StreamEvent>, String> {
+    let data = data.trim();
+    if data == "[DONE]" {
+        return Ok(vec![StreamEvent::Done]);
+    }
+    let chunk: StreamChunk = serde_json::from_str(data)
+        .map_err(|e| format!("Failed to parse OpenAI stream chunk: {}", e))?;
+    if let Some(error) = chunk.error {
+        return Err(format!("OpenAI stream error: {}", error.message));
+    }
+    let mut events = Vec::new();
+    for choice in chunk.choices {
+        if let Some(content) = choice.delta.content.filter(|c| !c.is_empty()) {
+            events.push(StreamEvent::Delta(content));
         }
-        Err(e) => {
-            log_to_file(&format!("OpenAI: Request error: {}", e));
-            Err(format!("Failed to connect to OpenAI: {}", e))
+        if choice.finish_reason.is_some() {
+            events.push(StreamEvent::Finished);
         }
     }
+    if let Some(usage) = chunk.usage {
+        events.push(StreamEvent::Usage(usage.into()));
+    }
+    Ok(events)
 }
 
 #[derive(Debug, Deserialize)]
 struct ModelData {
     id: String,
-    object: String,
 }
 
 #[derive(Debug, Deserialize)]
@@ -164,72 +486,6 @@ struct ModelsResponse {
     data: Vec<ModelData>,
 }
 
-pub async fn get_available_models(api_key: String) -> Result<Vec<String>, String> {
-    if api_key.trim().is_empty() {
-        return Err("OpenAI API key is required".to_string());
-    }
-
-    log_to_file("OpenAI: Fetching available models");
-
-    let client = reqwest::Client::new();
-
-    match client
-        .get("https://api.openai.com/v1/models")
-        .header("Authorization", format!("Bearer {}", api_key))
-        .send()
-        .await
-    {
-        Ok(response) => {
-            let status = response.status();
-            if !status.is_success() {
-                let error_text = response
-                    .text()
-                    .await
-                    .unwrap_or_else(|_| "Unknown error".to_string());
-                log_to_file(&format!(
-                    "OpenAI: Models API error {}: {}",
-                    status, error_text
-                ));
-                return Err(format!("OpenAI API error: {}", status));
-            }
-
-            match response.json::<ModelsResponse>().await {
-                Ok(models_response) => {
-                    let mut models: Vec<String> = models_response
-                        .data
-                        .into_iter()
-                        .filter(|model| {
-                            // Filter to only include relevant chat models
-                            model.id.starts_with("gpt-")
-                                || model.id == "o1-preview"
-                                || model.id == "o1-mini"
-                        })
-                        .map(|model| model.id)
-                        .collect();
-
-                    // Sort models with newer/better ones first
-                    models.sort_by(|a, b| {
-                        let order_a = get_model_priority(a);
-                        let order_b = get_model_priority(b);
-                        order_a.cmp(&order_b)
-                    });
-
-                    log_to_file(&format!("OpenAI: Found {} relevant models", models.len()));
-                    Ok(models)
-                }
-                Err(e) => {
-                    log_to_file(&format!("OpenAI: JSON parse error: {}", e));
-                    Err(format!("Failed to parse OpenAI models response: {}", e))
-                }
-            }
-        }
-        Err(e) => {
-            log_to_file(&format!("OpenAI: Models request error: {}", e));
-            Err(format!("Failed to connect to OpenAI: {}", e))
-        }
-    }
-}
-
 fn get_model_priority(model: &str) -> u32 {
     match model {
         "o1-preview" => 1,
@@ -241,3 +497,58 @@ fn get_model_priority(model: &str) -> u32 {
         _ => 6,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_endpoint_url() {
+        assert_eq!(
+            endpoint_url("", "chat/completions"),
+            "https://api.openai.com/v1/chat/completions"
+        );
+        assert_eq!(
+            endpoint_url("http://localhost:11434/v1/", "/models"),
+            "http://localhost:11434/v1/models"
+        );
+        assert!(is_default_base_url("https://api.openai.com/v1/"));
+        assert!(!is_default_base_url("http://127.0.0.1:8080/v1"));
+    }
+
+    #[test]
+    fn test_parse_stream_event() {
+        let delta = r#"{"choices":[{"delta":{"content":"Hi"},"finish_reason":null}]}"#;
+        assert_eq!(
+            parse_stream_event(delta).unwrap(),
+            vec![StreamEvent::Delta("Hi".into())]
+        );
+        let role_only = r#"{"choices":[{"delta":{"role":"assistant"},"finish_reason":null}]}"#;
+        assert!(parse_stream_event(role_only).unwrap().is_empty());
+        let stop = r#"{"choices":[{"delta":{},"finish_reason":"stop"}]}"#;
+        assert_eq!(
+            parse_stream_event(stop).unwrap(),
+            vec![StreamEvent::Finished]
+        );
+        assert_eq!(
+            parse_stream_event("[DONE]").unwrap(),
+            vec![StreamEvent::Done]
+        );
+
+        let usage = r#"{"choices":[],"usage":{"prompt_tokens":12,"completion_tokens":3,"prompt_tokens_details":{"cached_tokens":8}}}"#;
+        assert_eq!(
+            parse_stream_event(usage).unwrap(),
+            vec![StreamEvent::Usage(TokenUsage {
+                input_tokens: 12,
+                output_tokens: 3,
+                cached_input_tokens: 8
+            })]
+        );
+
+        let error = r#"{"error":{"message":"overloaded","type":"server_error"}}"#;
+        assert!(parse_stream_event(error)
+            .unwrap_err()
+            .contains("overloaded"));
+        assert!(parse_stream_event("{not json").is_err());
+    }
+}
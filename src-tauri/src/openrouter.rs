@@ -1,27 +1,28 @@
 use crate::{
-    assistants::render_user_prompt,
-    gate::{self, GateJson, GatePrompt},
+    llm::{
+        AccountUsage, AiError, ChatRequest, ChatResponse, ChatRole, DeltaCallback, ErrorCategory,
+        LlmProvider, TokenUsage,
+    },
     openai,
+    retry::{self, RetryPolicy},
     utils::log_to_file,
 };
+use async_trait::async_trait;
+use futures_util::StreamExt;
 use openrouter_rs::{
     api::chat::{ChatCompletionRequest, Message},
     api::credits::CreditsData,
-    types::Role,
+    error::OpenRouterError,
+    types::{ResponseFormat, ResponseUsage, Role},
     OpenRouterClient,
 };
-use serde::Serialize;
+use std::future::Future;
+use std::time::Duration;
 
 const HTTP_REFERER: &str = "https://neuro-note.local";
 const X_TITLE: &str = "Neuro Note";
 
-#[derive(Debug, Clone, Serialize)]
-pub struct CreditSummary {
-    pub total_credits: f64,
-    pub total_usage: f64,
-}
-
-impl From<CreditsData> for CreditSummary {
+impl From<CreditsData> for AccountUsage {
     fn from(value: CreditsData) -> Self {
         Self {
             total_credits: value.total_credits,
@@ -42,152 +43,241 @@ pub fn build_client(api_key: &str) -> Result<OpenRouterClient, String> {
         })
 }
 
-pub fn compose_messages(
-    system_prompt: &str,
-    output_policy: &str,
-    user_prompt_template: &str,
-    transcript: &str,
-    last_output: Option<&str>,
-) -> Vec<Message> {
-    let effective_system_prompt = if output_policy.trim().is_empty() {
-        system_prompt.to_string()
-    } else {
-        format!("{}\n\n{}", system_prompt, output_policy)
-    };
-
-    let mut messages = vec![Message::new(Role::System, &effective_system_prompt)];
-
-    if let Some(prev) = last_output {
-        if !prev.trim().is_empty() {
-            messages.push(Message::new(
-                Role::Assistant,
-                &format!("Previous assistant answer (for context):\n{}", prev),
-            ));
+fn build_chat_request(request: &ChatRequest) -> Result<ChatCompletionRequest, String> {
+    let messages = request
+        .messages
+        .iter()
+        .map(|m| {
+            let role = match m.role {
+                ChatRole::System => Role::System,
+                ChatRole::User => Role::User,
+                ChatRole::Assistant => Role::Assistant,
+            };
+            Message::new(role, &m.content)
+        })
+        .collect();
+
+    let temperature = openai::temperature_for_model(&request.model, request.temperature) as f64;
+    let mut builder = ChatCompletionRequest::builder();
+    builder
+        .model(request.model.clone())
+        .messages(messages)
+        .max_tokens(request.max_tokens)
+        .temperature(temperature);
+    if let Some(top_p) = openai::top_p_for_model(&request.model, request.top_p) {
+        builder.top_p(top_p as f64);
+    }
+    // The SDK has no stop sequences. Assistants pinned to OpenRouter cannot
+    // set them; one that inherits OpenRouter from the UI fails here rather
+    // than running without them.
+    if !request.stop.is_empty() {
+        return Err(format!(
+            "OpenRouter does not support stop sequences ({:?}); remove `stop` or set another provider on the assistant",
+            request.stop
+        ));
+    }
+    if let Some(format) = &request.response_format {
+        builder.response_format(ResponseFormat::json_schema(
+            format.name.clone(),
+            true,
+            format.schema.clone(),
+        ));
+    }
+    builder.build().map_err(|e| {
+        log_to_file(&format!("OpenRouter: Failed to build chat request: {}", e));
+        format!("OpenRouter request error: {}", e)
+    })
+}
+
+// The SDK does not surface cached token counts; the billed cost from the
+// generation stats already reflects any cache discount.
+fn token_usage(usage: &ResponseUsage) -> TokenUsage {
+    TokenUsage {
+        input_tokens: usage.prompt_tokens,
+        output_tokens: usage.completion_tokens,
+        cached_input_tokens: 0,
+    }
+}
+
+// The SDK does not expose response headers, so Retry-After is unavailable
+// here and retries fall back to plain backoff.
+fn ai_error(context: &str, error: OpenRouterError) -> AiError {
+    let message = format!("{}: {}", context, error);
+    log_to_file(&format!("OpenRouter: {}", message));
+    match error {
+        OpenRouterError::HttpRequest(_) => AiError::new(ErrorCategory::Network, message),
+        OpenRouterError::KeyNotConfigured => AiError::new(ErrorCategory::Auth, message),
+        OpenRouterError::ApiError {
+            code,
+            message: body,
+        }
+        | OpenRouterError::ModerationError {
+            code,
+            message: body,
+            ..
         }
+        | OpenRouterError::ProviderError {
+            code,
+            message: body,
+            ..
+        }
+        | OpenRouterError::ApiErrorWithMetadata {
+            code,
+            message: body,
+            ..
+        } => {
+            let status = u16::from(code);
+            AiError {
+                category: ErrorCategory::from_status(status, &body),
+                message,
+                status: Some(status),
+                retry_after: None,
+            }
+        }
+        _ => AiError::new(ErrorCategory::Other, message),
     }
+}
 
-    let user_prompt = render_user_prompt(user_prompt_template, transcript);
+fn timed_out(timeout: Duration) -> AiError {
+    log_to_file(&format!(
+        "OpenRouter: Request timed out after {}s",
+        timeout.as_secs()
+    ));
+    AiError::network(
+        "OpenRouter",
+        format!("timed out after {}s", timeout.as_secs()),
+    )
+}
 
-    messages.push(Message::new(Role::User, &user_prompt));
-    messages
+pub struct OpenRouterProvider {
+    client: OpenRouterClient,
+    timeout: Duration,
+    retry: RetryPolicy,
 }
 
-pub fn build_chat_request(
-    model: &str,
-    messages: Vec<Message>,
-    max_tokens: u32,
-) -> Result<ChatCompletionRequest, String> {
-    if let Some(system_msg) = messages.iter().find(|m| matches!(m.role, Role::System)) {
-        let user_msg = messages.iter().rev().find(|m| matches!(m.role, Role::User));
-        log_to_file(&format!(
-            "OpenRouter(Main): Prompt model={} system=<<<{}>>> user=<<<{}>>>",
-            model,
-            system_msg.content,
-            user_msg.map(|m| m.content.as_str()).unwrap_or("")
-        ));
+impl OpenRouterProvider {
+    pub fn new(api_key: &str, timeout: Duration) -> Result<Self, AiError> {
+        Ok(Self {
+            client: build_client(api_key)?,
+            timeout,
+            retry: RetryPolicy::default(),
+        })
     }
 
-    let temperature = openai::temperature_for_model(model, 0.0) as f64;
-    ChatCompletionRequest::builder()
-        .model(model.to_string())
-        .messages(messages)
-        .max_tokens(max_tokens)
-        .temperature(temperature)
-        .build()
-        .map_err(|e| {
-            log_to_file(&format!("OpenRouter: Failed to build chat request: {}", e));
-            format!("OpenRouter request error: {}", e)
+    // One SDK call with the provider timeout, retried on transient errors.
+    async fn call<T, F, Fut>(&self, context: &str, op: F) -> Result<T, AiError>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = Result<T, OpenRouterError>>,
+    {
+        let op = &op;
+        retry::with_retry("OpenRouter", &self.retry, || async move {
+            match tokio::time::timeout(self.timeout, op()).await {
+                Ok(result) => result.map_err(|e| ai_error(context, e)),
+                Err(_) => Err(timed_out(self.timeout)),
+            }
         })
+        .await
+    }
 }
 
-pub async fn list_models(api_key: &str) -> Result<Vec<String>, String> {
-    let client = build_client(api_key)?;
-    match client.list_models().await {
-        Ok(mut models) => {
-            let mut ids: Vec<String> = models.drain(..).map(|model| model.id).collect();
-            ids.sort();
-            Ok(ids)
-        }
-        Err(e) => {
-            log_to_file(&format!("OpenRouter: Failed to list models: {}", e));
-            Err(format!("Failed to fetch OpenRouter models: {}", e))
+#[async_trait]
+impl LlmProvider for OpenRouterProvider {
+    fn label(&self) -> &'static str {
+        "OpenRouter"
+    }
+
+    async fn chat(&self, request: &ChatRequest) -> Result<ChatResponse, AiError> {
+        let request = build_chat_request(request)?;
+        let response = self
+            .call("OpenRouter API error", || {
+                self.client.send_chat_completion(&request)
+            })
+            .await?;
+        match response.choices.first() {
+            Some(choice) => Ok(ChatResponse {
+                text: choice.content().unwrap_or_default().to_string(),
+                usage: response.usage.as_ref().map(token_usage),
+                generation_id: Some(response.id.clone()),
+            }),
+            None => Err(AiError::new(
+                ErrorCategory::Other,
+                "No response from OpenRouter",
+            )),
         }
     }
-}
 
-pub async fn get_credits(api_key: &str) -> Result<CreditSummary, String> {
-    let client = build_client(api_key)?;
-    match client.get_credits().await {
-        Ok(data) => Ok(data.into()),
-        Err(e) => {
-            log_to_file(&format!("OpenRouter: Failed to fetch credits: {}", e));
-            Err(format!("Failed to fetch OpenRouter credits: {}", e))
+    async fn chat_stream(
+        &self,
+        request: &ChatRequest,
+        on_delta: &mut DeltaCallback<'_>,
+    ) -> Result<ChatResponse, AiError> {
+        let request = build_chat_request(request)?;
+        let mut stream = self
+            .call("OpenRouter stream error", || {
+                self.client.stream_chat_completion(&request)
+            })
+            .await?;
+
+        let mut text = String::new();
+        let mut usage = None;
+        let mut generation_id = None;
+        loop {
+            let event = match tokio::time::timeout(self.timeout, stream.next()).await {
+                Ok(Some(event)) => event,
+                Ok(None) => break,
+                Err(_) => return Err(timed_out(self.timeout)),
+            };
+            let chunk = event.map_err(|e| ai_error("OpenRouter stream chunk error", e))?;
+            if generation_id.is_none() && !chunk.id.is_empty() {
+                generation_id = Some(chunk.id.clone());
+            }
+            if let Some(counts) = chunk.usage.as_ref() {
+                usage = Some(token_usage(counts));
+            }
+            if let Some(content) = chunk.choices.first().and_then(|choice| choice.content()) {
+                if content.is_empty() {
+                    continue;
+                }
+                text.push_str(content);
+                on_delta(content)?;
+            }
         }
+        Ok(ChatResponse {
+            text,
+            usage,
+            generation_id,
+        })
     }
-}
 
-pub async fn run_gate(
-    api_key: &str,
-    model: &str,
-    main_system_prompt: &str,
-    gate_instructions: &str,
-    current_transcript: String,
-    previous_transcript: String,
-    last_output: Option<String>,
-) -> Result<GateJson, String> {
-    let prompt = match gate::prepare_gate_prompt(
-        "OpenRouter",
-        main_system_prompt,
-        gate_instructions,
-        &current_transcript,
-        &previous_transcript,
-        last_output.as_deref(),
-    ) {
-        Ok(prompt) => prompt,
-        Err(skip) => return Ok(skip),
-    };
-
-    gate::log_gate_prompt("OpenRouter", model, &prompt);
-
-    let GatePrompt {
-        system_prompt,
-        user_prompt,
-        ..
-    } = prompt;
-
-    let messages = vec![
-        Message::new(Role::System, &system_prompt),
-        Message::new(Role::User, &user_prompt),
-    ];
-
-    let request = ChatCompletionRequest::builder()
-        .model(model.to_string())
-        .messages(messages)
-        .max_tokens(120)
-        .temperature(0.0)
-        .build()
-        .map_err(|e| {
-            log_to_file(&format!("OpenRouter(Gate): Failed to build request: {}", e));
-            format!("OpenRouter gate request error: {}", e)
-        })?;
-
-    let client = build_client(api_key)?;
-    let response = client.send_chat_completion(&request).await.map_err(|e| {
-        log_to_file(&format!("OpenRouter(Gate): API error: {}", e));
-        format!("OpenRouter gate API error: {}", e)
-    })?;
-
-    if let Some(choice) = response.choices.first() {
-        let content = choice.content().unwrap_or_default().trim().to_string();
-        log_to_file(&format!("OpenRouter(Gate): Raw response=<<<{}>>>", content));
-
-        Ok(gate::interpret_gate_response(
-            "OpenRouter",
-            &content,
-            &current_transcript,
-            &previous_transcript,
-        ))
-    } else {
-        Err("No response from OpenRouter".into())
+    async fn list_models(&self) -> Result<Vec<String>, AiError> {
+        let models = self
+            .call("Failed to fetch OpenRouter models", || {
+                self.client.list_models()
+            })
+            .await?;
+        let mut ids: Vec<String> = models.into_iter().map(|model| model.id).collect();
+        ids.sort();
+        Ok(ids)
+    }
+
+    async fn usage(&self) -> Result<Option<AccountUsage>, AiError> {
+        let credits = self
+            .call("Failed to fetch OpenRouter credits", || {
+                self.client.get_credits()
+            })
+            .await?;
+        Ok(Some(credits.into()))
+    }
+
+    // Generation stats appear a few seconds after the response; a 404 just
+    // means "not yet" and the caller tries again later.
+    async fn generation_cost(&self, generation_id: &str) -> Result<Option<f64>, AiError> {
+        let generation = self
+            .call("Failed to fetch OpenRouter generation", || {
+                self.client.get_generation(generation_id)
+            })
+            .await?;
+        Ok(Some(generation.total_cost))
     }
 }
@@ -0,0 +1,607 @@
+use crate::utils::log_to_file;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+// Reference documents of an assistant (`documents` in assistants.json), cut
+// into chunks and searched with BM25 for the latest transcript turn. The
+// chunks that match go into `{{context}}`. Embeddings, when configured,
+// only rerank; everything else is local and deterministic.
+
+// BM25 parameters, the usual defaults
+const K1: f64 = 1.2;
+const B: f64 = 0.75;
+// Reciprocal-rank fusion constant for the embedding rerank
+const RRF_K: f64 = 60.0;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RetrievalConfig {
+    // Chunks injected per answer
+    #[serde(default = "default_top_k")]
+    pub top_k: usize,
+    // Target chunk size and the tail repeated at the start of the next chunk,
+    // in characters
+    #[serde(default = "default_chunk_chars")]
+    pub chunk_chars: usize,
+    #[serde(default = "default_overlap_chars")]
+    pub overlap_chars: usize,
+    // OpenAI-compatible embedding model (openai section of config.json);
+    // unset means BM25 only
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub embedding_model: Option<String>,
+}
+
+fn default_top_k() -> usize {
+    4
+}
+
+fn default_chunk_chars() -> usize {
+    1200
+}
+
+fn default_overlap_chars() -> usize {
+    200
+}
+
+impl Default for RetrievalConfig {
+    fn default() -> Self {
+        Self {
+            top_k: default_top_k(),
+            chunk_chars: default_chunk_chars(),
+            overlap_chars: default_overlap_chars(),
+            embedding_model: None,
+        }
+    }
+}
+
+impl RetrievalConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.top_k == 0 {
+            return Err("retrieval.top_k must be at least 1".to_string());
+        }
+        if self.chunk_chars < 100 {
+            return Err("retrieval.chunk_chars must be at least 100".to_string());
+        }
+        if self.overlap_chars >= self.chunk_chars {
+            return Err("retrieval.overlap_chars must be smaller than chunk_chars".to_string());
+        }
+        if self
+            .embedding_model
+            .as_ref()
+            .is_some_and(|m| m.trim().is_empty())
+        {
+            return Err("retrieval.embedding_model is empty".to_string());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chunk {
+    // File name the chunk came from, shown to the model
+    pub source: String,
+    // Position within that file, from 1
+    pub index: usize,
+    pub text: String,
+}
+
+// Split text into chunks of about `chunk_chars`, on paragraph breaks where
+// possible. Each chunk after the first starts with the last `overlap_chars`
+// of the previous one, cut at a word boundary.
+pub fn chunk_text(text: &str, chunk_chars: usize, overlap_chars: usize) -> Vec<String> {
+    let mut pieces: Vec<String> = Vec::new();
+    for paragraph in text.split("\n\n") {
+        let paragraph = paragraph.trim();
+        if paragraph.is_empty() {
+            continue;
+        }
+        if paragraph.chars().count() <= chunk_chars {
+            pieces.push(paragraph.to_string());
+        } else {
+            // Leave room for the overlap in front of each piece
+            let room = chunk_chars.saturating_sub(overlap_chars + 2).max(1);
+            pieces.extend(split_long(paragraph, room));
+        }
+    }
+
+    let mut chunks: Vec<String> = Vec::new();
+    let mut current = String::new();
+    for piece in pieces {
+        let len = current.chars().count() + piece.chars().count() + 2;
+        if !current.is_empty() && len > chunk_chars {
+            let overlap = tail_words(&current, overlap_chars);
+            chunks.push(std::mem::take(&mut current));
+            // Skip the overlap when it would overflow the next chunk by itself
+            if overlap.chars().count() + piece.chars().count() + 2 <= chunk_chars {
+                current = overlap;
+            }
+        }
+        if !current.is_empty() {
+            current.push_str("\n\n");
+        }
+        current.push_str(&piece);
+    }
+    if !current.is_empty() {
+        chunks.push(current);
+    }
+    chunks
+}
+
+// A paragraph longer than a chunk, split at whitespace.
+fn split_long(paragraph: &str, chunk_chars: usize) -> Vec<String> {
+    let mut parts = Vec::new();
+    let mut current = String::new();
+    for word in paragraph.split_whitespace() {
+        if !current.is_empty() && current.chars().count() + word.chars().count() + 1 > chunk_chars {
+            parts.push(std::mem::take(&mut current));
+        }
+        if !current.is_empty() {
+            current.push(' ');
+        }
+        current.push_str(word);
+    }
+    if !current.is_empty() {
+        parts.push(current);
+    }
+    parts
+}
+
+// At most the last `max_chars` of `text`, starting at a word.
+fn tail_words(text: &str, max_chars: usize) -> String {
+    let total = text.chars().count();
+    if max_chars == 0 {
+        return String::new();
+    }
+    if total <= max_chars {
+        return text.to_string();
+    }
+    let start = text
+        .char_indices()
+        .nth(total - max_chars)
+        .map_or(text.len(), |(i, _)| i);
+    let tail = &text[start..];
+    // Drop the partial word the cut landed in
+    if text[..start].ends_with(char::is_whitespace) {
+        return tail.trim().to_string();
+    }
+    tail.find(char::is_whitespace)
+        .map_or(String::new(), |i| tail[i..].trim().to_string())
+}
+
+const STOPWORDS: &[&str] = &[
+    "a", "an", "and", "are", "as", "at", "be", "but", "by", "do", "for", "from", "had", "has",
+    "have", "he", "her", "his", "how", "i", "if", "in", "is", "it", "its", "me", "my", "of", "on",
+    "or", "our", "she", "so", "that", "the", "their", "them", "then", "there", "they", "this",
+    "to", "was", "we", "were", "what", "when", "where", "which", "who", "why", "will", "with",
+    "you", "your",
+];
+
+// Lowercased words of two or more characters, without common English stopwords.
+pub fn tokenize(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| word.chars().count() >= 2)
+        .map(str::to_lowercase)
+        .filter(|word| !STOPWORDS.contains(&word.as_str()))
+        .collect()
+}
+
+// Okapi BM25 over a fixed set of chunks.
+#[derive(Debug, Default)]
+pub struct Bm25Index {
+    // Term counts per chunk
+    docs: Vec<HashMap<String, u32>>,
+    lengths: Vec<usize>,
+    avg_length: f64,
+    // Number of chunks containing each term
+    doc_freq: HashMap<String, usize>,
+}
+
+impl Bm25Index {
+    pub fn new<'a>(texts: impl IntoIterator<Item = &'a str>) -> Self {
+        let mut index = Bm25Index::default();
+        for text in texts {
+            let tokens = tokenize(text);
+            let mut counts: HashMap<String, u32> = HashMap::new();
+            for token in &tokens {
+                *counts.entry(token.clone()).or_default() += 1;
+            }
+            for term in counts.keys() {
+                *index.doc_freq.entry(term.clone()).or_default() += 1;
+            }
+            index.lengths.push(tokens.len());
+            index.docs.push(counts);
+        }
+        let total: usize = index.lengths.iter().sum();
+        index.avg_length = total as f64 / index.docs.len().max(1) as f64;
+        index
+    }
+
+    // (chunk position, score) of the chunks matching the query, best first;
+    // equal scores keep document order.
+    pub fn search(&self, query: &str, limit: usize) -> Vec<(usize, f64)> {
+        let mut terms = tokenize(query);
+        let mut seen = Vec::new();
+        terms.retain(|t| {
+            let new = !seen.contains(t);
+            seen.push(t.clone());
+            new
+        });
+        let n = self.docs.len() as f64;
+        let mut scored: Vec<(usize, f64)> = self
+            .docs
+            .iter()
+            .enumerate()
+            .map(|(i, counts)| {
+                let norm = 1.0 - B + B * self.lengths[i] as f64 / self.avg_length.max(1.0);
+                let score = terms
+                    .iter()
+                    .filter_map(|term| {
+                        let tf = *counts.get(term)? as f64;
+                        let df = self.doc_freq[term] as f64;
+                        let idf = (1.0 + (n - df + 0.5) / (df + 0.5)).ln();
+                        Some(idf * tf * (K1 + 1.0) / (tf + K1 * norm))
+                    })
+                    .sum::<f64>();
+                (i, score)
+            })
+            .filter(|(_, score)| *score > 0.0)
+            .collect();
+        scored.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+        scored.truncate(limit);
+        scored
+    }
+}
+
+pub fn cosine(a: &[f32], b: &[f32]) -> f64 {
+    let (mut dot, mut norm_a, mut norm_b) = (0.0f64, 0.0f64, 0.0f64);
+    for (x, y) in a.iter().zip(b) {
+        dot += *x as f64 * *y as f64;
+        norm_a += *x as f64 * *x as f64;
+        norm_b += *y as f64 * *y as f64;
+    }
+    if norm_a == 0.0 || norm_b == 0.0 {
+        return 0.0;
+    }
+    dot / (norm_a.sqrt() * norm_b.sqrt())
+}
+
+// Reciprocal-rank fusion of the BM25 ranking and the embedding similarity,
+// so a chunk without shared keywords can still be picked. Ties keep the
+// BM25 order, then document order.
+pub fn hybrid_rank(
+    keyword: &[(usize, f64)],
+    query_vector: &[f32],
+    chunk_vectors: &[Vec<f32>],
+    limit: usize,
+) -> Vec<usize> {
+    let mut by_similarity: Vec<(usize, f64)> = chunk_vectors
+        .iter()
+        .enumerate()
+        .map(|(i, v)| (i, cosine(query_vector, v)))
+        .collect();
+    by_similarity.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+
+    let keyword_rank = |i: usize| keyword.iter().position(|(k, _)| *k == i);
+    let mut fused: Vec<(usize, f64, usize)> = by_similarity
+        .iter()
+        .enumerate()
+        .map(|(rank, (i, _))| {
+            let keyword = keyword_rank(*i);
+            let score = 1.0 / (RRF_K + rank as f64 + 1.0)
+                + keyword.map_or(0.0, |r| 1.0 / (RRF_K + r as f64 + 1.0));
+            (*i, score, keyword.unwrap_or(usize::MAX))
+        })
+        .collect();
+    fused.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.2.cmp(&b.2)).then(a.0.cmp(&b.0)));
+    fused.into_iter().take(limit).map(|(i, _, _)| i).collect()
+}
+
+// Plain text of a reference document: Markdown and text files as they are,
+// PDFs through their text layer.
+pub fn load_document(path: &Path) -> Result<String, String> {
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or("")
+        .to_ascii_lowercase();
+    match extension.as_str() {
+        "pdf" => {
+            // pdf-extract panics on some malformed files
+            let path_buf = path.to_path_buf();
+            std::panic::catch_unwind(move || pdf_extract::extract_text(&path_buf))
+                .map_err(|_| format!("Failed to read PDF {}", path.display()))?
+                .map_err(|e| format!("Failed to read PDF {}: {}", path.display(), e))
+        }
+        "md" | "markdown" | "txt" | "text" | "" => fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e)),
+        other => Err(format!(
+            "Unsupported document type .{} for {}",
+            other,
+            path.display()
+        )),
+    }
+}
+
+// Retrieved chunks as they appear in `{{context}}`.
+pub fn render_context(chunks: &[&Chunk]) -> String {
+    chunks
+        .iter()
+        .map(|chunk| format!("[{} #{}]\n{}", chunk.source, chunk.index, chunk.text))
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+type FileStamp = Option<(SystemTime, u64)>;
+
+fn file_stamp(path: &Path) -> FileStamp {
+    let meta = fs::metadata(path).ok()?;
+    Some((meta.modified().ok()?, meta.len()))
+}
+
+// The chunks and BM25 index of one assistant's documents.
+pub struct DocumentIndex {
+    pub chunks: Vec<Chunk>,
+    bm25: Bm25Index,
+    config: RetrievalConfig,
+    stamps: Vec<(PathBuf, FileStamp)>,
+    // Chunk embeddings for `config.embedding_model`, computed on first use
+    embeddings: Mutex<Option<Arc<Vec<Vec<f32>>>>>,
+}
+
+impl DocumentIndex {
+    // Unreadable documents are logged and left out rather than failing the
+    // analysis.
+    pub fn build(paths: &[PathBuf], config: &RetrievalConfig) -> Self {
+        let mut chunks = Vec::new();
+        let mut stamps = Vec::new();
+        for path in paths {
+            stamps.push((path.clone(), file_stamp(path)));
+            let text = match load_document(path) {
+                Ok(text) => text,
+                Err(e) => {
+                    log_to_file(&format!("Retrieval: {}", e));
+                    continue;
+                }
+            };
+            let source = path
+                .file_name()
+                .map(|n| n.to_string_lossy().to_string())
+                .unwrap_or_else(|| path.display().to_string());
+            for (i, text) in chunk_text(&text, config.chunk_chars, config.overlap_chars)
+                .into_iter()
+                .enumerate()
+            {
+                chunks.push(Chunk {
+                    source: source.clone(),
+                    index: i + 1,
+                    text,
+                });
+            }
+        }
+        let bm25 = Bm25Index::new(chunks.iter().map(|c| c.text.as_str()));
+        log_to_file(&format!(
+            "Retrieval: Indexed {} chunks from {} documents",
+            chunks.len(),
+            paths.len()
+        ));
+        Self {
+            chunks,
+            bm25,
+            config: config.clone(),
+            stamps,
+            embeddings: Mutex::new(None),
+        }
+    }
+
+    // Whether the documents or settings changed since the index was built.
+    fn is_stale(&self, paths: &[PathBuf], config: &RetrievalConfig) -> bool {
+        self.config != *config
+            || self.stamps.len() != paths.len()
+            || self
+                .stamps
+                .iter()
+                .zip(paths)
+                .any(|((path, stamp), current)| path != current || *stamp != file_stamp(path))
+    }
+
+    // BM25 matches for the query, best first, as positions in `chunks`.
+    pub fn keyword_search(&self, query: &str, limit: usize) -> Vec<(usize, f64)> {
+        self.bm25.search(query, limit)
+    }
+
+    pub fn cached_embeddings(&self) -> Option<Arc<Vec<Vec<f32>>>> {
+        self.embeddings.lock().unwrap().clone()
+    }
+
+    pub fn store_embeddings(&self, vectors: Vec<Vec<f32>>) -> Arc<Vec<Vec<f32>>> {
+        let vectors = Arc::new(vectors);
+        *self.embeddings.lock().unwrap() = Some(vectors.clone());
+        vectors
+    }
+}
+
+// Document indexes by assistant id, rebuilt when a document or the
+// assistant's retrieval settings change.
+#[derive(Clone, Default)]
+pub struct DocumentIndexes {
+    indexes: Arc<Mutex<HashMap<String, Arc<DocumentIndex>>>>,
+}
+
+impl DocumentIndexes {
+    // Reading and parsing the documents runs on a blocking thread, without
+    // the lock, so other assistants are not held up by it.
+    pub async fn get(
+        &self,
+        assistant_id: &str,
+        paths: &[PathBuf],
+        config: &RetrievalConfig,
+    ) -> Result<Arc<DocumentIndex>, String> {
+        let cached = self.indexes.lock().unwrap().get(assistant_id).cloned();
+        if let Some(index) = cached.filter(|index| !index.is_stale(paths, config)) {
+            return Ok(index);
+        }
+        let (build_paths, build_config) = (paths.to_vec(), config.clone());
+        let index = tauri::async_runtime::spawn_blocking(move || {
+            DocumentIndex::build(&build_paths, &build_config)
+        })
+        .await
+        .map_err(|e| format!("Failed to index documents of {}: {}", assistant_id, e))?;
+        let index = Arc::new(index);
+        self.indexes
+            .lock()
+            .unwrap()
+            .insert(assistant_id.to_string(), index.clone());
+        Ok(index)
+    }
+}
+
+// Resolve document paths relative to the directory of assistants.json.
+pub fn resolve_paths(base_dir: &Path, documents: &[String]) -> Vec<PathBuf> {
+    documents
+        .iter()
+        .map(|document| {
+            let path = Path::new(document);
+            if path.is_absolute() {
+                path.to_path_buf()
+            } else {
+                base_dir.join(path)
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chunk_text() {
+        let text = "First paragraph about Rust.\n\nSecond paragraph about Kafka.\n\n\nThird one.";
+        assert_eq!(
+            chunk_text(text, 1000, 100),
+            vec!["First paragraph about Rust.\n\nSecond paragraph about Kafka.\n\nThird one."]
+        );
+
+        let long = (1..=60)
+            .map(|i| format!("word{}", i))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let chunks = chunk_text(&format!("Intro line.\n\n{}", long), 100, 20);
+        assert!(chunks.len() > 2);
+        assert!(
+            chunks.iter().all(|c| c.chars().count() <= 100),
+            "{:?}",
+            chunks
+        );
+        // Each chunk repeats whole words from the end of the previous one
+        for pair in chunks.windows(2) {
+            let first_word = pair[1].split_whitespace().next().unwrap();
+            assert!(pair[0].split_whitespace().any(|w| w == first_word));
+        }
+        assert!(chunks.concat().contains("word60"));
+        assert!(chunk_text("  \n\n ", 100, 20).is_empty());
+    }
+
+    #[test]
+    fn test_bm25_ranking() {
+        assert_eq!(
+            tokenize("What's your experience with Kafka, C++ & K8s?"),
+            vec!["experience", "kafka", "k8s"]
+        );
+        let chunks = [
+            "Led the migration of the billing system to Kafka event streams.",
+            "Hobbies: climbing, chess and cooking.",
+            "Built Kafka consumers in Rust; tuned Kafka partitions for throughput.",
+            "Mentored three junior engineers.",
+        ];
+        let index = Bm25Index::new(chunks);
+        let hits = index.search("Tell me about your Kafka experience in Rust", 3);
+        let order: Vec<usize> = hits.iter().map(|(i, _)| *i).collect();
+        assert_eq!(order, vec![2, 0]);
+        assert!(index.search("the and of", 3).is_empty());
+        assert!(index.search("", 3).is_empty());
+
+        // Equal scores keep document order
+        let index = Bm25Index::new(["rust one", "rust two", "rust three"]);
+        let order: Vec<usize> = index.search("rust", 2).iter().map(|(i, _)| *i).collect();
+        assert_eq!(order, vec![0, 1]);
+    }
+
+    #[test]
+    fn test_hybrid_rank() {
+        assert!((cosine(&[1.0, 0.0], &[1.0, 0.0]) - 1.0).abs() < 1e-9);
+        assert_eq!(cosine(&[1.0, 0.0], &[0.0, 0.0]), 0.0);
+
+        let vectors = vec![vec![0.0, 1.0], vec![1.0, 0.1], vec![0.9, 0.0]];
+        // Keyword matches lead; chunk 2 shares no keywords but is the
+        // closest to the query, so it still beats an unrelated chunk
+        let keyword = vec![(1, 2.0), (0, 1.0)];
+        assert_eq!(
+            hybrid_rank(&keyword, &[1.0, 0.0], &vectors, 3),
+            vec![1, 0, 2]
+        );
+        assert_eq!(
+            hybrid_rank(&[(1, 2.0)], &[1.0, 0.0], &vectors, 3),
+            vec![1, 2, 0]
+        );
+        assert_eq!(hybrid_rank(&[], &[1.0, 0.0], &vectors, 1), vec![2]);
+    }
+
+    #[test]
+    fn test_document_index() {
+        let dir = tempfile::tempdir().unwrap();
+        let cv = dir.path().join("cv.md");
+        fs::write(
+            &cv,
+            "# Experience\n\nFive years of Postgres tuning at Acme.\n\nLed a Kubernetes rollout.",
+        )
+        .unwrap();
+        let paths = resolve_paths(
+            dir.path(),
+            &["cv.md".to_string(), "missing.txt".to_string()],
+        );
+        assert_eq!(paths[0], cv);
+
+        let config = RetrievalConfig {
+            chunk_chars: 100,
+            overlap_chars: 0,
+            ..RetrievalConfig::default()
+        };
+        assert!(config.validate().is_ok());
+        let indexes = DocumentIndexes::default();
+        let get = |config: &RetrievalConfig| {
+            tauri::async_runtime::block_on(indexes.get("interview", &paths, config)).unwrap()
+        };
+        let index = get(&config);
+        assert_eq!(index.chunks.len(), 1);
+        let hits = index.keyword_search("how did you tune postgres", config.top_k);
+        let found: Vec<&Chunk> = hits.iter().map(|(i, _)| &index.chunks[*i]).collect();
+        assert_eq!(
+            render_context(&found),
+            "[cv.md #1]\n# Experience\n\nFive years of Postgres tuning at Acme.\n\nLed a Kubernetes rollout."
+        );
+        assert!(Arc::ptr_eq(&index, &get(&config)));
+
+        // Edits and new settings rebuild the index
+        fs::write(&cv, "Ten years of Oracle.").unwrap();
+        let rebuilt = get(&config);
+        assert!(rebuilt.keyword_search("postgres", 4).is_empty());
+        assert_eq!(rebuilt.keyword_search("oracle", 4).len(), 1);
+        let wider = RetrievalConfig {
+            top_k: 2,
+            ..config.clone()
+        };
+        assert!(!Arc::ptr_eq(&rebuilt, &get(&wider)));
+
+        assert!(load_document(&dir.path().join("cv.docx")).is_err());
+        assert!(RetrievalConfig {
+            overlap_chars: 100,
+            ..config
+        }
+        .validate()
+        .is_err());
+    }
+}
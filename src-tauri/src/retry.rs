@@ -0,0 +1,198 @@
+use crate::{llm::AiError, utils::log_to_file};
+use chrono::{DateTime, Utc};
+use reqwest::header::HeaderMap;
+use std::future::Future;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// Shared retry policy for provider calls: honours Retry-After, otherwise
+// backs off exponentially with jitter. Only rate limits, server errors and
+// network failures are retried.
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(20),
+        }
+    }
+}
+
+impl RetryPolicy {
+    // "Equal jitter": half the capped exponential delay plus a random share
+    // of the other half, so concurrent clients spread out but never retry
+    // immediately. `jitter` is in [0, 1].
+    pub fn backoff(&self, attempt: u32, jitter: f64) -> Duration {
+        let exp = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.max_delay);
+        let half = exp / 2;
+        half + half.mul_f64(jitter.clamp(0.0, 1.0))
+    }
+
+    fn delay_for(&self, attempt: u32, error: &AiError) -> Duration {
+        match error.retry_after {
+            Some(wait) => wait.min(self.max_delay),
+            None => self.backoff(attempt, jitter()),
+        }
+    }
+}
+
+fn jitter() -> f64 {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.subsec_nanos())
+        .unwrap_or(0);
+    // Scramble the low bits; good enough to de-synchronise retries
+    (nanos.wrapping_mul(2_654_435_761) % 10_000) as f64 / 10_000.0
+}
+
+// `Retry-After` is either delta-seconds or an HTTP date.
+pub fn parse_retry_after_value(value: &str, now: DateTime<Utc>) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(secs) = value.parse::<f64>() {
+        return (secs >= 0.0).then(|| Duration::from_secs_f64(secs));
+    }
+    let at = DateTime::parse_from_rfc2822(value).ok()?;
+    Some(
+        (at.with_timezone(&Utc) - now)
+            .to_std()
+            .unwrap_or(Duration::ZERO),
+    )
+}
+
+pub fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    // OpenAI also sends a millisecond variant
+    if let Some(ms) = headers
+        .get("retry-after-ms")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().parse::<f64>().ok())
+    {
+        return Some(Duration::from_secs_f64(ms.max(0.0) / 1000.0));
+    }
+    headers
+        .get(reqwest::header::RETRY_AFTER)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| parse_retry_after_value(v, Utc::now()))
+}
+
+// Run `op` until it succeeds, fails with a non-retryable error, or the
+// policy's retries are used up.
+pub async fn with_retry<T, F, Fut>(
+    provider_label: &str,
+    policy: &RetryPolicy,
+    mut op: F,
+) -> Result<T, AiError>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, AiError>>,
+{
+    let mut attempt = 0;
+    loop {
+        match op().await {
+            Ok(value) => return Ok(value),
+            Err(err) if err.is_retryable() && attempt < policy.max_retries => {
+                let delay = policy.delay_for(attempt, &err);
+                attempt += 1;
+                log_to_file(&format!(
+                    "{}: {} (category={:?}); retry {}/{} in {} ms",
+                    provider_label,
+                    err.message,
+                    err.category,
+                    attempt,
+                    policy.max_retries,
+                    delay.as_millis()
+                ));
+                tokio::time::sleep(delay).await;
+            }
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::ErrorCategory;
+    use std::cell::Cell;
+
+    #[test]
+    fn test_backoff_grows_and_caps() {
+        let policy = RetryPolicy::default();
+        assert_eq!(policy.backoff(0, 0.0), Duration::from_millis(250));
+        assert_eq!(policy.backoff(0, 1.0), Duration::from_millis(500));
+        assert_eq!(policy.backoff(3, 1.0), Duration::from_secs(4));
+        assert_eq!(policy.backoff(20, 1.0), policy.max_delay);
+        assert!(policy.backoff(20, 0.0) >= policy.max_delay / 2);
+    }
+
+    #[test]
+    fn test_parse_retry_after_value() {
+        let now = DateTime::parse_from_rfc2822("Wed, 21 Oct 2015 07:28:00 GMT")
+            .unwrap()
+            .with_timezone(&Utc);
+        assert_eq!(
+            parse_retry_after_value("7", now),
+            Some(Duration::from_secs(7))
+        );
+        assert_eq!(
+            parse_retry_after_value("Wed, 21 Oct 2015 07:28:30 GMT", now),
+            Some(Duration::from_secs(30))
+        );
+        assert_eq!(
+            parse_retry_after_value("Wed, 21 Oct 2015 07:27:00 GMT", now),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(parse_retry_after_value("soon", now), None);
+    }
+
+    #[test]
+    fn test_with_retry_stops_on_permanent_errors() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .unwrap();
+        let policy = RetryPolicy {
+            max_retries: 2,
+            base_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(2),
+        };
+
+        let calls = Cell::new(0);
+        let result: Result<(), AiError> = runtime.block_on(with_retry("Test", &policy, || {
+            calls.set(calls.get() + 1);
+            async { Err(AiError::new(ErrorCategory::RateLimit, "slow down")) }
+        }));
+        assert_eq!(result.unwrap_err().category, ErrorCategory::RateLimit);
+        assert_eq!(calls.get(), 3);
+
+        calls.set(0);
+        let result: Result<(), AiError> = runtime.block_on(with_retry("Test", &policy, || {
+            calls.set(calls.get() + 1);
+            async { Err(AiError::new(ErrorCategory::Auth, "bad key")) }
+        }));
+        assert!(result.is_err());
+        assert_eq!(calls.get(), 1);
+
+        calls.set(0);
+        let result = runtime.block_on(with_retry("Test", &policy, || {
+            calls.set(calls.get() + 1);
+            let attempt = calls.get();
+            async move {
+                if attempt < 2 {
+                    Err(AiError::new(ErrorCategory::Network, "reset"))
+                } else {
+                    Ok(attempt)
+                }
+            }
+        }));
+        assert_eq!(result.unwrap(), 2);
+    }
+}
@@ -0,0 +1,388 @@
+use crate::{
+    gate::{self, GateJson, GateParse},
+    llm::{
+        AiError, ChatMessage, ChatRequest, ChatRole, ErrorCategory, JsonSchemaFormat, LlmProvider,
+        TokenUsage,
+    },
+    utils::log_to_file,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+
+// Assistant id used for the router's own usage and task tracking
+pub const ROUTER_ID: &str = "router";
+
+// An assistant the router may pick.
+#[derive(Debug, Clone)]
+pub struct RouteCandidate {
+    pub id: String,
+    pub name: String,
+    pub description: String,
+    pub gate_instructions: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RouteChoice {
+    pub assistant_id: String,
+    pub run: bool,
+    pub reason: Option<String>,
+    pub confidence: Option<f32>,
+}
+
+#[derive(Debug, Clone)]
+pub struct RouteOutcome {
+    // One entry per candidate, in candidate order
+    pub choices: Vec<RouteChoice>,
+    pub usage: Option<TokenUsage>,
+    pub generation_id: Option<String>,
+    pub called_model: bool,
+    pub parse: GateParse,
+}
+
+#[derive(Debug, Clone)]
+pub struct RouterOptions {
+    pub model: String,
+    pub candidates: Vec<RouteCandidate>,
+    // Assistant selected in the UI; the only one the fallback may run
+    pub primary_id: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct RouteJson {
+    id: String,
+    run: bool,
+    #[serde(default)]
+    reason: Option<String>,
+    #[serde(default)]
+    confidence: Option<f32>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RouterJson {
+    assistants: Vec<RouteJson>,
+}
+
+pub fn router_response_format(candidates: &[RouteCandidate]) -> JsonSchemaFormat {
+    let ids: Vec<&str> = candidates.iter().map(|c| c.id.as_str()).collect();
+    JsonSchemaFormat {
+        name: "assistant_routing".into(),
+        schema: json!({
+            "type": "object",
+            "properties": {
+                "assistants": {
+                    "type": "array",
+                    "items": {
+                        "type": "object",
+                        "properties": {
+                            "id": {"type": "string", "enum": ids},
+                            "run": {"type": "boolean"},
+                            "reason": {"type": "string"},
+                            "confidence": {"type": "number"}
+                        },
+                        "required": ["id", "run", "reason", "confidence"],
+                        "additionalProperties": false
+                    }
+                }
+            },
+            "required": ["assistants"],
+            "additionalProperties": false
+        }),
+    }
+}
+
+pub fn prepare_router_prompt(
+    candidates: &[RouteCandidate],
+    current_transcript: &str,
+    previous_transcript: &str,
+    last_output: Option<&str>,
+) -> (String, String) {
+    let assistants: Vec<String> = candidates
+        .iter()
+        .map(|c| {
+            format!(
+                "- id: {}\n  name: {}\n  purpose: {}\n  run when: {}",
+                c.id, c.name, c.description, c.gate_instructions
+            )
+        })
+        .collect();
+    let system_prompt = format!(
+        "You route a live conversation transcript to assistants. For each assistant below decide whether it should run now, following its rules. Several, one or none may run.\nAssistants:\n{}\nOutput MUST be STRICT JSON: {{\"assistants\": [{{\"id\": string, \"run\": boolean, \"reason\": string, \"confidence\": number}}]}} with one entry per assistant. No extra text.",
+        assistants.join("\n")
+    );
+    let user_prompt = format!(
+        "Current transcript:\n{}\n\nPrevious transcript:\n{}\n\nLast output (optional):\n{}",
+        current_transcript,
+        previous_transcript,
+        last_output.unwrap_or("")
+    );
+    (system_prompt, user_prompt)
+}
+
+fn lenient_route(value: &Value) -> Option<RouteJson> {
+    let map = value.as_object()?;
+    let id = map.get("id").and_then(Value::as_str)?.trim().to_string();
+    let run = map.get("run").and_then(gate::lenient_bool).or_else(|| {
+        match map
+            .get("instruction")
+            .and_then(Value::as_str)?
+            .trim()
+            .to_ascii_uppercase()
+            .as_str()
+        {
+            "NEEDED" => Some(true),
+            "NOT_NEEDED" => Some(false),
+            _ => None,
+        }
+    })?;
+    Some(RouteJson {
+        id,
+        run,
+        reason: map.get("reason").and_then(Value::as_str).map(String::from),
+        confidence: map.get("confidence").and_then(gate::lenient_number),
+    })
+}
+
+fn parse_routes(content: &str) -> Option<(Vec<RouteJson>, GateParse)> {
+    let content = content.trim();
+    if let Ok(router) = serde_json::from_str::<RouterJson>(content) {
+        return Some((router.assistants, GateParse::Strict));
+    }
+    let map: Map<String, Value> = gate::extract_json_object(content)?;
+    if let Ok(router) = serde_json::from_value::<RouterJson>(Value::Object(map.clone())) {
+        return Some((router.assistants, GateParse::Extracted));
+    }
+    let routes: Vec<RouteJson> = map
+        .get("assistants")?
+        .as_array()?
+        .iter()
+        .filter_map(lenient_route)
+        .collect();
+    (!routes.is_empty()).then_some((routes, GateParse::Lenient))
+}
+
+// Map the model's answer onto the candidates: unknown ids are dropped, the
+// first entry per id wins, and unmentioned assistants do not run.
+pub fn parse_router_response(
+    content: &str,
+    candidates: &[RouteCandidate],
+) -> Option<(Vec<RouteChoice>, GateParse)> {
+    let (routes, parse) = parse_routes(content)?;
+    let choices = candidates
+        .iter()
+        .map(
+            |candidate| match routes.iter().find(|r| r.id == candidate.id) {
+                Some(route) => RouteChoice {
+                    assistant_id: candidate.id.clone(),
+                    run: route.run,
+                    reason: route.reason.clone(),
+                    confidence: gate::normalize_confidence(route.confidence),
+                },
+                None => RouteChoice {
+                    assistant_id: candidate.id.clone(),
+                    run: false,
+                    reason: Some("Not selected by router".into()),
+                    confidence: None,
+                },
+            },
+        )
+        .collect();
+    Some((choices, parse))
+}
+
+// Without usable JSON only the primary assistant may run, on the local decision.
+fn fallback_choices(
+    candidates: &[RouteCandidate],
+    primary_id: &str,
+    fallback: &GateJson,
+) -> Vec<RouteChoice> {
+    candidates
+        .iter()
+        .map(|candidate| {
+            let primary = candidate.id == primary_id;
+            RouteChoice {
+                assistant_id: candidate.id.clone(),
+                run: primary && fallback.run,
+                reason: if primary {
+                    fallback.reason.clone()
+                } else {
+                    Some("Router fallback: primary assistant only".into())
+                },
+                confidence: Some(fallback.confidence.unwrap_or(0.3).min(0.3)),
+            }
+        })
+        .collect()
+}
+
+// One gate call that picks which of the candidate assistants should run.
+pub async fn route_assistants(
+    provider: &dyn LlmProvider,
+    opts: RouterOptions,
+    current_transcript: String,
+    previous_transcript: String,
+    last_output: Option<String>,
+    fallback: GateJson,
+) -> Result<RouteOutcome, AiError> {
+    let label = provider.label();
+    if current_transcript.trim().is_empty() || opts.candidates.is_empty() {
+        log_to_file(&format!(
+            "{}(Router): Skip — empty transcript or no assistants",
+            label
+        ));
+        let choices = opts
+            .candidates
+            .iter()
+            .map(|c| RouteChoice {
+                assistant_id: c.id.clone(),
+                run: false,
+                reason: Some("Empty transcript".into()),
+                confidence: Some(1.0),
+            })
+            .collect();
+        return Ok(RouteOutcome {
+            choices,
+            usage: None,
+            generation_id: None,
+            called_model: false,
+            parse: GateParse::Local,
+        });
+    }
+
+    let (system_prompt, user_prompt) = prepare_router_prompt(
+        &opts.candidates,
+        &current_transcript,
+        &previous_transcript,
+        last_output.as_deref(),
+    );
+    log_to_file(&format!(
+        "{}(Router): Request model={} candidates={} current_len={} previous_len={}",
+        label,
+        opts.model,
+        opts.candidates.len(),
+        current_transcript.len(),
+        previous_transcript.len()
+    ));
+
+    let mut request = ChatRequest {
+        model: opts.model,
+        messages: vec![
+            ChatMessage::new(ChatRole::System, system_prompt),
+            ChatMessage::new(ChatRole::User, user_prompt),
+        ],
+        // Room for a short reason per assistant
+        max_tokens: 120 + 60 * opts.candidates.len() as u32,
+        temperature: 0.0,
+        response_format: Some(router_response_format(&opts.candidates)),
+        top_p: None,
+        stop: Vec::new(),
+    };
+
+    let response = match provider.chat(&request).await {
+        Err(e)
+            if matches!(e.status, Some(400 | 422))
+                && e.category != ErrorCategory::ModelNotFound =>
+        {
+            log_to_file(&format!(
+                "{}(Router): structured output rejected ({}); retrying without it",
+                label, e
+            ));
+            request.response_format = None;
+            provider.chat(&request).await
+        }
+        other => other,
+    }
+    .map_err(|e| {
+        log_to_file(&format!("{}(Router): API error: {}", label, e));
+        e
+    })?;
+    let content = response.text.trim().to_string();
+    log_to_file(&format!(
+        "{}(Router): Raw response=<<<{}>>>",
+        label, content
+    ));
+
+    let (choices, parse) = match parse_router_response(&content, &opts.candidates) {
+        Some(parsed) => parsed,
+        None => {
+            log_to_file(&format!(
+                "{}(Router): No usable JSON; falling back to the local decision for {}",
+                label, opts.primary_id
+            ));
+            (
+                fallback_choices(&opts.candidates, &opts.primary_id, &fallback),
+                GateParse::Fallback,
+            )
+        }
+    };
+    let selected: Vec<&str> = choices
+        .iter()
+        .filter(|c| c.run)
+        .map(|c| c.assistant_id.as_str())
+        .collect();
+    log_to_file(&format!(
+        "{}(Router): Selected {:?} parse={}",
+        label,
+        selected,
+        parse.as_str()
+    ));
+
+    Ok(RouteOutcome {
+        choices,
+        usage: response.usage,
+        generation_id: response.generation_id,
+        called_model: true,
+        parse,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidate(id: &str) -> RouteCandidate {
+        RouteCandidate {
+            id: id.into(),
+            name: id.into(),
+            description: String::new(),
+            gate_instructions: "Run when relevant".into(),
+        }
+    }
+
+    #[test]
+    fn test_parse_router_response() {
+        let candidates = vec![
+            candidate("interview"),
+            candidate("consistency"),
+            candidate("medical"),
+        ];
+        let content = "```json\n{\"assistants\": [\
+            {\"id\": \"consistency\", \"run\": true, \"reason\": \"contradiction\", \"confidence\": 0.8},\
+            {\"id\": \"interview\", \"run\": true, \"reason\": \"new question\", \"confidence\": 90},\
+            {\"id\": \"unknown\", \"run\": true, \"reason\": \"\", \"confidence\": 1}\
+        ]}\n```";
+        let (choices, parse) = parse_router_response(content, &candidates).unwrap();
+        assert_eq!(parse, GateParse::Extracted);
+        let ids: Vec<&str> = choices.iter().map(|c| c.assistant_id.as_str()).collect();
+        assert_eq!(ids, vec!["interview", "consistency", "medical"]);
+        assert!(choices[0].run && choices[1].run && !choices[2].run);
+        assert_eq!(choices[0].confidence, Some(0.9));
+
+        let (choices, parse) = parse_router_response(
+            r#"{"assistants": [{"id": "medical", "run": "yes"}]}"#,
+            &candidates,
+        )
+        .unwrap();
+        assert_eq!(parse, GateParse::Lenient);
+        assert!(choices[2].run && !choices[0].run);
+
+        assert!(parse_router_response("Run the interview helper.", &candidates).is_none());
+
+        let fallback = GateJson {
+            run: true,
+            instruction: Some("NEEDED".into()),
+            reason: Some("Local: completed question".into()),
+            confidence: Some(0.9),
+        };
+        let choices = fallback_choices(&candidates, "consistency", &fallback);
+        assert!(!choices[0].run && choices[1].run && !choices[2].run);
+    }
+}
@@ -0,0 +1,1312 @@
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+
+use crate::ai_tasks::AiTaskKind;
+use crate::gate::GateParse;
+use crate::llm::TokenUsage;
+use crate::transcription::{TranscriptSegment, TranscriptUpdate, TranslationStatus};
+use crate::usage::{CostSource, UsageFilters, UsageRecord};
+use crate::utils::log_to_file;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS sessions (
+    id TEXT PRIMARY KEY,
+    started_at TEXT NOT NULL,
+    ended_at TEXT,
+    audio_path TEXT NOT NULL,
+    audio_format TEXT NOT NULL,
+    duration_ms INTEGER,
+    title TEXT
+);
+CREATE TABLE IF NOT EXISTS transcript_segments (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    session_id TEXT NOT NULL REFERENCES sessions(id) ON DELETE CASCADE,
+    seq INTEGER NOT NULL,
+    speaker INTEGER NOT NULL,
+    language TEXT,
+    text TEXT NOT NULL,
+    start_ms INTEGER,
+    end_ms INTEGER,
+    confidence REAL,
+    translation_status TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS gate_decisions (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    session_id TEXT NOT NULL REFERENCES sessions(id) ON DELETE CASCADE,
+    assistant_id TEXT NOT NULL,
+    model TEXT NOT NULL,
+    run INTEGER NOT NULL,
+    instruction TEXT,
+    reason TEXT,
+    confidence REAL,
+    created_at TEXT NOT NULL,
+    parse_path TEXT
+);
+CREATE TABLE IF NOT EXISTS assistant_answers (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    session_id TEXT NOT NULL REFERENCES sessions(id) ON DELETE CASCADE,
+    request_id TEXT NOT NULL,
+    assistant_id TEXT NOT NULL,
+    provider TEXT NOT NULL,
+    model TEXT NOT NULL,
+    answer TEXT NOT NULL,
+    started_at TEXT NOT NULL,
+    completed_at TEXT NOT NULL,
+    data TEXT
+);
+CREATE TABLE IF NOT EXISTS ai_usage (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    session_id TEXT REFERENCES sessions(id) ON DELETE SET NULL,
+    request_id TEXT NOT NULL,
+    kind TEXT NOT NULL,
+    assistant_id TEXT NOT NULL,
+    provider TEXT NOT NULL,
+    model TEXT NOT NULL,
+    input_tokens INTEGER NOT NULL,
+    cached_input_tokens INTEGER NOT NULL,
+    output_tokens INTEGER NOT NULL,
+    cost_usd REAL,
+    cost_source TEXT NOT NULL,
+    generation_id TEXT,
+    created_at TEXT NOT NULL,
+    cost_attempts INTEGER NOT NULL DEFAULT 0
+);
+CREATE INDEX IF NOT EXISTS idx_segments_session ON transcript_segments(session_id, seq);
+CREATE INDEX IF NOT EXISTS idx_gate_session ON gate_decisions(session_id);
+CREATE INDEX IF NOT EXISTS idx_answers_session ON assistant_answers(session_id);
+CREATE INDEX IF NOT EXISTS idx_usage_created ON ai_usage(created_at);
+";
+
+// Full-text index over transcript segments and assistant answers. Rows are
+// keyed by source id (segments even, answers odd) and kept in sync by
+// triggers, so cascading session deletes clean the index too.
+const SEARCH_SCHEMA: &str = "
+CREATE VIRTUAL TABLE IF NOT EXISTS search_index USING fts5(
+    text,
+    session_id UNINDEXED,
+    kind UNINDEXED,
+    speaker UNINDEXED,
+    language UNINDEXED,
+    assistant_id UNINDEXED,
+    offset_ms UNINDEXED,
+    tokenize = 'unicode61 remove_diacritics 2'
+);
+CREATE TRIGGER IF NOT EXISTS search_segments_insert AFTER INSERT ON transcript_segments BEGIN
+    INSERT INTO search_index (rowid, text, session_id, kind, speaker, language, assistant_id, offset_ms)
+    VALUES (new.id * 2, new.text, new.session_id, 'segment', new.speaker, new.language, NULL, new.start_ms);
+END;
+CREATE TRIGGER IF NOT EXISTS search_segments_delete AFTER DELETE ON transcript_segments BEGIN
+    DELETE FROM search_index WHERE rowid = old.id * 2;
+END;
+CREATE TRIGGER IF NOT EXISTS search_answers_insert AFTER INSERT ON assistant_answers BEGIN
+    INSERT INTO search_index (rowid, text, session_id, kind, speaker, language, assistant_id, offset_ms)
+    SELECT new.id * 2 + 1, new.answer, new.session_id, 'answer', NULL, NULL, new.assistant_id,
+           MAX(0, CAST((julianday(new.completed_at) - julianday(s.started_at)) * 86400000 AS INTEGER))
+    FROM sessions s WHERE s.id = new.session_id;
+END;
+CREATE TRIGGER IF NOT EXISTS search_answers_delete AFTER DELETE ON assistant_answers BEGIN
+    DELETE FROM search_index WHERE rowid = old.id * 2 + 1;
+END;
+";
+
+// Index rows written before the search index existed.
+const SEARCH_BACKFILL: &str = "
+INSERT INTO search_index (rowid, text, session_id, kind, speaker, language, assistant_id, offset_ms)
+SELECT id * 2, text, session_id, 'segment', speaker, language, NULL, start_ms FROM transcript_segments;
+INSERT INTO search_index (rowid, text, session_id, kind, speaker, language, assistant_id, offset_ms)
+SELECT a.id * 2 + 1, a.answer, a.session_id, 'answer', NULL, NULL, a.assistant_id,
+       MAX(0, CAST((julianday(a.completed_at) - julianday(s.started_at)) * 86400000 AS INTEGER))
+FROM assistant_answers a JOIN sessions s ON s.id = a.session_id;
+";
+
+const DEFAULT_SEARCH_LIMIT: u32 = 50;
+// How often a recording's transcript is written while it grows, so a crash
+// loses at most this much; endpoints save right away.
+const CHECKPOINT_INTERVAL: Duration = Duration::from_secs(5);
+
+// Cost lookups per usage row before its provider cost is given up on
+pub const MAX_COST_ATTEMPTS: i64 = 5;
+
+// WHERE clause shared by usage queries; binds session, assistant, from, to.
+const USAGE_FILTER: &str = "(?1 IS NULL OR session_id = ?1)
+    AND (?2 IS NULL OR assistant_id = ?2)
+    AND (?3 IS NULL OR substr(created_at, 1, length(?3)) >= ?3)
+    AND (?4 IS NULL OR substr(created_at, 1, length(?4)) <= ?4)";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SessionSummary {
+    pub id: String,
+    pub started_at: String,
+    pub ended_at: Option<String>,
+    pub audio_path: String,
+    pub audio_format: String,
+    pub duration_ms: Option<u64>,
+    pub segment_count: u32,
+    pub answer_count: u32,
+    #[serde(default)]
+    pub title: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GateRecord {
+    pub assistant_id: String,
+    pub model: String,
+    pub run: bool,
+    pub instruction: Option<String>,
+    pub reason: Option<String>,
+    pub confidence: Option<f32>,
+    pub created_at: String,
+    // None for decisions recorded before parse paths were tracked
+    #[serde(default)]
+    pub parse_path: Option<GateParse>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AnswerRecord {
+    pub request_id: String,
+    pub assistant_id: String,
+    pub provider: String,
+    pub model: String,
+    pub answer: String,
+    // Answer parsed against the assistant's output_schema
+    #[serde(default)]
+    pub data: Option<serde_json::Value>,
+    pub started_at: String,
+    pub completed_at: String,
+}
+
+// All filters are optional and combine with AND. Speaker and language only
+// match transcript hits; assistant_id only matches answer hits. Dates are
+// compared against the session start as prefixes, so "2025-03-01" and full
+// RFC 3339 timestamps both work.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct SearchFilters {
+    #[serde(default)]
+    pub speaker: Option<i64>,
+    #[serde(default)]
+    pub language: Option<String>,
+    #[serde(default)]
+    pub assistant_id: Option<String>,
+    #[serde(default)]
+    pub from: Option<String>,
+    #[serde(default)]
+    pub to: Option<String>,
+    #[serde(default)]
+    pub limit: Option<u32>,
+}
+
+// Filters over answers with structured data; all optional, combined with AND.
+// `field` matches a key anywhere in the data (e.g. "severity" inside a list
+// of findings) and `value` its value as text ("high", "0.8", "true").
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct StructuredFilters {
+    #[serde(default)]
+    pub session_id: Option<String>,
+    #[serde(default)]
+    pub assistant_id: Option<String>,
+    #[serde(default)]
+    pub field: Option<String>,
+    #[serde(default)]
+    pub value: Option<String>,
+    #[serde(default)]
+    pub limit: Option<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StructuredAnswer {
+    pub session_id: String,
+    #[serde(flatten)]
+    pub answer: AnswerRecord,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SearchHitKind {
+    Segment,
+    Answer,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SearchHit {
+    pub session_id: String,
+    pub session_started_at: String,
+    pub kind: SearchHitKind,
+    // Matched terms are wrapped in [brackets]
+    pub snippet: String,
+    // Offset into the recording: segment start, or answer completion time
+    pub offset_ms: Option<u64>,
+    pub speaker: Option<i64>,
+    pub language: Option<String>,
+    pub assistant_id: Option<String>,
+}
+
+// Turn free text into an FTS5 query: every word must match, as a prefix.
+// Quoting each term keeps operators and punctuation from being parsed.
+fn fts_query(raw: &str) -> Option<String> {
+    let terms: Vec<String> = raw
+        .split_whitespace()
+        .map(|term| term.replace('"', ""))
+        .filter(|term| !term.is_empty())
+        .map(|term| format!("\"{}\"*", term))
+        .collect();
+    if terms.is_empty() {
+        None
+    } else {
+        Some(terms.join(" "))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SessionDetail {
+    pub session: SessionSummary,
+    pub segments: Vec<TranscriptSegment>,
+    pub gate_decisions: Vec<GateRecord>,
+    pub answers: Vec<AnswerRecord>,
+}
+
+pub fn now_timestamp() -> String {
+    chrono::Local::now().to_rfc3339()
+}
+
+// Database lives next to other per-user app data, not in the repo config dir.
+pub fn default_db_path() -> PathBuf {
+    let mut base = dirs_next::data_local_dir().unwrap_or_else(std::env::temp_dir);
+    base.push("com.pawel.assistant");
+    base.push("sessions.db");
+    base
+}
+
+fn translation_status_str(status: TranslationStatus) -> &'static str {
+    match status {
+        TranslationStatus::None => "none",
+        TranslationStatus::Original => "original",
+        TranslationStatus::Translation => "translation",
+    }
+}
+
+fn parse_translation_status(raw: &str) -> TranslationStatus {
+    match raw {
+        "original" => TranslationStatus::Original,
+        "translation" => TranslationStatus::Translation,
+        _ => TranslationStatus::None,
+    }
+}
+
+pub struct SessionStore {
+    conn: Connection,
+}
+
+impl SessionStore {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        let path = path.as_ref();
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create session store directory: {}", e))?;
+        }
+        let conn = Connection::open(path)
+            .map_err(|e| format!("Failed to open session store at {:?}: {}", path, e))?;
+        log_to_file(&format!("Session store opened at {:?}", path));
+        Self::init(conn)
+    }
+
+    #[cfg(test)]
+    pub fn open_in_memory() -> Result<Self, String> {
+        let conn = Connection::open_in_memory()
+            .map_err(|e| format!("Failed to open in-memory session store: {}", e))?;
+        Self::init(conn)
+    }
+
+    fn init(conn: Connection) -> Result<Self, String> {
+        conn.execute_batch("PRAGMA foreign_keys = ON;")
+            .map_err(|e| format!("Failed to enable foreign keys: {}", e))?;
+        conn.execute_batch(SCHEMA)
+            .map_err(|e| format!("Failed to create session schema: {}", e))?;
+        // Stores created before gate parse paths were recorded
+        if conn
+            .prepare("SELECT parse_path FROM gate_decisions LIMIT 0")
+            .is_err()
+        {
+            conn.execute_batch("ALTER TABLE gate_decisions ADD COLUMN parse_path TEXT;")
+                .map_err(|e| format!("Failed to migrate gate decisions: {}", e))?;
+        }
+        // Stores created before answers carried structured data
+        if conn
+            .prepare("SELECT data FROM assistant_answers LIMIT 0")
+            .is_err()
+        {
+            conn.execute_batch("ALTER TABLE assistant_answers ADD COLUMN data TEXT;")
+                .map_err(|e| format!("Failed to migrate assistant answers: {}", e))?;
+        }
+        // Stores created before sessions had titles
+        if conn.prepare("SELECT title FROM sessions LIMIT 0").is_err() {
+            conn.execute_batch("ALTER TABLE sessions ADD COLUMN title TEXT;")
+                .map_err(|e| format!("Failed to migrate sessions: {}", e))?;
+        }
+        // Stores created before cost lookups were capped
+        if conn
+            .prepare("SELECT cost_attempts FROM ai_usage LIMIT 0")
+            .is_err()
+        {
+            conn.execute_batch(
+                "ALTER TABLE ai_usage ADD COLUMN cost_attempts INTEGER NOT NULL DEFAULT 0;",
+            )
+            .map_err(|e| format!("Failed to migrate AI usage: {}", e))?;
+        }
+
+        let has_search_index = conn
+            .query_row(
+                "SELECT 1 FROM sqlite_master WHERE name = 'search_index'",
+                [],
+                |_| Ok(()),
+            )
+            .optional()
+            .map_err(|e| format!("Failed to inspect session schema: {}", e))?
+            .is_some();
+        conn.execute_batch(SEARCH_SCHEMA)
+            .map_err(|e| format!("Failed to create search index: {}", e))?;
+        if !has_search_index {
+            conn.execute_batch(SEARCH_BACKFILL)
+                .map_err(|e| format!("Failed to build search index: {}", e))?;
+        }
+        Ok(Self { conn })
+    }
+
+    pub fn search(&self, query: &str, filters: &SearchFilters) -> Result<Vec<SearchHit>, String> {
+        let Some(match_expr) = fts_query(query) else {
+            return Err("Search query is empty".to_string());
+        };
+        let limit = filters.limit.unwrap_or(DEFAULT_SEARCH_LIMIT).max(1);
+
+        let mut stmt = self
+            .conn
+            .prepare(
+                "SELECT search_index.session_id, s.started_at, search_index.kind,
+                        snippet(search_index, 0, '[', ']', '…', 12),
+                        search_index.offset_ms, search_index.speaker, search_index.language,
+                        search_index.assistant_id
+                 FROM search_index JOIN sessions s ON s.id = search_index.session_id
+                 WHERE search_index MATCH ?1
+                   AND (?2 IS NULL OR (search_index.kind = 'segment' AND search_index.speaker = ?2))
+                   AND (?3 IS NULL OR (search_index.kind = 'segment' AND lower(search_index.language) = lower(?3)))
+                   AND (?4 IS NULL OR (search_index.kind = 'answer' AND search_index.assistant_id = ?4))
+                   AND (?5 IS NULL OR substr(s.started_at, 1, length(?5)) >= ?5)
+                   AND (?6 IS NULL OR substr(s.started_at, 1, length(?6)) <= ?6)
+                 ORDER BY rank
+                 LIMIT ?7",
+            )
+            .map_err(|e| format!("Failed to prepare search query: {}", e))?;
+        let rows = stmt
+            .query_map(
+                params![
+                    match_expr,
+                    filters.speaker,
+                    filters.language,
+                    filters.assistant_id,
+                    filters.from,
+                    filters.to,
+                    limit as i64,
+                ],
+                |row| {
+                    let kind = match row.get::<_, String>(2)?.as_str() {
+                        "answer" => SearchHitKind::Answer,
+                        _ => SearchHitKind::Segment,
+                    };
+                    Ok(SearchHit {
+                        session_id: row.get(0)?,
+                        session_started_at: row.get(1)?,
+                        kind,
+                        snippet: row.get(3)?,
+                        offset_ms: row.get::<_, Option<i64>>(4)?.map(|v| v.max(0) as u64),
+                        speaker: row.get(5)?,
+                        language: row.get(6)?,
+                        assistant_id: row.get(7)?,
+                    })
+                },
+            )
+            .map_err(|e| format!("Failed to search sessions: {}", e))?;
+        rows.collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("Failed to read search result: {}", e))
+    }
+
+    pub fn create_session(&self, audio_path: &str, audio_format: &str) -> Result<String, String> {
+        let now = chrono::Local::now();
+        let mut id = now.format("%Y%m%d-%H%M%S-%3f").to_string();
+        // Two sessions in the same millisecond only happen in tests, but keep ids unique
+        let mut suffix = 1;
+        while self.session_exists(&id)? {
+            suffix += 1;
+            id = format!("{}-{}", now.format("%Y%m%d-%H%M%S-%3f"), suffix);
+        }
+        self.conn
+            .execute(
+                "INSERT INTO sessions (id, started_at, audio_path, audio_format) VALUES (?1, ?2, ?3, ?4)",
+                params![id, now.to_rfc3339(), audio_path, audio_format],
+            )
+            .map_err(|e| format!("Failed to create session: {}", e))?;
+        Ok(id)
+    }
+
+    fn session_exists(&self, id: &str) -> Result<bool, String> {
+        self.conn
+            .query_row("SELECT 1 FROM sessions WHERE id = ?1", params![id], |_| {
+                Ok(())
+            })
+            .optional()
+            .map(|row| row.is_some())
+            .map_err(|e| format!("Failed to query session: {}", e))
+    }
+
+    // An empty title clears it.
+    pub fn set_title(&self, id: &str, title: &str) -> Result<(), String> {
+        let title = Some(title.trim()).filter(|t| !t.is_empty());
+        let changed = self
+            .conn
+            .execute(
+                "UPDATE sessions SET title = ?2 WHERE id = ?1",
+                params![id, title],
+            )
+            .map_err(|e| format!("Failed to set session title: {}", e))?;
+        if changed == 0 {
+            return Err(format!("Session not found: {}", id));
+        }
+        Ok(())
+    }
+
+    pub fn finish_session(&self, id: &str, duration_ms: Option<u64>) -> Result<(), String> {
+        self.conn
+            .execute(
+                "UPDATE sessions SET ended_at = ?2, duration_ms = ?3 WHERE id = ?1",
+                params![id, now_timestamp(), duration_ms.map(|d| d as i64)],
+            )
+            .map_err(|e| format!("Failed to finish session: {}", e))?;
+        Ok(())
+    }
+
+    // Replace the stored transcript of a session with the given final segments.
+    pub fn replace_segments(
+        &mut self,
+        id: &str,
+        segments: &[TranscriptSegment],
+    ) -> Result<(), String> {
+        self.save_segments_from(id, 0, segments)
+    }
+
+    // Replace the stored segments from `first_seq` on, keeping earlier ones.
+    pub fn save_segments_from(
+        &mut self,
+        id: &str,
+        first_seq: usize,
+        segments: &[TranscriptSegment],
+    ) -> Result<(), String> {
+        let tx = self
+            .conn
+            .transaction()
+            .map_err(|e| format!("Failed to start transaction: {}", e))?;
+        tx.execute(
+            "DELETE FROM transcript_segments WHERE session_id = ?1 AND seq >= ?2",
+            params![id, first_seq as i64],
+        )
+        .map_err(|e| format!("Failed to clear transcript segments: {}", e))?;
+        for (seq, segment) in (first_seq..).zip(segments) {
+            tx.execute(
+                "INSERT INTO transcript_segments
+                    (session_id, seq, speaker, language, text, start_ms, end_ms, confidence, translation_status)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+                params![
+                    id,
+                    seq as i64,
+                    segment.speaker,
+                    segment.language,
+                    segment.text,
+                    segment.start_ms.map(|v| v as i64),
+                    segment.end_ms.map(|v| v as i64),
+                    segment.confidence.map(|v| v as f64),
+                    translation_status_str(segment.translation_status),
+                ],
+            )
+            .map_err(|e| format!("Failed to store transcript segment: {}", e))?;
+        }
+        tx.commit()
+            .map_err(|e| format!("Failed to commit transcript segments: {}", e))
+    }
+
+    pub fn add_gate_decision(&self, id: &str, record: &GateRecord) -> Result<(), String> {
+        self.conn
+            .execute(
+                "INSERT INTO gate_decisions
+                    (session_id, assistant_id, model, run, instruction, reason, confidence, created_at,
+                     parse_path)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+                params![
+                    id,
+                    record.assistant_id,
+                    record.model,
+                    record.run,
+                    record.instruction,
+                    record.reason,
+                    record.confidence.map(|v| v as f64),
+                    record.created_at,
+                    record.parse_path.map(|p| p.as_str()),
+                ],
+            )
+            .map_err(|e| format!("Failed to store gate decision: {}", e))?;
+        Ok(())
+    }
+
+    pub fn add_answer(&self, id: &str, record: &AnswerRecord) -> Result<(), String> {
+        self.conn
+            .execute(
+                "INSERT INTO assistant_answers
+                    (session_id, request_id, assistant_id, provider, model, answer, started_at, completed_at, data)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+                params![
+                    id,
+                    record.request_id,
+                    record.assistant_id,
+                    record.provider,
+                    record.model,
+                    record.answer,
+                    record.started_at,
+                    record.completed_at,
+                    record.data.as_ref().map(|data| data.to_string()),
+                ],
+            )
+            .map_err(|e| format!("Failed to store assistant answer: {}", e))?;
+        Ok(())
+    }
+
+    // Usage rows outlive their session (session_id is cleared on delete) so
+    // spend reports stay complete. Returns the row id.
+    pub fn add_usage(&self, record: &UsageRecord) -> Result<i64, String> {
+        self.conn
+            .execute(
+                "INSERT INTO ai_usage
+                    (session_id, request_id, kind, assistant_id, provider, model, input_tokens,
+                     cached_input_tokens, output_tokens, cost_usd, cost_source, generation_id, created_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)",
+                params![
+                    record.session_id,
+                    record.request_id,
+                    record.kind.as_str(),
+                    record.assistant_id,
+                    record.provider,
+                    record.model,
+                    record.tokens.input_tokens,
+                    record.tokens.cached_input_tokens,
+                    record.tokens.output_tokens,
+                    record.cost_usd,
+                    record.cost_source.as_str(),
+                    record.generation_id,
+                    record.created_at,
+                ],
+            )
+            .map_err(|e| format!("Failed to record AI usage: {}", e))?;
+        Ok(self.conn.last_insert_rowid())
+    }
+
+    // Matching usage rows, oldest first.
+    pub fn usage_records(&self, filters: &UsageFilters) -> Result<Vec<UsageRecord>, String> {
+        let mut stmt = self
+            .conn
+            .prepare(&format!(
+                "SELECT session_id, request_id, kind, assistant_id, provider, model, input_tokens,
+                        cached_input_tokens, output_tokens, cost_usd, cost_source, generation_id, created_at
+                 FROM ai_usage WHERE {} ORDER BY created_at, id",
+                USAGE_FILTER
+            ))
+            .map_err(|e| format!("Failed to prepare usage query: {}", e))?;
+        let rows = stmt
+            .query_map(
+                params![
+                    filters.session_id,
+                    filters.assistant_id,
+                    filters.from,
+                    filters.to
+                ],
+                |row| {
+                    Ok(UsageRecord {
+                        session_id: row.get(0)?,
+                        request_id: row.get(1)?,
+                        kind: AiTaskKind::parse(&row.get::<_, String>(2)?)
+                            .unwrap_or(AiTaskKind::Analysis),
+                        assistant_id: row.get(3)?,
+                        provider: row.get(4)?,
+                        model: row.get(5)?,
+                        tokens: TokenUsage {
+                            input_tokens: row.get(6)?,
+                            cached_input_tokens: row.get(7)?,
+                            output_tokens: row.get(8)?,
+                        },
+                        cost_usd: row.get(9)?,
+                        cost_source: CostSource::parse(&row.get::<_, String>(10)?),
+                        generation_id: row.get(11)?,
+                        created_at: row.get(12)?,
+                    })
+                },
+            )
+            .map_err(|e| format!("Failed to load AI usage: {}", e))?;
+        rows.collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("Failed to read AI usage: {}", e))
+    }
+
+    // Known spend of the matching rows; unpriced calls count as zero.
+    pub fn total_cost(&self, filters: &UsageFilters) -> Result<f64, String> {
+        self.conn
+            .query_row(
+                &format!(
+                    "SELECT COALESCE(SUM(cost_usd), 0) FROM ai_usage WHERE {}",
+                    USAGE_FILTER
+                ),
+                params![
+                    filters.session_id,
+                    filters.assistant_id,
+                    filters.from,
+                    filters.to
+                ],
+                |row| row.get(0),
+            )
+            .map_err(|e| format!("Failed to sum AI usage: {}", e))
+    }
+
+    // Rows whose provider can report the billed cost but has not yet, and
+    // that still have cost lookups left.
+    pub fn unresolved_generations(&self, limit: u32) -> Result<Vec<(i64, String)>, String> {
+        let mut stmt = self
+            .conn
+            .prepare(
+                "SELECT id, generation_id FROM ai_usage
+                 WHERE generation_id IS NOT NULL AND cost_source != 'provider'
+                   AND cost_attempts < ?2
+                 ORDER BY id DESC LIMIT ?1",
+            )
+            .map_err(|e| format!("Failed to prepare usage query: {}", e))?;
+        let rows = stmt
+            .query_map(params![limit as i64, MAX_COST_ATTEMPTS], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })
+            .map_err(|e| format!("Failed to load AI usage: {}", e))?;
+        rows.collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("Failed to read AI usage: {}", e))
+    }
+
+    // A cost lookup that found nothing; rows stop being retried after
+    // MAX_COST_ATTEMPTS of them.
+    pub fn record_cost_attempt(&self, usage_id: i64) -> Result<(), String> {
+        self.conn
+            .execute(
+                "UPDATE ai_usage SET cost_attempts = cost_attempts + 1 WHERE id = ?1",
+                params![usage_id],
+            )
+            .map_err(|e| format!("Failed to update AI usage: {}", e))?;
+        Ok(())
+    }
+
+    pub fn set_provider_cost(&self, usage_id: i64, cost_usd: f64) -> Result<(), String> {
+        self.conn
+            .execute(
+                "UPDATE ai_usage SET cost_usd = ?2, cost_source = 'provider' WHERE id = ?1",
+                params![usage_id, cost_usd],
+            )
+            .map_err(|e| format!("Failed to update AI usage cost: {}", e))?;
+        Ok(())
+    }
+
+    // Most recent sessions first.
+    pub fn list_sessions(&self) -> Result<Vec<SessionSummary>, String> {
+        let mut stmt = self
+            .conn
+            .prepare(
+                "SELECT s.id, s.started_at, s.ended_at, s.audio_path, s.audio_format, s.duration_ms,
+                        (SELECT COUNT(*) FROM transcript_segments t WHERE t.session_id = s.id),
+                        (SELECT COUNT(*) FROM assistant_answers a WHERE a.session_id = s.id),
+                        s.title
+                 FROM sessions s
+                 ORDER BY s.started_at DESC, s.id DESC",
+            )
+            .map_err(|e| format!("Failed to prepare session query: {}", e))?;
+        let rows = stmt
+            .query_map([], Self::summary_from_row)
+            .map_err(|e| format!("Failed to list sessions: {}", e))?;
+        rows.collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("Failed to read session row: {}", e))
+    }
+
+    pub fn get_session(&self, id: &str) -> Result<SessionDetail, String> {
+        let session = self
+            .conn
+            .query_row(
+                "SELECT s.id, s.started_at, s.ended_at, s.audio_path, s.audio_format, s.duration_ms,
+                        (SELECT COUNT(*) FROM transcript_segments t WHERE t.session_id = s.id),
+                        (SELECT COUNT(*) FROM assistant_answers a WHERE a.session_id = s.id),
+                        s.title
+                 FROM sessions s WHERE s.id = ?1",
+                params![id],
+                Self::summary_from_row,
+            )
+            .optional()
+            .map_err(|e| format!("Failed to load session: {}", e))?
+            .ok_or_else(|| format!("Session not found: {}", id))?;
+
+        let segments = self.load_segments(id)?;
+
+        let mut stmt = self
+            .conn
+            .prepare(
+                "SELECT assistant_id, model, run, instruction, reason, confidence, created_at,
+                        parse_path
+                 FROM gate_decisions WHERE session_id = ?1 ORDER BY id",
+            )
+            .map_err(|e| format!("Failed to prepare gate query: {}", e))?;
+        let gate_decisions = stmt
+            .query_map(params![id], |row| {
+                Ok(GateRecord {
+                    assistant_id: row.get(0)?,
+                    model: row.get(1)?,
+                    run: row.get(2)?,
+                    instruction: row.get(3)?,
+                    reason: row.get(4)?,
+                    confidence: row.get::<_, Option<f64>>(5)?.map(|v| v as f32),
+                    created_at: row.get(6)?,
+                    parse_path: row
+                        .get::<_, Option<String>>(7)?
+                        .and_then(|p| GateParse::parse(&p)),
+                })
+            })
+            .and_then(|rows| rows.collect::<Result<Vec<_>, _>>())
+            .map_err(|e| format!("Failed to load gate decisions: {}", e))?;
+
+        let mut stmt = self
+            .conn
+            .prepare(
+                "SELECT request_id, assistant_id, provider, model, answer, started_at, completed_at, data
+                 FROM assistant_answers WHERE session_id = ?1 ORDER BY id",
+            )
+            .map_err(|e| format!("Failed to prepare answer query: {}", e))?;
+        let answers = stmt
+            .query_map(params![id], |row| Self::answer_from_row(row, 0))
+            .and_then(|rows| rows.collect::<Result<Vec<_>, _>>())
+            .map_err(|e| format!("Failed to load assistant answers: {}", e))?;
+
+        Ok(SessionDetail {
+            session,
+            segments,
+            gate_decisions,
+            answers,
+        })
+    }
+
+    fn load_segments(&self, id: &str) -> Result<Vec<TranscriptSegment>, String> {
+        let mut stmt = self
+            .conn
+            .prepare(
+                "SELECT speaker, language, text, start_ms, end_ms, confidence, translation_status
+                 FROM transcript_segments WHERE session_id = ?1 ORDER BY seq",
+            )
+            .map_err(|e| format!("Failed to prepare segment query: {}", e))?;
+        stmt.query_map(params![id], |row| {
+            Ok(TranscriptSegment {
+                speaker: row.get(0)?,
+                language: row.get(1)?,
+                text: row.get(2)?,
+                start_ms: row.get::<_, Option<i64>>(3)?.map(|v| v as u64),
+                end_ms: row.get::<_, Option<i64>>(4)?.map(|v| v as u64),
+                confidence: row.get::<_, Option<f64>>(5)?.map(|v| v as f32),
+                is_final: true,
+                translation_status: parse_translation_status(&row.get::<_, String>(6)?),
+            })
+        })
+        .and_then(|rows| rows.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| format!("Failed to load transcript segments: {}", e))
+    }
+
+    // Remove a session and everything recorded for it. Returns the audio path
+    // so the caller can decide whether to delete the recording as well.
+    pub fn delete_session(&self, id: &str) -> Result<String, String> {
+        let audio_path: String = self
+            .conn
+            .query_row(
+                "SELECT audio_path FROM sessions WHERE id = ?1",
+                params![id],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(|e| format!("Failed to load session: {}", e))?
+            .ok_or_else(|| format!("Session not found: {}", id))?;
+        self.conn
+            .execute("DELETE FROM sessions WHERE id = ?1", params![id])
+            .map_err(|e| format!("Failed to delete session: {}", e))?;
+        Ok(audio_path)
+    }
+
+    // Answer columns starting at `first`, in table order with data last.
+    fn answer_from_row(row: &rusqlite::Row<'_>, first: usize) -> rusqlite::Result<AnswerRecord> {
+        Ok(AnswerRecord {
+            request_id: row.get(first)?,
+            assistant_id: row.get(first + 1)?,
+            provider: row.get(first + 2)?,
+            model: row.get(first + 3)?,
+            answer: row.get(first + 4)?,
+            started_at: row.get(first + 5)?,
+            completed_at: row.get(first + 6)?,
+            data: row
+                .get::<_, Option<String>>(first + 7)?
+                .and_then(|data| serde_json::from_str(&data).ok()),
+        })
+    }
+
+    // Answers with structured data, oldest first.
+    pub fn structured_answers(
+        &self,
+        filters: &StructuredFilters,
+    ) -> Result<Vec<StructuredAnswer>, String> {
+        let limit = filters.limit.unwrap_or(DEFAULT_SEARCH_LIMIT).max(1);
+        let mut stmt = self
+            .conn
+            .prepare(
+                "SELECT a.session_id, a.request_id, a.assistant_id, a.provider, a.model, a.answer,
+                        a.started_at, a.completed_at, a.data
+                 FROM assistant_answers a
+                 WHERE a.data IS NOT NULL
+                   AND (?1 IS NULL OR a.session_id = ?1)
+                   AND (?2 IS NULL OR a.assistant_id = ?2)
+                   AND (?3 IS NULL OR EXISTS (
+                        SELECT 1 FROM json_tree(a.data) t
+                        WHERE t.key = ?3
+                          AND (?4 IS NULL OR CASE t.type
+                                WHEN 'true' THEN 'true'
+                                WHEN 'false' THEN 'false'
+                                ELSE CAST(t.atom AS TEXT) END = ?4)))
+                 ORDER BY a.id
+                 LIMIT ?5",
+            )
+            .map_err(|e| format!("Failed to prepare structured answer query: {}", e))?;
+        let rows = stmt
+            .query_map(
+                params![
+                    filters.session_id,
+                    filters.assistant_id,
+                    filters.field,
+                    filters.value,
+                    limit as i64,
+                ],
+                |row| {
+                    Ok(StructuredAnswer {
+                        session_id: row.get(0)?,
+                        answer: Self::answer_from_row(row, 1)?,
+                    })
+                },
+            )
+            .map_err(|e| format!("Failed to query structured answers: {}", e))?;
+        rows.collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("Failed to read structured answer: {}", e))
+    }
+
+    fn summary_from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<SessionSummary> {
+        Ok(SessionSummary {
+            id: row.get(0)?,
+            started_at: row.get(1)?,
+            ended_at: row.get(2)?,
+            audio_path: row.get(3)?,
+            audio_format: row.get(4)?,
+            duration_ms: row.get::<_, Option<i64>>(5)?.map(|v| v as u64),
+            segment_count: row.get::<_, i64>(6)? as u32,
+            answer_count: row.get::<_, i64>(7)? as u32,
+            title: row.get(8)?,
+        })
+    }
+}
+
+// The session currently being recorded. Providers emit the whole transcript
+// on every update, so only the latest final segments are kept; when the
+// transcript is cleared mid-session the previous segments are archived.
+#[derive(Debug)]
+pub struct ActiveSession {
+    pub id: String,
+    pub audio_path: String,
+    // Mirrors the stored title for `{{session_title}}`
+    pub title: Option<String>,
+    started: Instant,
+    archived: Vec<TranscriptSegment>,
+    current: Vec<TranscriptSegment>,
+    // Archived segments already in the store; they no longer change
+    saved_archived: usize,
+    last_checkpoint: Instant,
+    unsaved: bool,
+}
+
+impl ActiveSession {
+    pub fn new(id: String, audio_path: String) -> Self {
+        Self {
+            id,
+            audio_path,
+            title: None,
+            started: Instant::now(),
+            archived: Vec::new(),
+            current: Vec::new(),
+            saved_archived: 0,
+            last_checkpoint: Instant::now(),
+            unsaved: false,
+        }
+    }
+
+    pub fn observe(&mut self, update: &TranscriptUpdate) {
+        let finals: Vec<TranscriptSegment> = update
+            .segments
+            .iter()
+            .filter(|s| s.is_final)
+            .cloned()
+            .collect();
+        if finals.is_empty() {
+            return;
+        }
+        // The last final segment may still grow; everything before it must match
+        let stable = self.current.len().saturating_sub(1);
+        let continues = finals.len() >= self.current.len()
+            && self.current[..stable] == finals[..stable]
+            && self
+                .current
+                .last()
+                .zip(finals.get(stable))
+                .map(|(prev, next)| next.text.starts_with(prev.text.as_str()))
+                .unwrap_or(true);
+        if !continues {
+            self.archived.append(&mut self.current);
+        }
+        self.unsaved |= self.current != finals;
+        self.current = finals;
+    }
+
+    // Segments to store since the last checkpoint, as (first seq, segments),
+    // once something changed and an endpoint or CHECKPOINT_INTERVAL has passed.
+    pub fn checkpoint(&mut self, endpoint: bool) -> Option<(usize, Vec<TranscriptSegment>)> {
+        if !self.unsaved || (!endpoint && self.last_checkpoint.elapsed() < CHECKPOINT_INTERVAL) {
+            return None;
+        }
+        let first_seq = self.saved_archived;
+        let segments = self.archived[first_seq..]
+            .iter()
+            .chain(self.current.iter())
+            .cloned()
+            .collect();
+        self.saved_archived = self.archived.len();
+        self.last_checkpoint = Instant::now();
+        self.unsaved = false;
+        Some((first_seq, segments))
+    }
+
+    pub fn final_segments(&self) -> Vec<TranscriptSegment> {
+        self.archived
+            .iter()
+            .chain(self.current.iter())
+            .cloned()
+            .collect()
+    }
+
+    pub fn elapsed_ms(&self) -> u64 {
+        self.started.elapsed().as_millis() as u64
+    }
+}
+
+// Exact duration for WAV files; other formats fall back to the caller's estimate.
+pub fn audio_duration_ms(path: &str) -> Option<u64> {
+    let reader = hound::WavReader::open(path).ok()?;
+    let spec = reader.spec();
+    if spec.sample_rate == 0 {
+        return None;
+    }
+    Some(reader.duration() as u64 * 1000 / spec.sample_rate as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn segment(text: &str, speaker: i64, is_final: bool) -> TranscriptSegment {
+        TranscriptSegment {
+            speaker,
+            language: Some("en".into()),
+            text: text.into(),
+            start_ms: Some(0),
+            end_ms: Some(500),
+            confidence: Some(0.9),
+            is_final,
+            translation_status: TranslationStatus::None,
+        }
+    }
+
+    fn update(segments: Vec<TranscriptSegment>) -> TranscriptUpdate {
+        TranscriptUpdate {
+            segments,
+            text: "rendered".into(),
+            endpoint: false,
+        }
+    }
+
+    #[test]
+    fn test_session_round_trip_and_delete() {
+        let mut store = SessionStore::open_in_memory().unwrap();
+        let id = store.create_session("/tmp/a.wav", "wav").unwrap();
+        store
+            .replace_segments(&id, &[segment("Hello.", 1, true), segment("Hi.", 2, true)])
+            .unwrap();
+        store
+            .add_gate_decision(
+                &id,
+                &GateRecord {
+                    assistant_id: "general".into(),
+                    model: "gpt-4.1-nano".into(),
+                    run: true,
+                    instruction: Some("answer".into()),
+                    reason: None,
+                    confidence: Some(0.5),
+                    created_at: now_timestamp(),
+                    parse_path: Some(GateParse::Extracted),
+                },
+            )
+            .unwrap();
+        store
+            .add_answer(
+                &id,
+                &AnswerRecord {
+                    request_id: "r1".into(),
+                    assistant_id: "general".into(),
+                    provider: "openai".into(),
+                    model: "gpt-4.1".into(),
+                    answer: "Sure.".into(),
+                    data: None,
+                    started_at: now_timestamp(),
+                    completed_at: now_timestamp(),
+                },
+            )
+            .unwrap();
+        store.finish_session(&id, Some(1500)).unwrap();
+        store.set_title(&id, " Design review ").unwrap();
+        assert!(store.set_title("missing", "x").is_err());
+
+        let sessions = store.list_sessions().unwrap();
+        assert_eq!(sessions.len(), 1);
+        assert_eq!(sessions[0].title.as_deref(), Some("Design review"));
+        assert_eq!(sessions[0].segment_count, 2);
+        assert_eq!(sessions[0].answer_count, 1);
+        assert_eq!(sessions[0].duration_ms, Some(1500));
+
+        let detail = store.get_session(&id).unwrap();
+        assert_eq!(detail.segments[1].text, "Hi.");
+        assert_eq!(detail.segments[1].speaker, 2);
+        assert!(detail.gate_decisions[0].run);
+        assert_eq!(
+            detail.gate_decisions[0].parse_path,
+            Some(GateParse::Extracted)
+        );
+        assert_eq!(detail.answers[0].model, "gpt-4.1");
+
+        assert_eq!(store.delete_session(&id).unwrap(), "/tmp/a.wav");
+        assert!(store.list_sessions().unwrap().is_empty());
+        assert!(store.get_session(&id).is_err());
+        let orphans: i64 = store
+            .conn
+            .query_row("SELECT COUNT(*) FROM transcript_segments", [], |r| r.get(0))
+            .unwrap();
+        assert_eq!(orphans, 0);
+    }
+
+    #[test]
+    fn test_usage_survives_session_delete() {
+        let store = SessionStore::open_in_memory().unwrap();
+        let id = store.create_session("/tmp/a.wav", "wav").unwrap();
+        let mut record = UsageRecord {
+            request_id: "r1".into(),
+            session_id: Some(id.clone()),
+            kind: AiTaskKind::Gate,
+            assistant_id: "general".into(),
+            provider: "openrouter".into(),
+            model: "deepseek/deepseek-chat".into(),
+            tokens: TokenUsage {
+                input_tokens: 120,
+                output_tokens: 20,
+                cached_input_tokens: 0,
+            },
+            cost_usd: None,
+            cost_source: CostSource::Unpriced,
+            generation_id: Some("gen-1".into()),
+            created_at: "2025-03-01T10:00:00+01:00".into(),
+        };
+        store.add_usage(&record).unwrap();
+        record.generation_id = Some("gen-2".into());
+        record.created_at = "2025-04-02T10:00:00+01:00".into();
+        let lost = store.add_usage(&record).unwrap();
+        record.generation_id = None;
+        store.add_usage(&record).unwrap();
+
+        // A generation whose cost never shows up is given up on
+        for _ in 0..MAX_COST_ATTEMPTS {
+            store.record_cost_attempt(lost).unwrap();
+        }
+        let pending = store.unresolved_generations(10).unwrap();
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].1, "gen-1");
+        store.set_provider_cost(pending[0].0, 0.002).unwrap();
+        assert!(store.unresolved_generations(10).unwrap().is_empty());
+
+        let march = UsageFilters {
+            from: Some("2025-03".into()),
+            to: Some("2025-03".into()),
+            ..Default::default()
+        };
+        let rows = store.usage_records(&march).unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].kind, AiTaskKind::Gate);
+        assert_eq!(rows[0].cost_usd, Some(0.002));
+        assert_eq!(rows[0].cost_source, CostSource::Provider);
+        assert_eq!(store.total_cost(&march).unwrap(), 0.002);
+
+        store.delete_session(&id).unwrap();
+        let rows = store.usage_records(&UsageFilters::default()).unwrap();
+        assert_eq!(rows.len(), 3);
+        assert!(rows.iter().all(|r| r.session_id.is_none()));
+    }
+
+    #[test]
+    fn test_active_session_keeps_segments_across_clear() {
+        let mut active = ActiveSession::new("s".into(), "/tmp/a.wav".into());
+        active.observe(&update(vec![
+            segment("Hello", 1, true),
+            segment("wor", 1, false),
+        ]));
+        active.observe(&update(vec![segment("Hello world", 1, true)]));
+        // Transcript cleared: the provider restarts from an empty token list
+        active.observe(&update(vec![segment("Next topic", 2, true)]));
+
+        let texts: Vec<String> = active
+            .final_segments()
+            .into_iter()
+            .map(|s| s.text)
+            .collect();
+        assert_eq!(texts, vec!["Hello world", "Next topic"]);
+    }
+
+    #[test]
+    fn test_active_session_checkpoints() {
+        let mut store = SessionStore::open_in_memory().unwrap();
+        let id = store.create_session("/tmp/a.wav", "wav").unwrap();
+        let mut active = ActiveSession::new(id.clone(), "/tmp/a.wav".into());
+        let mut save = |active: &mut ActiveSession, endpoint: bool| {
+            let (first_seq, segments) = active.checkpoint(endpoint)?;
+            store.save_segments_from(&id, first_seq, &segments).unwrap();
+            Some(store.get_session(&id).unwrap().segments)
+        };
+
+        active.observe(&update(vec![segment("Hello", 1, true)]));
+        // Not yet due without an endpoint
+        assert!(save(&mut active, false).is_none());
+        active.observe(&update(vec![segment("Hello world", 1, true)]));
+        assert_eq!(save(&mut active, true).unwrap().len(), 1);
+        assert!(save(&mut active, true).is_none());
+
+        // After a clear the earlier segment is archived and kept as stored
+        active.observe(&update(vec![segment("Next", 2, true)]));
+        active.observe(&update(vec![segment("Next topic", 2, true)]));
+        let stored = save(&mut active, true).unwrap();
+        let texts: Vec<&str> = stored.iter().map(|s| s.text.as_str()).collect();
+        assert_eq!(texts, vec!["Hello world", "Next topic"]);
+        active.observe(&update(vec![segment("Next topic too", 2, true)]));
+        let (first_seq, segments) = active.checkpoint(true).unwrap();
+        assert_eq!((first_seq, segments.len()), (1, 1));
+    }
+
+    #[test]
+    fn test_search_with_filters() {
+        let mut store = SessionStore::open_in_memory().unwrap();
+        let id = store.create_session("/tmp/a.wav", "wav").unwrap();
+        let mut german = segment("Wir besprechen das Budget", 2, true);
+        german.language = Some("de".into());
+        german.start_ms = Some(42_000);
+        store
+            .replace_segments(&id, &[segment("The budget is tight.", 1, true), german])
+            .unwrap();
+        store
+            .add_answer(
+                &id,
+                &AnswerRecord {
+                    request_id: "r1".into(),
+                    assistant_id: "consistency".into(),
+                    provider: "openai".into(),
+                    model: "gpt-4.1".into(),
+                    answer: "Budget numbers conflict with Q3.".into(),
+                    data: Some(serde_json::json!({"findings": [
+                        {"claim": "Budget is 10k", "contradicts": "Q3 plan", "severity": "high", "resolved": false}
+                    ]})),
+                    started_at: now_timestamp(),
+                    completed_at: now_timestamp(),
+                },
+            )
+            .unwrap();
+
+        let all = store.search("budg", &SearchFilters::default()).unwrap();
+        assert_eq!(all.len(), 3);
+        assert!(all.iter().all(|hit| hit.snippet.contains('[')));
+
+        let by_language = SearchFilters {
+            language: Some("DE".into()),
+            ..Default::default()
+        };
+        let hits = store.search("budget", &by_language).unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].session_id, id);
+        assert_eq!(hits[0].offset_ms, Some(42_000));
+        assert_eq!(hits[0].speaker, Some(2));
+
+        let by_assistant = SearchFilters {
+            assistant_id: Some("consistency".into()),
+            ..Default::default()
+        };
+        let hits = store.search("budget", &by_assistant).unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].kind, SearchHitKind::Answer);
+        assert!(hits[0].offset_ms.is_some());
+
+        let future = SearchFilters {
+            from: Some("2999-01-01".into()),
+            ..Default::default()
+        };
+        assert!(store.search("budget", &future).unwrap().is_empty());
+
+        let structured = |field: &str, value: &str| {
+            store
+                .structured_answers(&StructuredFilters {
+                    field: Some(field.into()),
+                    value: Some(value.into()),
+                    ..Default::default()
+                })
+                .unwrap()
+        };
+        let high = structured("severity", "high");
+        assert_eq!(high.len(), 1);
+        assert_eq!(high[0].session_id, id);
+        assert_eq!(
+            high[0].answer.data.as_ref().unwrap()["findings"][0]["claim"],
+            "Budget is 10k"
+        );
+        assert_eq!(structured("resolved", "false").len(), 1);
+        assert!(structured("severity", "low").is_empty());
+        assert_eq!(
+            store.get_session(&id).unwrap().answers[0].data,
+            high[0].answer.data
+        );
+
+        // Operators and quotes are treated as plain text
+        assert!(store
+            .search("\"budget OR (", &SearchFilters::default())
+            .is_ok());
+        assert!(store.search("   ", &SearchFilters::default()).is_err());
+
+        store.delete_session(&id).unwrap();
+        assert!(store
+            .search("budget", &SearchFilters::default())
+            .unwrap()
+            .is_empty());
+    }
+}
@@ -1,10 +1,19 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+
 use futures_util::{SinkExt, StreamExt};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
-use tauri::Emitter;
-use tokio::{select, sync::mpsc};
-use tokio_tungstenite::{connect_async_tls_with_config, tungstenite::protocol::Message};
+use tokio::{net::TcpStream, select, sync::mpsc};
+use tokio_tungstenite::{
+    connect_async_tls_with_config, tungstenite::protocol::Message, MaybeTlsStream, WebSocketStream,
+};
 
+pub use crate::transcription::AudioChunk;
+use crate::transcription::{
+    SessionChannels, TranscriptEvent, TranscriptSegment, TranscriptUpdate, TranscriptionControl,
+    TranslationStatus,
+};
 use crate::utils::log_to_file;
 
 const SONIOX_URL: &str = "wss://stt-rt.soniox.com/transcribe-websocket";
@@ -18,6 +27,33 @@ pub struct SonioxOptions {
     pub audio_format: String, // "auto" or "pcm_s16le"
     #[serde(default = "default_translation")]
     pub translation: String, // "none" | "one_way" | "two_way"
+    #[serde(default = "default_model")]
+    pub model: String,
+    #[serde(default = "default_language_hints", alias = "languageHints")]
+    pub language_hints: Vec<String>,
+    // Domain context: free text plus a list of terms the model should favour
+    #[serde(default)]
+    pub context: String,
+    #[serde(default, alias = "contextTerms")]
+    pub context_terms: Vec<String>,
+    #[serde(default = "default_true", alias = "enableEndpointDetection")]
+    pub enable_endpoint_detection: bool,
+    #[serde(default = "default_true", alias = "enableSpeakerDiarization")]
+    pub enable_speaker_diarization: bool,
+    // Target for "one_way" translation
+    #[serde(default = "default_translation_target", alias = "translationTarget")]
+    pub translation_target: String,
+    // Language pair for "two_way" translation
+    #[serde(
+        default = "default_translation_language_a",
+        alias = "translationLanguageA"
+    )]
+    pub translation_language_a: String,
+    #[serde(
+        default = "default_translation_language_b",
+        alias = "translationLanguageB"
+    )]
+    pub translation_language_b: String,
 }
 
 fn default_audio_format() -> String {
@@ -26,212 +62,571 @@ fn default_audio_format() -> String {
 fn default_translation() -> String {
     "none".into()
 }
+fn default_model() -> String {
+    "stt-rt-preview-v2".into()
+}
+fn default_language_hints() -> Vec<String> {
+    vec!["en".into(), "pl".into()]
+}
+fn default_true() -> bool {
+    true
+}
+fn default_translation_target() -> String {
+    "es".into()
+}
+fn default_translation_language_a() -> String {
+    "en".into()
+}
+fn default_translation_language_b() -> String {
+    "es".into()
+}
 
-#[derive(Debug, Clone)]
-pub struct AudioChunk {
-    pub samples: Vec<i16>,
-    pub channels: u16,
-    pub sample_rate: u32,
+// Fill in a missing API key from SONIOX_API_KEY or config/soniox.local.json.
+pub fn resolve_api_key(opts: &mut SonioxOptions) -> Result<(), String> {
+    if !opts.api_key.trim().is_empty() {
+        return Ok(());
+    }
+    // Try environment variable
+    if let Ok(k) = std::env::var("SONIOX_API_KEY") {
+        opts.api_key = k;
+    }
+    // Try config file: config/soniox.local.json
+    if opts.api_key.trim().is_empty() {
+        let mut cfg_path =
+            std::env::current_dir().unwrap_or_else(|_| std::path::PathBuf::from("."));
+        cfg_path.push("config/soniox.local.json");
+        if let Ok(txt) = std::fs::read_to_string(&cfg_path) {
+            if let Ok(json) = serde_json::from_str::<serde_json::Value>(&txt) {
+                if let Some(k) = json.get("api_key").and_then(|v| v.as_str()) {
+                    opts.api_key = k.to_string();
+                }
+            }
+        }
+    }
+    if opts.api_key.trim().is_empty() {
+        return Err("Missing Soniox API key. Provide api_key, SONIOX_API_KEY env, or config/soniox.local.json".into());
+    }
+    Ok(())
 }
 
-#[derive(Debug, Clone)]
-pub enum SonioxControl {
-    ClearTranscript,
+// Reconnect policy for dropped websocket sessions
+const MAX_RECONNECT_ATTEMPTS: u32 = 8;
+const RECONNECT_BASE_DELAY_MS: u64 = 500;
+const RECONNECT_MAX_DELAY_MS: u64 = 30_000;
+// Audio held while disconnected: 30 s of 16 kHz mono PCM16
+const REPLAY_BUFFER_MAX_BYTES: usize = 16_000 * 2 * 30;
+// 16 kHz mono PCM16
+const BYTES_PER_MS: u64 = 32;
+
+type SonioxSocket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+// Delay before reconnect attempt `attempt` (1-based): 0.5s, 1s, 2s, ... capped at 30s.
+pub fn reconnect_delay(attempt: u32) -> Duration {
+    let exponent = attempt.saturating_sub(1).min(16);
+    let delay_ms = RECONNECT_BASE_DELAY_MS.saturating_mul(1u64 << exponent);
+    Duration::from_millis(delay_ms.min(RECONNECT_MAX_DELAY_MS))
+}
+
+// Soniox error codes worth reconnecting for: timeouts, throttling and server errors.
+fn is_retryable_error(code: i64) -> bool {
+    code == 408 || code == 429 || code >= 500
 }
 
-#[derive(Clone)]
-pub struct SonioxHandle {
-    pub tx: mpsc::Sender<AudioChunk>,
-    pub ctrl: mpsc::Sender<SonioxControl>,
+// Bounded FIFO of PCM frames captured while the websocket is down. When full,
+// the oldest audio is dropped so the replay stays close to real time.
+#[derive(Debug)]
+pub struct ReplayBuffer {
+    frames: VecDeque<Vec<u8>>,
+    bytes: usize,
+    max_bytes: usize,
+    dropped_bytes: usize,
 }
 
-impl SonioxHandle {
-    pub fn try_send(&self, chunk: AudioChunk) {
-        let _ = self.tx.try_send(chunk);
+impl ReplayBuffer {
+    pub fn new(max_bytes: usize) -> Self {
+        Self {
+            frames: VecDeque::new(),
+            bytes: 0,
+            max_bytes,
+            dropped_bytes: 0,
+        }
     }
+
+    pub fn push(&mut self, frame: Vec<u8>) {
+        self.bytes += frame.len();
+        self.frames.push_back(frame);
+        while self.bytes > self.max_bytes {
+            match self.frames.pop_front() {
+                Some(old) => {
+                    self.bytes -= old.len();
+                    self.dropped_bytes += old.len();
+                }
+                None => break,
+            }
+        }
+    }
+
+    pub fn pop(&mut self) -> Option<Vec<u8>> {
+        let frame = self.frames.pop_front()?;
+        self.bytes -= frame.len();
+        Some(frame)
+    }
+
+    // Put a frame that failed to send back at the head of the queue.
+    pub fn requeue(&mut self, frame: Vec<u8>) {
+        self.bytes += frame.len();
+        self.frames.push_front(frame);
+    }
+
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+
+    pub fn take_dropped_bytes(&mut self) -> usize {
+        std::mem::take(&mut self.dropped_bytes)
+    }
+}
+
+// Rendering state that survives reconnects: final tokens are kept, while
+// tentative text from the lost connection is discarded. Only audio that never
+// reached the old connection is replayed, so that text is not recognized again.
+// Token times restart at 0 on every connection, so they are shifted by the
+// audio that earlier connections carried (or that was dropped in between).
+#[derive(Debug, Default)]
+struct TranscriptState {
+    final_tokens: Vec<serde_json::Value>,
+    last_emitted_text: String,
+    suppress_repeat: Option<String>,
+    // Session time at which the current connection's audio starts
+    offset_ms: u64,
+    // Audio sent on the current connection
+    connection_bytes: u64,
+    // Flush requested while no connection could take it
+    pending_flush: bool,
 }
 
-pub async fn start_session(
-    app: tauri::AppHandle,
-    opts: SonioxOptions,
-) -> Result<SonioxHandle, String> {
+impl TranscriptState {
+    fn audio_sent(&mut self, bytes: usize) {
+        self.connection_bytes += bytes as u64;
+    }
+
+    // Audio that never reached any connection, e.g. dropped from the replay buffer
+    fn audio_skipped(&mut self, bytes: usize) {
+        self.offset_ms += bytes as u64 / BYTES_PER_MS;
+    }
+
+    // A new connection starts right after the audio the previous one received.
+    fn start_connection(&mut self) {
+        self.offset_ms += self.connection_bytes / BYTES_PER_MS;
+        self.connection_bytes = 0;
+    }
+
+    fn shift_times(&self, token: &serde_json::Value) -> serde_json::Value {
+        let mut token = token.clone();
+        if self.offset_ms > 0 {
+            for key in ["start_ms", "end_ms"] {
+                if let Some(ms) = token.get(key).and_then(|v| v.as_u64()) {
+                    token[key] = json!(ms + self.offset_ms);
+                }
+            }
+        }
+        token
+    }
+
+    fn clear(&mut self) {
+        if self.suppress_repeat.is_none() {
+            self.suppress_repeat = Some(self.last_emitted_text.clone());
+        }
+        self.final_tokens.clear();
+        self.last_emitted_text.clear();
+    }
+
+    // Fold one Soniox response into the state; returns the update to emit, if any.
+    fn ingest(&mut self, res: &serde_json::Value) -> Option<TranscriptUpdate> {
+        // Collect tokens
+        let mut non_final: Vec<serde_json::Value> = Vec::new();
+        let mut has_tokens = false;
+        let mut endpoint = false;
+        if let Some(tokens) = res.get("tokens").and_then(|t| t.as_array()) {
+            for token in tokens {
+                let text = token.get("text").and_then(|t| t.as_str()).unwrap_or("");
+                if text.is_empty() {
+                    continue;
+                }
+                has_tokens = true;
+                if token
+                    .get("is_final")
+                    .and_then(|f| f.as_bool())
+                    .unwrap_or(false)
+                {
+                    // Endpoint detection marks the end of an utterance with a final `<end>` token
+                    endpoint |= text.trim().eq_ignore_ascii_case("<end>");
+                    self.final_tokens.push(self.shift_times(token));
+                } else {
+                    non_final.push(self.shift_times(token));
+                }
+            }
+        }
+
+        // Always emit transcript updates to show real-time progress
+        let text = render_tokens(&self.final_tokens, &non_final);
+
+        // If we have any meaningful content, emit it
+        if has_tokens || !text.is_empty() {
+            if self.suppress_repeat.is_some() {
+                if self.suppress_repeat.as_deref() == Some(text.as_str()) {
+                    // Skip emitting the same text immediately after a clear request
+                    return None;
+                }
+                self.suppress_repeat = None;
+            }
+            log_to_file(&format!("Emitting transcript: '{}'", text));
+            self.last_emitted_text = text.clone();
+            Some(TranscriptUpdate {
+                segments: build_segments(&self.final_tokens, &non_final),
+                text,
+                endpoint,
+            })
+        } else {
+            // Debug: Even emit empty responses to see if events are working
+            log_to_file("Received Soniox response with no tokens");
+            self.last_emitted_text = "[no speech detected]".to_string();
+            Some(TranscriptUpdate::text_only("[no speech detected]"))
+        }
+    }
+}
+
+enum PumpOutcome {
+    // Audio channel closed or the server finished the stream
+    Ended,
+    // Connection dropped; worth reconnecting
+    Lost(String),
+    // Non-recoverable error reported by Soniox
+    Fatal(String),
+}
+
+// The realtime API takes context as a single string, so terms are appended
+// to the free text as a list.
+fn session_context(opts: &SonioxOptions) -> String {
+    let text = opts.context.trim();
+    let terms: Vec<&str> = opts
+        .context_terms
+        .iter()
+        .map(|t| t.trim())
+        .filter(|t| !t.is_empty())
+        .collect();
+    if terms.is_empty() {
+        return text.to_string();
+    }
+    let terms = format!("Terms: {}", terms.join(", "));
+    if text.is_empty() {
+        terms
+    } else {
+        format!("{text}\n{terms}")
+    }
+}
+
+pub fn build_session_config(opts: &SonioxOptions) -> serde_json::Value {
+    let language_hints: Vec<&str> = opts
+        .language_hints
+        .iter()
+        .map(|l| l.trim())
+        .filter(|l| !l.is_empty())
+        .collect();
+    let mut config = json!({
+      "api_key": opts.api_key,
+      "model": opts.model,
+      "language_hints": language_hints,
+      "enable_language_identification": true,
+      "enable_speaker_diarization": opts.enable_speaker_diarization,
+      "context": session_context(opts),
+      "enable_endpoint_detection": opts.enable_endpoint_detection,
+    });
+
+    if opts.audio_format == "auto" {
+        config["audio_format"] = json!("auto");
+    } else {
+        config["audio_format"] = json!("pcm_s16le");
+        config["sample_rate"] = json!(16000);
+        config["num_channels"] = json!(1);
+    }
+
+    match opts.translation.as_str() {
+        "one_way" => {
+            config["translation"] =
+                json!({"type": "one_way", "target_language": opts.translation_target});
+        }
+        "two_way" => {
+            config["translation"] = json!({
+                "type": "two_way",
+                "language_a": opts.translation_language_a,
+                "language_b": opts.translation_language_b,
+            });
+        }
+        _ => {}
+    }
+
+    config
+}
+
+async fn connect(app: &tauri::AppHandle, opts: &SonioxOptions) -> Result<SonioxSocket, String> {
+    let (mut ws, _resp) = connect_async_tls_with_config(SONIOX_URL, None, false, None)
+        .await
+        .map_err(|e| format!("connect failed: {e}"))?;
+    TranscriptEvent::Status("connected").emit(app);
+
+    let config = build_session_config(opts);
+    ws.send(Message::Text(config.to_string()))
+        .await
+        .map_err(|e| format!("send config failed: {e}"))?;
+    TranscriptEvent::Status("config_sent").emit(app);
+    Ok(ws)
+}
+
+pub fn start_session(app: tauri::AppHandle, opts: SonioxOptions) -> SessionChannels {
     // Channel from audio thread to WS task
     let (tx, mut rx) = mpsc::channel::<AudioChunk>(32);
-    let (ctrl_tx, mut ctrl_rx) = mpsc::channel::<SonioxControl>(8);
-
-    let app_for_task = app.clone();
-    let opts_for_task = opts.clone();
+    let (ctrl_tx, mut ctrl_rx) = mpsc::channel::<TranscriptionControl>(8);
 
     tauri::async_runtime::spawn(async move {
         log_to_file("Soniox: connecting...");
-        let _ = app_for_task.emit("soniox-status", "connecting");
-
-        let (mut ws, _resp) =
-            match connect_async_tls_with_config(SONIOX_URL, None, false, None).await {
-                Ok(pair) => pair,
-                Err(e) => {
-                    let _ = app_for_task.emit("soniox-error", format!("connect failed: {e}"));
-                    return;
+        TranscriptEvent::Status("connecting").emit(&app);
+
+        let mut transcript = TranscriptState::default();
+        let mut replay = ReplayBuffer::new(REPLAY_BUFFER_MAX_BYTES);
+        let mut attempt: u32 = 0;
+
+        loop {
+            let failure = match connect(&app, &opts).await {
+                Ok(ws) => {
+                    if attempt > 0 {
+                        log_to_file(&format!(
+                            "Soniox: reconnected on attempt {}, replaying {} buffered bytes",
+                            attempt,
+                            replay.bytes()
+                        ));
+                    }
+                    transcript.start_connection();
+                    match pump(
+                        ws,
+                        &app,
+                        &mut rx,
+                        &mut ctrl_rx,
+                        &mut transcript,
+                        &mut replay,
+                        &mut attempt,
+                    )
+                    .await
+                    {
+                        PumpOutcome::Ended => break,
+                        PumpOutcome::Fatal(message) => {
+                            TranscriptEvent::Error(message).emit(&app);
+                            break;
+                        }
+                        PumpOutcome::Lost(message) => message,
+                    }
                 }
+                Err(message) => message,
             };
-        let _ = app_for_task.emit("soniox-status", "connected");
-
-        let mut config = json!({
-          "api_key": opts_for_task.api_key,
-          "model": "stt-rt-preview-v2",
-          "language_hints": ["en", "pl"],
-          "enable_language_identification": true,
-          "enable_speaker_diarization": true,
-          "context": "",
-          "enable_endpoint_detection": true,
-        });
 
-        if opts_for_task.audio_format == "auto" {
-            config["audio_format"] = json!("auto");
-        } else {
-            config["audio_format"] = json!("pcm_s16le");
-            config["sample_rate"] = json!(16000);
-            config["num_channels"] = json!(1);
+            attempt += 1;
+            if attempt > MAX_RECONNECT_ATTEMPTS {
+                TranscriptEvent::Error(format!(
+                    "giving up after {} reconnect attempts: {}",
+                    MAX_RECONNECT_ATTEMPTS, failure
+                ))
+                .emit(&app);
+                break;
+            }
+
+            let delay = reconnect_delay(attempt);
+            log_to_file(&format!(
+                "Soniox: connection lost ({}); reconnect attempt {} in {:?}",
+                failure, attempt, delay
+            ));
+            TranscriptEvent::Status("reconnecting").emit(&app);
+
+            if !wait_for_reconnect(delay, &mut rx, &mut ctrl_rx, &mut transcript, &mut replay).await
+            {
+                log_to_file("Soniox: audio channel closed while reconnecting");
+                break;
+            }
         }
 
-        match opts_for_task.translation.as_str() {
-            "one_way" => {
-                config["translation"] = json!({"type": "one_way", "target_language": "es"});
+        log_to_file("Soniox: session ended");
+        TranscriptEvent::Status("ended").emit(&app);
+    });
+
+    SessionChannels { tx, ctrl: ctrl_tx }
+}
+
+// Keep buffering audio and honouring control commands until the backoff
+// delay elapses. Returns false when the audio channel closed meanwhile.
+async fn wait_for_reconnect(
+    delay: Duration,
+    rx: &mut mpsc::Receiver<AudioChunk>,
+    ctrl_rx: &mut mpsc::Receiver<TranscriptionControl>,
+    transcript: &mut TranscriptState,
+    replay: &mut ReplayBuffer,
+) -> bool {
+    let sleep = tokio::time::sleep(delay);
+    tokio::pin!(sleep);
+    loop {
+        select! {
+          _ = &mut sleep => return true,
+          cmd = ctrl_rx.recv() => {
+            match cmd {
+                Some(TranscriptionControl::ClearTranscript) => {
+                    log_to_file("Soniox: clearing transcript tokens while reconnecting");
+                    transcript.clear();
+                }
+                Some(TranscriptionControl::Flush) => {
+                    log_to_file("Soniox: deferring finalization until reconnected");
+                    transcript.pending_flush = true;
+                }
+                None => {}
             }
-            "two_way" => {
-                config["translation"] =
-                    json!({"type": "two_way", "language_a": "en", "language_b": "es"});
+          }
+          maybe_chunk = rx.recv() => {
+            match maybe_chunk {
+              Some(chunk) => {
+                replay.push(to_pcm16_mono_16k(&chunk.samples, chunk.channels, chunk.sample_rate));
+                let dropped = replay.take_dropped_bytes();
+                if dropped > 0 {
+                  log_to_file(&format!("Soniox: replay buffer full, dropped {} bytes", dropped));
+                  transcript.audio_skipped(dropped);
+                }
+              }
+              None => return false,
             }
-            _ => {}
+          }
         }
+    }
+}
 
-        if let Err(e) = ws.send(Message::Text(config.to_string())).await {
-            let _ = app_for_task.emit("soniox-error", format!("send config failed: {e}"));
-            return;
-        }
-        let _ = app_for_task.emit("soniox-status", "config_sent");
+async fn pump(
+    ws: SonioxSocket,
+    app: &tauri::AppHandle,
+    rx: &mut mpsc::Receiver<AudioChunk>,
+    ctrl_rx: &mut mpsc::Receiver<TranscriptionControl>,
+    transcript: &mut TranscriptState,
+    replay: &mut ReplayBuffer,
+    attempt: &mut u32,
+) -> PumpOutcome {
+    let (mut ws_sink, mut ws_reader) = ws.split();
 
-        // Buffers for rendering tokens and tracking currently emitted text
-        let mut final_tokens: Vec<serde_json::Value> = Vec::new();
-        let mut last_emitted_text: String = String::new();
-        let mut suppress_repeat: Option<String> = None;
+    // Replay audio captured while disconnected, oldest first
+    while let Some(frame) = replay.pop() {
+        let sz = frame.len();
+        if let Err(e) = ws_sink.send(Message::Binary(frame.clone())).await {
+            replay.requeue(frame);
+            return PumpOutcome::Lost(format!("replay audio failed: {e}"));
+        }
+        transcript.audio_sent(sz);
+    }
 
-        // Reader task: receive messages and render transcript
-        let (mut ws_sink, mut ws_reader) = ws.split();
+    // Finalize requested while disconnected, now that the audio has caught up
+    if transcript.pending_flush {
+        log_to_file("Soniox: sending deferred finalization");
+        let finalize = json!({"type": "finalize"}).to_string();
+        if let Err(e) = ws_sink.send(Message::Text(finalize)).await {
+            return PumpOutcome::Lost(format!("send finalize failed: {e}"));
+        }
+        transcript.pending_flush = false;
+    }
 
-        // Pump loop: read from both audio channel and ws
-        let mut sent_bytes: usize = 0;
-        loop {
-            select! {
-              cmd = ctrl_rx.recv() => {
-                if let Some(control) = cmd {
-                    match control {
-                        SonioxControl::ClearTranscript => {
-                            log_to_file("Soniox: clearing transcript tokens via control command");
-                            if suppress_repeat.is_none() {
-                                suppress_repeat = Some(last_emitted_text.clone());
-                            }
-                            final_tokens.clear();
-                            last_emitted_text.clear();
+    // Pump loop: read from both audio channel and ws
+    let mut sent_bytes: usize = 0;
+    loop {
+        select! {
+          cmd = ctrl_rx.recv() => {
+            if let Some(control) = cmd {
+                match control {
+                    TranscriptionControl::ClearTranscript => {
+                        log_to_file("Soniox: clearing transcript tokens via control command");
+                        transcript.clear();
+                    }
+                    TranscriptionControl::Flush => {
+                        log_to_file("Soniox: requesting manual finalization");
+                        let finalize = json!({"type": "finalize"}).to_string();
+                        if let Err(e) = ws_sink.send(Message::Text(finalize)).await {
+                            transcript.pending_flush = true;
+                            return PumpOutcome::Lost(format!("send finalize failed: {e}"));
                         }
                     }
                 }
+            }
+          }
+          // Audio: convert and send binary frames
+          maybe_chunk = rx.recv() => {
+            if let Some(chunk) = maybe_chunk {
+              let frame = to_pcm16_mono_16k(&chunk.samples, chunk.channels, chunk.sample_rate);
+              let sz = frame.len();
+              if let Err(e) = ws_sink.send(Message::Binary(frame.clone())).await {
+                replay.push(frame);
+                return PumpOutcome::Lost(format!("send audio failed: {e}"));
               }
-              // Audio: convert and send binary frames
-              maybe_chunk = rx.recv() => {
-                if let Some(chunk) = maybe_chunk {
-                  let frame = to_pcm16_mono_16k(&chunk.samples, chunk.channels, chunk.sample_rate);
-                  let sz = frame.len();
-                  if let Err(e) = ws_sink.send(Message::Binary(frame)).await {
-                    let _ = app_for_task.emit("soniox-error", format!("send audio failed: {e}"));
-                    break;
-                  }
-                  sent_bytes += sz;
-                  if sent_bytes >= 48000 {
-                    let _ = app_for_task.emit("soniox-bytes", sent_bytes);
-                    sent_bytes = 0;
-                  }
-                } else {
-                  // Channel closed: send empty frame to end and break
-                  let _ = ws_sink.send(Message::Text(String::new())).await; // empty string signals end-of-audio
-                  break;
-                }
+              transcript.audio_sent(sz);
+              sent_bytes += sz;
+              if sent_bytes >= 48000 {
+                TranscriptEvent::BytesSent(sent_bytes).emit(app);
+                sent_bytes = 0;
               }
-              // Websocket messages
-              msg = ws_reader.next() => {
-                match msg {
-                  Some(Ok(Message::Text(txt))) => {
-                    if let Ok(res) = serde_json::from_str::<serde_json::Value>(&txt) {
-                      if res.get("error_code").is_some() {
-                        let code = res["error_code"].to_string();
-                        let msg = res["error_message"].to_string();
-                        let _ = app_for_task.emit("soniox-error", format!("{code} - {msg}"));
-                        break;
-                      }
-
-                      // Debug: Log the raw response
-                      log_to_file(&format!("Soniox response: {}", txt));
-
-                      // Collect tokens
-                      let mut non_final: Vec<serde_json::Value> = Vec::new();
-                      let mut has_tokens = false;
-                      if let Some(tokens) = res.get("tokens").and_then(|t| t.as_array()) {
-                        for token in tokens {
-                          if token.get("text").and_then(|t| t.as_str()).unwrap_or("").is_empty() { continue; }
-                          has_tokens = true;
-                          if token.get("is_final").and_then(|f| f.as_bool()).unwrap_or(false) {
-                            final_tokens.push(token.clone());
-                          } else {
-                            non_final.push(token.clone());
-                          }
-                        }
-                      }
-
-                      // Always emit transcript updates to show real-time progress
-                      let text = render_tokens(&final_tokens, &non_final);
-
-                      // If we have any meaningful content, emit it
-                      if has_tokens || !text.is_empty() {
-                        if suppress_repeat.is_some() {
-                          if suppress_repeat.as_deref() == Some(text.as_str()) {
-                            // Skip emitting the same text immediately after a clear request
-                            continue;
-                          } else {
-                            suppress_repeat = None;
-                          }
-                        }
-                        log_to_file(&format!("Emitting transcript: '{}'", text));
-                        last_emitted_text = text.clone();
-                        let _ = app_for_task.emit("soniox-transcript", text);
-                      } else {
-                        // Debug: Even emit empty responses to see if events are working
-                        log_to_file("Received Soniox response with no tokens");
-                        let _ = app_for_task.emit("soniox-transcript", "[no speech detected]");
-                        last_emitted_text = "[no speech detected]".to_string();
-                      }
-
-                      if res.get("finished").and_then(|f| f.as_bool()).unwrap_or(false) { let _ = app_for_task.emit("soniox-status", "finished"); break; }
+            } else {
+              // Channel closed: send empty frame to end and break
+              let _ = ws_sink.send(Message::Text(String::new())).await; // empty string signals end-of-audio
+              return PumpOutcome::Ended;
+            }
+          }
+          // Websocket messages
+          msg = ws_reader.next() => {
+            match msg {
+              Some(Ok(Message::Text(txt))) => {
+                if let Ok(res) = serde_json::from_str::<serde_json::Value>(&txt) {
+                  if res.get("error_code").is_some() {
+                    let code = res["error_code"].to_string();
+                    let msg = res["error_message"].to_string();
+                    let message = format!("{code} - {msg}");
+                    if res["error_code"].as_i64().map(is_retryable_error).unwrap_or(false) {
+                      return PumpOutcome::Lost(message);
                     }
+                    return PumpOutcome::Fatal(message);
                   }
-                  Some(Ok(Message::Binary(_bin))) => {
-                    // ignore binary messages from server
+
+                  // Only a connection the server answers on counts as recovered;
+                  // one dropped right after the handshake keeps backing off
+                  if *attempt > 0 {
+                    log_to_file(&format!("Soniox: recovered after {} attempt(s)", attempt));
+                    TranscriptEvent::Status("recovered").emit(app);
+                    *attempt = 0;
                   }
-                  Some(Ok(Message::Ping(_))) | Some(Ok(Message::Pong(_))) | Some(Ok(Message::Frame(_))) => {
-                    // ignore control frames
+
+                  // Debug: Log the raw response
+                  log_to_file(&format!("Soniox response: {}", txt));
+
+                  if let Some(update) = transcript.ingest(&res) {
+                    TranscriptEvent::Transcript(update).emit(app);
+                  }
+
+                  if res.get("finished").and_then(|f| f.as_bool()).unwrap_or(false) {
+                    TranscriptEvent::Status("finished").emit(app);
+                    return PumpOutcome::Ended;
                   }
-                  Some(Ok(Message::Close(_))) => { let _ = app_for_task.emit("soniox-status", "closed"); break; },
-                  Some(Err(e)) => { let _ = app_for_task.emit("soniox-error", format!("ws read error: {e}")); break; }
-                  None => break,
                 }
               }
+              Some(Ok(Message::Binary(_bin))) => {
+                // ignore binary messages from server
+              }
+              Some(Ok(Message::Ping(_))) | Some(Ok(Message::Pong(_))) | Some(Ok(Message::Frame(_))) => {
+                // ignore control frames
+              }
+              Some(Ok(Message::Close(_))) => return PumpOutcome::Lost("connection closed by server".into()),
+              Some(Err(e)) => return PumpOutcome::Lost(format!("ws read error: {e}")),
+              None => return PumpOutcome::Lost("connection dropped".into()),
             }
+          }
         }
-
-        log_to_file("Soniox: session ended");
-        let _ = app_for_task.emit("soniox-status", "ended");
-    });
-
-    Ok(SonioxHandle { tx, ctrl: ctrl_tx })
+    }
 }
 
 pub fn render_tokens(
@@ -321,6 +716,117 @@ pub fn render_tokens(
     result
 }
 
+// Group tokens into segments: a new segment starts whenever speaker, language,
+// finality or translation status changes. Text is cleaned but not reformatted.
+pub fn build_segments(
+    final_tokens: &[serde_json::Value],
+    non_final_tokens: &[serde_json::Value],
+) -> Vec<TranscriptSegment> {
+    struct Pending {
+        segment: TranscriptSegment,
+        confidence_sum: f32,
+        confidence_count: u32,
+    }
+
+    let mut segments: Vec<TranscriptSegment> = Vec::new();
+    let mut pending: Option<Pending> = None;
+    let mut last_speaker_seen: Option<i64> = None;
+
+    let finish = |pending: Pending, segments: &mut Vec<TranscriptSegment>| {
+        let mut segment = pending.segment;
+        segment.text = segment.text.trim().to_string();
+        if pending.confidence_count > 0 {
+            segment.confidence = Some(pending.confidence_sum / pending.confidence_count as f32);
+        }
+        if !segment.text.is_empty() {
+            segments.push(segment);
+        }
+    };
+
+    let tokens = final_tokens
+        .iter()
+        .map(|t| (t, true))
+        .chain(non_final_tokens.iter().map(|t| (t, false)));
+    for (token, is_final) in tokens {
+        let text = token
+            .get("text")
+            .and_then(|t| t.as_str())
+            .map(clean_transcript_text_preserve_spacing)
+            .unwrap_or_default();
+        if text.trim().is_empty() {
+            continue;
+        }
+
+        // Tentative tokens inherit the last final speaker, as in render_tokens
+        let fallback = if is_final { None } else { last_speaker_seen };
+        let speaker = extract_speaker_id(token.get("speaker"), fallback);
+        if is_final {
+            last_speaker_seen = Some(speaker);
+        }
+        let language = token
+            .get("language")
+            .and_then(|l| l.as_str())
+            .filter(|l| !l.is_empty())
+            .map(|l| l.to_string());
+        let translation_status = match token.get("translation_status").and_then(|s| s.as_str()) {
+            Some("original") => TranslationStatus::Original,
+            Some("translation") => TranslationStatus::Translation,
+            _ => TranslationStatus::None,
+        };
+        let start_ms = token.get("start_ms").and_then(|v| v.as_u64());
+        let end_ms = token.get("end_ms").and_then(|v| v.as_u64());
+        let confidence = token
+            .get("confidence")
+            .and_then(|v| v.as_f64())
+            .map(|c| c as f32);
+
+        let continues = pending.as_ref().is_some_and(|p| {
+            p.segment.speaker == speaker
+                && p.segment.language == language
+                && p.segment.is_final == is_final
+                && p.segment.translation_status == translation_status
+        });
+        if !continues {
+            if let Some(done) = pending.take() {
+                finish(done, &mut segments);
+            }
+            pending = Some(Pending {
+                segment: TranscriptSegment {
+                    speaker,
+                    language,
+                    text: String::new(),
+                    start_ms,
+                    end_ms,
+                    confidence: None,
+                    is_final,
+                    translation_status,
+                },
+                confidence_sum: 0.0,
+                confidence_count: 0,
+            });
+        }
+
+        if let Some(current) = pending.as_mut() {
+            current.segment.text.push_str(&text);
+            if current.segment.start_ms.is_none() {
+                current.segment.start_ms = start_ms;
+            }
+            if end_ms.is_some() {
+                current.segment.end_ms = end_ms;
+            }
+            if let Some(c) = confidence {
+                current.confidence_sum += c;
+                current.confidence_count += 1;
+            }
+        }
+    }
+
+    if let Some(done) = pending.take() {
+        finish(done, &mut segments);
+    }
+    segments
+}
+
 fn extract_speaker_id(raw: Option<&serde_json::Value>, fallback: Option<i64>) -> i64 {
     if let Some(value) = raw {
         if let Some(id) = value.as_i64() {
@@ -336,7 +842,7 @@ fn extract_speaker_id(raw: Option<&serde_json::Value>, fallback: Option<i64>) ->
 }
 
 // Clean up transcript text by removing technical tags but preserving original spacing
-fn clean_transcript_text_preserve_spacing(text: &str) -> String {
+pub(crate) fn clean_transcript_text_preserve_spacing(text: &str) -> String {
     let mut cleaned = text.to_string();
 
     // Remove common technical tags and markers
@@ -364,7 +870,7 @@ fn clean_transcript_text_preserve_spacing(text: &str) -> String {
 }
 
 // Format a transcript line with proper capitalization and punctuation
-fn format_transcript_line(text: &str) -> String {
+pub(crate) fn format_transcript_line(text: &str) -> String {
     let trimmed = text.trim();
     if trimmed.is_empty() {
         return String::new();
@@ -393,6 +899,16 @@ fn format_transcript_line(text: &str) -> String {
 }
 
 pub fn to_pcm16_mono_16k(samples: &[i16], channels: u16, sample_rate: u32) -> Vec<u8> {
+    // Convert to bytes little-endian
+    let mono = to_mono_16k(samples, channels, sample_rate);
+    let mut bytes = Vec::with_capacity(mono.len() * 2);
+    for s in mono {
+        bytes.extend_from_slice(&s.to_le_bytes());
+    }
+    bytes
+}
+
+pub fn to_mono_16k(samples: &[i16], channels: u16, sample_rate: u32) -> Vec<i16> {
     // Downmix to mono
     let mono: Vec<i16> = if channels <= 1 {
         samples.to_vec()
@@ -408,12 +924,7 @@ pub fn to_pcm16_mono_16k(samples: &[i16], channels: u16, sample_rate: u32) -> Ve
 
     // Naive resample to 16000 Hz
     if sample_rate == 16_000 {
-        // Convert to bytes little-endian
-        let mut bytes = Vec::with_capacity(mono.len() * 2);
-        for s in mono {
-            bytes.extend_from_slice(&s.to_le_bytes());
-        }
-        return bytes;
+        return mono;
     }
 
     let in_rate = sample_rate as f32;
@@ -432,11 +943,7 @@ pub fn to_pcm16_mono_16k(samples: &[i16], channels: u16, sample_rate: u32) -> Ve
         let interp = s0 + (s1 - s0) * frac;
         out.push(interp as i16);
     }
-    let mut bytes = Vec::with_capacity(out.len() * 2);
-    for s in out {
-        bytes.extend_from_slice(&s.to_le_bytes());
-    }
-    bytes
+    out
 }
 
 #[cfg(test)]
@@ -484,4 +991,170 @@ mod tests {
         // Expect roughly doubled samples (linear interpolation)
         assert!(bytes.len() >= samples.len() * 2);
     }
+
+    #[test]
+    fn test_reconnect_delay_grows_and_caps() {
+        assert_eq!(reconnect_delay(1), Duration::from_millis(500));
+        assert_eq!(reconnect_delay(2), Duration::from_millis(1000));
+        assert_eq!(reconnect_delay(4), Duration::from_millis(4000));
+        assert_eq!(reconnect_delay(20), Duration::from_millis(30_000));
+    }
+
+    #[test]
+    fn test_replay_buffer_drops_oldest_when_full() {
+        let mut buf = ReplayBuffer::new(6);
+        buf.push(vec![1, 1]);
+        buf.push(vec![2, 2]);
+        buf.push(vec![3, 3]);
+        buf.push(vec![4, 4]);
+        assert_eq!(buf.bytes(), 6);
+        assert_eq!(buf.take_dropped_bytes(), 2);
+        assert_eq!(buf.pop(), Some(vec![2, 2]));
+        buf.requeue(vec![2, 2]);
+        assert_eq!(buf.pop(), Some(vec![2, 2]));
+        assert_eq!(buf.pop(), Some(vec![3, 3]));
+        assert_eq!(buf.pop(), Some(vec![4, 4]));
+        assert_eq!(buf.pop(), None);
+        assert_eq!(buf.bytes(), 0);
+    }
+
+    #[test]
+    fn test_transcript_state_discards_tentative_text_across_reconnect() {
+        let mut state = TranscriptState::default();
+        let text = state
+            .ingest(&json!({"tokens": [
+                {"text": "Hello ", "is_final": true, "speaker": "1"},
+                {"text": "wor", "is_final": false, "speaker": "1"}
+            ]}))
+            .unwrap()
+            .text;
+        assert!(text.contains("Hello wor"));
+        // The lost connection's tentative text is gone; only new audio is recognized
+        state.start_connection();
+        let text = state
+            .ingest(&json!({"tokens": [{"text": "again", "is_final": true, "speaker": "1"}]}))
+            .unwrap()
+            .text;
+        assert!(text.contains("Hello again"));
+        assert!(!text.contains("wor"));
+
+        let update = state
+            .ingest(&json!({"tokens": [
+                {"text": ".", "is_final": true, "speaker": "1"},
+                {"text": "<end>", "is_final": true, "speaker": "1"}
+            ]}))
+            .unwrap();
+        assert!(update.endpoint);
+        assert!(!update.text.contains("<end>"));
+    }
+
+    #[test]
+    fn test_transcript_state_offsets_times_after_reconnect() {
+        let mut state = TranscriptState::default();
+        state.start_connection();
+        state.ingest(&json!({"tokens": [
+            {"text": "One", "is_final": true, "speaker": "1", "start_ms": 100, "end_ms": 400}
+        ]}));
+        // Two seconds sent, then half a second dropped while disconnected
+        state.audio_sent(2_000 * BYTES_PER_MS as usize);
+        state.audio_skipped(500 * BYTES_PER_MS as usize);
+        state.start_connection();
+        let update = state
+            .ingest(&json!({"tokens": [
+                {"text": " two", "is_final": true, "speaker": "1", "start_ms": 0, "end_ms": 300},
+                {"text": " thr", "is_final": false, "speaker": "1", "start_ms": 300, "end_ms": 500}
+            ]}))
+            .unwrap();
+        let times: Vec<Option<u64>> = state
+            .final_tokens
+            .iter()
+            .map(|t| t["start_ms"].as_u64())
+            .collect();
+        assert_eq!(times, vec![Some(100), Some(2_500)]);
+        assert_eq!(update.segments.last().unwrap().end_ms, Some(3_000));
+    }
+
+    #[test]
+    fn test_retryable_error_codes() {
+        assert!(is_retryable_error(503));
+        assert!(is_retryable_error(408));
+        assert!(!is_retryable_error(401));
+    }
+
+    #[test]
+    fn test_session_config_uses_options() {
+        let opts: SonioxOptions = serde_json::from_value(json!({
+            "api_key": "k",
+            "model": "stt-rt-v3",
+            "language_hints": ["de", "fr"],
+            "context": "Quarterly planning",
+            "context_terms": ["Kubernetes", " "],
+            "enable_speaker_diarization": false,
+            "translation": "two_way",
+            "translation_language_a": "de",
+            "translation_language_b": "fr"
+        }))
+        .unwrap();
+        let config = build_session_config(&opts);
+        assert_eq!(config["model"], "stt-rt-v3");
+        assert_eq!(config["language_hints"], json!(["de", "fr"]));
+        assert_eq!(config["context"], "Quarterly planning\nTerms: Kubernetes");
+        assert_eq!(config["enable_speaker_diarization"], false);
+        assert_eq!(config["enable_endpoint_detection"], true);
+        assert_eq!(config["translation"]["language_a"], "de");
+        assert_eq!(config["translation"]["language_b"], "fr");
+    }
+
+    #[test]
+    fn test_session_config_defaults_match_previous_behaviour() {
+        let opts: SonioxOptions = serde_json::from_value(json!({"api_key": "k"})).unwrap();
+        let config = build_session_config(&opts);
+        assert_eq!(config["model"], "stt-rt-preview-v2");
+        assert_eq!(config["language_hints"], json!(["en", "pl"]));
+        assert_eq!(config["context"], "");
+        assert!(config.get("translation").is_none());
+    }
+
+    #[test]
+    fn test_build_segments_groups_tokens() {
+        let final_tokens = vec![
+            json!({"text": "Hallo ", "is_final": true, "speaker": "1", "language": "de",
+                   "start_ms": 100, "end_ms": 400, "confidence": 0.9}),
+            json!({"text": "zusammen", "is_final": true, "speaker": "1", "language": "de",
+                   "start_ms": 400, "end_ms": 900, "confidence": 0.7}),
+            json!({"text": "Hello everyone", "is_final": true, "speaker": "1", "language": "en",
+                   "translation_status": "translation"}),
+            json!({"text": "Bonjour", "is_final": true, "speaker": 2, "language": "fr",
+                   "start_ms": 1200, "end_ms": 1500}),
+        ];
+        let non_final = vec![
+            json!({"text": " à tous", "is_final": false, "language": "fr",
+                                    "start_ms": 1500, "end_ms": 1900}),
+        ];
+
+        let segments = build_segments(&final_tokens, &non_final);
+        assert_eq!(segments.len(), 4);
+
+        assert_eq!(segments[0].text, "Hallo zusammen");
+        assert_eq!(segments[0].speaker, 1);
+        assert_eq!(segments[0].language.as_deref(), Some("de"));
+        assert_eq!(
+            (segments[0].start_ms, segments[0].end_ms),
+            (Some(100), Some(900))
+        );
+        assert!((segments[0].confidence.unwrap() - 0.8).abs() < 1e-6);
+        assert!(segments[0].is_final);
+
+        assert_eq!(
+            segments[1].translation_status,
+            TranslationStatus::Translation
+        );
+        assert_eq!(segments[1].start_ms, None);
+        assert_eq!(segments[1].confidence, None);
+
+        // Tentative text inherits the last final speaker
+        assert_eq!(segments[3].speaker, 2);
+        assert_eq!(segments[3].text, "à tous");
+        assert!(!segments[3].is_final);
+    }
 }
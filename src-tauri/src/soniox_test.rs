@@ -29,11 +29,13 @@ mod soniox_tests {
                         .unwrap_or("none")
                         .to_string();
 
-                    return Ok(SonioxOptions {
-                        api_key,
-                        audio_format,
-                        translation,
-                    });
+                    // Remaining session parameters fall back to their defaults
+                    return serde_json::from_value::<SonioxOptions>(serde_json::json!({
+                        "api_key": api_key,
+                        "audio_format": audio_format,
+                        "translation": translation,
+                    }))
+                    .map_err(|e| e.to_string());
                 }
             }
         }
@@ -0,0 +1,196 @@
+use crate::{gate, llm::JsonSchemaFormat};
+use serde_json::{Map, Value};
+
+// Assistant answers shaped by an `output_schema`. Providers with a
+// JSON-schema mode enforce it; the others only see it in the prompt, so the
+// answer is parsed tolerantly and checked here before the UI gets it.
+
+// The schema subset this module checks: type, properties, required, items,
+// enum and additionalProperties. Other keywords are passed to the provider
+// but not checked locally.
+pub fn check_schema(schema: &Value) -> Result<(), String> {
+    let map = schema
+        .as_object()
+        .ok_or_else(|| "output_schema must be a JSON object".to_string())?;
+    // Structured-output modes only accept an object at the root
+    if map.get("type").and_then(Value::as_str) != Some("object") {
+        return Err("output_schema must have \"type\": \"object\" at the root".to_string());
+    }
+    Ok(())
+}
+
+pub fn response_format(assistant_id: &str, schema: &Value) -> JsonSchemaFormat {
+    // OpenAI limits names to letters, digits, '_' and '-'
+    let name: String = assistant_id
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    JsonSchemaFormat {
+        name: format!("{}_output", name),
+        schema: schema.clone(),
+    }
+}
+
+// Appended to the output policy for providers that cannot enforce the schema.
+pub fn schema_instruction(schema: &Value) -> String {
+    format!(
+        "Reply with a single JSON object matching this JSON Schema, and nothing else:\n{}",
+        schema
+    )
+}
+
+// The answer as JSON: the whole text, or the first object inside it (code
+// fences, a sentence before it). Err when there is none or it breaks the schema.
+pub fn parse_output(text: &str, schema: &Value) -> Result<Value, String> {
+    let value = match serde_json::from_str::<Value>(text.trim()) {
+        Ok(value) => value,
+        Err(_) => gate::extract_json_object(text)
+            .map(Value::Object)
+            .ok_or_else(|| "no JSON object in the answer".to_string())?,
+    };
+    validate(&value, schema, "$")?;
+    Ok(value)
+}
+
+fn type_matches(value: &Value, expected: &str) -> bool {
+    match expected {
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "string" => value.is_string(),
+        "number" => value.is_number(),
+        "integer" => value.is_i64() || value.is_u64(),
+        "boolean" => value.is_boolean(),
+        "null" => value.is_null(),
+        _ => true,
+    }
+}
+
+fn validate(value: &Value, schema: &Value, path: &str) -> Result<(), String> {
+    let Some(schema) = schema.as_object() else {
+        return Ok(());
+    };
+    match schema.get("type") {
+        Some(Value::String(expected)) if !type_matches(value, expected) => {
+            return Err(format!("{} should be {}", path, expected));
+        }
+        // Nullable fields are written as ["string", "null"]
+        Some(Value::Array(types))
+            if !types
+                .iter()
+                .filter_map(Value::as_str)
+                .any(|t| type_matches(value, t)) =>
+        {
+            return Err(format!("{} has the wrong type", path));
+        }
+        _ => {}
+    }
+    if let Some(allowed) = schema.get("enum").and_then(Value::as_array) {
+        if !allowed.contains(value) {
+            return Err(format!(
+                "{} is not one of {}",
+                path,
+                Value::from(allowed.clone())
+            ));
+        }
+    }
+    if let Value::Object(map) = value {
+        validate_object(map, schema, path)?;
+    }
+    if let (Value::Array(items), Some(item_schema)) = (value, schema.get("items")) {
+        for (index, item) in items.iter().enumerate() {
+            validate(item, item_schema, &format!("{}[{}]", path, index))?;
+        }
+    }
+    Ok(())
+}
+
+fn validate_object(
+    map: &Map<String, Value>,
+    schema: &Map<String, Value>,
+    path: &str,
+) -> Result<(), String> {
+    let properties = schema.get("properties").and_then(Value::as_object);
+    for key in schema
+        .get("required")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_str)
+    {
+        if !map.contains_key(key) {
+            return Err(format!("{} is missing '{}'", path, key));
+        }
+    }
+    for (key, field) in map {
+        let field_path = format!("{}.{}", path, key);
+        match properties.and_then(|p| p.get(key)) {
+            Some(field_schema) => validate(field, field_schema, &field_path)?,
+            None if schema.get("additionalProperties") == Some(&Value::Bool(false)) => {
+                return Err(format!("{} is not allowed", field_path));
+            }
+            None => {}
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_output_checks_schema() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "findings": {
+                    "type": "array",
+                    "items": {
+                        "type": "object",
+                        "properties": {
+                            "claim": {"type": "string"},
+                            "contradicts": {"type": "string"},
+                            "evidence": {"type": "string"},
+                            "severity": {"type": "string", "enum": ["low", "medium", "high"]}
+                        },
+                        "required": ["claim", "contradicts", "evidence", "severity"],
+                        "additionalProperties": false
+                    }
+                }
+            },
+            "required": ["findings"],
+            "additionalProperties": false
+        });
+        assert!(check_schema(&schema).is_ok());
+        assert!(check_schema(&json!({"type": "array"})).is_err());
+        assert_eq!(
+            response_format("medical v2", &schema).name,
+            "medical_v2_output"
+        );
+
+        let answer = "Here you go:\n```json\n{\"findings\": [{\"claim\": \"Started in 2019\", \"contradicts\": \"Started in 2021\", \"evidence\": \"Speaker 1, twice\", \"severity\": \"high\"}]}\n```";
+        let data = parse_output(answer, &schema).unwrap();
+        assert_eq!(data["findings"][0]["severity"], "high");
+        assert_eq!(
+            parse_output(r#"{"findings": []}"#, &schema).unwrap(),
+            json!({"findings": []})
+        );
+
+        let err = parse_output(
+            r#"{"findings": [{"claim": "a", "contradicts": "b", "evidence": "c", "severity": "fatal"}]}"#,
+            &schema,
+        )
+        .unwrap_err();
+        assert!(err.contains("$.findings[0].severity"), "{}", err);
+        assert!(parse_output(r#"{"findings": "none"}"#, &schema).is_err());
+        assert!(parse_output(r#"{}"#, &schema).is_err());
+        assert!(parse_output("No contradictions found.", &schema).is_err());
+    }
+}
@@ -0,0 +1,410 @@
+use crate::{
+    memory::{self, TranscriptWindow},
+    transcription::TranscriptSegment,
+};
+
+// Variables an assistant's `user_prompt` may use, as `{{name}}` or with
+// filters: `{{transcript | speaker:2}}`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Variable {
+    // Whole transcript, or summary and recent part after a compaction
+    Transcript,
+    Summary,
+    Recent,
+    // Last run of speech by one speaker
+    LatestTurn,
+    LastOutput,
+    Speakers,
+    Language,
+    Date,
+    SessionTitle,
+    // Background supplied with the assistant, e.g. a CV, plus the matching
+    // chunks of its reference documents
+    Context,
+    // Previous pipeline step's output; the transcript otherwise
+    Input,
+}
+
+impl Variable {
+    fn parse(name: &str) -> Option<Self> {
+        Some(match name {
+            // Upper case is the historical spelling
+            "transcript" | "TRANSCRIPT" => Self::Transcript,
+            "summary" => Self::Summary,
+            "recent" => Self::Recent,
+            "latest_turn" => Self::LatestTurn,
+            "last_output" => Self::LastOutput,
+            "speakers" => Self::Speakers,
+            "language" => Self::Language,
+            "date" => Self::Date,
+            "session_title" => Self::SessionTitle,
+            "context" => Self::Context,
+            "input" => Self::Input,
+            _ => return None,
+        })
+    }
+
+    // Carries (part of) the conversation, or what a pipeline made of it
+    fn is_transcript(self) -> bool {
+        matches!(
+            self,
+            Self::Transcript | Self::Summary | Self::Recent | Self::LatestTurn | Self::Input
+        )
+    }
+
+    // Holds speaker lines, so speaker filters apply
+    fn has_speaker_lines(self) -> bool {
+        matches!(self, Self::Transcript | Self::Recent | Self::LatestTurn)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Filter {
+    // Only lines of this speaker
+    Speaker(i64),
+    // Every speaker but this one
+    ExceptSpeaker(i64),
+}
+
+impl Filter {
+    fn parse(source: &str) -> Result<Self, String> {
+        let (name, arg) = source
+            .split_once(':')
+            .map(|(name, arg)| (name.trim(), Some(arg.trim())))
+            .unwrap_or((source.trim(), None));
+        let speaker = || {
+            arg.and_then(|arg| arg.parse::<i64>().ok())
+                .ok_or_else(|| format!("filter '{}' needs a speaker number", name))
+        };
+        match name {
+            "speaker" => Ok(Self::Speaker(speaker()?)),
+            "except_speaker" => Ok(Self::ExceptSpeaker(speaker()?)),
+            _ => Err(format!("unknown template filter '{}'", name)),
+        }
+    }
+
+    fn keeps(self, speaker: Option<i64>) -> bool {
+        match self {
+            Self::Speaker(id) => speaker == Some(id),
+            Self::ExceptSpeaker(id) => speaker.is_some_and(|s| s != id),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Part {
+    Text(String),
+    Slot(Variable, Vec<Filter>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+// Values available while rendering a template.
+#[derive(Debug, Clone)]
+pub struct PromptVars<'a> {
+    pub transcript: &'a str,
+    pub window: Option<&'a TranscriptWindow>,
+    pub last_output: Option<&'a str>,
+    // Chronological segments behind `transcript`, when the backend has them
+    pub segments: &'a [TranscriptSegment],
+    pub session_title: Option<&'a str>,
+    pub context: &'a str,
+    // Output of the previous pipeline step
+    pub input: Option<&'a str>,
+    pub date: String,
+}
+
+impl<'a> PromptVars<'a> {
+    pub fn new(transcript: &'a str) -> Self {
+        Self {
+            transcript,
+            window: None,
+            last_output: None,
+            segments: &[],
+            session_title: None,
+            context: "",
+            input: None,
+            date: chrono::Local::now().format("%Y-%m-%d").to_string(),
+        }
+    }
+}
+
+impl Template {
+    // Text sent as is, with the transcript appended
+    pub fn literal(text: &str) -> Self {
+        Self {
+            parts: vec![Part::Text(text.to_string())],
+        }
+    }
+
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let mut parts = Vec::new();
+        let mut rest = source;
+        while let Some(start) = rest.find("{{") {
+            if start > 0 {
+                parts.push(Part::Text(rest[..start].to_string()));
+            }
+            let after = &rest[start + 2..];
+            let end = after.find("}}").ok_or_else(|| {
+                let snippet: String = rest[start..].chars().take(20).collect();
+                format!("unclosed '{{{{' in '{}'", snippet)
+            })?;
+            let mut pieces = after[..end].split('|');
+            let name = pieces.next().unwrap_or_default().trim();
+            let variable = Variable::parse(name)
+                .ok_or_else(|| format!("unknown template variable '{{{{{}}}}}'", name))?;
+            let filters = pieces.map(Filter::parse).collect::<Result<Vec<_>, _>>()?;
+            if !filters.is_empty() && !variable.has_speaker_lines() {
+                return Err(format!(
+                    "speaker filters only apply to transcript variables, not '{{{{{}}}}}'",
+                    name
+                ));
+            }
+            parts.push(Part::Slot(variable, filters));
+            rest = &after[end + 2..];
+        }
+        if !rest.is_empty() {
+            parts.push(Part::Text(rest.to_string()));
+        }
+        Ok(Self { parts })
+    }
+
+    // Whether the template places the transcript itself; if not, the whole
+    // transcript is appended after it.
+    fn places_transcript(&self) -> bool {
+        self.parts.iter().any(|part| match part {
+            Part::Slot(variable, _) => variable.is_transcript(),
+            Part::Text(_) => false,
+        })
+    }
+
+    pub fn render(&self, vars: &PromptVars) -> String {
+        let mut out = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Text(text) => out.push_str(text),
+                Part::Slot(variable, filters) => out.push_str(&value(*variable, filters, vars)),
+            }
+        }
+        if self.places_transcript() {
+            return out;
+        }
+        let full = value(Variable::Transcript, &[], vars);
+        if out.trim().is_empty() {
+            return full;
+        }
+        if !out.ends_with('\n') {
+            out.push_str("\n\n");
+        }
+        out.push_str(&full);
+        out
+    }
+}
+
+// Speaker of a rendered "Speaker N: [lang] ..." line; 0 for "Speaker:".
+fn line_speaker(line: &str) -> Option<i64> {
+    let (label, _) = line.strip_prefix("Speaker")?.split_once(':')?;
+    let label = label.trim();
+    if label.is_empty() {
+        Some(0)
+    } else {
+        label.parse().ok()
+    }
+}
+
+// Rendered transcript lines with their speaker. Unlabelled lines continue
+// the previous speaker; separator lines are dropped.
+fn speaker_lines(text: &str) -> Vec<(Option<i64>, &str)> {
+    let mut current = None;
+    text.lines()
+        .filter(|line| !line.trim().is_empty() && !line.trim().chars().all(|c| c == '='))
+        .map(|line| {
+            if let Some(speaker) = line_speaker(line) {
+                current = Some(speaker);
+            }
+            (current, line)
+        })
+        .collect()
+}
+
+fn apply_filters(text: &str, filters: &[Filter]) -> String {
+    if filters.is_empty() {
+        return text.to_string();
+    }
+    speaker_lines(text)
+        .into_iter()
+        .filter(|(speaker, _)| filters.iter().all(|f| f.keeps(*speaker)))
+        .map(|(_, line)| line)
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn speakers(text: &str) -> String {
+    let mut ids: Vec<i64> = Vec::new();
+    for (speaker, _) in speaker_lines(text) {
+        if let Some(id) = speaker.filter(|id| !ids.contains(id)) {
+            ids.push(id);
+        }
+    }
+    ids.iter()
+        .map(|id| {
+            if *id > 0 {
+                format!("Speaker {}", id)
+            } else {
+                "Speaker".to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+// Languages from the "[lang]" labels, in order of appearance.
+fn languages(text: &str) -> String {
+    let mut found: Vec<&str> = Vec::new();
+    for (_, line) in speaker_lines(text) {
+        let Some((_, rest)) = line.split_once(':') else {
+            continue;
+        };
+        let language = rest
+            .trim_start()
+            .strip_prefix('[')
+            .and_then(|rest| rest.split_once(']'))
+            .map(|(language, _)| language.trim());
+        if let Some(language) = language.filter(|l| !l.is_empty() && *l != "?") {
+            if !found.contains(&language) {
+                found.push(language);
+            }
+        }
+    }
+    found.join(", ")
+}
+
+pub(crate) fn latest_turn(vars: &PromptVars) -> String {
+    if let Some(last) = vars.segments.last() {
+        let start = vars
+            .segments
+            .iter()
+            .rposition(|s| s.speaker != last.speaker)
+            .map_or(0, |i| i + 1);
+        return memory::render_segments(&vars.segments[start..]);
+    }
+    let text = vars
+        .window
+        .map_or(vars.transcript, |window| window.recent.as_str());
+    speaker_lines(text)
+        .into_iter()
+        .rev()
+        .find(|(speaker, _)| speaker.is_some())
+        .map(|(_, line)| line.to_string())
+        .unwrap_or_default()
+}
+
+fn value(variable: Variable, filters: &[Filter], vars: &PromptVars) -> String {
+    match variable {
+        Variable::Transcript => match vars.window {
+            Some(window) => TranscriptWindow {
+                summary: window.summary.clone(),
+                recent: apply_filters(&window.recent, filters),
+            }
+            .combined(),
+            None => apply_filters(vars.transcript, filters),
+        },
+        Variable::Summary => vars
+            .window
+            .map(|window| window.summary.clone())
+            .unwrap_or_default(),
+        Variable::Recent => apply_filters(
+            vars.window
+                .map_or(vars.transcript, |window| window.recent.as_str()),
+            filters,
+        ),
+        Variable::LatestTurn => apply_filters(&latest_turn(vars), filters),
+        Variable::LastOutput => vars.last_output.unwrap_or_default().to_string(),
+        Variable::Speakers => speakers(
+            vars.window
+                .map_or(vars.transcript, |window| window.recent.as_str()),
+        ),
+        Variable::Language => languages(
+            vars.window
+                .map_or(vars.transcript, |window| window.recent.as_str()),
+        ),
+        Variable::Date => vars.date.clone(),
+        Variable::SessionTitle => vars.session_title.unwrap_or_default().to_string(),
+        Variable::Context => vars.context.to_string(),
+        Variable::Input => match vars.input {
+            Some(input) => input.to_string(),
+            None => value(Variable::Transcript, &[], vars),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TRANSCRIPT: &str = "Speaker 1: [en] Tell me about yourself.\nSpeaker 2: [en] I build audio tools.\nSpeaker 1: [pl] Dlaczego?\n===============================";
+
+    #[test]
+    fn test_parse_rejects_unknown_variables() {
+        assert!(Template::parse("Plain text").is_ok());
+        assert!(Template::parse("{{TRANSCRIPT}} {{ latest_turn | speaker:1 }}").is_ok());
+        let err = Template::parse("Hi {{candidate_name}}").unwrap_err();
+        assert!(err.contains("{{candidate_name}}"), "{}", err);
+        assert!(Template::parse("{{transcript | shout}}").is_err());
+        assert!(Template::parse("{{transcript | speaker:x}}").is_err());
+        assert!(Template::parse("{{date | speaker:1}}").is_err());
+        assert!(Template::parse("{{summary | speaker:1}}").is_err());
+        assert!(Template::parse("{{transcript").is_err());
+    }
+
+    #[test]
+    fn test_render_variables() {
+        let vars = PromptVars {
+            last_output: Some("Earlier answer"),
+            session_title: Some("Interview"),
+            context: "CV: ten years of Rust",
+            date: "2025-03-01".into(),
+            ..PromptVars::new(TRANSCRIPT)
+        };
+        let render = |source: &str| Template::parse(source).unwrap().render(&vars);
+        assert_eq!(
+            render("{{session_title}} {{date}}: {{speakers}} ({{language}})\n{{context}}\n{{last_output}}\n{{latest_turn}}"),
+            "Interview 2025-03-01: Speaker 1, Speaker 2 (en, pl)\nCV: ten years of Rust\nEarlier answer\nSpeaker 1: [pl] Dlaczego?"
+        );
+        assert_eq!(
+            render("{{transcript | except_speaker:1}}"),
+            "Speaker 2: [en] I build audio tools."
+        );
+        assert_eq!(
+            render("{{transcript|speaker:1}}"),
+            "Speaker 1: [en] Tell me about yourself.\nSpeaker 1: [pl] Dlaczego?"
+        );
+        // Without a transcript slot the transcript is appended
+        assert_eq!(
+            render("Answer as {{session_title}}."),
+            format!("Answer as Interview.\n\n{}", TRANSCRIPT)
+        );
+
+        let window = TranscriptWindow {
+            summary: "They met.".into(),
+            recent: "Speaker 2: [en] Sure.".into(),
+        };
+        let vars = PromptVars {
+            window: Some(&window),
+            ..PromptVars::new(TRANSCRIPT)
+        };
+        let template = Template::parse("{{summary}}|{{recent}}").unwrap();
+        assert_eq!(template.render(&vars), "They met.|Speaker 2: [en] Sure.");
+
+        // A pipeline step's input replaces the appended transcript
+        let template = Template::parse("Shorten: {{input}}").unwrap();
+        let vars = PromptVars {
+            input: Some("A long draft."),
+            ..PromptVars::new(TRANSCRIPT)
+        };
+        assert_eq!(template.render(&vars), "Shorten: A long draft.");
+    }
+}
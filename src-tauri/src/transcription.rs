@@ -1,40 +1,294 @@
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter};
 use tokio::sync::mpsc;
 
-use crate::soniox::SonioxControl;
+use crate::utils::log_to_file;
 
-// Public, shared audio chunk type for all providers. For now, alias Soniox's.
-pub type AudioChunk = crate::soniox::AudioChunk;
+// Event names consumed by the UI. They keep the historical `soniox-*` prefix
+// so the frontend listeners work regardless of which provider is active.
+pub const TRANSCRIPT_EVENT: &str = "soniox-transcript";
+pub const STATUS_EVENT: &str = "soniox-status";
+pub const ERROR_EVENT: &str = "soniox-error";
+pub const BYTES_EVENT: &str = "soniox-bytes";
+// Typed counterpart of TRANSCRIPT_EVENT, emitted with every transcript update.
+pub const TRANSCRIPT_UPDATE_EVENT: &str = "transcript-update";
 
-// Handle returned by providers; exposes a sending channel for audio.
-#[derive(Clone)]
-pub struct TranscriptionHandle {
-    pub tx: mpsc::Sender<AudioChunk>,
-    pub ctrl: Option<mpsc::Sender<SonioxControl>>,
+// Public, shared audio chunk type for all providers.
+#[derive(Debug, Clone)]
+pub struct AudioChunk {
+    pub samples: Vec<i16>,
+    pub channels: u16,
+    pub sample_rate: u32,
+}
+
+// Control messages every provider task understands.
+#[derive(Debug, Clone)]
+pub enum TranscriptionControl {
+    // Forget accumulated tokens; the next update starts from an empty transcript.
+    ClearTranscript,
+    // Ask the backend to finalize any pending (non-final) text now.
+    Flush,
 }
 
 // Enum of known providers for visibility in state/logs.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ProviderKind {
     Soniox,
+    Whisper,
 }
 
-pub mod providers {
-    use super::TranscriptionHandle;
+// Provider selection plus provider-specific options, as sent by the UI:
+// `{ "provider": "soniox", "api_key": "...", ... }`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "provider", rename_all = "snake_case")]
+pub enum TranscriptionOptions {
+    Soniox(crate::soniox::SonioxOptions),
+    // Offline, CPU-only transcription with a local GGML model
+    Whisper(crate::whisper::WhisperOptions),
+}
+
+// A speech-to-text backend fed from the capture callbacks. Implementations
+// own their session task and report results through `TranscriptEvent`.
+// Backends only start their session; audio and control handling goes through
+// the `SessionChannels` they expose.
+pub trait TranscriptionProvider: Send {
+    fn kind(&self) -> ProviderKind;
+
+    // Spawn the session task. Must not block on network I/O.
+    fn start(&mut self, app: AppHandle) -> Result<(), String>;
+
+    // Channels of the running session, if any
+    fn session(&self) -> Option<&SessionChannels>;
+
+    fn session_mut(&mut self) -> &mut Option<SessionChannels>;
+
+    // Hand a chunk of captured audio to the session. Never blocks; chunks are
+    // dropped when the session is not running or its queue is full.
+    fn push_audio(&self, chunk: AudioChunk) {
+        if let Some(session) = self.session() {
+            session.push_audio(chunk);
+        }
+    }
+
+    fn clear(&self) -> Result<(), String> {
+        match self.session() {
+            Some(session) => session.control(TranscriptionControl::ClearTranscript),
+            None => Ok(()),
+        }
+    }
+
+    fn flush(&self) -> Result<(), String> {
+        match self.session() {
+            Some(session) => session.control(TranscriptionControl::Flush),
+            None => Ok(()),
+        }
+    }
 
+    // Close the audio channel so the session can drain and end gracefully.
+    fn stop(&mut self) {
+        *self.session_mut() = None;
+    }
+
+    fn is_running(&self) -> bool {
+        self.session()
+            .map(|session| !session.is_closed())
+            .unwrap_or(false)
+    }
+}
+
+pub fn build_provider(opts: TranscriptionOptions) -> Box<dyn TranscriptionProvider> {
+    match opts {
+        TranscriptionOptions::Soniox(opts) => {
+            Box::new(providers::soniox_adapter::SonioxProvider::new(opts))
+        }
+        TranscriptionOptions::Whisper(opts) => {
+            Box::new(providers::whisper_adapter::WhisperProvider::new(opts))
+        }
+    }
+}
+
+// Channels connecting the capture side with a running provider task.
+#[derive(Clone)]
+pub struct SessionChannels {
+    pub tx: mpsc::Sender<AudioChunk>,
+    pub ctrl: mpsc::Sender<TranscriptionControl>,
+}
+
+impl SessionChannels {
+    pub fn push_audio(&self, chunk: AudioChunk) {
+        let _ = self.tx.try_send(chunk);
+    }
+
+    pub fn control(&self, command: TranscriptionControl) -> Result<(), String> {
+        self.ctrl
+            .try_send(command)
+            .map_err(|e| format!("Failed to send transcription control: {}", e))
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.tx.is_closed()
+    }
+}
+
+// Whether a segment is spoken text, or one side of a translation.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TranslationStatus {
+    #[default]
+    None,
+    Original,
+    Translation,
+}
+
+// A run of consecutive tokens sharing speaker, language, finality and
+// translation status. Timing and confidence are absent when the provider
+// does not report them.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TranscriptSegment {
+    // 0 when the speaker is unknown
+    pub speaker: i64,
+    pub language: Option<String>,
+    pub text: String,
+    pub start_ms: Option<u64>,
+    pub end_ms: Option<u64>,
+    pub confidence: Option<f32>,
+    pub is_final: bool,
+    #[serde(default)]
+    pub translation_status: TranslationStatus,
+}
+
+// Full transcript state after an update: typed segments plus the legacy
+// rendered string (`Speaker N: [lang] ...`).
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TranscriptUpdate {
+    pub segments: Vec<TranscriptSegment>,
+    pub text: String,
+    // The recognizer detected the end of an utterance in this update
+    #[serde(default)]
+    pub endpoint: bool,
+}
+
+impl TranscriptUpdate {
+    // Status text without any recognized segments, e.g. "[no speech detected]".
+    pub fn text_only(text: impl Into<String>) -> Self {
+        Self {
+            segments: Vec::new(),
+            text: text.into(),
+            endpoint: false,
+        }
+    }
+}
+
+// Events a provider reports back to the UI.
+#[derive(Debug, Clone)]
+pub enum TranscriptEvent {
+    Transcript(TranscriptUpdate),
+    Status(&'static str),
+    Error(String),
+    BytesSent(usize),
+}
+
+impl TranscriptEvent {
+    pub fn emit(self, app: &AppHandle) {
+        let _ = match self {
+            TranscriptEvent::Transcript(update) => {
+                let _ = app.emit(TRANSCRIPT_EVENT, update.text.clone());
+                app.emit(TRANSCRIPT_UPDATE_EVENT, update)
+            }
+            TranscriptEvent::Status(status) => app.emit(STATUS_EVENT, status),
+            TranscriptEvent::Error(message) => {
+                log_to_file(&format!("Transcription error: {}", message));
+                app.emit(ERROR_EVENT, message)
+            }
+            TranscriptEvent::BytesSent(bytes) => app.emit(BYTES_EVENT, bytes),
+        };
+    }
+}
+
+pub mod providers {
     pub mod soniox_adapter {
-        use super::TranscriptionHandle;
         use crate::soniox::{self, SonioxOptions};
+        use crate::transcription::{ProviderKind, SessionChannels, TranscriptionProvider};
         use tauri::AppHandle;
 
-        pub async fn start_session(
-            app: AppHandle,
+        pub struct SonioxProvider {
             opts: SonioxOptions,
-        ) -> Result<TranscriptionHandle, String> {
-            let handle = soniox::start_session(app, opts).await?;
-            Ok(TranscriptionHandle {
-                tx: handle.tx,
-                ctrl: Some(handle.ctrl),
-            })
+            session: Option<SessionChannels>,
+        }
+
+        impl SonioxProvider {
+            pub fn new(opts: SonioxOptions) -> Self {
+                Self {
+                    opts,
+                    session: None,
+                }
+            }
+        }
+
+        impl TranscriptionProvider for SonioxProvider {
+            fn kind(&self) -> ProviderKind {
+                ProviderKind::Soniox
+            }
+
+            fn start(&mut self, app: AppHandle) -> Result<(), String> {
+                if self.is_running() {
+                    return Err("Soniox session already running".into());
+                }
+                let mut opts = self.opts.clone();
+                soniox::resolve_api_key(&mut opts)?;
+                self.session = Some(soniox::start_session(app, opts));
+                Ok(())
+            }
+
+            fn session(&self) -> Option<&SessionChannels> {
+                self.session.as_ref()
+            }
+
+            fn session_mut(&mut self) -> &mut Option<SessionChannels> {
+                &mut self.session
+            }
+        }
+    }
+
+    pub mod whisper_adapter {
+        use crate::transcription::{ProviderKind, SessionChannels, TranscriptionProvider};
+        use crate::whisper::{self, WhisperOptions};
+        use tauri::AppHandle;
+
+        pub struct WhisperProvider {
+            opts: WhisperOptions,
+            session: Option<SessionChannels>,
+        }
+
+        impl WhisperProvider {
+            pub fn new(opts: WhisperOptions) -> Self {
+                Self {
+                    opts,
+                    session: None,
+                }
+            }
+        }
+
+        impl TranscriptionProvider for WhisperProvider {
+            fn kind(&self) -> ProviderKind {
+                ProviderKind::Whisper
+            }
+
+            fn start(&mut self, app: AppHandle) -> Result<(), String> {
+                if self.is_running() {
+                    return Err("Whisper session already running".into());
+                }
+                self.session = Some(whisper::start_session(app, self.opts.clone())?);
+                Ok(())
+            }
+
+            fn session(&self) -> Option<&SessionChannels> {
+                self.session.as_ref()
+            }
+
+            fn session_mut(&mut self) -> &mut Option<SessionChannels> {
+                &mut self.session
+            }
         }
     }
 }
@@ -0,0 +1,350 @@
+use crate::{ai_tasks::AiTaskKind, llm::TokenUsage};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+const DEFAULT_REPORT_LIMIT: u32 = 100;
+
+// USD per million tokens, as published on provider price pages.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ModelPrice {
+    pub input: f64,
+    pub output: f64,
+    // Falls back to the input price when the model has no cache discount
+    #[serde(default)]
+    pub cached_input: Option<f64>,
+}
+
+// Keyed by model id, e.g. "gpt-4.1" or "anthropic/claude-sonnet-4".
+pub type PriceTable = HashMap<String, ModelPrice>;
+
+// Exact id first, then without an OpenRouter-style "vendor/" prefix, then the
+// undated alias of a dated snapshot ("gpt-4.1-2025-04-14" -> "gpt-4.1").
+pub fn find_price<'a>(table: &'a PriceTable, model: &str) -> Option<&'a ModelPrice> {
+    let bare = model.rsplit_once('/').map(|(_, name)| name);
+    let candidates = std::iter::once(model).chain(bare);
+    for candidate in candidates {
+        if let Some(price) = table.get(candidate) {
+            return Some(price);
+        }
+        let snapshot = table
+            .iter()
+            .filter(|(key, _)| {
+                candidate
+                    .strip_prefix(key.as_str())
+                    .and_then(|rest| rest.strip_prefix('-'))
+                    .is_some_and(|rest| rest.starts_with(|c: char| c.is_ascii_digit()))
+            })
+            .max_by_key(|(key, _)| key.len());
+        if let Some((_, price)) = snapshot {
+            return Some(price);
+        }
+    }
+    None
+}
+
+pub fn cost_usd(price: &ModelPrice, usage: &TokenUsage) -> f64 {
+    let cached = usage.cached_input_tokens.min(usage.input_tokens) as f64;
+    let uncached = usage.input_tokens as f64 - cached;
+    let cached_rate = price.cached_input.unwrap_or(price.input);
+    (uncached * price.input + cached * cached_rate + usage.output_tokens as f64 * price.output)
+        / 1_000_000.0
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CostSource {
+    // Computed from the configured price table
+    PriceTable,
+    // Reported by the provider (OpenRouter generation stats)
+    Provider,
+    // Model missing from the price table and no provider figure yet
+    Unpriced,
+    // Served by an OpenAI-compatible base_url whose model is not in the
+    // price table; counted as free
+    SelfHosted,
+}
+
+impl CostSource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::PriceTable => "price_table",
+            Self::Provider => "provider",
+            Self::Unpriced => "unpriced",
+            Self::SelfHosted => "self_hosted",
+        }
+    }
+
+    pub fn parse(value: &str) -> Self {
+        match value {
+            "price_table" => Self::PriceTable,
+            "provider" => Self::Provider,
+            "self_hosted" => Self::SelfHosted,
+            _ => Self::Unpriced,
+        }
+    }
+}
+
+// One gate or analysis call.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct UsageRecord {
+    pub request_id: String,
+    pub session_id: Option<String>,
+    pub kind: AiTaskKind,
+    pub assistant_id: String,
+    pub provider: String,
+    pub model: String,
+    #[serde(flatten)]
+    pub tokens: TokenUsage,
+    pub cost_usd: Option<f64>,
+    pub cost_source: CostSource,
+    pub generation_id: Option<String>,
+    pub created_at: String,
+}
+
+impl UsageRecord {
+    // Fill in the cost from the price table; provider figures win later.
+    pub fn apply_prices(&mut self, table: &PriceTable) {
+        if self.cost_source == CostSource::Provider {
+            return;
+        }
+        match find_price(table, &self.model) {
+            Some(price) => {
+                self.cost_usd = Some(cost_usd(price, &self.tokens));
+                self.cost_source = CostSource::PriceTable;
+            }
+            None if self.cost_source == CostSource::SelfHosted => {
+                self.cost_usd = Some(0.0);
+            }
+            None => {
+                self.cost_usd = None;
+                self.cost_source = CostSource::Unpriced;
+            }
+        }
+    }
+}
+
+// Same conventions as session search: optional, AND-ed, dates as prefixes
+// of the RFC 3339 `created_at`, so "2025-03" selects a whole month.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct UsageFilters {
+    #[serde(default)]
+    pub session_id: Option<String>,
+    #[serde(default)]
+    pub assistant_id: Option<String>,
+    #[serde(default)]
+    pub from: Option<String>,
+    #[serde(default)]
+    pub to: Option<String>,
+    // Number of most recent requests listed individually
+    #[serde(default)]
+    pub limit: Option<u32>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct UsageTotals {
+    pub requests: u32,
+    pub gate_requests: u32,
+    pub input_tokens: u64,
+    pub cached_input_tokens: u64,
+    pub output_tokens: u64,
+    pub cost_usd: f64,
+    // Requests whose cost is unknown and therefore missing from `cost_usd`
+    pub unpriced_requests: u32,
+}
+
+impl UsageTotals {
+    fn add(&mut self, record: &UsageRecord) {
+        self.requests += 1;
+        if record.kind == AiTaskKind::Gate {
+            self.gate_requests += 1;
+        }
+        self.input_tokens += record.tokens.input_tokens as u64;
+        self.cached_input_tokens += record.tokens.cached_input_tokens as u64;
+        self.output_tokens += record.tokens.output_tokens as u64;
+        match record.cost_usd {
+            Some(cost) => self.cost_usd += cost,
+            None => self.unpriced_requests += 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct UsageGroup {
+    pub key: String,
+    #[serde(flatten)]
+    pub totals: UsageTotals,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct UsageReport {
+    pub totals: UsageTotals,
+    // Calls made outside a recorded session only count towards the totals
+    pub by_session: Vec<UsageGroup>,
+    pub by_assistant: Vec<UsageGroup>,
+    pub by_model: Vec<UsageGroup>,
+    // Most recent first
+    pub requests: Vec<UsageRecord>,
+}
+
+fn groups(map: BTreeMap<String, UsageTotals>) -> Vec<UsageGroup> {
+    let mut groups: Vec<UsageGroup> = map
+        .into_iter()
+        .map(|(key, totals)| UsageGroup { key, totals })
+        .collect();
+    // Most expensive first; ties keep key order
+    groups.sort_by(|a, b| b.totals.cost_usd.total_cmp(&a.totals.cost_usd));
+    groups
+}
+
+impl UsageReport {
+    // `records` must be ordered oldest first.
+    pub fn build(records: Vec<UsageRecord>, limit: Option<u32>) -> Self {
+        let mut totals = UsageTotals::default();
+        let mut by_session: BTreeMap<String, UsageTotals> = BTreeMap::new();
+        let mut by_assistant: BTreeMap<String, UsageTotals> = BTreeMap::new();
+        let mut by_model: BTreeMap<String, UsageTotals> = BTreeMap::new();
+        for record in &records {
+            totals.add(record);
+            if let Some(session_id) = &record.session_id {
+                by_session
+                    .entry(session_id.clone())
+                    .or_default()
+                    .add(record);
+            }
+            by_assistant
+                .entry(record.assistant_id.clone())
+                .or_default()
+                .add(record);
+            by_model
+                .entry(format!("{}:{}", record.provider, record.model))
+                .or_default()
+                .add(record);
+        }
+        let limit = limit.unwrap_or(DEFAULT_REPORT_LIMIT) as usize;
+        Self {
+            totals,
+            by_session: groups(by_session),
+            by_assistant: groups(by_assistant),
+            by_model: groups(by_model),
+            requests: records.into_iter().rev().take(limit).collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn price(input: f64, output: f64, cached_input: Option<f64>) -> ModelPrice {
+        ModelPrice {
+            input,
+            output,
+            cached_input,
+        }
+    }
+
+    fn record(
+        session: Option<&str>,
+        assistant: &str,
+        kind: AiTaskKind,
+        cost: Option<f64>,
+    ) -> UsageRecord {
+        UsageRecord {
+            request_id: "r".into(),
+            session_id: session.map(String::from),
+            kind,
+            assistant_id: assistant.into(),
+            provider: "openai".into(),
+            model: "gpt-4.1".into(),
+            tokens: TokenUsage {
+                input_tokens: 100,
+                output_tokens: 10,
+                cached_input_tokens: 0,
+            },
+            cost_usd: cost,
+            cost_source: if cost.is_some() {
+                CostSource::PriceTable
+            } else {
+                CostSource::Unpriced
+            },
+            generation_id: None,
+            created_at: "2025-03-01T10:00:00+00:00".into(),
+        }
+    }
+
+    #[test]
+    fn test_find_price_and_cost() {
+        let table: PriceTable = [
+            ("gpt-4.1".to_string(), price(2.0, 8.0, Some(0.5))),
+            ("gpt-4.1-nano".to_string(), price(0.1, 0.4, None)),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(find_price(&table, "gpt-4.1").unwrap().input, 2.0);
+        assert_eq!(
+            find_price(&table, "openai/gpt-4.1-nano").unwrap().input,
+            0.1
+        );
+        assert_eq!(find_price(&table, "gpt-4.1-2025-04-14").unwrap().input, 2.0);
+        assert!(find_price(&table, "gpt-4.1-mini").is_none());
+
+        let usage = TokenUsage {
+            input_tokens: 1_000_000,
+            output_tokens: 500_000,
+            cached_input_tokens: 400_000,
+        };
+        let cost = cost_usd(&table["gpt-4.1"], &usage);
+        assert!((cost - (0.6 * 2.0 + 0.4 * 0.5 + 0.5 * 8.0)).abs() < 1e-9);
+        let cost = cost_usd(&table["gpt-4.1-nano"], &usage);
+        assert!((cost - (0.1 + 0.5 * 0.4)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_apply_prices_keeps_self_hosted_free() {
+        let table: PriceTable = [("gpt-4.1".to_string(), price(2.0, 8.0, None))]
+            .into_iter()
+            .collect();
+        let mut local = record(None, "general", AiTaskKind::Embedding, None);
+        local.model = "nomic-embed-text".into();
+        local.cost_source = CostSource::SelfHosted;
+        local.apply_prices(&table);
+        assert_eq!(local.cost_usd, Some(0.0));
+        assert_eq!(local.cost_source, CostSource::SelfHosted);
+
+        // A priced model still costs money on a compatible server
+        local.model = "gpt-4.1".into();
+        local.apply_prices(&table);
+        assert_eq!(local.cost_source, CostSource::PriceTable);
+
+        let mut unknown = record(None, "general", AiTaskKind::Embedding, None);
+        unknown.model = "nomic-embed-text".into();
+        unknown.apply_prices(&table);
+        assert_eq!(unknown.cost_usd, None);
+        assert_eq!(unknown.cost_source, CostSource::Unpriced);
+    }
+
+    #[test]
+    fn test_report_groups_and_limits() {
+        let records = vec![
+            record(Some("s1"), "general", AiTaskKind::Gate, Some(0.01)),
+            record(Some("s1"), "general", AiTaskKind::Analysis, Some(0.10)),
+            record(Some("s2"), "coach", AiTaskKind::Analysis, None),
+            record(None, "coach", AiTaskKind::Analysis, Some(0.20)),
+        ];
+        let report = UsageReport::build(records, Some(2));
+        assert_eq!(report.totals.requests, 4);
+        assert_eq!(report.totals.gate_requests, 1);
+        assert_eq!(report.totals.unpriced_requests, 1);
+        assert_eq!(report.totals.input_tokens, 400);
+        assert!((report.totals.cost_usd - 0.31).abs() < 1e-9);
+
+        let sessions: Vec<&str> = report.by_session.iter().map(|g| g.key.as_str()).collect();
+        assert_eq!(sessions, vec!["s1", "s2"]);
+        assert_eq!(report.by_assistant[0].key, "coach");
+        assert_eq!(report.by_assistant[0].totals.requests, 2);
+        assert_eq!(report.by_model[0].key, "openai:gpt-4.1");
+
+        assert_eq!(report.requests.len(), 2);
+        assert_eq!(report.requests[0].session_id, None);
+    }
+}
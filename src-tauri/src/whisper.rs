@@ -0,0 +1,474 @@
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::time::Duration;
+use tokio::sync::mpsc::{self, error::TryRecvError};
+
+use crate::soniox::{build_segments, render_tokens, to_mono_16k};
+use crate::transcription::{
+    AudioChunk, SessionChannels, TranscriptEvent, TranscriptUpdate, TranscriptionControl,
+};
+use crate::utils::log_to_file;
+
+const SAMPLE_RATE: usize = 16_000;
+// Windows shorter than this are not worth a Whisper pass when draining.
+const MIN_DRAIN_MS: u32 = 300;
+// How long the session thread sleeps when neither audio nor a control
+// message is waiting.
+const IDLE_POLL: Duration = Duration::from_millis(20);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct WhisperOptions {
+    #[serde(alias = "modelPath")]
+    pub model_path: String,
+    #[serde(default = "default_language")]
+    pub language: String, // "auto" or an ISO code such as "de"
+    #[serde(default = "default_window_ms", alias = "windowMs")]
+    pub window_ms: u32,
+    #[serde(default = "default_step_ms", alias = "stepMs")]
+    pub step_ms: u32,
+    #[serde(default = "default_keep_ms", alias = "keepMs")]
+    pub keep_ms: u32,
+    #[serde(default)]
+    pub threads: u32, // 0 = pick from available parallelism
+}
+
+pub fn default_language() -> String {
+    "auto".into()
+}
+pub fn default_window_ms() -> u32 {
+    10_000
+}
+pub fn default_step_ms() -> u32 {
+    3_000
+}
+pub fn default_keep_ms() -> u32 {
+    200
+}
+
+// What the session should do with the audio gathered so far.
+#[derive(Debug, Clone, PartialEq)]
+pub enum WindowPass {
+    // Re-transcribe the open window; the result is still tentative.
+    Partial(Vec<f32>),
+    // The window is complete; its transcript becomes final.
+    Commit(Vec<f32>),
+}
+
+// Sliding window over 16 kHz mono audio. Every `step` new samples the open
+// window is re-transcribed; once it reaches `window` samples all but the last
+// `keep` are committed. Those start the next window, so partial passes hear
+// the boundary but each sample ends up in exactly one committed transcript.
+#[derive(Debug)]
+pub struct SlidingWindow {
+    window: usize,
+    step: usize,
+    keep: usize,
+    buffer: Vec<f32>,
+    since_last_pass: usize,
+}
+
+impl SlidingWindow {
+    pub fn new(window_ms: u32, step_ms: u32, keep_ms: u32) -> Self {
+        let window = ms_to_samples(window_ms.max(1_000));
+        let step = ms_to_samples(step_ms.max(250)).min(window);
+        let keep = ms_to_samples(keep_ms).min(window / 2);
+        Self {
+            window,
+            step,
+            keep,
+            buffer: Vec::with_capacity(window),
+            since_last_pass: 0,
+        }
+    }
+
+    pub fn push(&mut self, samples: &[f32]) -> Option<WindowPass> {
+        self.buffer.extend_from_slice(samples);
+        self.since_last_pass += samples.len();
+
+        if self.buffer.len() >= self.window {
+            let carried = self.buffer.split_off(self.window - self.keep);
+            let committed = std::mem::replace(&mut self.buffer, carried);
+            self.since_last_pass = self.buffer.len() - self.keep;
+            return Some(WindowPass::Commit(committed));
+        }
+
+        if self.since_last_pass >= self.step {
+            self.since_last_pass = 0;
+            return Some(WindowPass::Partial(self.buffer.clone()));
+        }
+
+        None
+    }
+
+    // Commit whatever is buffered (an explicit flush). A tail shorter than
+    // MIN_DRAIN_MS stays buffered and starts the next window.
+    pub fn drain(&mut self) -> Option<WindowPass> {
+        if self.buffer.len() < ms_to_samples(MIN_DRAIN_MS) {
+            return None;
+        }
+        self.since_last_pass = 0;
+        Some(WindowPass::Commit(std::mem::take(&mut self.buffer)))
+    }
+
+    // End of audio: commit everything, padding a short tail with silence.
+    pub fn finish(&mut self) -> Option<WindowPass> {
+        if self.buffer.is_empty() {
+            return None;
+        }
+        self.since_last_pass = 0;
+        let mut audio = std::mem::take(&mut self.buffer);
+        audio.resize(audio.len().max(ms_to_samples(MIN_DRAIN_MS)), 0.0);
+        Some(WindowPass::Commit(audio))
+    }
+
+    pub fn clear(&mut self) {
+        self.buffer.clear();
+        self.since_last_pass = 0;
+    }
+}
+
+fn ms_to_samples(ms: u32) -> usize {
+    (ms as usize) * SAMPLE_RATE / 1000
+}
+
+fn to_f32(samples: &[i16]) -> Vec<f32> {
+    samples
+        .iter()
+        .map(|&s| s as f32 / i16::MAX as f32)
+        .collect()
+}
+
+// Accumulated transcript in Soniox token shape so the shared renderer
+// produces the same text format the UI already understands.
+#[derive(Debug, Default)]
+struct TranscriptState {
+    final_tokens: Vec<serde_json::Value>,
+    tentative: Option<serde_json::Value>,
+}
+
+impl TranscriptState {
+    fn apply(&mut self, pass_is_final: bool, text: String, language: &str) {
+        let token = json!({ "text": text, "is_final": pass_is_final, "language": language });
+        if pass_is_final {
+            if !text.trim().is_empty() {
+                self.final_tokens.push(token);
+            }
+            self.tentative = None;
+        } else {
+            self.tentative = Some(token);
+        }
+    }
+
+    fn render(&self) -> String {
+        let non_final: Vec<serde_json::Value> = self.tentative.iter().cloned().collect();
+        render_tokens(&self.final_tokens, &non_final)
+    }
+
+    fn update(&self) -> TranscriptUpdate {
+        let non_final: Vec<serde_json::Value> = self.tentative.iter().cloned().collect();
+        TranscriptUpdate {
+            segments: build_segments(&self.final_tokens, &non_final),
+            text: self.render(),
+            endpoint: false,
+        }
+    }
+
+    fn clear(&mut self) {
+        self.final_tokens.clear();
+        self.tentative = None;
+    }
+}
+
+pub fn start_session(
+    app: tauri::AppHandle,
+    opts: WhisperOptions,
+) -> Result<SessionChannels, String> {
+    if opts.model_path.trim().is_empty() {
+        return Err("Whisper model path is required".into());
+    }
+    if !std::path::Path::new(&opts.model_path).is_file() {
+        return Err(format!("Whisper model not found at: {}", opts.model_path));
+    }
+    engine::ensure_available()?;
+
+    // Local inference can lag behind capture; allow a deeper queue than Soniox.
+    let (tx, mut rx) = mpsc::channel::<AudioChunk>(256);
+    let (ctrl_tx, mut ctrl_rx) = mpsc::channel::<TranscriptionControl>(8);
+
+    // Inference is CPU-bound, so the session runs on its own OS thread.
+    std::thread::spawn(move || {
+        log_to_file(&format!("Whisper: loading model {}", opts.model_path));
+        TranscriptEvent::Status("connecting").emit(&app);
+
+        let mut engine = match engine::Engine::load(&opts) {
+            Ok(engine) => engine,
+            Err(e) => {
+                TranscriptEvent::Error(format!("model load failed: {e}")).emit(&app);
+                return;
+            }
+        };
+        TranscriptEvent::Status("connected").emit(&app);
+
+        let mut window = SlidingWindow::new(opts.window_ms, opts.step_ms, opts.keep_ms);
+        let mut transcript = TranscriptState::default();
+
+        let mut run_pass = |pass: WindowPass, transcript: &mut TranscriptState| -> bool {
+            let (is_final, audio) = match pass {
+                WindowPass::Partial(audio) => (false, audio),
+                WindowPass::Commit(audio) => (true, audio),
+            };
+            match engine.transcribe(&audio) {
+                Ok((text, language)) => {
+                    transcript.apply(is_final, text, &language);
+                    let update = transcript.update();
+                    log_to_file(&format!(
+                        "Whisper: emitting transcript final={} len={}",
+                        is_final,
+                        update.text.len()
+                    ));
+                    TranscriptEvent::Transcript(update).emit(&app);
+                    true
+                }
+                Err(e) => {
+                    TranscriptEvent::Error(format!("inference failed: {e}")).emit(&app);
+                    false
+                }
+            }
+        };
+
+        // Both channels are polled so a flush or clear also applies while the
+        // mic is paused and no audio arrives.
+        loop {
+            match ctrl_rx.try_recv() {
+                Ok(TranscriptionControl::ClearTranscript) => {
+                    log_to_file("Whisper: clearing transcript via control command");
+                    window.clear();
+                    transcript.clear();
+                    continue;
+                }
+                Ok(TranscriptionControl::Flush) => {
+                    if let Some(pass) = window.drain() {
+                        if !run_pass(pass, &mut transcript) {
+                            break;
+                        }
+                    }
+                    continue;
+                }
+                Err(TryRecvError::Empty) | Err(TryRecvError::Disconnected) => {}
+            }
+
+            let chunk = match rx.try_recv() {
+                Ok(chunk) => chunk,
+                Err(TryRecvError::Empty) => {
+                    std::thread::sleep(IDLE_POLL);
+                    continue;
+                }
+                Err(TryRecvError::Disconnected) => {
+                    // Channel closed: transcribe the tail and finish
+                    if let Some(pass) = window.finish() {
+                        run_pass(pass, &mut transcript);
+                    }
+                    TranscriptEvent::Status("finished").emit(&app);
+                    break;
+                }
+            };
+
+            let mono = to_mono_16k(&chunk.samples, chunk.channels, chunk.sample_rate);
+            if let Some(pass) = window.push(&to_f32(&mono)) {
+                if !run_pass(pass, &mut transcript) {
+                    break;
+                }
+            }
+        }
+
+        log_to_file("Whisper: session ended");
+        TranscriptEvent::Status("ended").emit(&app);
+    });
+
+    Ok(SessionChannels { tx, ctrl: ctrl_tx })
+}
+
+#[cfg(feature = "local-whisper")]
+mod engine {
+    use super::WhisperOptions;
+    use whisper_rs::{
+        FullParams, SamplingStrategy, WhisperContext, WhisperContextParameters, WhisperState,
+    };
+
+    pub fn ensure_available() -> Result<(), String> {
+        Ok(())
+    }
+
+    pub struct Engine {
+        state: WhisperState,
+        language: Option<String>,
+        threads: i32,
+    }
+
+    impl Engine {
+        pub fn load(opts: &WhisperOptions) -> Result<Self, String> {
+            let mut params = WhisperContextParameters::default();
+            params.use_gpu(false);
+            let ctx = WhisperContext::new_with_params(&opts.model_path, params)
+                .map_err(|e| format!("{e}"))?;
+            let state = ctx.create_state().map_err(|e| format!("{e}"))?;
+            let threads = if opts.threads > 0 {
+                opts.threads as i32
+            } else {
+                std::thread::available_parallelism()
+                    .map(|n| n.get().min(8) as i32)
+                    .unwrap_or(4)
+            };
+            let language = match opts.language.trim() {
+                "" | "auto" => None,
+                code => Some(code.to_string()),
+            };
+            Ok(Self {
+                state,
+                language,
+                threads,
+            })
+        }
+
+        // Returns the window transcript and the (detected or configured) language.
+        pub fn transcribe(&mut self, audio: &[f32]) -> Result<(String, String), String> {
+            let mut params = FullParams::new(SamplingStrategy::Greedy { best_of: 1 });
+            params.set_n_threads(self.threads);
+            params.set_language(Some(self.language.as_deref().unwrap_or("auto")));
+            params.set_translate(false);
+            params.set_no_context(true);
+            params.set_suppress_blank(true);
+            params.set_print_special(false);
+            params.set_print_progress(false);
+            params.set_print_realtime(false);
+            params.set_print_timestamps(false);
+
+            self.state.full(params, audio).map_err(|e| format!("{e}"))?;
+
+            let segments = self.state.full_n_segments().map_err(|e| format!("{e}"))?;
+            let mut text = String::new();
+            for i in 0..segments {
+                let segment = self
+                    .state
+                    .full_get_segment_text_lossy(i)
+                    .map_err(|e| format!("{e}"))?;
+                text.push_str(&segment);
+            }
+
+            let language = match &self.language {
+                Some(code) => code.clone(),
+                None => self
+                    .state
+                    .full_lang_id_from_state()
+                    .ok()
+                    .and_then(whisper_rs::get_lang_str)
+                    .unwrap_or("?")
+                    .to_string(),
+            };
+
+            Ok((text, language))
+        }
+    }
+}
+
+#[cfg(not(feature = "local-whisper"))]
+mod engine {
+    use super::WhisperOptions;
+
+    const NOT_BUILT: &str =
+        "Local Whisper transcription is not available in this build (enable the `local-whisper` feature)";
+
+    pub fn ensure_available() -> Result<(), String> {
+        Err(NOT_BUILT.into())
+    }
+
+    pub struct Engine;
+
+    impl Engine {
+        pub fn load(_opts: &WhisperOptions) -> Result<Self, String> {
+            Err(NOT_BUILT.into())
+        }
+
+        pub fn transcribe(&mut self, _audio: &[f32]) -> Result<(String, String), String> {
+            Err(NOT_BUILT.into())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sliding_window_emits_partial_every_step() {
+        let mut window = SlidingWindow::new(4_000, 1_000, 0);
+        let second = vec![0.0f32; SAMPLE_RATE];
+        assert_eq!(window.push(&second[..SAMPLE_RATE / 2]), None);
+        match window.push(&second[..SAMPLE_RATE / 2]) {
+            Some(WindowPass::Partial(audio)) => assert_eq!(audio.len(), SAMPLE_RATE),
+            other => panic!("expected partial pass, got {:?}", other.map(|_| ())),
+        }
+        match window.push(&second) {
+            Some(WindowPass::Partial(audio)) => assert_eq!(audio.len(), 2 * SAMPLE_RATE),
+            other => panic!("expected partial pass, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn test_sliding_window_commits_and_keeps_overlap() {
+        let mut window = SlidingWindow::new(2_000, 1_000, 200);
+        let second = vec![0.5f32; SAMPLE_RATE];
+        assert!(matches!(window.push(&second), Some(WindowPass::Partial(_))));
+        // The last 200 ms are left to the next window instead of being
+        // committed twice
+        let keep = ms_to_samples(200);
+        match window.push(&second) {
+            Some(WindowPass::Commit(audio)) => assert_eq!(audio.len(), 2 * SAMPLE_RATE - keep),
+            other => panic!("expected commit, got {:?}", other.map(|_| ())),
+        }
+        match window.push(&second) {
+            Some(WindowPass::Partial(audio)) => assert_eq!(audio.len(), SAMPLE_RATE + keep),
+            other => panic!("expected partial pass, got {:?}", other.map(|_| ())),
+        }
+        match window.push(&second[..SAMPLE_RATE - keep]) {
+            Some(WindowPass::Commit(audio)) => assert_eq!(audio.len(), 2 * SAMPLE_RATE - keep),
+            other => panic!("expected commit, got {:?}", other.map(|_| ())),
+        }
+        // A tail shorter than MIN_DRAIN_MS waits for more audio on a flush,
+        // and is padded when the audio ends
+        assert!(window.drain().is_none());
+        match window.finish() {
+            Some(WindowPass::Commit(audio)) => {
+                assert_eq!(audio.len(), ms_to_samples(MIN_DRAIN_MS));
+                assert_eq!(audio[..keep], second[..keep]);
+            }
+            other => panic!("expected commit, got {:?}", other.map(|_| ())),
+        }
+        assert!(window.finish().is_none());
+    }
+
+    #[test]
+    fn test_sliding_window_drain_returns_tail() {
+        let mut window = SlidingWindow::new(10_000, 3_000, 200);
+        window.push(&vec![0.1f32; SAMPLE_RATE]);
+        match window.drain() {
+            Some(WindowPass::Commit(audio)) => assert_eq!(audio.len(), SAMPLE_RATE),
+            other => panic!("expected commit, got {:?}", other.map(|_| ())),
+        }
+        assert!(window.drain().is_none());
+    }
+
+    #[test]
+    fn test_transcript_state_renders_like_soniox() {
+        let mut state = TranscriptState::default();
+        state.apply(true, " hello there".into(), "en");
+        state.apply(false, " how are".into(), "en");
+        let text = state.render();
+        assert!(text.contains("Speaker: [en] Hello there how are"));
+        assert!(text.contains("==============================="));
+
+        state.apply(true, " how are you".into(), "en");
+        assert!(state.render().contains("Hello there how are you."));
+    }
+}
@@ -17,8 +17,11 @@ let sonioxEnableChk: HTMLInputElement | null;
 let sonioxStatusEl: HTMLElement | null;
 let sonioxConnected = false;
 let sonioxStarting = false;
+let appConfig: any = null;
 let openaiApiKeyInp: HTMLInputElement | null;
 let openrouterApiKeyInp: HTMLInputElement | null;
+let anthropicApiKeyInp: HTMLInputElement | null;
+let geminiApiKeyInp: HTMLInputElement | null;
 let assistantSel: HTMLSelectElement | null;
 let aiProviderSel: HTMLSelectElement | null;
 let aiModelSel: HTMLSelectElement | null;
@@ -31,6 +34,8 @@ let aiNextBtn: HTMLButtonElement | null;
 let aiPosEl: HTMLElement | null;
 let btnClearSession: HTMLButtonElement | null;
 let aiAnswers: string[] = [];
+// Structured data parsed from each answer, when its assistant has an output_schema
+let aiAnswerData: unknown[] = [];
 let aiIndex: number = -1; // -1 means no history yet
 let lastTranscript = "";
 let lastAnalyzedStable = "";
@@ -39,6 +44,9 @@ let gateCountEl: HTMLElement | null;
 let gateRuns = 0;
 let modelCountEl: HTMLElement | null;
 let modelRuns = 0;
+let aiCostEl: HTMLElement | null;
+let aiCostUsd = 0;
+let aiUnpricedRuns = 0;
 let gateLastEl: HTMLElement | null;
 let transcriptEl: HTMLElement | null;
 let currentStateType: RecordingState["type"] = "Idle";
@@ -53,30 +61,48 @@ let savedVoiceConfig: {
   quality: string;
 } | null = null;
 
+type AiProviderId = "openai" | "openrouter" | "anthropic" | "gemini";
+
+const AI_PROVIDERS: AiProviderId[] = ["openai", "openrouter", "anthropic", "gemini"];
+
 const providerSelectedModels: Record<string, string> = {
   openai: "gpt-4.1",
   openrouter: "deepseek/deepseek-chat-v3-0324:free",
+  anthropic: "claude-sonnet-4-0",
+  gemini: "gemini-2.5-flash",
 };
 
 const providerModelCache: Record<string, string[]> = {
   openai: [],
   openrouter: [],
+  anthropic: [],
+  gemini: [],
 };
 
-function getCurrentProvider(): "openai" | "openrouter" {
-  return aiProviderSel?.value === "openrouter" ? "openrouter" : "openai";
+function getCurrentProvider(): AiProviderId {
+  const value = aiProviderSel?.value as AiProviderId;
+  return AI_PROVIDERS.includes(value) ? value : "openai";
 }
 
-function getApiKeyForProvider(provider: "openai" | "openrouter"): string {
-  if (provider === "openrouter") {
-    return openrouterApiKeyInp?.value.trim() || "";
-  }
-  return openaiApiKeyInp?.value.trim() || "";
+function getApiKeyForProvider(provider: AiProviderId): string {
+  const inputs: Record<AiProviderId, HTMLInputElement | null> = {
+    openai: openaiApiKeyInp,
+    openrouter: openrouterApiKeyInp,
+    anthropic: anthropicApiKeyInp,
+    gemini: geminiApiKeyInp,
+  };
+  return inputs[provider]?.value.trim() || "";
+}
+
+// OpenAI-compatible local servers can be configured to run without a key
+function providerReady(provider: AiProviderId): boolean {
+  if (getApiKeyForProvider(provider)) return true;
+  return provider === "openai" && appConfig?.openai?.require_api_key === false;
 }
 
-async function fetchModelsForMain(provider: "openai" | "openrouter") {
+async function fetchModelsForMain(provider: AiProviderId) {
   const apiKey = getApiKeyForProvider(provider);
-  if (!apiKey) {
+  if (!providerReady(provider)) {
     providerModelCache[provider] = [];
     if (getCurrentProvider() === provider) {
       populateModelOptions(provider);
@@ -100,7 +126,7 @@ async function fetchModelsForMain(provider: "openai" | "openrouter") {
   }
 }
 
-function populateModelOptions(provider: "openai" | "openrouter") {
+function populateModelOptions(provider: AiProviderId) {
   const select = aiModelSel;
   if (!select) return;
 
@@ -226,8 +252,9 @@ async function tryStartSoniox(opts: SonioxStartOptions = {}): Promise<boolean> {
     return false;
   }
 
+  const transcriptionProvider = appConfig?.ui?.transcription_provider || "soniox";
   const api_key = (sonioxApiKeyInp?.value || "").trim();
-  if (!api_key) {
+  if (transcriptionProvider === "soniox" && !api_key) {
     if (requireKeyPrompt) {
       alert("Please enter your Soniox API key.");
       console.error("[Soniox] start aborted — missing API key");
@@ -242,18 +269,26 @@ async function tryStartSoniox(opts: SonioxStartOptions = {}): Promise<boolean> {
     sonioxStatusEl.className = "soniox-status connecting";
   }
 
-  console.log("[Soniox] invoking start_soniox_session...");
+  console.log("[Soniox] invoking start_transcription_session...");
   sonioxConnected = false;
   sonioxStarting = true;
 
   try {
-    await invoke("start_soniox_session", {
-      opts: { api_key, audio_format: "pcm_s16le", translation: "none" },
-    });
-    console.log("[Soniox] start_soniox_session returned successfully");
+    const opts =
+      transcriptionProvider === "whisper"
+        ? { provider: "whisper", ...(appConfig?.whisper || {}) }
+        : {
+            provider: "soniox",
+            translation: "none",
+            ...(appConfig?.soniox || {}),
+            api_key,
+            audio_format: "pcm_s16le",
+          };
+    await invoke("start_transcription_session", { opts });
+    console.log("[Soniox] start_transcription_session returned successfully");
     return true;
   } catch (err) {
-    console.error("[Soniox] start_soniox_session failed", err);
+    console.error("[Soniox] start_transcription_session failed", err);
     sonioxStarting = false;
     throw err;
   }
@@ -492,6 +527,62 @@ async function loadInitialConfiguration() {
   }
 }
 
+type PipelineStep = { assistant?: string; prompt?: string };
+type Pipeline = { id: string; name: string; description: string; steps: PipelineStep[] };
+type AssistantsChangedPayload = { assistants: any[]; default_assistant: string; pipelines: Pipeline[] };
+
+// Pipelines share the assistant selector, as "pipeline:<id>"
+const PIPELINE_PREFIX = "pipeline:";
+let pipelines: Pipeline[] = [];
+
+function selectedPipeline(): Pipeline | null {
+  const value = assistantSel?.value || "";
+  if (!value.startsWith(PIPELINE_PREFIX)) return null;
+  const id = value.slice(PIPELINE_PREFIX.length);
+  return pipelines.find(p => p.id === id) ?? null;
+}
+
+// Assistant for gate calls; a pipeline is gated by its last named assistant
+function selectedAssistantId(): string | null {
+  const pipeline = selectedPipeline();
+  if (pipeline) {
+    return pipeline.steps.map(s => s.assistant).filter((id): id is string => !!id).pop() ?? null;
+  }
+  return assistantSel?.value || null;
+}
+
+// Fill the selector, keeping the current choice when it still exists
+function populateAssistants(assistants: any[], defaultId: string, pipelineList: Pipeline[] = []) {
+  if (!assistantSel) return;
+  const previous = assistantSel.value;
+  pipelines = pipelineList;
+  assistantSel.innerHTML = "";
+  assistants.forEach(assistant => {
+    const option = document.createElement("option");
+    option.value = assistant.id;
+    option.textContent = assistant.name;
+    option.title = assistant.description;
+    assistantSel!.appendChild(option);
+  });
+  if (pipelines.length > 0) {
+    const group = document.createElement("optgroup");
+    group.label = "Pipelines";
+    pipelines.forEach(pipeline => {
+      const option = document.createElement("option");
+      option.value = `${PIPELINE_PREFIX}${pipeline.id}`;
+      option.textContent = pipeline.name;
+      option.title = pipeline.description;
+      group.appendChild(option);
+    });
+    assistantSel.appendChild(group);
+  }
+  const values = Array.from(assistantSel.options).map(o => o.value);
+  assistantSel.value = values.includes(previous) ? previous : defaultId;
+  assistantSel.disabled = false;
+  assistantSel.style.backgroundColor = "";
+  assistantSel.style.color = "";
+}
+
 // Assistant management functions
 async function loadAssistants() {
   try {
@@ -499,26 +590,13 @@ async function loadAssistants() {
     await invoke("load_assistants");
     const assistants = await invoke<any[]>("get_assistants");
     const defaultId = await invoke<string>("get_default_assistant_id");
+    const pipelineList = await invoke<Pipeline[]>("get_pipelines");
 
     console.log("Loaded assistants:", assistants);
     console.log("Default ID:", defaultId);
 
     if (assistantSel && assistants.length > 0) {
-      // Clear loading option
-      assistantSel.innerHTML = "";
-
-      // Add assistants to selector
-      assistants.forEach(assistant => {
-        const option = document.createElement("option");
-        option.value = assistant.id;
-        option.textContent = assistant.name;
-        option.title = assistant.description;
-        assistantSel!.appendChild(option);
-        console.log(`Added assistant: ${assistant.name} (${assistant.id})`);
-      });
-
-      // Select default assistant
-      assistantSel.value = defaultId;
+      populateAssistants(assistants, defaultId, pipelineList);
       console.log("Assistant selector populated successfully");
     } else {
       throw new Error("No assistants returned from backend");
@@ -544,20 +622,29 @@ async function loadAssistants() {
 let analyzing = false;
 let currentStreamId: string | null = null;
 let currentStreamText = "";
+let currentGateId: string | null = null;
+// Router mode: concurrent analyses keyed by "<request id>:<assistant id>"
+type RoutedStream = { assistantId: string; text: string; done: boolean };
+const routedStreams = new Map<string, RoutedStream>();
+let lastAnswerByAssistant: Record<string, string> = {};
+
+function newRequestId(): string {
+  return typeof crypto !== "undefined" && "randomUUID" in crypto
+    ? crypto.randomUUID()
+    : `${Date.now()}-${Math.round(Math.random() * 1e6)}`;
+}
 
 async function analyzeWithAI(transcriptOverride?: string) {
   const transcriptToAnalyze = (transcriptOverride ?? lastTranscript).trim();
   const provider = getCurrentProvider();
   const apiKey = getApiKeyForProvider(provider);
 
-  if (analyzing || !apiKey || !transcriptToAnalyze) {
+  if (analyzing || !providerReady(provider) || !transcriptToAnalyze) {
     return;
   }
 
   analyzing = true;
-  const requestId = typeof crypto !== "undefined" && "randomUUID" in crypto
-    ? crypto.randomUUID()
-    : `${Date.now()}-${Math.round(Math.random() * 1e6)}`;
+  const requestId = newRequestId();
 
   currentStreamId = requestId;
   currentStreamText = "";
@@ -576,19 +663,99 @@ async function analyzeWithAI(transcriptOverride?: string) {
   modelRuns += 1;
   if (modelCountEl) modelCountEl.textContent = `Model: ${modelRuns}`;
 
+  const pipeline = selectedPipeline();
   try {
     await invoke("stream_ai_analysis", {
       provider,
       apiKey,
       model: selectedModel || null,
-      assistantId: assistantSel?.value || null,
+      assistantId: pipeline ? null : assistantSel?.value || null,
+      pipelineId: pipeline?.id ?? null,
       requestId,
       transcript: transcriptToAnalyze,
       lastOutput: lastAnswer,
     });
   } catch (error) {
     console.error("❌ AI analysis error:", error);
-    handleAiStreamError(requestId, String(error));
+    // Usually already reported through the ai-analysis-error event
+    if (currentStreamId === requestId) {
+      handleAiStreamError(requestId, toAiError(error));
+    }
+  }
+}
+
+function assistantName(id: string): string {
+  const option = assistantSel?.querySelector(`option[value="${CSS.escape(id)}"]`);
+  return option?.textContent || id;
+}
+
+function renderRoutedStreams() {
+  if (!aiAnalysisEl) return;
+  const placeholder = aiAnalysisEl.querySelector(".placeholder");
+  if (placeholder) placeholder.remove();
+  aiAnalysisEl.textContent = Array.from(routedStreams.values())
+    .map((stream) => `[${assistantName(stream.assistantId)}]\n${stream.text || "…"}`)
+    .join("\n\n");
+  aiAnalysisEl.scrollTop = aiAnalysisEl.scrollHeight;
+}
+
+// Run several assistants on the same transcript; the backend streams each
+// under its own request id.
+async function analyzeRouted(transcript: string, assistantIds: string[]) {
+  const provider = getCurrentProvider();
+  const apiKey = getApiKeyForProvider(provider);
+  if (analyzing || !providerReady(provider) || !transcript || assistantIds.length === 0) {
+    return;
+  }
+
+  analyzing = true;
+  const requestId = newRequestId();
+  routedStreams.clear();
+  for (const id of assistantIds) {
+    routedStreams.set(`${requestId}:${id}`, { assistantId: id, text: "", done: false });
+  }
+  setAiStatus("analyzing", `Analyzing (${assistantIds.length})...`);
+  renderRoutedStreams();
+
+  const selectedModel = aiModelSel?.value || providerSelectedModels[provider];
+  const lastAnswer = aiAnswers.length > 0 ? aiAnswers[aiAnswers.length - 1] : null;
+  modelRuns += assistantIds.length;
+  if (modelCountEl) modelCountEl.textContent = `Model: ${modelRuns}`;
+
+  try {
+    await invoke("stream_ai_analysis", {
+      provider,
+      apiKey,
+      model: selectedModel || null,
+      assistantId: null,
+      assistantIds,
+      requestId,
+      transcript,
+      lastOutput: lastAnswer,
+      lastOutputs: lastAnswerByAssistant,
+    });
+  } catch (error) {
+    // Only raised when every assistant failed; each was reported already
+    console.error("❌ Routed AI analysis error:", error);
+  }
+}
+
+function finishRoutedStream(requestId: string, text: string) {
+  const stream = routedStreams.get(requestId);
+  if (!stream) return;
+  stream.text = text;
+  stream.done = true;
+  renderRoutedStreams();
+
+  if (Array.from(routedStreams.values()).some((s) => !s.done)) {
+    return;
+  }
+  analyzing = false;
+  setAiStatus("ready", "Ready");
+  const answered = Array.from(routedStreams.values()).filter((s) => s.text.trim().length > 0);
+  routedStreams.clear();
+  if (answered.length > 0) {
+    pushAiAnswer(answered.map((s) => `[${assistantName(s.assistantId)}]\n${s.text}`).join("\n\n"));
   }
 }
 
@@ -603,12 +770,29 @@ function setAiStatus(state: AiStatusState, text: string) {
 
 type AiStreamPayload = {
   request_id: string;
+  assistant_id?: string;
   segment?: string | null;
   final_text?: string | null;
+  // Final answer parsed against the assistant's output_schema
+  data?: unknown;
   done: boolean;
 };
 
 function processAiStreamPayload(payload: AiStreamPayload) {
+  const routed = routedStreams.get(payload.request_id);
+  if (routed) {
+    if (payload.segment) {
+      routed.text += payload.segment;
+      renderRoutedStreams();
+    }
+    if (payload.done) {
+      const finalText = (payload.final_text ?? routed.text).trim();
+      if (finalText) lastAnswerByAssistant[routed.assistantId] = finalText;
+      finishRoutedStream(payload.request_id, finalText);
+    }
+    return;
+  }
+
   if (!currentStreamId || payload.request_id !== currentStreamId) {
     return;
   }
@@ -640,12 +824,64 @@ function processAiStreamPayload(payload: AiStreamPayload) {
     }
 
     if (finalText.length > 0) {
-      pushAiAnswer(finalText);
+      const assistantId = payload.assistant_id || assistantSel?.value;
+      if (assistantId) lastAnswerByAssistant[assistantId] = finalText;
+      pushAiAnswer(finalText, payload.data ?? null);
     }
   }
 }
 
-function handleAiStreamError(requestId: string, message: string) {
+type AiErrorCategory =
+  | "auth"
+  | "quota"
+  | "rate_limit"
+  | "network"
+  | "model_not_found"
+  | "server"
+  | "budget"
+  | "cancelled"
+  | "other";
+
+type AiError = {
+  category: AiErrorCategory;
+  message: string;
+  status?: number | null;
+};
+
+const AI_ERROR_LABELS: Record<AiErrorCategory, string> = {
+  auth: "Check API key",
+  quota: "Quota exhausted",
+  rate_limit: "Rate limited",
+  network: "Network error",
+  model_not_found: "Model not found",
+  server: "Provider unavailable",
+  budget: "Budget exceeded",
+  cancelled: "Cancelled",
+  other: "Error",
+};
+
+// What the user can do about it, where there is something to do.
+const AI_ERROR_HINTS: Partial<Record<AiErrorCategory, string>> = {
+  auth: "Update the provider API key in Settings.",
+  quota: "Add credits or switch provider.",
+  rate_limit: "Retries were exhausted; wait a moment before the next request.",
+  model_not_found: "Pick another model in Settings.",
+  budget: "Raise the limit under \"budget\" in config.json or wait for the day or session to roll over.",
+};
+
+// Commands reject with either a structured AiError or a plain string.
+function toAiError(err: unknown): AiError {
+  if (err && typeof err === "object" && "category" in err && "message" in err) {
+    return err as AiError;
+  }
+  return { category: "other", message: String(err) };
+}
+
+function handleAiStreamError(requestId: string, error: AiError) {
+  if (routedStreams.has(requestId)) {
+    finishRoutedStream(requestId, `Error: ${error.message}`);
+    return;
+  }
   if (currentStreamId && currentStreamId !== requestId) {
     return;
   }
@@ -653,20 +889,111 @@ function handleAiStreamError(requestId: string, message: string) {
   analyzing = false;
   currentStreamId = null;
   currentStreamText = "";
-  setAiStatus("error", "Error");
+  setAiStatus("error", AI_ERROR_LABELS[error.category] ?? "Error");
 
   if (aiAnalysisEl) {
-    aiAnalysisEl.textContent = `Error: ${message}`;
+    const hint = AI_ERROR_HINTS[error.category];
+    aiAnalysisEl.textContent = hint ? `Error: ${error.message}\n${hint}` : `Error: ${error.message}`;
+  }
+}
+
+type AiUsagePayload = {
+  request_id: string;
+  kind: "analysis" | "gate" | "summary" | "embedding";
+  model: string;
+  input_tokens: number;
+  output_tokens: number;
+  cached_input_tokens: number;
+  cost_usd: number | null;
+};
+
+function renderAiCost() {
+  if (!aiCostEl) return;
+  const unpriced = aiUnpricedRuns > 0 ? ` (+${aiUnpricedRuns} unpriced)` : "";
+  aiCostEl.textContent = `Cost: $${aiCostUsd.toFixed(4)}${unpriced}`;
+}
+
+function handleAiUsage(payload: AiUsagePayload) {
+  if (typeof payload.cost_usd === "number") {
+    aiCostUsd += payload.cost_usd;
+  } else {
+    aiUnpricedRuns += 1;
+  }
+  renderAiCost();
+}
+
+type BudgetPayload = {
+  request_id: string;
+  kind: "analysis" | "gate" | "summary" | "embedding";
+  scope: "daily" | "session";
+  spent_usd: number;
+  limit_usd: number;
+  action: "block" | "downgrade" | null;
+  model: string;
+};
+
+function handleBudgetEvent(payload: BudgetPayload, exceeded: boolean) {
+  if (!aiCostEl) return;
+  const scope = payload.scope === "daily" ? "Daily" : "Session";
+  const state = exceeded
+    ? payload.action === "downgrade" ? `exceeded, using ${payload.model}` : "exceeded"
+    : "nearly reached";
+  aiCostEl.classList.toggle("budget-warning", !exceeded);
+  aiCostEl.classList.toggle("budget-exceeded", exceeded);
+  aiCostEl.setAttribute(
+    "title",
+    `${scope} budget ${state}: $${payload.spent_usd.toFixed(4)} of $${payload.limit_usd.toFixed(2)}`,
+  );
+}
+
+type AiCancelledPayload = {
+  request_id: string;
+  kind: "analysis" | "gate";
+  assistant_id: string;
+  reason: "requested" | "superseded";
+};
+
+function handleAiCancelled(payload: AiCancelledPayload) {
+  if (payload.kind === "gate") {
+    if (currentGateId === payload.request_id) currentGateId = null;
+    return;
+  }
+  if (routedStreams.has(payload.request_id)) {
+    finishRoutedStream(payload.request_id, "");
+    return;
+  }
+  if (currentStreamId !== payload.request_id) {
+    return;
+  }
+
+  analyzing = false;
+  currentStreamId = null;
+  currentStreamText = "";
+  setAiStatus("ready", "Cancelled");
+}
+
+// Stop paying for requests whose output the UI no longer shows
+function cancelInFlightAiRequests() {
+  for (const requestId of [currentStreamId, currentGateId, ...routedStreams.keys()]) {
+    if (requestId) {
+      invoke("cancel_ai_request", { requestId }).catch((e) => {
+        console.warn("Failed to cancel AI request:", e);
+      });
+    }
   }
 }
 
 async function clearTranscriptAndHistory() {
+  cancelInFlightAiRequests();
+  currentGateId = null;
   lastTranscript = "";
   lastAnalyzedStable = "";
   lastAnalysisAt = 0;
   analyzing = false;
   currentStreamId = null;
   currentStreamText = "";
+  routedStreams.clear();
+  lastAnswerByAssistant = {};
 
   if (transcriptEl) {
     transcriptEl.innerHTML = `<div class="placeholder">${DEFAULT_TRANSCRIPT_PLACEHOLDER}</div>`;
@@ -674,6 +1001,7 @@ async function clearTranscriptAndHistory() {
   }
 
   aiAnswers = [];
+  aiAnswerData = [];
   aiIndex = -1;
   renderAiAnswer();
   setAiStatus("ready", "Ready");
@@ -686,6 +1014,9 @@ async function clearTranscriptAndHistory() {
   if (gateCountEl) gateCountEl.textContent = "Gate: 0";
   modelRuns = 0;
   if (modelCountEl) modelCountEl.textContent = "Model: 0";
+  aiCostUsd = 0;
+  aiUnpricedRuns = 0;
+  renderAiCost();
   if (gateLastEl) {
     gateLastEl.textContent = "Decision: -";
     gateLastEl.removeAttribute("title");
@@ -792,14 +1123,36 @@ async function setupConfigModal() {
   const configOpenrouterGateModel = document.querySelector("#config-openrouter-gate-model") as HTMLSelectElement;
   const configOpenrouterCredits = document.querySelector("#config-openrouter-credits") as HTMLElement;
 
+  const configAnthropicKey = document.querySelector("#config-anthropic-key") as HTMLInputElement;
+  const configAnthropicModel = document.querySelector("#config-anthropic-model") as HTMLSelectElement;
+  const configAnthropicGateModel = document.querySelector("#config-anthropic-gate-model") as HTMLSelectElement;
+
+  const configGeminiKey = document.querySelector("#config-gemini-key") as HTMLInputElement;
+  const configGeminiModel = document.querySelector("#config-gemini-model") as HTMLSelectElement;
+  const configGeminiGateModel = document.querySelector("#config-gemini-gate-model") as HTMLSelectElement;
+
+  // Per-provider fields in the modal and the defaults used when nothing is saved
+  const providerFields: Record<AiProviderId, {
+    key: HTMLInputElement;
+    model: HTMLSelectElement;
+    gate: HTMLSelectElement;
+    defaultModel: string;
+    defaultGate: string;
+  }> = {
+    openai: { key: configOpenaiKey, model: configOpenaiModel, gate: configOpenaiGateModel, defaultModel: "gpt-4.1", defaultGate: "gpt-4.1-nano" },
+    openrouter: { key: configOpenrouterKey, model: configOpenrouterModel, gate: configOpenrouterGateModel, defaultModel: "deepseek/deepseek-chat-v3-0324:free", defaultGate: "deepseek/deepseek-chat-v3-0324:free" },
+    anthropic: { key: configAnthropicKey, model: configAnthropicModel, gate: configAnthropicGateModel, defaultModel: "claude-sonnet-4-0", defaultGate: "claude-3-5-haiku-latest" },
+    gemini: { key: configGeminiKey, model: configGeminiModel, gate: configGeminiGateModel, defaultModel: "gemini-2.5-flash", defaultGate: "gemini-2.5-flash-lite" },
+  };
+
   const configRecordingFormat = document.querySelector("#config-recording-format") as HTMLSelectElement;
   const configRecordingQuality = document.querySelector("#config-recording-quality") as HTMLSelectElement;
   const configRecordingAuto = document.querySelector("#config-recording-auto") as HTMLInputElement;
   const configDefaultAssistant = document.querySelector("#config-default-assistant") as HTMLSelectElement;
+  const configGateRouter = document.querySelector("#config-gate-router") as HTMLInputElement;
 
   let latestConfig: any = null;
-  let openaiDebounce: number | undefined;
-  let openrouterDebounce: number | undefined;
+  const keyDebounce: Partial<Record<AiProviderId, number>> = {};
 
   const fillSelect = (
     select: HTMLSelectElement | null,
@@ -835,23 +1188,16 @@ async function setupConfigModal() {
     }
   };
 
-  async function fetchConfigModels(provider: "openai" | "openrouter") {
-    const key = provider === "openrouter"
-      ? configOpenrouterKey?.value.trim()
-      : configOpenaiKey?.value.trim();
-    const modelSelect = provider === "openrouter" ? configOpenrouterModel : configOpenaiModel;
-    const gateSelect = provider === "openrouter" ? configOpenrouterGateModel : configOpenaiGateModel;
-    if (!key || !modelSelect || !gateSelect) return;
+  async function fetchConfigModels(provider: AiProviderId) {
+    const fields = providerFields[provider];
+    const key = fields.key?.value.trim();
+    const keyless = provider === "openai" && latestConfig?.openai?.require_api_key === false;
+    if ((!key && !keyless) || !fields.model || !fields.gate) return;
 
     try {
-      const models = await invoke<string[]>("get_ai_models", { provider, apiKey: key });
-      if (provider === "openrouter") {
-        fillSelect(modelSelect, models, latestConfig?.openrouter?.model, "deepseek/deepseek-chat-v3-0324:free");
-        fillSelect(gateSelect, models, latestConfig?.openrouter?.gate_model, "deepseek/deepseek-chat-v3-0324:free");
-      } else {
-        fillSelect(modelSelect, models, latestConfig?.openai?.model, "gpt-4.1");
-        fillSelect(gateSelect, models, latestConfig?.openai?.gate_model, "gpt-4.1-nano");
-      }
+      const models = await invoke<string[]>("get_ai_models", { provider, apiKey: key || "" });
+      fillSelect(fields.model, models, latestConfig?.[provider]?.model, fields.defaultModel);
+      fillSelect(fields.gate, models, latestConfig?.[provider]?.gate_model, fields.defaultGate);
     } catch (error) {
       console.error(`❌ Failed to fetch ${provider} models for config:`, error);
     }
@@ -895,24 +1241,24 @@ async function setupConfigModal() {
       if (configAiProvider) configAiProvider.value = config.ui?.ai_provider || "openai";
       if (configAiEnable) configAiEnable.checked = config.ui?.enable_ai || false;
 
-      if (configOpenaiKey) configOpenaiKey.value = config.openai?.api_key || "";
-      if (configOpenrouterKey) configOpenrouterKey.value = config.openrouter?.api_key || "";
-
-      fillSelect(configOpenaiModel, config.openai?.model ? [config.openai.model] : [], config.openai?.model, "gpt-4.1");
-      fillSelect(configOpenaiGateModel, config.openai?.gate_model ? [config.openai.gate_model] : [], config.openai?.gate_model, "gpt-4.1-nano");
-      fillSelect(configOpenrouterModel, config.openrouter?.model ? [config.openrouter.model] : [], config.openrouter?.model, "deepseek/deepseek-chat-v3-0324:free");
-      fillSelect(configOpenrouterGateModel, config.openrouter?.gate_model ? [config.openrouter.gate_model] : [], config.openrouter?.gate_model, "deepseek/deepseek-chat-v3-0324:free");
+      AI_PROVIDERS.forEach((provider) => {
+        const fields = providerFields[provider];
+        const saved = config[provider];
+        if (fields.key) fields.key.value = saved?.api_key || "";
+        fillSelect(fields.model, saved?.model ? [saved.model] : [], saved?.model, fields.defaultModel);
+        fillSelect(fields.gate, saved?.gate_model ? [saved.gate_model] : [], saved?.gate_model, fields.defaultGate);
+      });
 
       if (configRecordingFormat) configRecordingFormat.value = config.recording?.default_format || "mp3";
       if (configRecordingQuality) configRecordingQuality.value = config.recording?.default_quality || "verylow";
       if (configRecordingAuto) configRecordingAuto.checked = config.recording?.auto_detect_enabled !== false;
       if (configDefaultAssistant) configDefaultAssistant.value = config.ui?.default_assistant || "general";
+      if (configGateRouter) configGateRouter.checked = config.ui?.gate_router || false;
 
-      if (configOpenaiKey?.value.trim()) {
-        await fetchConfigModels("openai");
+      for (const provider of AI_PROVIDERS) {
+        await fetchConfigModels(provider);
       }
       if (configOpenrouterKey?.value.trim()) {
-        await fetchConfigModels("openrouter");
         await refreshConfigOpenrouterCredits();
       } else if (configOpenrouterCredits) {
         configOpenrouterCredits.textContent = "--";
@@ -927,31 +1273,49 @@ async function setupConfigModal() {
   async function saveConfiguration() {
     try {
       const config = {
+        ...(latestConfig || {}),
         soniox: {
+          translation: "none",
+          ...(latestConfig?.soniox || {}),
           api_key: configSonioxKey?.value || "",
           audio_format: configSonioxFormat?.value || "pcm_s16le",
-          translation: "none",
         },
         openai: {
+          ...(latestConfig?.openai || {}),
           api_key: configOpenaiKey?.value || "",
           model: configOpenaiModel?.value || "gpt-4.1",
           gate_model: configOpenaiGateModel?.value || "gpt-4.1-nano",
         },
         openrouter: {
+          ...(latestConfig?.openrouter || {}),
           api_key: configOpenrouterKey?.value || "",
           model: configOpenrouterModel?.value || "deepseek/deepseek-chat-v3-0324:free",
           gate_model: configOpenrouterGateModel?.value || "deepseek/deepseek-chat-v3-0324:free",
         },
+        anthropic: {
+          ...(latestConfig?.anthropic || {}),
+          api_key: configAnthropicKey?.value || "",
+          model: configAnthropicModel?.value || providerFields.anthropic.defaultModel,
+          gate_model: configAnthropicGateModel?.value || providerFields.anthropic.defaultGate,
+        },
+        gemini: {
+          ...(latestConfig?.gemini || {}),
+          api_key: configGeminiKey?.value || "",
+          model: configGeminiModel?.value || providerFields.gemini.defaultModel,
+          gate_model: configGeminiGateModel?.value || providerFields.gemini.defaultGate,
+        },
         recording: {
           default_format: configRecordingFormat?.value || "mp3",
           default_quality: configRecordingQuality?.value || "verylow",
           auto_detect_enabled: configRecordingAuto?.checked !== false,
         },
         ui: {
+          ...(latestConfig?.ui || {}),
           enable_soniox: configSonioxEnable?.checked || false,
           enable_ai: configAiEnable?.checked || false,
           default_assistant: configDefaultAssistant?.value || "general",
           ai_provider: configAiProvider?.value || "openai",
+          gate_router: configGateRouter?.checked || false,
         },
       };
 
@@ -1012,38 +1376,39 @@ async function setupConfigModal() {
     }
   });
 
-  configOpenaiKey?.addEventListener("input", () => {
-    window.clearTimeout(openaiDebounce);
-    openaiDebounce = window.setTimeout(() => {
-      if (configOpenaiKey?.value.trim()) {
-        fetchConfigModels("openai");
-      }
-    }, 800);
-  });
-
-  configOpenrouterKey?.addEventListener("input", () => {
-    window.clearTimeout(openrouterDebounce);
-    openrouterDebounce = window.setTimeout(() => {
-      if (configOpenrouterKey?.value.trim()) {
-        fetchConfigModels("openrouter");
-        refreshConfigOpenrouterCredits();
-      } else if (configOpenrouterCredits) {
-        configOpenrouterCredits.textContent = "--";
-        configOpenrouterCredits.removeAttribute("title");
-      }
-    }, 800);
+  AI_PROVIDERS.forEach((provider) => {
+    const keyInput = providerFields[provider].key;
+    keyInput?.addEventListener("input", () => {
+      window.clearTimeout(keyDebounce[provider]);
+      keyDebounce[provider] = window.setTimeout(() => {
+        if (keyInput.value.trim()) {
+          fetchConfigModels(provider);
+        }
+        if (provider !== "openrouter") return;
+        if (keyInput.value.trim()) {
+          refreshConfigOpenrouterCredits();
+        } else if (configOpenrouterCredits) {
+          configOpenrouterCredits.textContent = "--";
+          configOpenrouterCredits.removeAttribute("title");
+        }
+      }, 800);
+    });
   });
 }
 
 function applyConfigToUi(config: any) {
+  appConfig = config;
   if (sonioxApiKeyInp) sonioxApiKeyInp.value = config.soniox?.api_key || "";
   if (sonioxEnableChk) sonioxEnableChk.checked = config.ui?.enable_soniox || false;
 
   if (openaiApiKeyInp) openaiApiKeyInp.value = config.openai?.api_key || "";
   if (openrouterApiKeyInp) openrouterApiKeyInp.value = config.openrouter?.api_key || "";
+  if (anthropicApiKeyInp) anthropicApiKeyInp.value = config.anthropic?.api_key || "";
+  if (geminiApiKeyInp) geminiApiKeyInp.value = config.gemini?.api_key || "";
 
-  providerSelectedModels.openai = config.openai?.model || providerSelectedModels.openai;
-  providerSelectedModels.openrouter = config.openrouter?.model || providerSelectedModels.openrouter;
+  AI_PROVIDERS.forEach((provider) => {
+    providerSelectedModels[provider] = config[provider]?.model || providerSelectedModels[provider];
+  });
 
   if (aiProviderSel) aiProviderSel.value = config.ui?.ai_provider || "openai";
   if (aiEnableChk) aiEnableChk.checked = config.ui?.enable_ai || false;
@@ -1055,23 +1420,16 @@ function applyConfigToUi(config: any) {
 
   formatSel?.dispatchEvent(new Event("change"));
 
-  if (config.openai?.api_key) {
-    fetchModelsForMain("openai");
-  } else {
-    providerModelCache.openai = [];
-    if (getCurrentProvider() === "openai") {
-      populateModelOptions("openai");
-    }
-  }
-
-  if (config.openrouter?.api_key) {
-    fetchModelsForMain("openrouter");
-  } else {
-    providerModelCache.openrouter = [];
-    if (getCurrentProvider() === "openrouter") {
-      populateModelOptions("openrouter");
+  AI_PROVIDERS.forEach((provider) => {
+    if (providerReady(provider)) {
+      fetchModelsForMain(provider);
+    } else {
+      providerModelCache[provider] = [];
+      if (getCurrentProvider() === provider) {
+        populateModelOptions(provider);
+      }
     }
-  }
+  });
 
   updateOpenrouterCreditsDisplay(null);
   populateModelOptions(getCurrentProvider());
@@ -1095,6 +1453,8 @@ window.addEventListener("DOMContentLoaded", async () => {
   sonioxStatusEl = document.querySelector("#soniox-status");
   openaiApiKeyInp = document.querySelector("#openai-api");
   openrouterApiKeyInp = document.querySelector("#openrouter-api");
+  anthropicApiKeyInp = document.querySelector("#anthropic-api");
+  geminiApiKeyInp = document.querySelector("#gemini-api");
   assistantSel = document.querySelector("#assistant-select");
   aiProviderSel = document.querySelector("#ai-provider");
   aiModelSel = document.querySelector("#ai-model");
@@ -1113,7 +1473,7 @@ window.addEventListener("DOMContentLoaded", async () => {
 
   aiProviderSel?.addEventListener("change", async () => {
     const provider = getCurrentProvider();
-    if (getApiKeyForProvider(provider)) {
+    if (providerReady(provider)) {
       await fetchModelsForMain(provider);
     } else {
       populateModelOptions(provider);
@@ -1159,7 +1519,7 @@ window.addEventListener("DOMContentLoaded", async () => {
     // Debounce the API call
     clearTimeout((window as any).openaiModelTimeout);
     (window as any).openaiModelTimeout = setTimeout(() => {
-      if (openaiApiKeyInp?.value.trim().length) {
+      if (providerReady("openai")) {
         fetchModelsForMain("openai");
       } else {
         providerModelCache.openai = [];
@@ -1182,7 +1542,7 @@ window.addEventListener("DOMContentLoaded", async () => {
           statusEl.textContent = "Idle";
           // Auto-stop Soniox when stream fully stops
           if (sonioxConnected) {
-            invoke("stop_soniox_session").catch(() => {});
+            invoke("stop_transcription_session").catch(() => {});
           }
           break;
         case "Recording":
@@ -1198,7 +1558,7 @@ window.addEventListener("DOMContentLoaded", async () => {
           statusEl.textContent = "Paused";
           // In voice mode, Paused disarms stream; stop Soniox to avoid 408
           if (isVoiceMode && sonioxConnected) {
-            invoke("stop_soniox_session").catch(() => {});
+            invoke("stop_transcription_session").catch(() => {});
           }
           break;
         case "Starting":
@@ -1232,13 +1592,35 @@ window.addEventListener("DOMContentLoaded", async () => {
     processAiStreamPayload(event.payload);
   });
 
-  listen<{ request_id: string; message: string }>("ai-analysis-error", (event) => {
-    handleAiStreamError(event.payload.request_id, event.payload.message);
+  listen<AiError & { request_id: string }>("ai-analysis-error", (event) => {
+    handleAiStreamError(event.payload.request_id, event.payload);
+  });
+
+  listen<AiCancelledPayload>("ai-analysis-cancelled", (event) => {
+    handleAiCancelled(event.payload);
+  });
+
+  listen<AiUsagePayload>("ai-usage", (event) => {
+    handleAiUsage(event.payload);
+  });
+
+  listen<BudgetPayload>("budget-warning", (event) => {
+    handleBudgetEvent(event.payload, false);
+  });
+
+  listen<BudgetPayload>("budget-exceeded", (event) => {
+    handleBudgetEvent(event.payload, true);
+  });
+
+  // Assistants edited through the CRUD commands or by hand on disk
+  listen<AssistantsChangedPayload>("assistants-changed", (event) => {
+    populateAssistants(event.payload.assistants, event.payload.default_assistant, event.payload.pipelines);
   });
 
   // Soniox transcript events
   gateCountEl = document.getElementById("gate-count");
   modelCountEl = document.getElementById("model-count");
+  aiCostEl = document.getElementById("ai-cost");
   gateLastEl = document.getElementById("gate-last");
   aiPrevBtn = document.getElementById("ai-prev") as HTMLButtonElement | null;
   aiNextBtn = document.getElementById("ai-next") as HTMLButtonElement | null;
@@ -1296,43 +1678,95 @@ window.addEventListener("DOMContentLoaded", async () => {
         // Use lightweight gate to decide whether to invoke main model (strict)
         const provider = getCurrentProvider();
         const key = getApiKeyForProvider(provider);
-        if (key) {
+        if (providerReady(provider)) {
           const lastOut = aiAnalysisEl?.textContent || '';
           // Count a real gate request before invoking
           gateRuns += 1;
           if (gateCountEl) gateCountEl.textContent = `Gate: ${gateRuns}`;
 
-          invoke<{ run: boolean; instruction?: string; reason?: string; confidence?: number }>("should_run_analysis_gate", {
-            provider,
-            apiKey: key,
-            model: null, // Let backend use configured gate_model
-            assistantId: assistantSel?.value || null,
-            currentTranscript: stable,
-            previousTranscript: prevStable,
-            lastOutput: lastOut,
-          }).then((res) => {
-            console.log("Gate decision:", res);
-            if (gateLastEl) {
-              const decision = res?.instruction || (res?.run ? "NEEDED" : "NOT_NEEDED");
-              const conf = typeof res?.confidence === 'number' ? ` (${(res.confidence * 100).toFixed(0)}%)` : '';
-              gateLastEl.textContent = `Decision: ${decision}`;
-              gateLastEl.setAttribute('title', res?.reason ? `${res.reason}${conf}` : `Gate decision${conf}`);
-            }
-            if (res?.run) {
-              lastAnalyzedStable = stable;
-              lastAnalysisAt = now;
-              analyzeWithAI(stable);
-            } else {
-              console.log("⏭️ Skipping analysis due to gate decision.");
-            }
-          }).catch((err) => {
-            console.warn("Gate error:", err);
-            if (gateLastEl) {
-              gateLastEl.textContent = `Decision: ERROR`;
-              gateLastEl.setAttribute('title', String(err));
-            }
-            // Strict mode: do not run analysis on gate error
-          });
+          const gateId = newRequestId();
+          currentGateId = gateId;
+          if (appConfig?.ui?.gate_router) {
+            invoke<{ assistants: { assistant_id: string; run: boolean; reason?: string; confidence?: number }[]; parse_path: string }>("route_analysis_gate", {
+              provider,
+              apiKey: key,
+              model: null,
+              assistantId: selectedAssistantId(),
+              assistantIds: null, // All assistants enabled in assistants.json
+              currentTranscript: stable,
+              previousTranscript: prevStable,
+              lastOutput: lastOut,
+              requestId: gateId,
+            }).then((res) => {
+              if (currentGateId === gateId) currentGateId = null;
+              console.log("Router decision:", res);
+              const selected = res.assistants.filter((a) => a.run);
+              if (gateLastEl) {
+                gateLastEl.textContent = selected.length > 0
+                  ? `Decision: ${selected.map((a) => assistantName(a.assistant_id)).join(", ")}`
+                  : "Decision: NOT_NEEDED";
+                gateLastEl.setAttribute(
+                  "title",
+                  res.assistants.map((a) => `${assistantName(a.assistant_id)}: ${a.run ? "run" : "skip"} — ${a.reason ?? ""}`).join("\n") + ` [${res.parse_path}]`,
+                );
+              }
+              if (selected.length > 0) {
+                lastAnalyzedStable = stable;
+                lastAnalysisAt = now;
+                analyzeRouted(stable, selected.map((a) => a.assistant_id));
+              }
+            }).catch((err) => {
+              if (currentGateId === gateId) currentGateId = null;
+              const error = toAiError(err);
+              if (error.category === "cancelled") return;
+              console.warn("Router error:", error);
+              if (gateLastEl) {
+                gateLastEl.textContent = `Decision: ERROR`;
+                gateLastEl.setAttribute('title', `${AI_ERROR_LABELS[error.category] ?? "Error"}: ${error.message}`);
+              }
+            });
+          } else {
+            invoke<{ run: boolean; instruction?: string; reason?: string; confidence?: number; source?: "llm" | "local"; parse_path?: string }>("should_run_analysis_gate", {
+              provider,
+              apiKey: key,
+              model: null, // Let backend use configured gate_model
+              assistantId: selectedAssistantId(),
+              currentTranscript: stable,
+              previousTranscript: prevStable,
+              lastOutput: lastOut,
+              requestId: gateId,
+            }).then((res) => {
+              if (currentGateId === gateId) currentGateId = null;
+              console.log("Gate decision:", res);
+              if (gateLastEl) {
+                const decision = res?.instruction || (res?.run ? "NEEDED" : "NOT_NEEDED");
+                const conf = typeof res?.confidence === 'number' ? ` (${(res.confidence * 100).toFixed(0)}%)` : '';
+                gateLastEl.textContent = res?.source === "local" ? `Decision: ${decision} (local)` : `Decision: ${decision}`;
+                const parsed = res?.parse_path ? ` [${res.parse_path}]` : '';
+                gateLastEl.setAttribute('title', res?.reason ? `${res.reason}${conf}${parsed}` : `Gate decision${conf}${parsed}`);
+              }
+              if (res?.run) {
+                lastAnalyzedStable = stable;
+                lastAnalysisAt = now;
+                analyzeWithAI(stable);
+              } else {
+                console.log("⏭️ Skipping analysis due to gate decision.");
+              }
+            }).catch((err) => {
+              if (currentGateId === gateId) currentGateId = null;
+              const error = toAiError(err);
+              if (error.category === "cancelled") {
+                console.log("⏹️ Gate request cancelled.");
+                return;
+              }
+              console.warn("Gate error:", error);
+              if (gateLastEl) {
+                gateLastEl.textContent = `Decision: ERROR`;
+                gateLastEl.setAttribute('title', `${AI_ERROR_LABELS[error.category] ?? "Error"}: ${error.message}`);
+              }
+              // Strict mode: do not run analysis on gate error
+            });
+          }
         } else {
           // Strict mode: no gate key => do not analyze
           console.warn("Gate strict: missing AI provider API key; skipping analysis.");
@@ -1375,6 +1809,14 @@ window.addEventListener("DOMContentLoaded", async () => {
         sonioxStarting = false;
         sonioxConnected = true;
         break;
+      case "reconnecting":
+        // backend keeps buffering audio while it retries
+        sonioxStatusEl.textContent = "Reconnecting…";
+        sonioxStatusEl.className = "soniox-status connecting";
+        sonioxStarting = true;
+        sonioxConnected = false;
+        break;
+      case "recovered":
       case "config_sent":
         // minor state; keep as connected
         sonioxStatusEl.textContent = "Connected";
@@ -1468,7 +1910,7 @@ window.addEventListener("DOMContentLoaded", async () => {
           : "toggle-on idle";
         await tryStartSoniox({ requireKeyPrompt: true, reason });
       } else {
-        await invoke("stop_soniox_session");
+        await invoke("stop_transcription_session");
         if (sonioxStatusEl) {
           sonioxStatusEl.textContent = "Off";
           sonioxStatusEl.classList.remove("connecting", "connected", "error");
@@ -1493,12 +1935,51 @@ window.addEventListener("DOMContentLoaded", async () => {
 });
 
 // Push a new AI answer into history and show it
-function pushAiAnswer(answer: string) {
+function pushAiAnswer(answer: string, data: unknown = null) {
   aiAnswers.push(answer);
+  aiAnswerData.push(data);
   aiIndex = aiAnswers.length - 1; // auto-move to most recent
   renderAiAnswer();
 }
 
+// One card per item of each list in structured data (e.g. findings), with a
+// row per field; other fields are shown as a card of their own.
+function renderDataCards(container: HTMLElement, data: Record<string, unknown>) {
+  container.innerHTML = "";
+  const card = (fields: Record<string, unknown>) => {
+    const el = document.createElement("div");
+    el.className = "ai-card";
+    Object.entries(fields).forEach(([key, value]) => {
+      const row = document.createElement("div");
+      row.className = "ai-card-row";
+      const label = document.createElement("span");
+      label.className = "ai-card-key";
+      label.textContent = key;
+      const text = document.createElement("span");
+      text.textContent = typeof value === "string" ? value : JSON.stringify(value);
+      if (key === "severity" || key === "urgency") text.className = `ai-card-level ${String(value)}`;
+      row.append(label, text);
+      el.appendChild(row);
+    });
+    container.appendChild(el);
+  };
+  const scalars: Record<string, unknown> = {};
+  Object.entries(data).forEach(([key, value]) => {
+    if (Array.isArray(value) && value.every((item) => item && typeof item === "object")) {
+      if (value.length === 0) {
+        const empty = document.createElement("div");
+        empty.className = "placeholder";
+        empty.textContent = `No ${key.replace(/_/g, " ")}.`;
+        container.appendChild(empty);
+      }
+      value.forEach((item) => card(item as Record<string, unknown>));
+    } else {
+      scalars[key] = value;
+    }
+  });
+  if (Object.keys(scalars).length > 0) card(scalars);
+}
+
 // Render current AI answer and update navigation state
 function renderAiAnswer() {
   if (!aiAnalysisEl) return;
@@ -1515,7 +1996,12 @@ function renderAiAnswer() {
     }
   } else {
     if (placeholder) placeholder.remove();
-    aiAnalysisEl.textContent = aiAnswers[aiIndex] || "";
+    const data = aiAnswerData[aiIndex];
+    if (data && typeof data === "object") {
+      renderDataCards(aiAnalysisEl, data as Record<string, unknown>);
+    } else {
+      aiAnalysisEl.textContent = aiAnswers[aiIndex] || "";
+    }
     aiAnalysisEl.scrollTop = aiAnalysisEl.scrollHeight;
   }
 